@@ -0,0 +1,90 @@
+/*
+ * C bindings for the webtransport-quinn client.
+ *
+ * Every handle is owned by the caller and must be freed exactly once with the matching *_free function.
+ * Operations that can't complete immediately take a callback, which is invoked exactly once
+ * (except for wt_session_listen) with a wt_event on one of the client's runtime threads.
+ * Handles in the event are owned by the callee; data and message pointers are only valid during the callback.
+ */
+
+#ifndef WEBTRANSPORT_H
+#define WEBTRANSPORT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct WtClient wt_client;
+typedef struct WtSession wt_session;
+typedef struct WtStream wt_stream;
+
+typedef enum {
+    WT_OK = 0,
+    /* A pointer was null, a string wasn't valid UTF-8, or the stream doesn't support the operation. */
+    WT_INVALID_ARGUMENT = 1,
+    /* The operation failed immediately, ex. because the session is closed. */
+    WT_FAILED = 2,
+} wt_status;
+
+typedef enum {
+    /* wt_client_connect established a session, set in `session`. */
+    WT_EVENT_CONNECTED = 0,
+    /* A stream was opened or accepted, set in `stream`. */
+    WT_EVENT_STREAM = 1,
+    /* wt_stream_read received the bytes in `data` and `len`. */
+    WT_EVENT_DATA = 2,
+    /* wt_stream_read reached the end of the stream. */
+    WT_EVENT_FINISHED = 3,
+    /* wt_stream_write or wt_stream_finish completed. */
+    WT_EVENT_DONE = 4,
+    /* A datagram arrived, in `data` and `len`. */
+    WT_EVENT_DATAGRAM = 5,
+    /* The session was closed, with the reason in `message`. */
+    WT_EVENT_CLOSED = 6,
+    /* The operation failed, described by `message`; `code` is the peer's reset/stop code if any. */
+    WT_EVENT_ERROR = 7,
+} wt_event_kind;
+
+typedef struct {
+    wt_event_kind kind;
+    wt_session *session;
+    wt_stream *stream;
+    const uint8_t *data;
+    size_t len;
+    uint32_t code;
+    const char *message;
+} wt_event;
+
+typedef void (*wt_callback)(void *user, const wt_event *event);
+
+/* Clients */
+wt_status wt_client_new(wt_client **out);
+wt_status wt_client_new_with_root(const uint8_t *der, size_t len, wt_client **out);
+wt_status wt_client_connect(const wt_client *client, const char *url, wt_callback callback, void *user);
+void wt_client_free(wt_client *client);
+
+/* Sessions */
+wt_status wt_session_open_bi(const wt_session *session, wt_callback callback, void *user);
+wt_status wt_session_open_uni(const wt_session *session, wt_callback callback, void *user);
+wt_status wt_session_listen(const wt_session *session, wt_callback callback, void *user);
+wt_status wt_session_send_datagram(const wt_session *session, const uint8_t *data, size_t len);
+size_t wt_session_max_datagram_size(const wt_session *session);
+wt_status wt_session_close(const wt_session *session, uint32_t code, const char *reason);
+void wt_session_free(wt_session *session);
+
+/* Streams: operations on each half run in the order they're called, except wt_stream_reset, which aborts pending writes. */
+wt_status wt_stream_write(const wt_stream *stream, const uint8_t *data, size_t len, wt_callback callback, void *user);
+wt_status wt_stream_finish(const wt_stream *stream, wt_callback callback, void *user);
+wt_status wt_stream_reset(const wt_stream *stream, uint32_t code);
+wt_status wt_stream_read(const wt_stream *stream, size_t max, wt_callback callback, void *user);
+wt_status wt_stream_stop(const wt_stream *stream, uint32_t code);
+void wt_stream_free(wt_stream *stream);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
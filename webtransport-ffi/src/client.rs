@@ -0,0 +1,174 @@
+use std::{
+    ffi::{c_char, c_void, CStr},
+    future::Future,
+    sync::Arc,
+};
+
+use crate::{Callback, Event, WtCallback, WtSession, WtStatus};
+
+/// A WebTransport client and the runtime that drives it, see [`webtransport_quinn::Client`].
+pub struct WtClient {
+    client: webtransport_quinn::Client,
+    runtime: Runtime,
+}
+
+// The runtime shared by a client and everything it created, shut down once the last of them is freed.
+#[derive(Clone)]
+pub(crate) struct Runtime(Arc<Rt>);
+
+struct Rt(Option<tokio::runtime::Runtime>);
+
+impl Runtime {
+    fn new() -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("webtransport")
+            .build()?;
+
+        Ok(Self(Arc::new(Rt(Some(runtime)))))
+    }
+
+    // Run the future in the background, keeping the runtime alive until it's done.
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&self, fut: F) {
+        let runtime = self.clone();
+        self.handle().spawn(async move {
+            fut.await;
+            drop(runtime);
+        });
+    }
+
+    fn handle(&self) -> &tokio::runtime::Handle {
+        self.0 .0.as_ref().unwrap().handle()
+    }
+}
+
+impl Drop for Rt {
+    fn drop(&mut self) {
+        // The last handle may be freed on a runtime thread (ex. a stream freed in a callback), where blocking would panic.
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl WtClient {
+    fn new(roots: rustls::RootCertStore) -> Result<Self, WtStatus> {
+        let runtime = Runtime::new().map_err(|_| WtStatus::Failed)?;
+
+        let mut tls = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![webtransport_quinn::ALPN.to_vec()];
+
+        // Quinn needs the runtime to create the socket.
+        let _guard = runtime.handle().enter();
+        let mut endpoint = quinn::Endpoint::client("[::]:0".parse().unwrap())
+            .or_else(|_| quinn::Endpoint::client("0.0.0.0:0".parse().unwrap()))
+            .map_err(|_| WtStatus::Failed)?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(tls)));
+
+        Ok(Self {
+            client: webtransport_quinn::Client::new(endpoint),
+            runtime,
+        })
+    }
+}
+
+/// Create a client that trusts the platform's root certificates.
+///
+/// # Safety
+/// `out` must be a valid pointer, which is set to a client that must be freed with [`wt_client_free`].
+#[no_mangle]
+pub unsafe extern "C" fn wt_client_new(out: *mut *mut WtClient) -> WtStatus {
+    if out.is_null() {
+        return WtStatus::InvalidArgument;
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs().unwrap_or_default() {
+        roots.add(&rustls::Certificate(cert.0)).ok();
+    }
+
+    create(roots, out)
+}
+
+/// Create a client that only trusts the given DER-encoded certificate, ex. a self-signed certificate during development.
+///
+/// # Safety
+/// `der` must point to `len` readable bytes, and `out` must be a valid pointer, which is set to a client that must be freed with [`wt_client_free`].
+#[no_mangle]
+pub unsafe extern "C" fn wt_client_new_with_root(
+    der: *const u8,
+    len: usize,
+    out: *mut *mut WtClient,
+) -> WtStatus {
+    if der.is_null() || out.is_null() {
+        return WtStatus::InvalidArgument;
+    }
+
+    let der = std::slice::from_raw_parts(der, len);
+    let mut roots = rustls::RootCertStore::empty();
+    if roots.add(&rustls::Certificate(der.to_vec())).is_err() {
+        return WtStatus::InvalidArgument;
+    }
+
+    create(roots, out)
+}
+
+unsafe fn create(roots: rustls::RootCertStore, out: *mut *mut WtClient) -> WtStatus {
+    match WtClient::new(roots) {
+        Ok(client) => {
+            *out = Box::into_raw(Box::new(client));
+            WtStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Connect to the NUL-terminated `https://` URL, invoking the callback with a `Connected` or `Error` event.
+///
+/// # Safety
+/// `client` must be a valid client and `url` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wt_client_connect(
+    client: *const WtClient,
+    url: *const c_char,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    let (client, callback) = match (client.as_ref(), callback) {
+        (Some(client), Some(callback)) if !url.is_null() => (client, Callback::new(callback, user)),
+        _ => return WtStatus::InvalidArgument,
+    };
+
+    let uri: http::Uri = match CStr::from_ptr(url).to_str().map(str::parse) {
+        Ok(Ok(uri)) => uri,
+        _ => return WtStatus::InvalidArgument,
+    };
+
+    let inner = client.client.clone();
+    let runtime = client.runtime.clone();
+
+    client.runtime.spawn(async move {
+        let event = match inner.connect(&uri).await {
+            Ok(session) => Event::Connected(Box::new(WtSession::new(session, runtime))),
+            Err(err) => Event::Error(err.to_string(), 0),
+        };
+
+        callback.call(event);
+    });
+
+    WtStatus::Ok
+}
+
+/// Free the client. Sessions and streams it created keep working until they're freed too.
+///
+/// # Safety
+/// `client` must be a client created by this library, or null.
+#[no_mangle]
+pub unsafe extern "C" fn wt_client_free(client: *mut WtClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
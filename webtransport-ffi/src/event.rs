@@ -0,0 +1,150 @@
+use std::{
+    ffi::{c_char, c_void, CString},
+    ptr,
+};
+
+use bytes::Bytes;
+
+use crate::{WtSession, WtStream};
+
+/// What happened, which determines the fields of [`WtEvent`] that are set.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WtEventKind {
+    /// `wt_client_connect` established a session, set in `session`.
+    Connected = 0,
+
+    /// A stream was opened or accepted, set in `stream`.
+    Stream = 1,
+
+    /// `wt_stream_read` received the bytes in `data` and `len`.
+    Data = 2,
+
+    /// `wt_stream_read` reached the end of the stream; there's no more data.
+    Finished = 3,
+
+    /// `wt_stream_write` or `wt_stream_finish` completed.
+    Done = 4,
+
+    /// A datagram arrived, in `data` and `len`.
+    Datagram = 5,
+
+    /// The session was closed, with the reason in `message`.
+    Closed = 6,
+
+    /// The operation failed, described by `message`.
+    /// When the peer reset or stopped the stream, `code` is its error code.
+    Error = 7,
+}
+
+/// An event passed to a [`WtCallback`].
+#[repr(C)]
+pub struct WtEvent {
+    pub kind: WtEventKind,
+
+    /// A new session owned by the callee, or null.
+    pub session: *mut WtSession,
+
+    /// A new stream owned by the callee, or null.
+    pub stream: *mut WtStream,
+
+    /// Received bytes, only valid during the callback, or null.
+    pub data: *const u8,
+    pub len: usize,
+
+    /// An error code from the peer, or 0.
+    pub code: u32,
+
+    /// A NUL-terminated description, only valid during the callback, or null.
+    pub message: *const c_char,
+}
+
+/// Invoked with the result of an operation, see [`WtEvent`].
+pub type WtCallback = extern "C" fn(user: *mut c_void, event: *const WtEvent);
+
+// What happened, before it's converted into a WtEvent.
+pub(crate) enum Event {
+    Connected(Box<WtSession>),
+    Stream(WtStream),
+    Data(Bytes),
+    Finished,
+    Done,
+    Datagram(Bytes),
+    Closed(String),
+    Error(String, u32),
+}
+
+// A callback and the user pointer to invoke it with.
+#[derive(Clone, Copy)]
+pub(crate) struct Callback {
+    func: WtCallback,
+    user: *mut c_void,
+}
+
+// The caller promises the user pointer can be used from the runtime threads.
+unsafe impl Send for Callback {}
+
+impl Callback {
+    pub fn new(func: WtCallback, user: *mut c_void) -> Self {
+        Self { func, user }
+    }
+
+    pub fn call(&self, event: Event) {
+        let mut raw = WtEvent {
+            kind: WtEventKind::Done,
+            session: ptr::null_mut(),
+            stream: ptr::null_mut(),
+            data: ptr::null(),
+            len: 0,
+            code: 0,
+            message: ptr::null(),
+        };
+
+        // Keep the buffers alive until the callback returns.
+        let mut bytes = Bytes::new();
+        let mut message = None;
+
+        match event {
+            Event::Connected(session) => {
+                raw.kind = WtEventKind::Connected;
+                raw.session = Box::into_raw(session);
+            }
+            Event::Stream(stream) => {
+                raw.kind = WtEventKind::Stream;
+                raw.stream = Box::into_raw(Box::new(stream));
+            }
+            Event::Data(data) => {
+                raw.kind = WtEventKind::Data;
+                bytes = data;
+            }
+            Event::Finished => raw.kind = WtEventKind::Finished,
+            Event::Done => raw.kind = WtEventKind::Done,
+            Event::Datagram(data) => {
+                raw.kind = WtEventKind::Datagram;
+                bytes = data;
+            }
+            Event::Closed(reason) => {
+                raw.kind = WtEventKind::Closed;
+                message = Some(reason);
+            }
+            Event::Error(err, code) => {
+                raw.kind = WtEventKind::Error;
+                raw.code = code;
+                message = Some(err);
+            }
+        }
+
+        if !bytes.is_empty() {
+            raw.data = bytes.as_ptr();
+            raw.len = bytes.len();
+        }
+
+        // Interior NULs would truncate the message, so replace them.
+        let message = message.map(|message| CString::new(message.replace('\0', " ")).unwrap());
+        if let Some(message) = &message {
+            raw.message = message.as_ptr();
+        }
+
+        (self.func)(self.user, &raw);
+    }
+}
@@ -0,0 +1,37 @@
+//! A C ABI for the WebTransport client, so applications that aren't written in Rust (ex. game engines or mobile apps) can embed it.
+//!
+//! See `include/webtransport.h` for the declarations.
+//!
+//! # Handles
+//! Clients, sessions and streams are opaque pointers owned by the caller, who must free each of them exactly once.
+//! Freeing a handle doesn't cancel pending operations; they complete (or fail) and invoke their callback as usual.
+//! Sessions and streams keep the client's runtime alive, so they can be freed in any order.
+//!
+//! # Events
+//! Operations that can't complete immediately take a callback and a user pointer instead of returning a future.
+//! The callback is invoked exactly once with a [`WtEvent`], on one of the client's runtime threads.
+//! Any session or stream in the event is a new handle owned by the caller, while data and messages are only valid during the callback.
+
+mod client;
+mod event;
+mod session;
+mod stream;
+
+pub use client::*;
+pub use event::*;
+pub use session::*;
+pub use stream::*;
+
+/// The result of a function that returns immediately.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WtStatus {
+    /// The function succeeded, or the operation was started and its callback will be invoked.
+    Ok = 0,
+
+    /// A pointer was null, a string wasn't valid UTF-8, or the stream doesn't support the operation (ex. writing to a receive-only stream).
+    InvalidArgument = 1,
+
+    /// The operation failed immediately, ex. because the session is closed.
+    Failed = 2,
+}
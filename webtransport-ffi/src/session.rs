@@ -0,0 +1,201 @@
+use std::ffi::{c_char, c_void, CStr};
+
+use bytes::Bytes;
+use webtransport_quinn::Session;
+
+use crate::{client::Runtime, Callback, Event, WtCallback, WtStatus, WtStream};
+
+/// An established WebTransport session, see [`webtransport_quinn::Session`].
+pub struct WtSession {
+    session: Session,
+    runtime: Runtime,
+}
+
+impl WtSession {
+    pub(crate) fn new(session: Session, runtime: Runtime) -> Self {
+        Self { session, runtime }
+    }
+}
+
+// Validate the arguments of a function that takes a session and a callback.
+unsafe fn args<'a>(
+    session: *const WtSession,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> Option<(&'a WtSession, Callback)> {
+    Some((session.as_ref()?, Callback::new(callback?, user)))
+}
+
+/// Open a bidirectional stream, invoking the callback with a `Stream` or `Error` event.
+///
+/// # Safety
+/// `session` must be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_open_bi(
+    session: *const WtSession,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    let (session, callback) = match args(session, callback, user) {
+        Some(args) => args,
+        None => return WtStatus::InvalidArgument,
+    };
+
+    let inner = session.session.clone();
+    let runtime = session.runtime.clone();
+
+    session.runtime.spawn(async move {
+        let event = match inner.open_bi().await {
+            Ok((send, recv)) => Event::Stream(WtStream::new(Some(send), Some(recv), runtime)),
+            Err(err) => Event::Error(err.to_string(), 0),
+        };
+
+        callback.call(event);
+    });
+
+    WtStatus::Ok
+}
+
+/// Open a unidirectional stream, invoking the callback with a `Stream` or `Error` event.
+///
+/// # Safety
+/// `session` must be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_open_uni(
+    session: *const WtSession,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    let (session, callback) = match args(session, callback, user) {
+        Some(args) => args,
+        None => return WtStatus::InvalidArgument,
+    };
+
+    let inner = session.session.clone();
+    let runtime = session.runtime.clone();
+
+    session.runtime.spawn(async move {
+        let event = match inner.open_uni().await {
+            Ok(send) => Event::Stream(WtStream::new(Some(send), None, runtime)),
+            Err(err) => Event::Error(err.to_string(), 0),
+        };
+
+        callback.call(event);
+    });
+
+    WtStatus::Ok
+}
+
+/// Receive the peer's streams and datagrams until the session is closed.
+///
+/// Unlike other operations, the callback is invoked repeatedly: with a `Stream` event for each incoming stream,
+/// a `Datagram` event for each datagram, and finally a `Closed` event. It may be invoked concurrently from different threads.
+/// Call this at most once per session.
+///
+/// # Safety
+/// `session` must be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_listen(
+    session: *const WtSession,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    let (session, callback) = match args(session, callback, user) {
+        Some(args) => args,
+        None => return WtStatus::InvalidArgument,
+    };
+
+    let inner = session.session.clone();
+    let runtime = session.runtime.clone();
+    session.runtime.spawn(async move {
+        loop {
+            let runtime = runtime.clone();
+            let event = tokio::select! {
+                res = inner.accept_uni() => res.map(|recv| Event::Stream(WtStream::new(None, Some(recv), runtime))),
+                res = inner.accept_bi() => res.map(|(send, recv)| Event::Stream(WtStream::new(Some(send), Some(recv), runtime))),
+                res = inner.recv_datagram() => res.map(Event::Datagram),
+            };
+
+            match event {
+                Ok(event) => callback.call(event),
+                Err(err) => return callback.call(Event::Closed(err.to_string())),
+            }
+        }
+    });
+
+    WtStatus::Ok
+}
+
+/// Send an unreliable datagram, which is copied. See [`Session::send_datagram`].
+///
+/// # Safety
+/// `session` must be a valid session and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_send_datagram(
+    session: *const WtSession,
+    data: *const u8,
+    len: usize,
+) -> WtStatus {
+    let session = match session.as_ref() {
+        Some(session) if !data.is_null() || len == 0 => session,
+        _ => return WtStatus::InvalidArgument,
+    };
+
+    let payload = match len {
+        0 => Bytes::new(),
+        _ => Bytes::copy_from_slice(std::slice::from_raw_parts(data, len)),
+    };
+
+    match session.session.send_datagram(payload) {
+        Ok(()) => WtStatus::Ok,
+        Err(_) => WtStatus::Failed,
+    }
+}
+
+/// Return the maximum payload size of a datagram, or 0 if datagrams are unsupported. See [`Session::max_datagram_size`].
+///
+/// # Safety
+/// `session` must be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_max_datagram_size(session: *const WtSession) -> usize {
+    session
+        .as_ref()
+        .and_then(|session| session.session.max_datagram_size())
+        .unwrap_or(0)
+}
+
+/// Close the session with an error code and an optional NUL-terminated reason. See [`Session::close`].
+///
+/// # Safety
+/// `session` must be a valid session, and `reason` a valid NUL-terminated string or null.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_close(
+    session: *const WtSession,
+    code: u32,
+    reason: *const c_char,
+) -> WtStatus {
+    let session = match session.as_ref() {
+        Some(session) => session,
+        None => return WtStatus::InvalidArgument,
+    };
+
+    let reason = match reason.is_null() {
+        true => Default::default(),
+        false => CStr::from_ptr(reason).to_string_lossy(),
+    };
+
+    session.session.close(code, &reason);
+    WtStatus::Ok
+}
+
+/// Free the session handle, which closes the session once its streams and pending operations are done too.
+/// Use [`wt_session_close`] to close it immediately.
+///
+/// # Safety
+/// `session` must be a session created by this library, or null.
+#[no_mangle]
+pub unsafe extern "C" fn wt_session_free(session: *mut WtSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
@@ -0,0 +1,260 @@
+use std::ffi::c_void;
+
+use tokio::sync::mpsc;
+use webtransport_quinn::{ReadError, RecvStream, SendStream, WriteError};
+
+use crate::{client::Runtime, Callback, Event, WtCallback, WtStatus};
+
+// The largest chunk returned by a read when the caller doesn't choose.
+const DEFAULT_READ_SIZE: usize = 64 * 1024;
+
+/// A stream, which can be sent, received, or both if it's bidirectional.
+///
+/// Operations on each half run in the order they were started: they're queued when the function is called,
+/// and a single task per half runs them one at a time.
+pub struct WtStream {
+    send: Option<SendQueue>,
+    recv: Option<mpsc::UnboundedSender<RecvOp>>,
+}
+
+// The operations for the send half, and a separate channel so a reset doesn't wait behind them.
+struct SendQueue {
+    ops: mpsc::UnboundedSender<(SendOp, Callback)>,
+    reset: mpsc::UnboundedSender<u32>,
+}
+
+enum SendOp {
+    Write(Vec<u8>),
+    Finish,
+}
+
+enum RecvOp {
+    Read(usize, Callback),
+    Stop(u32),
+}
+
+impl WtStream {
+    pub(crate) fn new(
+        send: Option<SendStream>,
+        recv: Option<RecvStream>,
+        runtime: Runtime,
+    ) -> Self {
+        let send = send.map(|stream| {
+            let (ops, ops_rx) = mpsc::unbounded_channel();
+            let (reset, reset_rx) = mpsc::unbounded_channel();
+            runtime.spawn(run_send(stream, ops_rx, reset_rx));
+            SendQueue { ops, reset }
+        });
+
+        let recv = recv.map(|stream| {
+            let (ops, ops_rx) = mpsc::unbounded_channel();
+            runtime.spawn(run_recv(stream, ops_rx));
+            ops
+        });
+
+        Self { send, recv }
+    }
+}
+
+fn write_error(err: WriteError) -> Event {
+    let code = match err {
+        WriteError::Stopped(code) => code,
+        _ => 0,
+    };
+
+    Event::Error(err.to_string(), code)
+}
+
+// Run the send operations in order until the handle is freed, aborting the current one if the stream is reset.
+async fn run_send(
+    mut stream: SendStream,
+    mut ops: mpsc::UnboundedReceiver<(SendOp, Callback)>,
+    mut reset: mpsc::UnboundedReceiver<u32>,
+) {
+    loop {
+        let (op, callback) = tokio::select! {
+            op = ops.recv() => match op {
+                Some(op) => op,
+                None => return,
+            },
+            code = next_reset(&mut reset) => {
+                stream.reset(code).ok();
+                continue;
+            }
+        };
+
+        let res = tokio::select! {
+            res = run_send_op(&mut stream, op) => Ok(res),
+            code = next_reset(&mut reset) => Err(code),
+        };
+
+        let event = match res {
+            Ok(Ok(())) => Event::Done,
+            Ok(Err(err)) => write_error(err),
+            Err(code) => {
+                stream.reset(code).ok();
+                Event::Error(format!("reset with code {}", code), 0)
+            }
+        };
+
+        callback.call(event);
+    }
+}
+
+async fn run_send_op(stream: &mut SendStream, op: SendOp) -> Result<(), WriteError> {
+    match op {
+        SendOp::Write(data) => stream.write_all(&data).await,
+        SendOp::Finish => stream.finish().await,
+    }
+}
+
+// Wait for the next reset, or forever once the handle is freed.
+async fn next_reset(reset: &mut mpsc::UnboundedReceiver<u32>) -> u32 {
+    match reset.recv().await {
+        Some(code) => code,
+        None => std::future::pending().await,
+    }
+}
+
+// Run the receive operations in order until the handle is freed.
+async fn run_recv(mut stream: RecvStream, mut ops: mpsc::UnboundedReceiver<RecvOp>) {
+    while let Some(op) = ops.recv().await {
+        let (max, callback) = match op {
+            RecvOp::Read(max, callback) => (max, callback),
+            RecvOp::Stop(code) => {
+                stream.stop(code).ok();
+                continue;
+            }
+        };
+
+        let event = match stream.read_chunk(max, true).await {
+            Ok(Some(chunk)) => Event::Data(chunk.bytes),
+            Ok(None) => Event::Finished,
+            Err(ReadError::Reset(code)) => Event::Error(ReadError::Reset(code).to_string(), code),
+            Err(err) => Event::Error(err.to_string(), 0),
+        };
+
+        callback.call(event);
+    }
+}
+
+// Queue an operation on the send half, whose callback is invoked with the result.
+unsafe fn send(
+    stream: *const WtStream,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+    op: SendOp,
+) -> WtStatus {
+    let (send, callback) = match (stream.as_ref().and_then(|s| s.send.as_ref()), callback) {
+        (Some(send), Some(callback)) => (send, Callback::new(callback, user)),
+        _ => return WtStatus::InvalidArgument,
+    };
+
+    // The task only exits once the handle is freed, so this can't fail.
+    send.ops.send((op, callback)).ok();
+    WtStatus::Ok
+}
+
+/// Write all of the bytes, which are copied, invoking the callback with a `Done` or `Error` event.
+///
+/// # Safety
+/// `stream` must be a valid stream and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_write(
+    stream: *const WtStream,
+    data: *const u8,
+    len: usize,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    if data.is_null() && len > 0 {
+        return WtStatus::InvalidArgument;
+    }
+
+    let data = match len {
+        0 => Vec::new(),
+        _ => std::slice::from_raw_parts(data, len).to_vec(),
+    };
+
+    send(stream, callback, user, SendOp::Write(data))
+}
+
+/// Finish the stream once everything written was sent, invoking the callback with a `Done` or `Error` event.
+///
+/// # Safety
+/// `stream` must be a valid stream.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_finish(
+    stream: *const WtStream,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    send(stream, callback, user, SendOp::Finish)
+}
+
+/// Abandon the send half with an error code, without waiting for pending writes.
+/// The current write fails with an `Error` event, as do any queued after it.
+///
+/// # Safety
+/// `stream` must be a valid stream.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_reset(stream: *const WtStream, code: u32) -> WtStatus {
+    match stream.as_ref().and_then(|stream| stream.send.as_ref()) {
+        Some(send) => {
+            send.reset.send(code).ok();
+            WtStatus::Ok
+        }
+        None => WtStatus::InvalidArgument,
+    }
+}
+
+/// Read up to `max` bytes (or a default if 0), invoking the callback with a `Data`, `Finished` or `Error` event.
+///
+/// # Safety
+/// `stream` must be a valid stream.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_read(
+    stream: *const WtStream,
+    max: usize,
+    callback: Option<WtCallback>,
+    user: *mut c_void,
+) -> WtStatus {
+    let (recv, callback) = match (stream.as_ref().and_then(|s| s.recv.as_ref()), callback) {
+        (Some(recv), Some(callback)) => (recv, Callback::new(callback, user)),
+        _ => return WtStatus::InvalidArgument,
+    };
+
+    let max = match max {
+        0 => DEFAULT_READ_SIZE,
+        max => max,
+    };
+
+    recv.send(RecvOp::Read(max, callback)).ok();
+    WtStatus::Ok
+}
+
+/// Ask the peer to stop sending with an error code, once any pending reads are done.
+///
+/// # Safety
+/// `stream` must be a valid stream.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_stop(stream: *const WtStream, code: u32) -> WtStatus {
+    match stream.as_ref().and_then(|stream| stream.recv.as_ref()) {
+        Some(recv) => {
+            recv.send(RecvOp::Stop(code)).ok();
+            WtStatus::Ok
+        }
+        None => WtStatus::InvalidArgument,
+    }
+}
+
+/// Free the stream handle. Pending operations still complete, but a stream that wasn't finished is reset.
+///
+/// # Safety
+/// `stream` must be a stream created by this library, or null.
+#[no_mangle]
+pub unsafe extern "C" fn wt_stream_free(stream: *mut WtStream) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
@@ -0,0 +1,238 @@
+// Drive the C API against a server over localhost, checking the order of stream operations.
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    ptr,
+    time::Duration,
+};
+
+use tokio::sync::mpsc;
+use webtransport_ffi::*;
+
+// An event copied out of the callback, tagged with the index of the operation that produced it.
+#[derive(Debug)]
+struct Owned {
+    index: usize,
+    kind: WtEventKind,
+    session: *mut WtSession,
+    stream: *mut WtStream,
+    message: Option<String>,
+}
+
+// The raw pointers are only handed from the runtime thread to the test.
+unsafe impl Send for Owned {}
+
+// The user pointer of an operation, leaked since the callback may outlive the test's borrow.
+struct Op {
+    index: usize,
+    events: mpsc::UnboundedSender<Owned>,
+}
+
+fn op(events: &mpsc::UnboundedSender<Owned>, index: usize) -> *mut c_void {
+    let op = Op {
+        index,
+        events: events.clone(),
+    };
+
+    Box::into_raw(Box::new(op)) as *mut c_void
+}
+
+extern "C" fn callback(user: *mut c_void, event: *const WtEvent) {
+    // Every operation in these tests invokes its callback exactly once.
+    let op = unsafe { Box::from_raw(user as *mut Op) };
+    let event = unsafe { &*event };
+
+    let message = match event.message.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr(event.message) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    };
+
+    op.events
+        .send(Owned {
+            index: op.index,
+            kind: event.kind,
+            session: event.session,
+            stream: event.stream,
+            message,
+        })
+        .ok();
+}
+
+async fn next(events: &mut mpsc::UnboundedReceiver<Owned>) -> Owned {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out")
+        .unwrap()
+}
+
+// A client connected to a server, and the server's side of the session.
+struct Setup {
+    client: *mut WtClient,
+    session: *mut WtSession,
+    server: webtransport_quinn::Session,
+    _endpoint: webtransport_quinn::Server,
+}
+
+async fn setup(
+    events: &mut mpsc::UnboundedReceiver<Owned>,
+    tx: &mpsc::UnboundedSender<Owned>,
+) -> Setup {
+    let gen = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let der = gen.serialize_der().unwrap();
+    let key = rustls::PrivateKey(gen.serialize_private_key_der());
+
+    let mut server =
+        webtransport_quinn::ServerBuilder::new(vec![rustls::Certificate(der.clone())], key)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .unwrap();
+    let url = format!("https://localhost:{}/", server.local_addr().unwrap().port());
+
+    let accept = tokio::spawn(async move {
+        let session = server.accept().await.unwrap().ok().await.unwrap();
+        (server, session)
+    });
+
+    let mut client = ptr::null_mut();
+    let status = unsafe { wt_client_new_with_root(der.as_ptr(), der.len(), &mut client) };
+    assert_eq!(status, WtStatus::Ok);
+
+    let url = CString::new(url).unwrap();
+    let status = unsafe { wt_client_connect(client, url.as_ptr(), Some(callback), op(tx, 0)) };
+    assert_eq!(status, WtStatus::Ok);
+
+    let event = next(events).await;
+    assert_eq!(event.kind, WtEventKind::Connected, "{:?}", event.message);
+
+    let (endpoint, server) = accept.await.unwrap();
+
+    Setup {
+        client,
+        session: event.session,
+        server,
+        _endpoint: endpoint,
+    }
+}
+
+async fn open_uni(
+    setup: &Setup,
+    events: &mut mpsc::UnboundedReceiver<Owned>,
+    tx: &mpsc::UnboundedSender<Owned>,
+) -> *mut WtStream {
+    let status = unsafe { wt_session_open_uni(setup.session, Some(callback), op(tx, 0)) };
+    assert_eq!(status, WtStatus::Ok);
+
+    let event = next(events).await;
+    assert_eq!(event.kind, WtEventKind::Stream, "{:?}", event.message);
+    event.stream
+}
+
+fn free(setup: Setup, stream: *mut WtStream) {
+    unsafe {
+        wt_stream_free(stream);
+        wt_session_free(setup.session);
+        wt_client_free(setup.client);
+    }
+}
+
+#[tokio::test]
+async fn ordered_writes() {
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let setup = setup(&mut events, &tx).await;
+    let stream = open_uni(&setup, &mut events, &tx).await;
+
+    // Writes issued back to back, without waiting for their callbacks, must arrive in the order they were issued.
+    let chunks: Vec<Vec<u8>> = (0..200u32)
+        .map(|i| i.to_be_bytes().repeat(1 + i as usize % 64))
+        .collect();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let status = unsafe {
+            wt_stream_write(
+                stream,
+                chunk.as_ptr(),
+                chunk.len(),
+                Some(callback),
+                op(&tx, index),
+            )
+        };
+        assert_eq!(status, WtStatus::Ok);
+    }
+
+    let status = unsafe { wt_stream_finish(stream, Some(callback), op(&tx, chunks.len())) };
+    assert_eq!(status, WtStatus::Ok);
+
+    // The callbacks complete in order too.
+    for index in 0..=chunks.len() {
+        let event = next(&mut events).await;
+        assert_eq!(event.kind, WtEventKind::Done, "{:?}", event.message);
+        assert_eq!(event.index, index);
+    }
+
+    let mut recv = setup.server.accept_uni().await.unwrap();
+    let data = recv.read_to_end(usize::MAX).await.unwrap();
+    assert_eq!(data, chunks.concat());
+
+    free(setup, stream);
+}
+
+#[tokio::test]
+async fn reset_aborts_write() {
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let setup = setup(&mut events, &tx).await;
+    let stream = open_uni(&setup, &mut events, &tx).await;
+
+    // The server never reads, so this write blocks on flow control.
+    let blocked = vec![0u8; 16 * 1024 * 1024];
+    let status = unsafe {
+        wt_stream_write(
+            stream,
+            blocked.as_ptr(),
+            blocked.len(),
+            Some(callback),
+            op(&tx, 0),
+        )
+    };
+    assert_eq!(status, WtStatus::Ok);
+
+    let queued = [1u8; 16];
+    let status = unsafe {
+        wt_stream_write(
+            stream,
+            queued.as_ptr(),
+            queued.len(),
+            Some(callback),
+            op(&tx, 1),
+        )
+    };
+    assert_eq!(status, WtStatus::Ok);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(events.try_recv().is_err(), "the write should be blocked");
+
+    // The reset doesn't wait for the blocked write, which fails along with the one queued behind it.
+    assert_eq!(unsafe { wt_stream_reset(stream, 7) }, WtStatus::Ok);
+
+    for index in 0..2 {
+        let event = next(&mut events).await;
+        assert_eq!(event.kind, WtEventKind::Error);
+        assert_eq!(event.index, index);
+    }
+
+    let mut recv = setup.server.accept_uni().await.unwrap();
+    let err = recv.read_to_end(usize::MAX).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            webtransport_quinn::ReadToEndError::ReadError(webtransport_quinn::ReadError::Reset(7))
+        ),
+        "{:?}",
+        err
+    );
+
+    free(setup, stream);
+}
@@ -83,6 +83,23 @@ pub trait SendStream {
     /// Set the stream's priority relative to other streams on the same connection.
     /// A lower value will be sent first and zero is the default value.
     fn set_priority(&mut self, order: i32);
+
+    /// Share bandwidth with the other streams of the same priority, in proportion to the weight.
+    /// A weight of zero (the default) disables weighting.
+    ///
+    /// Returns false if the backend doesn't support weights, which is the default.
+    fn set_weight(&mut self, weight: u16) -> bool {
+        let _ = weight;
+        false
+    }
+
+    /// Place the stream in a named group, which a scheduler can treat as a unit (ex. all streams of a media track).
+    ///
+    /// Returns false if the backend doesn't support groups, which is the default.
+    fn set_group(&mut self, group: &str) -> bool {
+        let _ = group;
+        false
+    }
 }
 
 /// A trait describing the "receive" actions of a QUIC stream.
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use bytes::Bytes;
+use tokio::net::{TcpListener, TcpStream};
+
+// Runs an echo server and a client against it over HTTP/2, using a self-signed certificate.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = rustls::Certificate(cert.serialize_der()?);
+    let key_der = rustls::PrivateKey(cert.serialize_private_key_der());
+
+    let mut server_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert_der.clone()], key_der)?;
+    server_config.alpn_protocols = vec![webtransport_h2::ALPN.to_vec()]; // this one is important
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Err(err) = serve(acceptor, tcp).await {
+                    println!("server error: {}", err);
+                }
+            });
+        }
+    });
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&cert_der)?;
+
+    let mut client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    client_config.alpn_protocols = vec![webtransport_h2::ALPN.to_vec()];
+
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+    let tcp = TcpStream::connect(addr).await?;
+    let tls = connector
+        .connect("localhost".try_into()?, tcp)
+        .await
+        .context("failed TLS handshake")?;
+
+    let url = format!("https://localhost:{}/echo", addr.port()).parse()?;
+    let (session, driver) = webtransport_h2::connect(tls, &url).await?;
+    let driver = tokio::spawn(driver);
+
+    let (mut send, mut recv) = session.open_bi().await?;
+    send.write_all(b"hello bi").await?;
+    send.finish()?;
+    let msg = recv.read_to_end(1024).await?;
+    println!("bi: {}", String::from_utf8_lossy(&msg));
+
+    let mut send = session.open_uni().await?;
+    send.write_all(b"hello uni").await?;
+    send.finish()?;
+    let mut recv = session.accept_uni().await?;
+    let msg = recv.read_to_end(1024).await?;
+    println!("uni: {}", String::from_utf8_lossy(&msg));
+
+    session.send_datagram(Bytes::from_static(b"hello datagram"))?;
+    let msg = session.recv_datagram().await?;
+    println!("datagram: {}", String::from_utf8_lossy(&msg));
+
+    session.close(0, b"done");
+
+    // Wait until the close was sent and the server finished the CONNECT stream too.
+    driver.await??;
+
+    Ok(())
+}
+
+async fn serve(acceptor: tokio_rustls::TlsAcceptor, tcp: TcpStream) -> anyhow::Result<()> {
+    let tls = acceptor.accept(tcp).await?;
+
+    let request = webtransport_h2::accept(tls).await?;
+    println!("accepted session: {}", request.url());
+
+    let (session, driver) = request.ok()?;
+    tokio::spawn(driver);
+
+    loop {
+        tokio::select! {
+            biased;
+            err = session.closed() => {
+                println!("server session closed: {}", err);
+                return Ok(());
+            }
+            res = session.accept_bi() => {
+                let (mut send, mut recv) = res?;
+                let msg = recv.read_to_end(1024).await?;
+                send.write_all(&msg).await?;
+                send.finish()?;
+            },
+            res = session.accept_uni() => {
+                let mut recv = res?;
+                let msg = recv.read_to_end(1024).await?;
+                let mut send = session.open_uni().await?;
+                send.write_all(&msg).await?;
+                send.finish()?;
+            },
+            res = session.recv_datagram() => {
+                let msg = res?;
+                session.send_datagram(msg)?;
+            },
+        }
+    }
+}
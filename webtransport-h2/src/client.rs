@@ -0,0 +1,118 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http::{Method, Request, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    driver::{H2Stream, Transport},
+    ClientError, Driver, Role, Session, SessionError,
+};
+
+/// Establish a WebTransport session over an HTTP/2 connection, such as a TLS stream negotiated with the `h2` ALPN.
+///
+/// This performs the HTTP/2 handshake and sends an extended CONNECT request for the URL.
+/// The returned [`Driver`] must be spawned (or otherwise polled) for the session to make any progress.
+pub async fn connect<T>(io: T, url: &Uri) -> Result<(Session, Driver), ClientError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (send_request, mut conn) = h2::client::handshake(io).await?;
+
+    // The server's SETTINGS aren't exposed until they arrive, so wait for a PING round trip which must come after them.
+    let mut ping_pong = conn.ping_pong().expect("ping_pong already taken");
+    ping_pong.send_ping(h2::Ping::opaque())?;
+    drive(&mut conn, poll_fn(|cx| ping_pong.poll_pong(cx))).await??;
+
+    let mut send_request = drive(&mut conn, send_request.ready()).await??;
+
+    if !send_request.is_extended_connect_protocol_enabled() {
+        return Err(ClientError::ExtendedConnectDisabled);
+    }
+
+    let mut request = Request::builder()
+        .method(Method::CONNECT)
+        .uri(url.clone())
+        .body(())
+        .expect("invalid request");
+    request
+        .extensions_mut()
+        .insert(h2::ext::Protocol::from_static("webtransport"));
+
+    let (response, send) = send_request.send_request(request, false)?;
+    let response = drive(&mut conn, response).await??;
+
+    if !response.status().is_success() {
+        return Err(ClientError::HttpError(response.status()));
+    }
+
+    let transport = ClientTransport {
+        conn,
+        stream: H2Stream::new(send, response.into_body()),
+    };
+
+    Ok(Session::new(Role::Client, url.clone(), transport))
+}
+
+// Poll the connection while waiting for the future, failing if the connection ends first.
+async fn drive<T, F>(
+    conn: &mut h2::client::Connection<T, Bytes>,
+    fut: F,
+) -> Result<F::Output, ClientError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: Future,
+{
+    let mut fut = pin!(fut);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Ok(res));
+        }
+
+        match Pin::new(&mut *conn).poll(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Err(ClientError::Closed)),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+    .await
+}
+
+struct ClientTransport<T> {
+    conn: h2::client::Connection<T, Bytes>,
+    stream: H2Stream,
+}
+
+impl<T> Transport for ClientTransport<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SessionError>> {
+        Pin::new(&mut self.conn).poll(cx).map_err(Into::into)
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, SessionError>> {
+        self.stream.poll_recv(cx)
+    }
+
+    fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Bytes,
+    ) -> Poll<Result<(), SessionError>> {
+        self.stream.poll_send(cx, buf)
+    }
+
+    fn finish(&mut self) -> Result<(), SessionError> {
+        self.stream.finish()
+    }
+
+    fn release(&mut self) {
+        self.stream.release()
+    }
+}
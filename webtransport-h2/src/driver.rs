@@ -0,0 +1,249 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use webtransport_proto::{Capsule, CapsuleError};
+
+use crate::{state::State, SessionError};
+
+// The maximum number of chunks received per poll, so a busy connection doesn't starve the executor.
+const RECV_BUDGET: usize = 32;
+
+// The largest capsule we'll buffer, which is much larger than any we send.
+const MAX_CAPSULE_SIZE: usize = 1024 * 1024;
+
+/// A reliable, ordered byte stream that carries the session's capsules, along with the connection it's on.
+///
+/// This is implemented for the CONNECT stream of an HTTP/2 connection, but any other transport can be used with [`crate::Session::new`],
+/// as long as both endpoints implement it the same way (ex. webtransport-ws).
+pub trait Transport: Send + 'static {
+    /// Drive the connection itself, returning once it's closed.
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SessionError>>;
+
+    /// Receive the next chunk of capsules, or None once the peer finished sending.
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, SessionError>>;
+
+    /// Send a prefix of the buffer once there's capacity, advancing it.
+    fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Bytes,
+    ) -> Poll<Result<(), SessionError>>;
+
+    /// Finish sending after the last capsule.
+    fn finish(&mut self) -> Result<(), SessionError>;
+
+    /// Release any resources once both sides finished, so the connection can close.
+    fn release(&mut self);
+}
+
+// The CONNECT stream of an HTTP/2 connection, used by both the client and the server.
+// The handles are dropped on release, because h2 keeps the connection open while they exist.
+pub(crate) struct H2Stream {
+    inner: Option<(h2::SendStream<Bytes>, h2::RecvStream)>,
+}
+
+impl H2Stream {
+    pub fn new(send: h2::SendStream<Bytes>, recv: h2::RecvStream) -> Self {
+        Self {
+            inner: Some((send, recv)),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, SessionError>> {
+        let recv = match self.inner.as_mut() {
+            Some((_, recv)) => recv,
+            None => return Poll::Ready(Ok(None)),
+        };
+
+        let chunk = match recv.poll_data(cx) {
+            Poll::Ready(Some(Ok(chunk))) => chunk,
+            Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err.into())),
+            Poll::Ready(None) => return Poll::Ready(Ok(None)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        // The session has its own flow control and buffer limits, so release the HTTP/2 window right away.
+        recv.flow_control().release_capacity(chunk.len())?;
+
+        Poll::Ready(Ok(Some(chunk)))
+    }
+
+    pub fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Bytes,
+    ) -> Poll<Result<(), SessionError>> {
+        let send = match self.inner.as_mut() {
+            Some((send, _)) => send,
+            None => return Poll::Ready(Err(SessionError::ConnectionClosed)),
+        };
+
+        send.reserve_capacity(buf.len());
+
+        while send.capacity() == 0 {
+            match send.poll_capacity(cx) {
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err.into())),
+                Poll::Ready(None) => return Poll::Ready(Err(SessionError::ConnectionClosed)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let size = send.capacity().min(buf.len());
+        send.send_data(buf.split_to(size), false)?;
+
+        Poll::Ready(Ok(()))
+    }
+
+    pub fn finish(&mut self) -> Result<(), SessionError> {
+        if let Some((send, _)) = self.inner.as_mut() {
+            send.send_data(Bytes::new(), true)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn release(&mut self) {
+        self.inner = None;
+    }
+}
+
+/// Sends and receives everything for a [`crate::Session`], which makes no progress unless this is polled.
+///
+/// Spawn it (or otherwise poll it) right away; it resolves once the session is closed and the connection was shut down,
+/// or with an error if the connection failed.
+pub struct Driver {
+    transport: Box<dyn Transport>,
+    state: Arc<State>,
+
+    // A partial capsule from the peer.
+    buf: BytesMut,
+
+    // Set once we finished the CONNECT stream and the peer did too.
+    finished: bool,
+    done: bool,
+}
+
+impl Driver {
+    pub(crate) fn new(transport: Box<dyn Transport>, state: Arc<State>) -> Self {
+        Self {
+            transport,
+            state,
+            buf: BytesMut::new(),
+            finished: false,
+            done: false,
+        }
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Result<(), SessionError> {
+        if self.finished {
+            return Ok(());
+        }
+
+        while let Some(mut buf) = self.state.pop_outgoing(cx.waker()) {
+            match self.transport.poll_send(cx, &mut buf) {
+                Poll::Ready(Ok(())) => {
+                    if !buf.is_empty() {
+                        self.state.unpop_outgoing(buf);
+                    }
+                }
+                Poll::Ready(Err(err)) => return Err(err),
+                Poll::Pending => {
+                    self.state.unpop_outgoing(buf);
+                    break;
+                }
+            }
+        }
+
+        // Finish the CONNECT stream once the close capsule was sent.
+        if !self.finished && self.state.is_flushed() {
+            self.transport.finish()?;
+            self.finished = true;
+        }
+
+        Ok(())
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Result<(), SessionError> {
+        if self.done {
+            return Ok(());
+        }
+
+        for _ in 0..RECV_BUDGET {
+            let chunk = match self.transport.poll_recv(cx) {
+                Poll::Ready(Ok(Some(chunk))) => chunk,
+                Poll::Ready(Ok(None)) => {
+                    self.state.fail(SessionError::ConnectionClosed);
+                    self.done = true;
+                    return Ok(());
+                }
+                Poll::Ready(Err(err)) => return Err(err),
+                Poll::Pending => return Ok(()),
+            };
+
+            self.buf.extend_from_slice(&chunk);
+            self.decode();
+        }
+
+        // Come back later for the rest.
+        cx.waker().wake_by_ref();
+
+        Ok(())
+    }
+
+    fn decode(&mut self) {
+        loop {
+            let mut cursor = std::io::Cursor::new(&self.buf[..]);
+
+            match Capsule::decode(&mut cursor) {
+                Ok(capsule) => {
+                    let size = cursor.position() as usize;
+                    self.buf.advance(size);
+                    self.state.recv_capsule(capsule);
+                }
+                Err(CapsuleError::UnexpectedEnd) if self.buf.len() > MAX_CAPSULE_SIZE => {
+                    return self.state.violation("capsule too large")
+                }
+                Err(CapsuleError::UnexpectedEnd) => return,
+                Err(_) => return self.state.violation("invalid capsule"),
+            }
+        }
+    }
+}
+
+impl Future for Driver {
+    type Output = Result<(), SessionError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        let res = this.poll_recv(cx).and_then(|_| this.poll_send(cx));
+        if let Err(err) = res {
+            this.state.fail(err.clone());
+            return Poll::Ready(Err(err));
+        }
+
+        if this.done && this.finished {
+            this.transport.release();
+        }
+
+        match this.transport.poll_drive(cx) {
+            Poll::Ready(Ok(())) => {
+                this.state.fail(SessionError::ConnectionClosed);
+                Poll::Ready(Ok(()))
+            }
+            // The peer can close the connection as soon as both sides finished, before our shutdown is flushed.
+            Poll::Ready(Err(_)) if this.done && this.finished => Poll::Ready(Ok(())),
+            Poll::Ready(Err(err)) => {
+                this.state.fail(err.clone());
+                Poll::Ready(Err(err))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
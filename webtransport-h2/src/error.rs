@@ -0,0 +1,182 @@
+use std::{io, sync::Arc};
+
+use thiserror::Error;
+
+/// An error returned by [`crate::Session`], and by every stream once the session is closed.
+#[derive(Error, Debug, Clone)]
+pub enum SessionError {
+    #[error("connection error: {0}")]
+    ConnectionError(Arc<h2::Error>),
+
+    /// An error from a custom [`crate::Transport`].
+    #[error("transport error: {0}")]
+    TransportError(Arc<dyn std::error::Error + Send + Sync>),
+
+    /// The CONNECT stream or connection ended without a CLOSE_WEBTRANSPORT_SESSION capsule.
+    #[error("connection closed")]
+    ConnectionClosed,
+
+    /// The peer closed the session.
+    #[error("closed by peer: code={code} reason={reason}")]
+    Closed { code: u32, reason: String },
+
+    /// The session was closed with [`crate::Session::close`].
+    #[error("closed locally: code={code} reason={reason}")]
+    LocallyClosed { code: u32, reason: String },
+
+    /// The peer sent an invalid capsule, so the session was closed.
+    #[error("protocol violation: {0}")]
+    ProtocolViolation(&'static str),
+}
+
+impl From<h2::Error> for SessionError {
+    fn from(err: h2::Error) -> Self {
+        SessionError::ConnectionError(Arc::new(err))
+    }
+}
+
+impl webtransport_generic::SessionError for SessionError {
+    // Get the app error code from a CLOSE_WEBTRANSPORT_SESSION capsule
+    fn session_error(&self) -> Option<u32> {
+        match self {
+            SessionError::Closed { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// An error when writing to [`crate::SendStream`].
+#[derive(Error, Debug, Clone)]
+pub enum WriteError {
+    #[error("STOP_SENDING: {0}")]
+    Stopped(u32),
+
+    #[error("session error: {0}")]
+    SessionError(#[from] SessionError),
+
+    #[error("stream closed")]
+    Closed,
+}
+
+impl webtransport_generic::SessionError for WriteError {
+    // Get the app error code from a CLOSE_WEBTRANSPORT_SESSION capsule
+    fn session_error(&self) -> Option<u32> {
+        match self {
+            WriteError::SessionError(e) => e.session_error(),
+            _ => None,
+        }
+    }
+}
+
+impl webtransport_generic::StreamError for WriteError {
+    /// Get the error code from STOP_SENDING
+    fn stream_error(&self) -> Option<u32> {
+        match self {
+            WriteError::Stopped(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<WriteError> for io::Error {
+    fn from(err: WriteError) -> Self {
+        let kind = match err {
+            WriteError::Stopped(_) => io::ErrorKind::ConnectionReset,
+            WriteError::SessionError(_) => io::ErrorKind::NotConnected,
+            WriteError::Closed => io::ErrorKind::NotConnected,
+        };
+
+        io::Error::new(kind, err)
+    }
+}
+
+/// An error when reading from [`crate::RecvStream`].
+#[derive(Error, Debug, Clone)]
+pub enum ReadError {
+    #[error("session error: {0}")]
+    SessionError(#[from] SessionError),
+
+    #[error("RESET_STREAM: {0}")]
+    Reset(u32),
+
+    #[error("stream already closed")]
+    Closed,
+}
+
+impl webtransport_generic::SessionError for ReadError {
+    // Get the app error code from a CLOSE_WEBTRANSPORT_SESSION capsule
+    fn session_error(&self) -> Option<u32> {
+        match self {
+            ReadError::SessionError(e) => e.session_error(),
+            _ => None,
+        }
+    }
+}
+
+impl webtransport_generic::StreamError for ReadError {
+    /// Get the error code from RESET_STREAM
+    fn stream_error(&self) -> Option<u32> {
+        match self {
+            ReadError::Reset(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReadError> for io::Error {
+    fn from(err: ReadError) -> Self {
+        let kind = match err {
+            ReadError::Reset(_) => io::ErrorKind::ConnectionReset,
+            ReadError::SessionError(_) => io::ErrorKind::NotConnected,
+            ReadError::Closed => io::ErrorKind::NotConnected,
+        };
+
+        io::Error::new(kind, err)
+    }
+}
+
+/// An error returned by [`crate::RecvStream::read_to_end`].
+#[derive(Error, Debug, Clone)]
+pub enum ReadToEndError {
+    #[error("too long")]
+    TooLong,
+
+    #[error("read error: {0}")]
+    ReadError(#[from] ReadError),
+}
+
+/// An error returned by [`crate::Session::send_datagram`].
+#[derive(Error, Debug, Clone)]
+pub enum SendDatagramError {
+    #[error("session error: {0}")]
+    SessionError(#[from] SessionError),
+
+    #[error("datagram too large")]
+    TooLarge,
+}
+
+/// An error returned by [`crate::connect`].
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("http2 error: {0}")]
+    H2(#[from] h2::Error),
+
+    #[error("connection closed")]
+    Closed,
+
+    #[error("server doesn't support extended CONNECT")]
+    ExtendedConnectDisabled,
+
+    #[error("http error status: {0}")]
+    HttpError(http::StatusCode),
+}
+
+/// An error returned by [`crate::accept`] and [`crate::Request`].
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("http2 error: {0}")]
+    H2(#[from] h2::Error),
+
+    #[error("connection closed before a WebTransport request")]
+    Closed,
+}
@@ -0,0 +1,37 @@
+//! WebTransport over HTTP/2, for clients that can't reach the server over UDP.
+//!
+//! This implements the capsule mapping from draft-ietf-webtrans-http2: the session is an extended CONNECT stream,
+//! and every stream, datagram and flow control update is a capsule on it.
+//! The [`Session`], [`SendStream`] and [`RecvStream`] mirror the webtransport-quinn API and implement the
+//! webtransport-generic traits, so application code written against those traits works with either backend.
+//!
+//! The library doesn't spawn tasks, so [`connect`] and [`Request::ok`] return a [`Driver`] that must be spawned.
+//!
+//! The same capsules can be carried by another reliable transport instead of HTTP/2, by implementing [`Transport`]
+//! and creating the session with [`Session::new`]. This is how webtransport-ws runs over a WebSocket.
+//!
+//! There are a few limitations compared to QUIC:
+//! - Everything shares one TCP connection, so a lost packet stalls every stream and datagrams are delivered reliably.
+//! - The h2 crate can't send custom SETTINGS, so both endpoints start with fixed stream and flow control limits.
+//! - Stream priorities are ignored.
+mod client;
+mod driver;
+mod error;
+mod server;
+mod session;
+mod stream;
+
+pub use client::*;
+pub use driver::*;
+pub use error::*;
+pub use server::*;
+pub use session::*;
+pub use stream::*;
+
+// Internal
+mod state;
+
+pub use state::{Role, MAX_DATAGRAM_SIZE};
+
+/// The ALPN to negotiate with TLS, since WebTransport over HTTP/2 is plain HTTP/2.
+pub const ALPN: &[u8] = b"h2";
@@ -0,0 +1,194 @@
+use std::{
+    future::poll_fn,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http::{Method, Response, StatusCode, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    driver::{H2Stream, Transport},
+    Driver, Role, ServerError, Session, SessionError,
+};
+
+/// Perform the HTTP/2 handshake on an accepted connection and wait for a WebTransport CONNECT request.
+///
+/// Extended CONNECT is advertised in the server's SETTINGS. Any other request is answered with a 404.
+/// Only one session is supported per connection; later CONNECT requests are rejected the same way.
+pub async fn accept<T>(io: T) -> Result<Request, ServerError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut conn = h2::server::Builder::new()
+        .enable_connect_protocol()
+        .handshake(io)
+        .await?;
+
+    loop {
+        let (request, mut respond) = match poll_fn(|cx| conn.poll_accept(cx)).await {
+            Some(res) => res?,
+            None => return Err(ServerError::Closed),
+        };
+
+        let protocol = request.extensions().get::<h2::ext::Protocol>();
+        let webtransport = request.method() == Method::CONNECT
+            && protocol.map(|p| p.as_str()) == Some("webtransport");
+
+        if !webtransport {
+            reject(&mut respond, StatusCode::NOT_FOUND);
+            continue;
+        }
+
+        let url = request.uri().clone();
+
+        return Ok(Request {
+            url,
+            respond,
+            recv: request.into_body(),
+            conn: Box::new(conn),
+        });
+    }
+}
+
+fn reject(respond: &mut h2::server::SendResponse<Bytes>, status: StatusCode) {
+    let response = Response::builder().status(status).body(()).unwrap();
+    respond.send_response(response, true).ok();
+}
+
+// The connection with the request type erased.
+trait Serve: Send + 'static {
+    #[allow(clippy::type_complexity)]
+    fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        Option<
+            Result<
+                (
+                    http::Request<h2::RecvStream>,
+                    h2::server::SendResponse<Bytes>,
+                ),
+                h2::Error,
+            >,
+        >,
+    >;
+
+    fn graceful_shutdown(&mut self);
+}
+
+impl<T> Serve for h2::server::Connection<T, Bytes>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        Option<
+            Result<
+                (
+                    http::Request<h2::RecvStream>,
+                    h2::server::SendResponse<Bytes>,
+                ),
+                h2::Error,
+            >,
+        >,
+    > {
+        h2::server::Connection::poll_accept(self, cx)
+    }
+
+    fn graceful_shutdown(&mut self) {
+        h2::server::Connection::graceful_shutdown(self)
+    }
+}
+
+/// A WebTransport CONNECT request that can be accepted or rejected.
+pub struct Request {
+    url: Uri,
+    respond: h2::server::SendResponse<Bytes>,
+    recv: h2::RecvStream,
+    conn: Box<dyn Serve>,
+}
+
+impl Request {
+    /// Returns the URL provided by the client.
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+
+    /// Accept the session, returning a 200 OK.
+    ///
+    /// The returned [`Driver`] must be spawned (or otherwise polled) for the session to make any progress.
+    pub fn ok(mut self) -> Result<(Session, Driver), ServerError> {
+        let response = Response::builder().status(StatusCode::OK).body(()).unwrap();
+        let send = self.respond.send_response(response, false)?;
+
+        let transport = ServerTransport {
+            conn: self.conn,
+            stream: H2Stream::new(send, self.recv),
+        };
+
+        Ok(Session::new(Role::Server, self.url, transport))
+    }
+
+    /// Reject the session, returning your favorite HTTP status code, and close the connection.
+    pub async fn close(mut self, status: StatusCode) -> Result<(), ServerError> {
+        reject(&mut self.respond, status);
+        self.conn.graceful_shutdown();
+
+        // Drive the connection until the response is flushed and the client goes away.
+        while let Some(res) = poll_fn(|cx| self.conn.poll_accept(cx)).await {
+            let (_, mut respond) = res?;
+            reject(&mut respond, StatusCode::NOT_FOUND);
+        }
+
+        Ok(())
+    }
+}
+
+struct ServerTransport {
+    conn: Box<dyn Serve>,
+    stream: H2Stream,
+}
+
+impl Transport for ServerTransport {
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SessionError>> {
+        loop {
+            match self.conn.poll_accept(cx) {
+                // There's only one session per connection.
+                Poll::Ready(Some(Ok((_, mut respond)))) => {
+                    reject(&mut respond, StatusCode::NOT_FOUND)
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err.into())),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, SessionError>> {
+        self.stream.poll_recv(cx)
+    }
+
+    fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Bytes,
+    ) -> Poll<Result<(), SessionError>> {
+        self.stream.poll_send(cx, buf)
+    }
+
+    fn finish(&mut self) -> Result<(), SessionError> {
+        self.stream.finish()?;
+
+        // No more requests are accepted, so the connection closes once the client is done too.
+        self.conn.graceful_shutdown();
+
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.stream.release()
+    }
+}
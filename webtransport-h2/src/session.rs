@@ -0,0 +1,184 @@
+use std::{
+    future::poll_fn,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http::Uri;
+
+use crate::{
+    state::{Dir, State, MAX_DATAGRAM_SIZE},
+    Driver, RecvStream, Role, SendDatagramError, SendStream, SessionError, Transport,
+};
+
+/// An established WebTransport session over HTTP/2, mirroring `webtransport_quinn::Session`.
+///
+/// Streams and datagrams are multiplexed as capsules over the CONNECT stream by the [`Driver`], which has to be polled.
+/// The session is closed with code 0 once every clone is dropped, although existing streams keep it alive until then.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Handle>,
+    url: Uri,
+}
+
+// Closes the session once the last clone is dropped.
+struct Handle {
+    state: Arc<State>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.state.close(0, "");
+    }
+}
+
+impl Session {
+    /// Create a session over a custom [`Transport`], returning the [`Driver`] that must be polled.
+    ///
+    /// The URL is only informational. Use [`crate::connect`] or [`crate::accept`] for HTTP/2.
+    pub fn new<T: Transport>(role: Role, url: Uri, transport: T) -> (Self, Driver) {
+        let state = Arc::new(State::new(role));
+        let driver = Driver::new(Box::new(transport), state.clone());
+
+        let session = Self {
+            inner: Arc::new(Handle { state }),
+            url,
+        };
+
+        (session, driver)
+    }
+
+    fn state(&self) -> &Arc<State> {
+        &self.inner.state
+    }
+
+    /// The URL of the CONNECT request.
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+
+    /// Accept a new unidirectional stream opened by the peer.
+    pub async fn accept_uni(&self) -> Result<RecvStream, SessionError> {
+        poll_fn(|cx| self.poll_accept_uni(cx)).await
+    }
+
+    fn poll_accept_uni(&self, cx: &mut Context<'_>) -> Poll<Result<RecvStream, SessionError>> {
+        let id = std::task::ready!(self.state().poll_accept(cx, Dir::Uni))?;
+        Poll::Ready(Ok(RecvStream::new(id, self.state().clone())))
+    }
+
+    /// Accept a new bidirectional stream opened by the peer.
+    pub async fn accept_bi(&self) -> Result<(SendStream, RecvStream), SessionError> {
+        poll_fn(|cx| self.poll_accept_bi(cx)).await
+    }
+
+    fn poll_accept_bi(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(SendStream, RecvStream), SessionError>> {
+        let id = std::task::ready!(self.state().poll_accept(cx, Dir::Bi))?;
+        Poll::Ready(Ok(self.bi(id)))
+    }
+
+    /// Open a new unidirectional stream, waiting until the peer's stream limit allows it.
+    pub async fn open_uni(&self) -> Result<SendStream, SessionError> {
+        poll_fn(|cx| self.poll_open_uni(cx)).await
+    }
+
+    fn poll_open_uni(&self, cx: &mut Context<'_>) -> Poll<Result<SendStream, SessionError>> {
+        let id = std::task::ready!(self.state().poll_open(cx, Dir::Uni))?;
+        Poll::Ready(Ok(SendStream::new(id, self.state().clone())))
+    }
+
+    /// Open a new bidirectional stream, waiting until the peer's stream limit allows it.
+    pub async fn open_bi(&self) -> Result<(SendStream, RecvStream), SessionError> {
+        poll_fn(|cx| self.poll_open_bi(cx)).await
+    }
+
+    fn poll_open_bi(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(SendStream, RecvStream), SessionError>> {
+        let id = std::task::ready!(self.state().poll_open(cx, Dir::Bi))?;
+        Poll::Ready(Ok(self.bi(id)))
+    }
+
+    fn bi(&self, id: u64) -> (SendStream, RecvStream) {
+        let send = SendStream::new(id, self.state().clone());
+        let recv = RecvStream::new(id, self.state().clone());
+        (send, recv)
+    }
+
+    /// Send a datagram as a DATAGRAM capsule, up to [`Self::max_datagram_size`].
+    ///
+    /// Datagrams are delivered reliably by TCP, but they're dropped instead of queued when the connection is backed up.
+    pub fn send_datagram(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        self.state().send_datagram(payload)
+    }
+
+    /// Receive the next datagram. The oldest is dropped once too many are waiting.
+    pub async fn recv_datagram(&self) -> Result<Bytes, SessionError> {
+        poll_fn(|cx| self.state().poll_recv_datagram(cx)).await
+    }
+
+    /// The largest datagram payload, which doesn't depend on the path since there's no MTU.
+    pub fn max_datagram_size(&self) -> usize {
+        MAX_DATAGRAM_SIZE
+    }
+
+    /// Close the session with an error code and reason, which is truncated to 1024 bytes.
+    ///
+    /// Any data that wasn't sent yet is still sent, followed by the CLOSE_WEBTRANSPORT_SESSION capsule.
+    pub fn close(&self, code: u32, reason: &[u8]) {
+        self.state().close(code, &String::from_utf8_lossy(reason))
+    }
+
+    /// Wait until the session is closed, returning the reason.
+    pub async fn closed(&self) -> SessionError {
+        poll_fn(|cx| self.state().poll_closed(cx)).await
+    }
+
+    /// Return why the session was closed, or None if it's still open.
+    pub fn close_reason(&self) -> Option<SessionError> {
+        self.state().close_reason()
+    }
+}
+
+impl webtransport_generic::Session for Session {
+    type SendStream = SendStream;
+    type RecvStream = RecvStream;
+    type Error = SessionError;
+
+    fn poll_accept_uni(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::RecvStream, Self::Error>> {
+        Session::poll_accept_uni(self, cx)
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(Self::SendStream, Self::RecvStream), Self::Error>> {
+        Session::poll_accept_bi(self, cx)
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(Self::SendStream, Self::RecvStream), Self::Error>> {
+        Session::poll_open_bi(self, cx)
+    }
+
+    fn poll_open_uni(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        Session::poll_open_uni(self, cx)
+    }
+
+    fn close(&mut self, code: u32, reason: &[u8]) {
+        Session::close(self, code, reason)
+    }
+}
@@ -0,0 +1,790 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+use bytes::{Bytes, BytesMut};
+use webtransport_proto::{Capsule, VarInt};
+
+use crate::{ReadError, SendDatagramError, SessionError, WriteError};
+
+// h2 doesn't support custom SETTINGS, so both endpoints assume these initial limits and raise them with capsules.
+const INITIAL_MAX_STREAMS: u64 = 100;
+const INITIAL_MAX_STREAM_DATA: u64 = 256 * 1024;
+
+// The maximum payload of a WT_STREAM capsule, so one stream can't hold up the others for long.
+const MAX_CHUNK: usize = 16 * 1024;
+
+// Writes wait once this many bytes of capsules are queued for the driver.
+const MAX_OUTGOING: usize = 256 * 1024;
+
+// The oldest received datagram is dropped once this many are waiting.
+const MAX_DATAGRAMS: usize = 1024;
+
+/// The largest datagram payload, since there's no MTU over HTTP/2.
+pub const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// The endpoint that sent the CONNECT request, which determines the stream IDs each side uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Dir {
+    Bi,
+    Uni,
+}
+
+// Stream IDs are assigned like QUIC: the low bit is the initiator and the next bit is the direction.
+fn stream_id(role: Role, dir: Dir, index: u64) -> u64 {
+    (index << 2) | ((dir == Dir::Uni) as u64) << 1 | (role == Role::Server) as u64
+}
+
+fn initiator(id: u64) -> Role {
+    match id & 1 {
+        0 => Role::Client,
+        _ => Role::Server,
+    }
+}
+
+fn direction(id: u64) -> Dir {
+    match id & 2 {
+        0 => Dir::Bi,
+        _ => Dir::Uni,
+    }
+}
+
+// A counter for each direction.
+#[derive(Clone, Copy, Default)]
+struct PerDir {
+    bi: u64,
+    uni: u64,
+}
+
+impl PerDir {
+    fn new(value: u64) -> Self {
+        Self {
+            bi: value,
+            uni: value,
+        }
+    }
+
+    fn get(&self, dir: Dir) -> u64 {
+        match dir {
+            Dir::Bi => self.bi,
+            Dir::Uni => self.uni,
+        }
+    }
+
+    fn get_mut(&mut self, dir: Dir) -> &mut u64 {
+        match dir {
+            Dir::Bi => &mut self.bi,
+            Dir::Uni => &mut self.uni,
+        }
+    }
+}
+
+// Tasks waiting on the same condition, which are all woken since they share the session.
+#[derive(Default)]
+struct Wakers(Vec<Waker>);
+
+impl Wakers {
+    fn register(&mut self, waker: &Waker) {
+        if !self.0.iter().any(|w| w.will_wake(waker)) {
+            self.0.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        for waker in self.0.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+// Multiplexes the streams and datagrams of a session over the CONNECT stream, shared by every handle and the driver.
+pub(crate) struct State {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    role: Role,
+
+    // Each half is removed once it's done, and the stream once both are.
+    streams: HashMap<u64, Stream>,
+
+    // Streams opened by the peer that haven't been accepted yet.
+    incoming_bi: VecDeque<u64>,
+    incoming_uni: VecDeque<u64>,
+    accept: Wakers,
+
+    // The number of streams we opened, and the peer's limit.
+    opened: PerDir,
+    max_open: PerDir,
+    open: Wakers,
+
+    // The number of streams the peer opened and closed, and the limit we last announced.
+    peer_opened: PerDir,
+    peer_closed: PerDir,
+    max_peer: PerDir,
+
+    datagrams: VecDeque<Bytes>,
+    recv_datagram: Wakers,
+
+    // Encoded capsules waiting for the driver.
+    outgoing: VecDeque<Bytes>,
+    outgoing_size: usize,
+    write: Wakers,
+    driver: Option<Waker>,
+
+    closed: Option<SessionError>,
+    close: Wakers,
+}
+
+#[derive(Default)]
+struct Stream {
+    send: Option<SendHalf>,
+    recv: Option<RecvHalf>,
+}
+
+struct SendHalf {
+    // The total bytes sent and the peer's flow control limit.
+    sent: u64,
+    max: u64,
+
+    stopped: Option<u32>,
+    waker: Option<Waker>,
+}
+
+impl SendHalf {
+    fn new() -> Self {
+        Self {
+            sent: 0,
+            max: INITIAL_MAX_STREAM_DATA,
+            stopped: None,
+            waker: None,
+        }
+    }
+}
+
+struct RecvHalf {
+    chunks: VecDeque<Bytes>,
+
+    // The total bytes received and read, and the flow control limit we last announced.
+    received: u64,
+    consumed: u64,
+    max: u64,
+
+    fin: bool,
+    reset: Option<u32>,
+    waker: Option<Waker>,
+}
+
+impl RecvHalf {
+    fn new() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            received: 0,
+            consumed: 0,
+            max: INITIAL_MAX_STREAM_DATA,
+            fin: false,
+            reset: None,
+            waker: None,
+        }
+    }
+}
+
+impl State {
+    pub fn new(role: Role) -> Self {
+        let inner = Inner {
+            role,
+            streams: HashMap::new(),
+            incoming_bi: VecDeque::new(),
+            incoming_uni: VecDeque::new(),
+            accept: Wakers::default(),
+            opened: PerDir::default(),
+            max_open: PerDir::new(INITIAL_MAX_STREAMS),
+            open: Wakers::default(),
+            peer_opened: PerDir::default(),
+            peer_closed: PerDir::default(),
+            max_peer: PerDir::new(INITIAL_MAX_STREAMS),
+            datagrams: VecDeque::new(),
+            recv_datagram: Wakers::default(),
+            outgoing: VecDeque::new(),
+            outgoing_size: 0,
+            write: Wakers::default(),
+            driver: None,
+            closed: None,
+            close: Wakers::default(),
+        };
+
+        Self {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    pub fn poll_open(&self, cx: &mut Context<'_>, dir: Dir) -> Poll<Result<u64, SessionError>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(err) = &inner.closed {
+            return Poll::Ready(Err(err.clone()));
+        }
+
+        let index = inner.opened.get(dir);
+        if index >= inner.max_open.get(dir) {
+            inner.open.register(cx.waker());
+            return Poll::Pending;
+        }
+
+        *inner.opened.get_mut(dir) += 1;
+
+        let id = stream_id(inner.role, dir, index);
+        let stream = Stream {
+            send: Some(SendHalf::new()),
+            recv: (dir == Dir::Bi).then(RecvHalf::new),
+        };
+        inner.streams.insert(id, stream);
+
+        Poll::Ready(Ok(id))
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context<'_>, dir: Dir) -> Poll<Result<u64, SessionError>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(err) = &inner.closed {
+            return Poll::Ready(Err(err.clone()));
+        }
+
+        let incoming = match dir {
+            Dir::Bi => &mut inner.incoming_bi,
+            Dir::Uni => &mut inner.incoming_uni,
+        };
+
+        match incoming.pop_front() {
+            Some(id) => Poll::Ready(Ok(id)),
+            None => {
+                inner.accept.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn poll_write(
+        &self,
+        cx: &mut Context<'_>,
+        id: u64,
+        buf: &[u8],
+    ) -> Poll<Result<usize, WriteError>> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        if let Some(err) = &inner.closed {
+            return Poll::Ready(Err(err.clone().into()));
+        }
+
+        let send = match inner.streams.get_mut(&id).and_then(|s| s.send.as_mut()) {
+            Some(send) => send,
+            None => return Poll::Ready(Err(WriteError::Closed)),
+        };
+
+        if let Some(code) = send.stopped {
+            return Poll::Ready(Err(WriteError::Stopped(code)));
+        }
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let credit = send.max - send.sent;
+        if credit == 0 {
+            send.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        if inner.outgoing_size >= MAX_OUTGOING {
+            inner.write.register(cx.waker());
+            return Poll::Pending;
+        }
+
+        let size = buf.len().min(credit as usize).min(MAX_CHUNK);
+        send.sent += size as u64;
+
+        inner.enqueue(Capsule::WtStream {
+            id: varint(id),
+            fin: false,
+            data: Bytes::copy_from_slice(&buf[..size]),
+        });
+
+        Poll::Ready(Ok(size))
+    }
+
+    // Finish or reset the send half, or just forget it when None.
+    pub fn close_send(&self, id: u64, reset: Option<u32>, quiet: bool) -> Result<(), WriteError> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(err) = &inner.closed {
+            return Err(err.clone().into());
+        }
+
+        let send = match inner.streams.get_mut(&id).and_then(|s| s.send.take()) {
+            Some(send) => send,
+            None => return Err(WriteError::Closed),
+        };
+
+        // The stream was already reset in response to the STOP_SENDING.
+        if let Some(code) = send.stopped {
+            inner.cleanup(id);
+            return match quiet {
+                true => Ok(()),
+                false => Err(WriteError::Stopped(code)),
+            };
+        }
+
+        let capsule = match reset {
+            Some(code) => Capsule::WtResetStream {
+                id: varint(id),
+                code: code.into(),
+            },
+            None => Capsule::WtStream {
+                id: varint(id),
+                fin: true,
+                data: Bytes::new(),
+            },
+        };
+
+        inner.enqueue(capsule);
+        inner.cleanup(id);
+
+        Ok(())
+    }
+
+    pub fn poll_read(
+        &self,
+        cx: &mut Context<'_>,
+        id: u64,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ReadError>> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        if let Some(err) = &inner.closed {
+            return Poll::Ready(Err(err.clone().into()));
+        }
+
+        let recv = match inner.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) {
+            Some(recv) => recv,
+            None => return Poll::Ready(Err(ReadError::Closed)),
+        };
+
+        if let Some(code) = recv.reset {
+            return Poll::Ready(Err(ReadError::Reset(code)));
+        }
+
+        if let Some(mut chunk) = recv.chunks.pop_front() {
+            if chunk.len() > max {
+                recv.chunks.push_front(chunk.split_off(max));
+            }
+
+            recv.consumed += chunk.len() as u64;
+
+            // Raise the limit once half of the window was read.
+            let window = recv.consumed + INITIAL_MAX_STREAM_DATA;
+            if !recv.fin && recv.max + INITIAL_MAX_STREAM_DATA / 2 <= window {
+                recv.max = window;
+                inner.enqueue(Capsule::WtMaxStreamData {
+                    id: varint(id),
+                    max: varint(window),
+                });
+            }
+
+            return Poll::Ready(Ok(Some(chunk)));
+        }
+
+        if recv.fin {
+            return Poll::Ready(Ok(None));
+        }
+
+        recv.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    // Forget the receive half, asking the peer to stop sending with the code unless it already finished.
+    pub fn close_recv(&self, id: u64, stop: u32) -> Result<(), ReadError> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed.is_some() {
+            return Ok(());
+        }
+
+        let recv = match inner.streams.get_mut(&id).and_then(|s| s.recv.take()) {
+            Some(recv) => recv,
+            None => return Err(ReadError::Closed),
+        };
+
+        if !recv.fin && recv.reset.is_none() {
+            inner.enqueue(Capsule::WtStopSending {
+                id: varint(id),
+                code: stop.into(),
+            });
+        }
+
+        inner.cleanup(id);
+
+        Ok(())
+    }
+
+    pub fn send_datagram(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(err) = &inner.closed {
+            return Err(err.clone().into());
+        }
+
+        if payload.len() > MAX_DATAGRAM_SIZE {
+            return Err(SendDatagramError::TooLarge);
+        }
+
+        // Datagrams can't wait, so they're dropped when the connection is backed up.
+        if inner.outgoing_size < MAX_OUTGOING {
+            inner.enqueue(Capsule::Datagram { payload });
+        }
+
+        Ok(())
+    }
+
+    pub fn poll_recv_datagram(&self, cx: &mut Context<'_>) -> Poll<Result<Bytes, SessionError>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(err) = &inner.closed {
+            return Poll::Ready(Err(err.clone()));
+        }
+
+        match inner.datagrams.pop_front() {
+            Some(payload) => Poll::Ready(Ok(payload)),
+            None => {
+                inner.recv_datagram.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    // Close the session, sending a CLOSE_WEBTRANSPORT_SESSION capsule.
+    pub fn close(&self, code: u32, reason: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed.is_some() {
+            return;
+        }
+
+        let capsule = Capsule::close(code, reason);
+        if let Capsule::CloseWebTransportSession { reason, .. } = &capsule {
+            inner.closed = Some(SessionError::LocallyClosed {
+                code,
+                reason: reason.clone(),
+            });
+        }
+
+        inner.enqueue(capsule);
+        inner.wake_all();
+    }
+
+    // Mark the session as closed without sending anything, ex. because the connection failed.
+    pub fn fail(&self, err: SessionError) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed.is_none() {
+            inner.closed = Some(err);
+            inner.wake_all();
+        }
+    }
+
+    pub fn poll_closed(&self, cx: &mut Context<'_>) -> Poll<SessionError> {
+        let mut inner = self.inner.lock().unwrap();
+        match &inner.closed {
+            Some(err) => Poll::Ready(err.clone()),
+            None => {
+                inner.close.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn close_reason(&self) -> Option<SessionError> {
+        self.inner.lock().unwrap().closed.clone()
+    }
+
+    // Return the next encoded capsule to send, or register the driver to be woken.
+    pub fn pop_outgoing(&self, waker: &Waker) -> Option<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let buf = match inner.outgoing.pop_front() {
+            Some(buf) => buf,
+            None => {
+                inner.driver = Some(waker.clone());
+                return None;
+            }
+        };
+
+        inner.outgoing_size -= buf.len();
+        if inner.outgoing_size < MAX_OUTGOING {
+            inner.write.wake();
+        }
+
+        Some(buf)
+    }
+
+    // Return the unsent remainder of a capsule, which is sent first next time.
+    pub fn unpop_outgoing(&self, buf: Bytes) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.outgoing_size += buf.len();
+        inner.outgoing.push_front(buf);
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.closed.is_some() && inner.outgoing.is_empty()
+    }
+
+    // Apply a capsule from the peer, closing the session if it's invalid.
+    pub fn recv_capsule(&self, capsule: Capsule) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed.is_some() {
+            return;
+        }
+
+        if let Err(reason) = inner.apply(capsule) {
+            inner.closed = Some(SessionError::ProtocolViolation(reason));
+            inner.enqueue(Capsule::close(0, reason));
+            inner.wake_all();
+        }
+    }
+
+    pub fn violation(&self, reason: &'static str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed.is_none() {
+            inner.closed = Some(SessionError::ProtocolViolation(reason));
+            inner.enqueue(Capsule::close(0, reason));
+            inner.wake_all();
+        }
+    }
+}
+
+impl Inner {
+    fn enqueue(&mut self, capsule: Capsule) {
+        let mut buf = BytesMut::new();
+        capsule.encode(&mut buf);
+
+        self.outgoing_size += buf.len();
+        self.outgoing.push_back(buf.freeze());
+
+        if let Some(waker) = self.driver.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_all(&mut self) {
+        self.accept.wake();
+        self.open.wake();
+        self.recv_datagram.wake();
+        self.write.wake();
+        self.close.wake();
+
+        for stream in self.streams.values_mut() {
+            if let Some(waker) = stream.send.as_mut().and_then(|s| s.waker.take()) {
+                waker.wake();
+            }
+
+            if let Some(waker) = stream.recv.as_mut().and_then(|r| r.waker.take()) {
+                waker.wake();
+            }
+        }
+    }
+
+    fn apply(&mut self, capsule: Capsule) -> Result<(), &'static str> {
+        match capsule {
+            Capsule::CloseWebTransportSession { code, reason } => {
+                self.closed = Some(SessionError::Closed { code, reason });
+                self.wake_all();
+            }
+            Capsule::Datagram { payload } => {
+                if self.datagrams.len() >= MAX_DATAGRAMS {
+                    self.datagrams.pop_front();
+                }
+
+                self.datagrams.push_back(payload);
+                self.recv_datagram.wake();
+            }
+            Capsule::WtStream { id, fin, data } => {
+                let recv = match self.recv_half(id.into_inner())? {
+                    Some(recv) if !recv.fin && recv.reset.is_none() => recv,
+                    _ => return Ok(()),
+                };
+
+                recv.received += data.len() as u64;
+                if recv.received > recv.max {
+                    return Err("stream flow control exceeded");
+                }
+
+                if !data.is_empty() {
+                    recv.chunks.push_back(data);
+                }
+
+                recv.fin = fin;
+
+                if let Some(waker) = recv.waker.take() {
+                    waker.wake();
+                }
+            }
+            Capsule::WtResetStream { id, code } => {
+                let code = code_u32(code)?;
+                let recv = match self.recv_half(id.into_inner())? {
+                    Some(recv) => recv,
+                    None => return Ok(()),
+                };
+
+                recv.reset = Some(code);
+                recv.chunks.clear();
+
+                if let Some(waker) = recv.waker.take() {
+                    waker.wake();
+                }
+            }
+            Capsule::WtStopSending { id, code } => {
+                let code = code_u32(code)?;
+                let id = id.into_inner();
+                let send = match self.send_half(id)? {
+                    Some(send) if send.stopped.is_none() => send,
+                    _ => return Ok(()),
+                };
+
+                send.stopped = Some(code);
+
+                if let Some(waker) = send.waker.take() {
+                    waker.wake();
+                }
+
+                // Like QUIC, answer with a reset since the peer won't read anything else.
+                self.enqueue(Capsule::WtResetStream {
+                    id: varint(id),
+                    code: code.into(),
+                });
+            }
+            Capsule::WtMaxStreamData { id, max } => {
+                let send = match self.send_half(id.into_inner())? {
+                    Some(send) => send,
+                    None => return Ok(()),
+                };
+
+                send.max = send.max.max(max.into_inner());
+
+                if let Some(waker) = send.waker.take() {
+                    waker.wake();
+                }
+            }
+            Capsule::WtMaxStreams { bidi, max } => {
+                let dir = if bidi { Dir::Bi } else { Dir::Uni };
+                let limit = self.max_open.get_mut(dir);
+                *limit = (*limit).max(max.into_inner());
+
+                self.open.wake();
+            }
+            // Draining is only surfaced by the HTTP/3 backend, so it's ignored like an unknown capsule.
+            // The session-wide limits are only used by the HTTP/3 backend too, since this one has fixed limits.
+            Capsule::DrainWebTransportSession
+            | Capsule::WtStreamsBlocked { .. }
+            | Capsule::WtMaxData { .. }
+            | Capsule::WtDataBlocked { .. }
+            | Capsule::Unknown { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    // Return the stream the peer is sending on, opening it (and any before it) if it's new.
+    fn recv_half(&mut self, id: u64) -> Result<Option<&mut RecvHalf>, &'static str> {
+        if initiator(id) == self.role && direction(id) == Dir::Uni {
+            return Err("received data on a send-only stream");
+        }
+
+        Ok(self.stream(id)?.and_then(|s| s.recv.as_mut()))
+    }
+
+    // Return the stream we're sending on, which the peer can't open.
+    fn send_half(&mut self, id: u64) -> Result<Option<&mut SendHalf>, &'static str> {
+        if initiator(id) != self.role && direction(id) == Dir::Uni {
+            return Err("flow control for a receive-only stream");
+        }
+
+        Ok(self.stream(id)?.and_then(|s| s.send.as_mut()))
+    }
+
+    // Return the stream if it's still open, opening the peer's streams up to it like QUIC.
+    fn stream(&mut self, id: u64) -> Result<Option<&mut Stream>, &'static str> {
+        let role = initiator(id);
+        let dir = direction(id);
+        let index = id >> 2;
+
+        if role == self.role {
+            if index >= self.opened.get(dir) {
+                return Err("unknown stream");
+            }
+
+            return Ok(self.streams.get_mut(&id));
+        }
+
+        if index >= self.max_peer.get(dir) {
+            return Err("stream limit exceeded");
+        }
+
+        while self.peer_opened.get(dir) <= index {
+            let id = stream_id(role, dir, self.peer_opened.get(dir));
+            *self.peer_opened.get_mut(dir) += 1;
+
+            let stream = Stream {
+                send: (dir == Dir::Bi).then(SendHalf::new),
+                recv: Some(RecvHalf::new()),
+            };
+            self.streams.insert(id, stream);
+
+            match dir {
+                Dir::Bi => self.incoming_bi.push_back(id),
+                Dir::Uni => self.incoming_uni.push_back(id),
+            }
+
+            self.accept.wake();
+        }
+
+        Ok(self.streams.get_mut(&id))
+    }
+
+    // Remove the stream once both halves are done, allowing the peer to open another.
+    fn cleanup(&mut self, id: u64) {
+        match self.streams.get(&id) {
+            Some(stream) if stream.send.is_none() && stream.recv.is_none() => {}
+            _ => return,
+        }
+
+        self.streams.remove(&id);
+
+        if initiator(id) == self.role {
+            return;
+        }
+
+        let dir = direction(id);
+        *self.peer_closed.get_mut(dir) += 1;
+
+        // Only announce the new limit once it grew a bit, to avoid a capsule per stream.
+        let max = self.peer_closed.get(dir) + INITIAL_MAX_STREAMS;
+        if max >= self.max_peer.get(dir) + INITIAL_MAX_STREAMS / 4 {
+            *self.max_peer.get_mut(dir) = max;
+            self.enqueue(Capsule::WtMaxStreams {
+                bidi: dir == Dir::Bi,
+                max: varint(max),
+            });
+        }
+    }
+}
+
+fn varint(v: u64) -> VarInt {
+    VarInt::try_from(v).unwrap()
+}
+
+// WebTransport error codes are 32 bits.
+fn code_u32(code: VarInt) -> Result<u32, &'static str> {
+    u32::try_from(code.into_inner()).map_err(|_| "invalid error code")
+}
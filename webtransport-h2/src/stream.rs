@@ -0,0 +1,256 @@
+use std::{
+    future::poll_fn,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::{state::State, ReadError, ReadToEndError, WriteError};
+
+/// A stream that can be used to send bytes, multiplexed over the CONNECT stream.
+///
+/// Writes wait for the peer's flow control limit for the stream, and for the driver to catch up once enough is queued.
+/// The stream is finished when dropped, unless it was already finished or reset.
+pub struct SendStream {
+    id: u64,
+    state: Arc<State>,
+    done: bool,
+}
+
+impl SendStream {
+    pub(crate) fn new(id: u64, state: Arc<State>) -> Self {
+        Self {
+            id,
+            state,
+            done: false,
+        }
+    }
+
+    /// Write some data to the stream, returning the size written.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, WriteError> {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, WriteError>> {
+        if self.done {
+            return Poll::Ready(Err(WriteError::Closed));
+        }
+
+        self.state.poll_write(cx, self.id, buf)
+    }
+
+    /// Write all of the data to the stream.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<(), WriteError> {
+        while !buf.is_empty() {
+            let size = self.write(buf).await?;
+            buf = &buf[size..];
+        }
+
+        Ok(())
+    }
+
+    /// Write the chunk to the stream.
+    pub async fn write_chunk(&mut self, buf: Bytes) -> Result<(), WriteError> {
+        self.write_all(&buf).await
+    }
+
+    /// Finish the stream after the data already written, without waiting for it to be sent.
+    pub fn finish(&mut self) -> Result<(), WriteError> {
+        self.close(None)
+    }
+
+    /// Abruptly reset the stream with the provided error code.
+    pub fn reset(&mut self, code: u32) -> Result<(), WriteError> {
+        self.close(Some(code))
+    }
+
+    fn close(&mut self, reset: Option<u32>) -> Result<(), WriteError> {
+        if self.done {
+            return Err(WriteError::Closed);
+        }
+
+        self.done = true;
+        self.state.close_send(self.id, reset, false)
+    }
+}
+
+impl Drop for SendStream {
+    fn drop(&mut self) {
+        if !self.done {
+            self.state.close_send(self.id, None, true).ok();
+        }
+    }
+}
+
+impl tokio::io::AsyncWrite for SendStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        SendStream::poll_write(&mut self, cx, buf).map_err(Into::into)
+    }
+
+    // Everything written is already queued for the driver.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+
+        Poll::Ready(self.finish().map_err(Into::into))
+    }
+}
+
+impl webtransport_generic::SendStream for SendStream {
+    type Error = WriteError;
+
+    fn poll_send<B: Buf>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<Result<usize, Self::Error>> {
+        let res = SendStream::poll_write(self, cx, buf.chunk());
+        if let Poll::Ready(Ok(size)) = res {
+            buf.advance(size);
+        }
+
+        res
+    }
+
+    fn poll_finish(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(self.finish())
+    }
+
+    fn reset(&mut self, reset_code: u32) {
+        SendStream::reset(self, reset_code).ok();
+    }
+
+    /// Priorities aren't supported, so streams share the connection in the order they're written.
+    fn set_priority(&mut self, order: i32) {
+        let _ = order;
+    }
+}
+
+/// A stream that can be used to receive bytes, multiplexed over the CONNECT stream.
+///
+/// The peer can only send up to a window ahead of what was read, so a slow reader doesn't hold up other streams.
+/// The peer is asked to stop sending with code 0 when dropped, unless the stream was already finished.
+pub struct RecvStream {
+    id: u64,
+    state: Arc<State>,
+    done: bool,
+}
+
+impl RecvStream {
+    pub(crate) fn new(id: u64, state: Arc<State>) -> Self {
+        Self {
+            id,
+            state,
+            done: false,
+        }
+    }
+
+    /// Tell the other end to stop sending data with the given error code.
+    pub fn stop(&mut self, code: u32) -> Result<(), ReadError> {
+        if self.done {
+            return Err(ReadError::Closed);
+        }
+
+        self.done = true;
+        self.state.close_recv(self.id, code)
+    }
+
+    /// Read some data into the buffer and return the amount read, or None once the stream is finished.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadError> {
+        let chunk = self.read_chunk(buf.len()).await?;
+
+        Ok(chunk.map(|chunk| {
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            chunk.len()
+        }))
+    }
+
+    /// Read the next chunk of up to `max` bytes, or None once the stream is finished.
+    pub async fn read_chunk(&mut self, max: usize) -> Result<Option<Bytes>, ReadError> {
+        poll_fn(|cx| self.poll_read_chunk(cx, max)).await
+    }
+
+    fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ReadError>> {
+        if self.done {
+            return Poll::Ready(Err(ReadError::Closed));
+        }
+
+        self.state.poll_read(cx, self.id, max)
+    }
+
+    /// Read until the end of the stream, failing if it's longer than the limit.
+    pub async fn read_to_end(&mut self, size_limit: usize) -> Result<Vec<u8>, ReadToEndError> {
+        let mut buf = Vec::new();
+
+        while let Some(chunk) = self.read_chunk(usize::MAX).await? {
+            if buf.len() + chunk.len() > size_limit {
+                return Err(ReadToEndError::TooLong);
+            }
+
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(buf)
+    }
+}
+
+impl Drop for RecvStream {
+    fn drop(&mut self) {
+        if !self.done {
+            self.state.close_recv(self.id, 0).ok();
+        }
+    }
+}
+
+impl tokio::io::AsyncRead for RecvStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let res = ready!(self.poll_read_chunk(cx, buf.remaining()));
+        if let Some(chunk) = res? {
+            buf.put_slice(&chunk);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl webtransport_generic::RecvStream for RecvStream {
+    type Error = ReadError;
+
+    fn poll_recv<B: BufMut>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<Result<Option<usize>, Self::Error>> {
+        let chunk = ready!(self.poll_read_chunk(cx, buf.remaining_mut()))?;
+
+        Poll::Ready(Ok(chunk.map(|chunk| {
+            let size = chunk.len();
+            buf.put(chunk);
+            size
+        })))
+    }
+
+    fn stop(&mut self, error_code: u32) {
+        RecvStream::stop(self, error_code).ok();
+    }
+}
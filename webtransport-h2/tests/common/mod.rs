@@ -0,0 +1,204 @@
+// Shared setup for the loopback tests, which connect a client and server over TLS on localhost like the echo example.
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use webtransport_h2::{Session, SessionError};
+use webtransport_proto::{Capsule, CapsuleError};
+
+pub type Tls = tokio_rustls::client::TlsStream<TcpStream>;
+
+// A listener and a client config that trusts its self-signed certificate.
+pub struct Endpoints {
+    listener: TcpListener,
+    acceptor: tokio_rustls::TlsAcceptor,
+    connector: tokio_rustls::TlsConnector,
+}
+
+impl Endpoints {
+    pub async fn new() -> Self {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert_der = rustls::Certificate(cert.serialize_der().unwrap());
+        let key_der = rustls::PrivateKey(cert.serialize_private_key_der());
+
+        let mut server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der.clone()], key_der)
+            .unwrap();
+        server_config.alpn_protocols = vec![webtransport_h2::ALPN.to_vec()];
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&cert_der).unwrap();
+
+        let mut client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_config.alpn_protocols = vec![webtransport_h2::ALPN.to_vec()];
+
+        Self {
+            listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            acceptor: Arc::new(server_config).into(),
+            connector: Arc::new(client_config).into(),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    pub fn url(&self, path: &str) -> http::Uri {
+        format!("https://localhost:{}{}", self.addr().port(), path)
+            .parse()
+            .unwrap()
+    }
+
+    // Accept the next connection and wait for its CONNECT request.
+    pub async fn accept(&self) -> webtransport_h2::Request {
+        let (tcp, _) = self.listener.accept().await.unwrap();
+        let tls = self.acceptor.accept(tcp).await.unwrap();
+        webtransport_h2::accept(tls).await.unwrap()
+    }
+
+    // Open a TLS connection to the listener.
+    pub async fn dial(&self) -> Tls {
+        let tcp = TcpStream::connect(self.addr()).await.unwrap();
+        self.connector
+            .connect("localhost".try_into().unwrap(), tcp)
+            .await
+            .unwrap()
+    }
+}
+
+// A connected pair of sessions, with their drivers spawned.
+pub struct Pair {
+    pub client: Session,
+    pub server: Session,
+    pub client_driver: JoinHandle<Result<(), SessionError>>,
+    pub server_driver: JoinHandle<Result<(), SessionError>>,
+}
+
+pub async fn pair() -> Pair {
+    let endpoints = Arc::new(Endpoints::new().await);
+    let url = endpoints.url("/");
+
+    let server = endpoints.clone();
+    // The driver flushes the response, so it's spawned before the client can finish connecting.
+    let accept = tokio::spawn(async move {
+        let (session, driver) = server.accept().await.ok().unwrap();
+        (session, tokio::spawn(driver))
+    });
+
+    let tls = endpoints.dial().await;
+    let (client, client_driver) = webtransport_h2::connect(tls, &url).await.unwrap();
+    let (server, server_driver) = accept.await.unwrap();
+
+    Pair {
+        client,
+        server,
+        client_driver: tokio::spawn(client_driver),
+        server_driver,
+    }
+}
+
+// A client that writes capsules directly to the CONNECT stream, so it can break the rules.
+pub struct Raw {
+    send: h2::SendStream<Bytes>,
+    recv: h2::RecvStream,
+    buf: BytesMut,
+}
+
+impl Raw {
+    // Send an extended CONNECT request to the listener, returning the server's session once it's accepted.
+    pub async fn connect(endpoints: Arc<Endpoints>) -> (Self, Session) {
+        let server = endpoints.clone();
+        let accept = tokio::spawn(async move {
+            let (session, driver) = server.accept().await.ok().unwrap();
+            tokio::spawn(driver);
+            session
+        });
+
+        let tls = endpoints.dial().await;
+        let (send_request, mut conn) = h2::client::handshake(tls).await.unwrap();
+
+        // Wait for the server's SETTINGS, which come before the PONG, so extended CONNECT is enabled.
+        let mut ping_pong = conn.ping_pong().unwrap();
+        tokio::spawn(async move { conn.await.ok() });
+        ping_pong.ping(h2::Ping::opaque()).await.unwrap();
+
+        let mut send_request = send_request.ready().await.unwrap();
+        let mut request = http::Request::builder()
+            .method(http::Method::CONNECT)
+            .uri(endpoints.url("/"))
+            .body(())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(h2::ext::Protocol::from_static("webtransport"));
+
+        let (response, send) = send_request.send_request(request, false).unwrap();
+        let response = response.await.unwrap();
+        assert!(response.status().is_success());
+
+        let raw = Self {
+            send,
+            recv: response.into_body(),
+            buf: BytesMut::new(),
+        };
+
+        (raw, accept.await.unwrap())
+    }
+
+    pub fn send(&mut self, capsule: Capsule) {
+        let mut buf = BytesMut::new();
+        capsule.encode(&mut buf);
+        self.send.send_data(buf.freeze(), false).unwrap();
+    }
+
+    // Receive the next capsule, or None once the server finished the CONNECT stream.
+    pub async fn recv(&mut self) -> Option<Capsule> {
+        loop {
+            let mut cursor = std::io::Cursor::new(&self.buf[..]);
+            match Capsule::decode(&mut cursor) {
+                Ok(capsule) => {
+                    let size = cursor.position() as usize;
+                    self.buf.advance(size);
+                    return Some(capsule);
+                }
+                Err(CapsuleError::UnexpectedEnd) => {}
+                Err(err) => panic!("invalid capsule: {}", err),
+            }
+
+            let chunk = self.recv.data().await?.unwrap();
+            self.recv
+                .flow_control()
+                .release_capacity(chunk.len())
+                .unwrap();
+            self.buf.extend_from_slice(&chunk);
+        }
+    }
+
+    // Skip capsules until the server closes the session, returning the code and reason.
+    pub async fn closed(&mut self) -> (u32, String) {
+        loop {
+            match self.recv().await {
+                Some(Capsule::CloseWebTransportSession { code, reason }) => return (code, reason),
+                Some(_) => {}
+                None => panic!("finished without a close capsule"),
+            }
+        }
+    }
+}
+
+// Wait for the operation, failing if it's still pending, so a missed wakeup fails the test instead of hanging it.
+pub async fn timeout<F: std::future::Future>(fut: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(5), fut)
+        .await
+        .expect("timed out")
+}
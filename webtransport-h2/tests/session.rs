@@ -0,0 +1,226 @@
+mod common;
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use common::{pair, timeout, Endpoints, Raw};
+use webtransport_h2::{SendDatagramError, SessionError, MAX_DATAGRAM_SIZE};
+use webtransport_proto::{Capsule, VarInt};
+
+#[tokio::test]
+async fn bidi_echo() {
+    let pair = pair().await;
+
+    let server = tokio::spawn(async move {
+        let (mut send, mut recv) = pair.server.accept_bi().await.unwrap();
+        let msg = recv.read_to_end(1024).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().unwrap();
+        pair.server
+    });
+
+    let (mut send, mut recv) = pair.client.open_bi().await.unwrap();
+    send.write_all(b"hello bi").await.unwrap();
+    send.finish().unwrap();
+
+    let msg = timeout(recv.read_to_end(1024)).await.unwrap();
+    assert_eq!(msg, b"hello bi");
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn uni_both_ways() {
+    let pair = pair().await;
+
+    let mut send = pair.client.open_uni().await.unwrap();
+    send.write_all(b"to server").await.unwrap();
+    send.finish().unwrap();
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    assert_eq!(recv.read_to_end(1024).await.unwrap(), b"to server");
+
+    let mut send = pair.server.open_uni().await.unwrap();
+    send.write_all(b"to client").await.unwrap();
+    send.finish().unwrap();
+
+    let mut recv = timeout(pair.client.accept_uni()).await.unwrap();
+    assert_eq!(recv.read_to_end(1024).await.unwrap(), b"to client");
+}
+
+#[tokio::test]
+async fn datagrams() {
+    let pair = pair().await;
+    assert_eq!(pair.client.max_datagram_size(), MAX_DATAGRAM_SIZE);
+
+    pair.client
+        .send_datagram(Bytes::from_static(b"ping"))
+        .unwrap();
+    let msg = timeout(pair.server.recv_datagram()).await.unwrap();
+    assert_eq!(msg, "ping");
+
+    pair.server
+        .send_datagram(Bytes::from_static(b"pong"))
+        .unwrap();
+    let msg = timeout(pair.client.recv_datagram()).await.unwrap();
+    assert_eq!(msg, "pong");
+
+    let big = Bytes::from(vec![0; MAX_DATAGRAM_SIZE + 1]);
+    assert!(matches!(
+        pair.client.send_datagram(big),
+        Err(SendDatagramError::TooLarge)
+    ));
+}
+
+// Larger than the initial stream window, so it only completes if the reader raises the limit.
+#[tokio::test]
+async fn stream_flow_control() {
+    let pair = pair().await;
+    let data: Vec<u8> = (0..4_000_000u32).map(|i| i as u8).collect();
+
+    let (mut send, _) = pair.client.open_bi().await.unwrap();
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+        send.write_all(&data).await.unwrap();
+        send.finish().unwrap();
+    });
+
+    let (_, mut recv) = timeout(pair.server.accept_bi()).await.unwrap();
+    let msg = timeout(recv.read_to_end(usize::MAX)).await.unwrap();
+    assert_eq!(msg, expected);
+    writer.await.unwrap();
+}
+
+// More streams than the initial limit, so it only completes if closed streams raise the limit.
+#[tokio::test]
+async fn stream_limit() {
+    let pair = pair().await;
+
+    for i in 0..300u32 {
+        let mut send = timeout(pair.client.open_uni()).await.unwrap();
+        send.write_all(&i.to_be_bytes()).await.unwrap();
+        send.finish().unwrap();
+
+        let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+        assert_eq!(recv.read_to_end(4).await.unwrap(), i.to_be_bytes());
+    }
+
+    for i in 0..300u32 {
+        let (mut send, mut recv) = timeout(pair.client.open_bi()).await.unwrap();
+        send.write_all(&i.to_be_bytes()).await.unwrap();
+        send.finish().unwrap();
+
+        let (mut reply, mut request) = timeout(pair.server.accept_bi()).await.unwrap();
+        let msg = request.read_to_end(4).await.unwrap();
+        reply.write_all(&msg).await.unwrap();
+        reply.finish().unwrap();
+
+        assert_eq!(recv.read_to_end(4).await.unwrap(), i.to_be_bytes());
+    }
+}
+
+#[tokio::test]
+async fn close() {
+    let pair = pair().await;
+    pair.client.close(7, b"bye");
+
+    match timeout(pair.server.closed()).await {
+        SessionError::Closed { code, reason } => {
+            assert_eq!(code, 7);
+            assert_eq!(reason, "bye");
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    assert!(matches!(
+        pair.client.close_reason(),
+        Some(SessionError::LocallyClosed { code: 7, .. })
+    ));
+
+    // Both ends finish the CONNECT stream, so the drivers return.
+    timeout(pair.client_driver).await.unwrap().unwrap();
+    timeout(pair.server_driver).await.unwrap().unwrap();
+}
+
+// Draining is ignored, so the session keeps working.
+#[tokio::test]
+async fn drain() {
+    let endpoints = Arc::new(Endpoints::new().await);
+    let (mut raw, server) = Raw::connect(endpoints).await;
+
+    raw.send(Capsule::DrainWebTransportSession);
+    raw.send(Capsule::Datagram {
+        payload: Bytes::from_static(b"still here"),
+    });
+
+    let msg = timeout(server.recv_datagram()).await.unwrap();
+    assert_eq!(msg, "still here");
+    assert!(server.close_reason().is_none());
+}
+
+#[tokio::test]
+async fn raw_close() {
+    let endpoints = Arc::new(Endpoints::new().await);
+    let (mut raw, server) = Raw::connect(endpoints).await;
+
+    raw.send(Capsule::close(9, "raw"));
+
+    match timeout(server.closed()).await {
+        SessionError::Closed { code, reason } => {
+            assert_eq!(code, 9);
+            assert_eq!(reason, "raw");
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+// Send the capsules from a raw client, checking that the server closes the session with the reason.
+async fn violation(capsules: Vec<Capsule>, expected: &str) {
+    let endpoints = Arc::new(Endpoints::new().await);
+    let (mut raw, server) = Raw::connect(endpoints).await;
+
+    for capsule in capsules {
+        raw.send(capsule);
+    }
+
+    match timeout(server.closed()).await {
+        SessionError::ProtocolViolation(reason) => assert_eq!(reason, expected),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let (code, reason) = timeout(raw.closed()).await;
+    assert_eq!(code, 0);
+    assert_eq!(reason, expected);
+}
+
+fn stream(id: u64, len: usize) -> Capsule {
+    Capsule::WtStream {
+        id: VarInt::try_from(id).unwrap(),
+        fin: false,
+        data: Bytes::from(vec![0; len]),
+    }
+}
+
+#[tokio::test]
+async fn too_many_streams() {
+    // The 101st client uni stream.
+    violation(vec![stream(100 << 2 | 2, 1)], "stream limit exceeded").await;
+}
+
+#[tokio::test]
+async fn too_much_stream_data() {
+    // Write past the initial window of 256KiB without waiting for WT_MAX_STREAM_DATA.
+    let capsules = vec![stream(2, 128 * 1024), stream(2, 128 * 1024), stream(2, 1)];
+    violation(capsules, "stream flow control exceeded").await;
+}
+
+#[tokio::test]
+async fn data_on_send_only_stream() {
+    // The first server uni stream, which the client can't send on.
+    violation(vec![stream(3, 1)], "received data on a send-only stream").await;
+}
+
+#[tokio::test]
+async fn unopened_stream() {
+    // The first server bidi stream, which the server never opened.
+    violation(vec![stream(1, 1)], "unknown stream").await;
+}
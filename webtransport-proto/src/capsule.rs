@@ -0,0 +1,563 @@
+use bytes::{Buf, BufMut, Bytes};
+
+use super::{Frame, VarInt};
+
+use thiserror::Error;
+
+// The maximum size of the reason in a CLOSE_WEBTRANSPORT_SESSION capsule.
+pub const MAX_CLOSE_REASON: usize = 1024;
+
+// The maximum payload of the other capsules we buffer, which only contain a varint or two.
+const MAX_CONTROL_CAPSULE: usize = 16;
+
+// The most bytes in the header of a frame or capsule, which is two varints.
+const MAX_HEADER: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum CapsuleError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+
+    #[error("invalid utf8 reason")]
+    InvalidReason(#[from] std::str::Utf8Error),
+
+    #[error("reason too long")]
+    ReasonTooLong,
+
+    #[error("invalid capsule payload")]
+    InvalidPayload,
+
+    #[error("capsule too large")]
+    TooLarge,
+}
+
+// Capsules are sent on the CONNECT stream after the response, see RFC 9297.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capsule {
+    // Closes the session with an application error code and reason.
+    CloseWebTransportSession { code: u32, reason: String },
+
+    // Asks the peer to wrap up the session, ex. because the server is shutting down.
+    DrainWebTransportSession,
+
+    // An unreliable datagram, used when the transport doesn't support them natively (ex. HTTP/2).
+    Datagram { payload: Bytes },
+
+    // The remaining capsules are used to multiplex streams over HTTP/2, see draft-ietf-webtrans-http2.
+    // Stream IDs are assigned like QUIC stream IDs, so the low bits are the initiator and direction.
+
+    // Data for a stream, which is finished if `fin` is set.
+    WtStream { id: VarInt, fin: bool, data: Bytes },
+
+    // Abandons sending on a stream with an application error code.
+    WtResetStream { id: VarInt, code: VarInt },
+
+    // Asks the peer to stop sending on a stream with an application error code.
+    WtStopSending { id: VarInt, code: VarInt },
+
+    // Allows the peer to send up to `max` bytes on a stream.
+    WtMaxStreamData { id: VarInt, max: VarInt },
+
+    // Allows the peer to open up to `max` streams of the given direction in total.
+    // The HTTP/3 backend also uses this to limit the streams of each session, see draft-ietf-webtrans-http3 section 5.
+    WtMaxStreams { bidi: bool, max: VarInt },
+
+    // Indicates that we wanted to open a stream but were blocked by the peer's `max`.
+    WtStreamsBlocked { bidi: bool, max: VarInt },
+
+    // Allows the peer to send up to `max` bytes in total on all of the session's streams.
+    WtMaxData { max: VarInt },
+
+    // Indicates that we wanted to send data but were blocked by the peer's `max`.
+    WtDataBlocked { max: VarInt },
+
+    // Any other capsule, which should be ignored.
+    Unknown { typ: VarInt, payload: Bytes },
+}
+
+impl Capsule {
+    const DATAGRAM: VarInt = VarInt::from_u32(0x00);
+    const CLOSE_WEBTRANSPORT_SESSION: VarInt = VarInt::from_u32(0x2843);
+    const DRAIN_WEBTRANSPORT_SESSION: VarInt = VarInt::from_u32(0x78ae);
+    const WT_RESET_STREAM: VarInt = VarInt::from_u32(0x190b4d39);
+    const WT_STOP_SENDING: VarInt = VarInt::from_u32(0x190b4d3a);
+    const WT_STREAM: VarInt = VarInt::from_u32(0x190b4d3b);
+    const WT_STREAM_FIN: VarInt = VarInt::from_u32(0x190b4d3c);
+    const WT_MAX_DATA: VarInt = VarInt::from_u32(0x190b4d3d);
+    const WT_MAX_STREAM_DATA: VarInt = VarInt::from_u32(0x190b4d3e);
+    const WT_MAX_STREAMS_BIDI: VarInt = VarInt::from_u32(0x190b4d3f);
+    const WT_MAX_STREAMS_UNI: VarInt = VarInt::from_u32(0x190b4d40);
+    const WT_DATA_BLOCKED: VarInt = VarInt::from_u32(0x190b4d41);
+    const WT_STREAMS_BLOCKED_BIDI: VarInt = VarInt::from_u32(0x190b4d43);
+    const WT_STREAMS_BLOCKED_UNI: VarInt = VarInt::from_u32(0x190b4d44);
+
+    // Create a close capsule, truncating the reason to the maximum size on a character boundary.
+    pub fn close(code: u32, reason: &str) -> Self {
+        let mut end = reason.len().min(MAX_CLOSE_REASON);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        Self::CloseWebTransportSession {
+            code,
+            reason: reason[..end].to_string(),
+        }
+    }
+
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Self, CapsuleError> {
+        let typ = VarInt::decode(buf).map_err(|_| CapsuleError::UnexpectedEnd)?;
+        let size = VarInt::decode(buf).map_err(|_| CapsuleError::UnexpectedEnd)?;
+        let size = size.into_inner() as usize;
+
+        if buf.remaining() < size {
+            return Err(CapsuleError::UnexpectedEnd);
+        }
+
+        let mut payload = buf.copy_to_bytes(size);
+
+        match typ {
+            Self::CLOSE_WEBTRANSPORT_SESSION => {
+                if payload.remaining() < 4 {
+                    return Err(CapsuleError::UnexpectedEnd);
+                }
+
+                let code = payload.get_u32();
+                if payload.len() > MAX_CLOSE_REASON {
+                    return Err(CapsuleError::ReasonTooLong);
+                }
+
+                let reason = std::str::from_utf8(&payload)?.to_string();
+                Ok(Self::CloseWebTransportSession { code, reason })
+            }
+            Self::DRAIN_WEBTRANSPORT_SESSION => match payload.is_empty() {
+                true => Ok(Self::DrainWebTransportSession),
+                false => Err(CapsuleError::InvalidPayload),
+            },
+            Self::DATAGRAM => Ok(Self::Datagram { payload }),
+            Self::WT_STREAM | Self::WT_STREAM_FIN => {
+                let id = Self::decode_varint(&mut payload)?;
+                let fin = typ == Self::WT_STREAM_FIN;
+                Ok(Self::WtStream {
+                    id,
+                    fin,
+                    data: payload,
+                })
+            }
+            Self::WT_RESET_STREAM => {
+                let id = Self::decode_varint(&mut payload)?;
+                let code = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtResetStream { id, code })
+            }
+            Self::WT_STOP_SENDING => {
+                let id = Self::decode_varint(&mut payload)?;
+                let code = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtStopSending { id, code })
+            }
+            Self::WT_MAX_STREAM_DATA => {
+                let id = Self::decode_varint(&mut payload)?;
+                let max = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtMaxStreamData { id, max })
+            }
+            Self::WT_MAX_STREAMS_BIDI | Self::WT_MAX_STREAMS_UNI => {
+                let bidi = typ == Self::WT_MAX_STREAMS_BIDI;
+                let max = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtMaxStreams { bidi, max })
+            }
+            Self::WT_STREAMS_BLOCKED_BIDI | Self::WT_STREAMS_BLOCKED_UNI => {
+                let bidi = typ == Self::WT_STREAMS_BLOCKED_BIDI;
+                let max = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtStreamsBlocked { bidi, max })
+            }
+            Self::WT_MAX_DATA => {
+                let max = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtMaxData { max })
+            }
+            Self::WT_DATA_BLOCKED => {
+                let max = Self::decode_varint(&mut payload)?;
+                Ok(Self::WtDataBlocked { max })
+            }
+            typ => Ok(Self::Unknown { typ, payload }),
+        }
+    }
+
+    // The payload was already read in full, so running out is invalid rather than incomplete.
+    fn decode_varint(payload: &mut Bytes) -> Result<VarInt, CapsuleError> {
+        VarInt::decode(payload).map_err(|_| CapsuleError::InvalidPayload)
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        match self {
+            Self::CloseWebTransportSession { code, reason } => {
+                Self::CLOSE_WEBTRANSPORT_SESSION.encode(buf);
+                VarInt::try_from(4 + reason.len()).unwrap().encode(buf);
+                buf.put_u32(*code);
+                buf.put_slice(reason.as_bytes());
+            }
+            Self::DrainWebTransportSession => {
+                Self::encode_header(buf, Self::DRAIN_WEBTRANSPORT_SESSION, 0);
+            }
+            Self::Datagram { payload } => {
+                Self::encode_header(buf, Self::DATAGRAM, payload.len());
+                buf.put_slice(payload);
+            }
+            Self::WtStream { id, fin, data } => {
+                let typ = match fin {
+                    true => Self::WT_STREAM_FIN,
+                    false => Self::WT_STREAM,
+                };
+
+                Self::encode_header(buf, typ, id.size() + data.len());
+                id.encode(buf);
+                buf.put_slice(data);
+            }
+            Self::WtResetStream { id, code } => {
+                Self::encode_header(buf, Self::WT_RESET_STREAM, id.size() + code.size());
+                id.encode(buf);
+                code.encode(buf);
+            }
+            Self::WtStopSending { id, code } => {
+                Self::encode_header(buf, Self::WT_STOP_SENDING, id.size() + code.size());
+                id.encode(buf);
+                code.encode(buf);
+            }
+            Self::WtMaxStreamData { id, max } => {
+                Self::encode_header(buf, Self::WT_MAX_STREAM_DATA, id.size() + max.size());
+                id.encode(buf);
+                max.encode(buf);
+            }
+            Self::WtMaxStreams { bidi, max } => {
+                let typ = match bidi {
+                    true => Self::WT_MAX_STREAMS_BIDI,
+                    false => Self::WT_MAX_STREAMS_UNI,
+                };
+
+                Self::encode_header(buf, typ, max.size());
+                max.encode(buf);
+            }
+            Self::WtStreamsBlocked { bidi, max } => {
+                let typ = match bidi {
+                    true => Self::WT_STREAMS_BLOCKED_BIDI,
+                    false => Self::WT_STREAMS_BLOCKED_UNI,
+                };
+
+                Self::encode_header(buf, typ, max.size());
+                max.encode(buf);
+            }
+            Self::WtMaxData { max } => {
+                Self::encode_header(buf, Self::WT_MAX_DATA, max.size());
+                max.encode(buf);
+            }
+            Self::WtDataBlocked { max } => {
+                Self::encode_header(buf, Self::WT_DATA_BLOCKED, max.size());
+                max.encode(buf);
+            }
+            Self::Unknown { typ, payload } => {
+                typ.encode(buf);
+                VarInt::try_from(payload.len()).unwrap().encode(buf);
+                buf.put_slice(payload);
+            }
+        }
+    }
+
+    // Encode the capsule in an HTTP/3 DATA frame, which is how capsules are sent on the CONNECT stream, see RFC 9297 section 3.2.
+    pub fn encode_frame<B: BufMut>(&self, buf: &mut B) {
+        let mut payload = Vec::new();
+        self.encode(&mut payload);
+
+        Frame::DATA.encode(buf);
+        VarInt::try_from(payload.len()).unwrap().encode(buf);
+        buf.put_slice(&payload);
+    }
+
+    // The largest payload we buffer for a capsule of the given type, or None if it should be skipped without buffering.
+    // These are the capsules used by the HTTP/3 backend, see CapsuleReader.
+    fn max_payload(typ: VarInt) -> Option<usize> {
+        match typ {
+            Self::CLOSE_WEBTRANSPORT_SESSION => Some(4 + MAX_CLOSE_REASON),
+            Self::DRAIN_WEBTRANSPORT_SESSION
+            | Self::WT_MAX_DATA
+            | Self::WT_MAX_STREAMS_BIDI
+            | Self::WT_MAX_STREAMS_UNI
+            | Self::WT_DATA_BLOCKED
+            | Self::WT_STREAMS_BLOCKED_BIDI
+            | Self::WT_STREAMS_BLOCKED_UNI => Some(MAX_CONTROL_CAPSULE),
+            _ => None,
+        }
+    }
+
+    fn encode_header<B: BufMut>(buf: &mut B, typ: VarInt, size: usize) {
+        typ.encode(buf);
+        VarInt::try_from(size).unwrap().encode(buf);
+    }
+}
+
+// Decodes the capsules sent on the CONNECT stream of an HTTP/3 session, which are carried by DATA frames.
+//
+// The stream is fed in arbitrary chunks, so frames and capsules can be split anywhere.
+// Other frames are skipped, as are capsules the HTTP/3 backend doesn't use (ex. DATAGRAM), without buffering their payload.
+// Only the capsules we use are buffered, up to a small maximum, so the peer can't make us buffer an arbitrary amount.
+#[derive(Debug, Default)]
+pub struct CapsuleReader {
+    // The header of the current frame, until it's complete.
+    frame_header: Vec<u8>,
+
+    // The remaining size of the current frame, and whether it's a DATA frame.
+    frame: Option<(usize, bool)>,
+
+    // The header and payload of the current capsule.
+    capsule: Vec<u8>,
+
+    // The size of the current capsule once its header is known, including the header.
+    capsule_size: Option<usize>,
+
+    // The remaining payload of a capsule that's being skipped.
+    skip: usize,
+}
+
+impl CapsuleReader {
+    // Decode the capsules in the next chunk of the stream, appending them to the output.
+    // An error means the session should be closed, and the reader shouldn't be used again.
+    pub fn decode(&mut self, mut chunk: &[u8], out: &mut Vec<Capsule>) -> Result<(), CapsuleError> {
+        while !chunk.is_empty() {
+            let (remaining, data) = match self.frame {
+                Some(frame) => frame,
+                None => {
+                    // Frame headers are tiny, so add one byte at a time until it decodes.
+                    self.frame_header.push(chunk[0]);
+                    chunk = &chunk[1..];
+
+                    let mut cursor = std::io::Cursor::new(self.frame_header.as_slice());
+                    let header = Frame::decode(&mut cursor)
+                        .and_then(|typ| Ok((typ, VarInt::decode(&mut cursor)?)));
+
+                    match header {
+                        Ok((typ, size)) => {
+                            self.frame_header.clear();
+                            self.frame = Some((size.into_inner() as usize, typ == Frame::DATA));
+                        }
+                        Err(_) if self.frame_header.len() < MAX_HEADER => {}
+                        Err(_) => return Err(CapsuleError::InvalidPayload),
+                    }
+
+                    continue;
+                }
+            };
+
+            let size = remaining.min(chunk.len());
+            let (payload, rest) = chunk.split_at(size);
+            chunk = rest;
+
+            self.frame = match remaining - size {
+                0 => None,
+                remaining => Some((remaining, data)),
+            };
+
+            // Capsules can span DATA frames, and other frames (ex. unknown or reserved types) are ignored.
+            if data {
+                self.decode_data(payload, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Decode the capsules in the payload of a DATA frame.
+    fn decode_data(&mut self, mut data: &[u8], out: &mut Vec<Capsule>) -> Result<(), CapsuleError> {
+        loop {
+            if self.capsule_size == Some(self.capsule.len()) {
+                out.push(Capsule::decode(&mut self.capsule.as_slice())?);
+                self.capsule.clear();
+                self.capsule_size = None;
+            }
+
+            if data.is_empty() {
+                return Ok(());
+            }
+
+            if self.skip > 0 {
+                let size = self.skip.min(data.len());
+                self.skip -= size;
+                data = &data[size..];
+                continue;
+            }
+
+            let size = match self.capsule_size {
+                Some(size) => size,
+                None => {
+                    self.capsule.push(data[0]);
+                    data = &data[1..];
+
+                    let mut cursor = std::io::Cursor::new(self.capsule.as_slice());
+                    let header = VarInt::decode(&mut cursor)
+                        .and_then(|typ| Ok((typ, VarInt::decode(&mut cursor)?)));
+
+                    let (typ, size) = match header {
+                        Ok(header) => header,
+                        Err(_) if self.capsule.len() < MAX_HEADER => continue,
+                        Err(_) => return Err(CapsuleError::InvalidPayload),
+                    };
+
+                    let size = size.into_inner() as usize;
+                    match Capsule::max_payload(typ) {
+                        None => {
+                            self.capsule.clear();
+                            self.skip = size;
+                        }
+                        Some(max) if size > max => return Err(CapsuleError::TooLarge),
+                        Some(_) => self.capsule_size = Some(self.capsule.len() + size),
+                    }
+
+                    continue;
+                }
+            };
+
+            let needed = (size - self.capsule.len()).min(data.len());
+            self.capsule.extend_from_slice(&data[..needed]);
+            data = &data[needed..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(reader: &mut CapsuleReader, chunk: &[u8]) -> Vec<Capsule> {
+        let mut out = Vec::new();
+        reader.decode(chunk, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn framed_capsules() {
+        let mut buf = Vec::new();
+        Capsule::DrainWebTransportSession.encode_frame(&mut buf);
+        Capsule::close(7, "bye").encode_frame(&mut buf);
+
+        // Each capsule is in its own DATA frame.
+        assert_eq!(buf[0], 0x00);
+
+        let mut reader = CapsuleReader::default();
+        let capsules = decode_all(&mut reader, &buf);
+        assert_eq!(
+            capsules,
+            vec![Capsule::DrainWebTransportSession, Capsule::close(7, "bye")]
+        );
+    }
+
+    #[test]
+    fn split_anywhere() {
+        let mut buf = Vec::new();
+        Capsule::WtMaxData {
+            max: VarInt::from_u32(1 << 20),
+        }
+        .encode_frame(&mut buf);
+        Capsule::close(1, "done").encode_frame(&mut buf);
+
+        let mut reader = CapsuleReader::default();
+        let mut capsules = Vec::new();
+        for byte in &buf {
+            capsules.extend(decode_all(&mut reader, std::slice::from_ref(byte)));
+        }
+
+        assert_eq!(
+            capsules,
+            vec![
+                Capsule::WtMaxData {
+                    max: VarInt::from_u32(1 << 20)
+                },
+                Capsule::close(1, "done")
+            ]
+        );
+    }
+
+    #[test]
+    fn capsule_spans_frames() {
+        let mut capsule = Vec::new();
+        Capsule::close(3, "split across frames").encode(&mut capsule);
+
+        let mut buf = Vec::new();
+        let (first, second) = capsule.split_at(5);
+        crate::encode_data(first, &mut buf);
+
+        // An unknown frame between them is skipped.
+        VarInt::from_u32(0x21).encode(&mut buf);
+        VarInt::from_u32(3).encode(&mut buf);
+        buf.extend_from_slice(b"abc");
+
+        crate::encode_data(second, &mut buf);
+
+        let mut reader = CapsuleReader::default();
+        assert_eq!(
+            decode_all(&mut reader, &buf),
+            vec![Capsule::close(3, "split across frames")]
+        );
+    }
+
+    #[test]
+    fn unframed_is_not_a_capsule() {
+        // A capsule sent without a DATA frame is parsed as an unknown frame and skipped.
+        let mut buf = Vec::new();
+        Capsule::close(9, "").encode(&mut buf);
+
+        let mut reader = CapsuleReader::default();
+        assert_eq!(decode_all(&mut reader, &buf), vec![]);
+    }
+
+    #[test]
+    fn oversized_capsule() {
+        // Only the headers are sent, claiming a close reason far larger than allowed.
+        let mut capsule = Vec::new();
+        Capsule::CLOSE_WEBTRANSPORT_SESSION.encode(&mut capsule);
+        VarInt::from_u32(1 << 30).encode(&mut capsule);
+
+        let mut buf = Vec::new();
+        Frame::DATA.encode(&mut buf);
+        VarInt::from_u32(1 << 30).encode(&mut buf);
+        buf.extend_from_slice(&capsule);
+
+        let mut reader = CapsuleReader::default();
+        let res = reader.decode(&buf, &mut Vec::new());
+        assert!(matches!(res, Err(CapsuleError::TooLarge)));
+    }
+
+    #[test]
+    fn longest_reason() {
+        let reason = "x".repeat(MAX_CLOSE_REASON);
+
+        let mut buf = Vec::new();
+        Capsule::close(5, &reason).encode_frame(&mut buf);
+
+        let mut reader = CapsuleReader::default();
+        assert_eq!(
+            decode_all(&mut reader, &buf),
+            vec![Capsule::close(5, &reason)]
+        );
+    }
+
+    #[test]
+    fn skip_unused_capsules() {
+        let payload = Bytes::from(vec![0; 64 * 1024]);
+
+        let mut buf = Vec::new();
+        Capsule::Datagram {
+            payload: payload.clone(),
+        }
+        .encode_frame(&mut buf);
+        Capsule::Unknown {
+            typ: VarInt::from_u32(0x17),
+            payload,
+        }
+        .encode_frame(&mut buf);
+        Capsule::DrainWebTransportSession.encode_frame(&mut buf);
+
+        let mut reader = CapsuleReader::default();
+        let mut capsules = Vec::new();
+        for chunk in buf.chunks(1000) {
+            capsules.extend(decode_all(&mut reader, chunk));
+            assert!(reader.capsule.len() <= MAX_HEADER);
+        }
+
+        assert_eq!(capsules, vec![Capsule::DrainWebTransportSession]);
+    }
+}
@@ -2,7 +2,10 @@ use std::str::FromStr;
 
 use bytes::{Buf, BufMut};
 
-use super::{qpack, Frame, VarInt};
+use super::{
+    message::{encode_headers, from_header_map, to_header_map},
+    qpack, Draft, Frame, HeaderDump, VarInt,
+};
 
 use thiserror::Error;
 
@@ -12,8 +15,8 @@ pub enum ConnectError {
     #[error("unexpected end of input")]
     UnexpectedEnd,
 
-    #[error("qpack error")]
-    QpackError(#[from] qpack::DecodeError),
+    #[error("qpack error: {0}")]
+    QpackError(#[from] qpack::HeadersError),
 
     #[error("unexpected frame {0:?}")]
     UnexpectedFrame(Frame),
@@ -30,6 +33,12 @@ pub enum ConnectError {
     #[error("invalid status")]
     InvalidStatus(#[from] http::status::InvalidStatusCode),
 
+    #[error("invalid header name")]
+    InvalidHeaderName(#[from] http::header::InvalidHeaderName),
+
+    #[error("invalid header value")]
+    InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
+
     #[error("expected 200, got: {0:?}")]
     WrongStatus(Option<http::StatusCode>),
 
@@ -47,11 +56,27 @@ pub enum ConnectError {
 
     #[error("non-200 status: {0:?}")]
     ErrorStatus(http::StatusCode),
+
+    #[error("field section of {size} bytes exceeds the limit of {max}")]
+    HeadersTooLarge { size: u64, max: u64 },
+}
+
+impl ConnectError {
+    // Return a dump of the header block that failed to decode, if the `debug` feature is enabled.
+    pub fn header_dump(&self) -> Option<&HeaderDump> {
+        match self {
+            Self::QpackError(err) => err.dump.as_deref(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ConnectRequest {
     pub uri: http::Uri,
+
+    // Every header other than the pseudo-headers (ex. origin, authorization).
+    pub headers: http::HeaderMap,
 }
 
 impl ConnectRequest {
@@ -110,11 +135,22 @@ impl ConnectRequest {
             return Err(ConnectError::WrongAuthority);
         }
 
-        Ok(Self { uri })
+        let headers = to_header_map(&headers)?;
+
+        Ok(Self { uri, headers })
     }
 
     pub fn encode<B: BufMut>(&self, buf: &mut B) {
-        let mut headers = qpack::Headers::default();
+        encode_headers(&self.headers(), buf);
+    }
+
+    // Encode the request, unless its field section exceeds the peer's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    pub fn encode_max<B: BufMut>(&self, buf: &mut B, max: u64) -> Result<(), ConnectError> {
+        encode_headers_max(&self.headers(), buf, max)
+    }
+
+    fn headers(&self) -> qpack::Headers {
+        let mut headers = from_header_map(&self.headers);
         headers.set(":method", "CONNECT");
 
         if let Some(scheme) = self.uri.scheme() {
@@ -125,23 +161,24 @@ impl ConnectRequest {
             headers.set(":authority", host.as_str());
         }
 
-        headers.set(":path", self.uri.path());
+        // Include the query, which is part of the request target.
+        let path = self.uri.path_and_query().map_or("/", |path| path.as_str());
+        headers.set(":path", path);
         headers.set(":protocol", "webtransport");
 
-        // Use a temporary buffer so we can compute the size.
-        let mut tmp = Vec::new();
-        headers.encode(&mut tmp);
-        let size = VarInt::from_u32(tmp.len() as u32);
-
-        Frame::HEADERS.encode(buf);
-        size.encode(buf);
-        buf.put_slice(&tmp);
+        headers
     }
 }
 
 #[derive(Debug)]
 pub struct ConnectResponse {
     pub status: http::status::StatusCode,
+
+    // Every header other than the pseudo-headers (ex. retry-after, www-authenticate).
+    pub headers: http::HeaderMap,
+
+    // The draft used by the peer, which determines the headers we send.
+    pub draft: Draft,
 }
 
 impl ConnectResponse {
@@ -160,31 +197,62 @@ impl ConnectResponse {
 
         let headers = qpack::Headers::decode(&mut limit)?;
 
-        let status = match headers
+        // Any status is returned, so the caller can see the headers of a redirect or error.
+        let status = headers
             .get(":status")
             .map(http::StatusCode::from_str)
             .transpose()?
-        {
-            Some(status) if status.is_success() => status,
-            o => return Err(ConnectError::WrongStatus(o)),
+            .ok_or(ConnectError::WrongStatus(None))?;
+
+        // The server only tells us the draft when it's draft02.
+        let draft = match headers.get("sec-webtransport-http3-draft") {
+            Some("draft02") => Draft::Draft02,
+            _ => Draft::Draft07,
         };
 
-        Ok(Self { status })
+        let headers = to_header_map(&headers)?;
+
+        Ok(Self {
+            status,
+            headers,
+            draft,
+        })
     }
 
     pub fn encode<B: BufMut>(&self, buf: &mut B) {
-        let mut headers = qpack::Headers::default();
+        encode_headers(&self.headers(), buf);
+    }
+
+    // Encode the response, unless its field section exceeds the peer's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    pub fn encode_max<B: BufMut>(&self, buf: &mut B, max: u64) -> Result<(), ConnectError> {
+        encode_headers_max(&self.headers(), buf, max)
+    }
+
+    fn headers(&self) -> qpack::Headers {
+        let mut headers = from_header_map(&self.headers);
         headers.set(":status", self.status.as_str());
         headers.set(":protocol", "webtransport");
-        headers.set("sec-webtransport-http3-draft", "draft02");
 
-        // Use a temporary buffer so we can compute the size.
-        let mut tmp = Vec::new();
-        headers.encode(&mut tmp);
-        let size = VarInt::from_u32(tmp.len() as u32);
+        // Chrome refuses the session unless we confirm the draft.
+        if self.draft == Draft::Draft02 {
+            headers.set("sec-webtransport-http3-draft", "draft02");
+        }
 
-        Frame::HEADERS.encode(buf);
-        size.encode(buf);
-        buf.put_slice(&tmp);
+        headers
     }
 }
+
+// Encode the headers as a HEADERS frame, refusing if the field section is larger than the peer allows.
+fn encode_headers_max<B: BufMut>(
+    headers: &qpack::Headers,
+    buf: &mut B,
+    max: u64,
+) -> Result<(), ConnectError> {
+    let size = headers.size();
+    if size > max {
+        return Err(ConnectError::HeadersTooLarge { size, max });
+    }
+
+    encode_headers(headers, buf);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use bytes::{BufMut, Bytes};
+
+use super::{VarInt, VarIntUnexpectedEnd};
+
+// An HTTP/3 datagram, prefixed with the quarter stream ID of the associated request, see RFC 9297.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+    pub quarter_stream_id: VarInt,
+    pub payload: Bytes,
+}
+
+impl Datagram {
+    // The quarter stream ID is the stream ID of the request divided by 4, since it's always a client bidirectional stream.
+    pub fn quarter_stream_id(stream_id: VarInt) -> VarInt {
+        VarInt::try_from(stream_id.into_inner() / 4).unwrap()
+    }
+
+    // Decode a datagram without copying the payload.
+    pub fn decode(mut buf: Bytes) -> Result<Self, VarIntUnexpectedEnd> {
+        let quarter_stream_id = VarInt::decode(&mut buf)?;
+
+        Ok(Self {
+            quarter_stream_id,
+            payload: buf,
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        self.quarter_stream_id.encode(buf);
+        buf.put_slice(&self.payload);
+    }
+}
@@ -7,9 +7,13 @@ pub fn error_from_http3(code: u64) -> Option<u32> {
         return None;
     }
 
+    // Every 0x1f codepoint is reserved for greasing, so skip over them.
     let code = code - ERROR_FIRST;
-    let code = code / 0x1f;
+    if code % 0x1f == 0x1e {
+        return None;
+    }
 
+    let code = code - code / 0x1f;
     Some(code.try_into().unwrap())
 }
 
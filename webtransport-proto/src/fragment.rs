@@ -0,0 +1,42 @@
+use bytes::{BufMut, Bytes};
+
+use super::{VarInt, VarIntUnexpectedEnd};
+
+// A piece of a message that's too large for a single datagram.
+// This isn't part of any standard, so both endpoints need to use it: each fragment is prefixed with
+// the message ID, the index of the fragment, and the number of fragments in the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub message: VarInt,
+    pub index: VarInt,
+    pub count: VarInt,
+    pub payload: Bytes,
+}
+
+impl Fragment {
+    // Decode a fragment without copying the payload.
+    pub fn decode(mut buf: Bytes) -> Result<Self, VarIntUnexpectedEnd> {
+        let message = VarInt::decode(&mut buf)?;
+        let index = VarInt::decode(&mut buf)?;
+        let count = VarInt::decode(&mut buf)?;
+
+        Ok(Self {
+            message,
+            index,
+            count,
+            payload: buf,
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        self.message.encode(buf);
+        self.index.encode(buf);
+        self.count.encode(buf);
+        buf.put_slice(&self.payload);
+    }
+
+    // The size of everything before the payload.
+    pub fn header_size(&self) -> usize {
+        self.message.size() + self.index.size() + self.count.size()
+    }
+}
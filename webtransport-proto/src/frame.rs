@@ -28,5 +28,6 @@ frames! {
     DATA = 0x00,
     HEADERS = 0x01,
     SETTINGS = 0x04,
+    GOAWAY = 0x07,
     WEBTRANSPORT = 0x41,
 }
@@ -0,0 +1,43 @@
+use bytes::{Buf, BufMut};
+
+use super::{Frame, SettingsError, VarInt};
+
+// Sent on the control stream after SETTINGS to initiate a graceful shutdown, see RFC 9114 section 5.2.
+// The server sends the ID of the first request stream it won't process, so the client can safely retry those elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoAway {
+    pub id: VarInt,
+}
+
+impl GoAway {
+    // Decode the next frame on the control stream, returning None if it was some other frame that should be ignored.
+    // The frame is only consumed once it's complete, so retry with more data on UnexpectedEnd.
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Option<Self>, SettingsError> {
+        let typ = Frame::decode(buf).map_err(|_| SettingsError::UnexpectedEnd)?;
+        let size = VarInt::decode(buf).map_err(|_| SettingsError::UnexpectedEnd)?;
+
+        let mut limit = bytes::Buf::take(buf, size.into_inner() as usize);
+        if limit.remaining() < limit.limit() {
+            return Err(SettingsError::UnexpectedEnd);
+        }
+
+        if typ != Frame::GOAWAY {
+            limit.advance(limit.limit());
+            return Ok(None);
+        }
+
+        // This returns a different error because retrying won't help.
+        let id = VarInt::decode(&mut limit).map_err(|_| SettingsError::InvalidSize)?;
+        if limit.has_remaining() {
+            return Err(SettingsError::InvalidSize);
+        }
+
+        Ok(Some(Self { id }))
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        Frame::GOAWAY.encode(buf);
+        VarInt::try_from(self.id.size()).unwrap().encode(buf);
+        self.id.encode(buf);
+    }
+}
@@ -208,7 +208,7 @@ macro_rules! bits_decode {
         }
     };
     [ $( $name:ident => ( $($value:tt)* ), )* ] => {
-        $( const $name: HuffmanDecoder = bits_decode!( $( $value )* ); )*
+        $( #[allow(clippy::char_lit_as_u8)] const $name: HuffmanDecoder = bits_decode!( $( $value )* ); )*
     };
 }
 
@@ -351,11 +351,11 @@ impl<'a> Iterator for DecodeIter<'a> {
 }
 
 pub trait HpackStringDecode {
-    fn hpack_decode(&self) -> DecodeIter;
+    fn hpack_decode(&self) -> DecodeIter<'_>;
 }
 
 impl HpackStringDecode for Vec<u8> {
-    fn hpack_decode(&self) -> DecodeIter {
+    fn hpack_decode(&self) -> DecodeIter<'_> {
         DecodeIter {
             bit_pos: BitWindow::new(),
             content: self,
@@ -1,16 +1,32 @@
+mod capsule;
 mod connect;
+mod datagram;
 mod error;
+mod fragment;
 mod frame;
+mod goaway;
+mod message;
+mod parity;
+mod replay;
 mod settings;
 mod stream;
 mod varint;
 
+pub use capsule::*;
 pub use connect::*;
+pub use datagram::*;
 pub use error::*;
+pub use fragment::*;
 pub use frame::*;
+pub use goaway::*;
+pub use message::*;
+pub use parity::*;
+pub use replay::*;
 pub use settings::*;
 pub use stream::*;
 pub use varint::*;
 
 mod huffman;
 mod qpack;
+
+pub use qpack::HeaderDump;
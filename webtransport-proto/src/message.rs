@@ -0,0 +1,185 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{qpack, ConnectError, Frame, VarInt};
+
+// A plain HTTP/3 request, used for anything that isn't a WebTransport CONNECT.
+// The body is carried separately in DATA frames, see `encode_data` and `decode_data`.
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: http::Method,
+    pub uri: http::Uri,
+    pub headers: http::HeaderMap,
+}
+
+impl HttpRequest {
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Self, ConnectError> {
+        let headers = decode_headers(buf)?;
+
+        let method = headers
+            .get(":method")
+            .ok_or(ConnectError::WrongMethod(None))?
+            .try_into()?;
+
+        let mut parts = http::uri::Parts::default();
+        parts.scheme = headers
+            .get(":scheme")
+            .map(|scheme| scheme.try_into())
+            .transpose()?;
+        parts.authority = headers
+            .get(":authority")
+            .map(|auth| auth.try_into())
+            .transpose()?;
+        parts.path_and_query = headers
+            .get(":path")
+            .map(|path| path.try_into())
+            .transpose()?;
+        let uri = http::Uri::from_parts(parts)?;
+
+        Ok(Self {
+            method,
+            uri,
+            headers: to_header_map(&headers)?,
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        let mut headers = from_header_map(&self.headers);
+        headers.set(":method", self.method.as_str());
+
+        if let Some(scheme) = self.uri.scheme() {
+            headers.set(":scheme", scheme.as_str());
+        }
+
+        if let Some(host) = self.uri.authority() {
+            headers.set(":authority", host.as_str());
+        }
+
+        let path = self
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        headers.set(":path", path);
+
+        encode_headers(&headers, buf);
+    }
+}
+
+// The response to a plain HTTP/3 request.
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: http::StatusCode,
+    pub headers: http::HeaderMap,
+}
+
+impl HttpResponse {
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Self, ConnectError> {
+        let headers = decode_headers(buf)?;
+
+        let status = headers
+            .get(":status")
+            .ok_or(ConnectError::WrongStatus(None))?
+            .parse()?;
+
+        Ok(Self {
+            status,
+            headers: to_header_map(&headers)?,
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        let mut headers = from_header_map(&self.headers);
+        headers.set(":status", self.status.as_str());
+
+        encode_headers(&headers, buf);
+    }
+}
+
+// Encode a DATA frame containing the payload.
+pub fn encode_data<B: BufMut>(payload: &[u8], buf: &mut B) {
+    Frame::DATA.encode(buf);
+    VarInt::try_from(payload.len()).unwrap().encode(buf);
+    buf.put_slice(payload);
+}
+
+// Decode the DATA frames until the end of the buffer, concatenating their payloads.
+// This should be called with the remainder of the stream after the HEADERS frame.
+// Unknown frames are skipped and trailers are ignored.
+pub fn decode_data<B: Buf>(buf: &mut B) -> Result<Bytes, ConnectError> {
+    let mut body = BytesMut::new();
+
+    while buf.has_remaining() {
+        let typ = Frame::decode(buf).map_err(|_| ConnectError::UnexpectedEnd)?;
+        let size = VarInt::decode(buf).map_err(|_| ConnectError::UnexpectedEnd)?;
+        let size = size.into_inner() as usize;
+
+        if buf.remaining() < size {
+            return Err(ConnectError::UnexpectedEnd);
+        }
+
+        let payload = buf.copy_to_bytes(size);
+        if typ == Frame::DATA {
+            body.extend_from_slice(&payload);
+        }
+    }
+
+    Ok(body.freeze())
+}
+
+fn decode_headers<B: Buf>(buf: &mut B) -> Result<qpack::Headers, ConnectError> {
+    let typ = Frame::decode(buf).map_err(|_| ConnectError::UnexpectedEnd)?;
+    if typ != Frame::HEADERS {
+        return Err(ConnectError::UnexpectedFrame(typ));
+    }
+
+    let size = VarInt::decode(buf).map_err(|_| ConnectError::UnexpectedEnd)?;
+    let mut limit = Buf::take(buf, size.into_inner() as usize);
+    if limit.limit() > limit.remaining() {
+        // Not enough data in the buffer
+        return Err(ConnectError::UnexpectedEnd);
+    }
+
+    Ok(qpack::Headers::decode(&mut limit)?)
+}
+
+pub(crate) fn encode_headers<B: BufMut>(headers: &qpack::Headers, buf: &mut B) {
+    // Use a temporary buffer so we can compute the size.
+    let mut tmp = Vec::new();
+    headers.encode(&mut tmp);
+    let size = VarInt::from_u32(tmp.len() as u32);
+
+    Frame::HEADERS.encode(buf);
+    size.encode(buf);
+    buf.put_slice(&tmp);
+}
+
+// Convert everything except the pseudo-headers into a HeaderMap.
+pub(crate) fn to_header_map(headers: &qpack::Headers) -> Result<http::HeaderMap, ConnectError> {
+    let mut map = http::HeaderMap::new();
+
+    for (name, value) in headers.iter() {
+        if name.starts_with(':') {
+            continue;
+        }
+
+        let name = http::header::HeaderName::from_bytes(name.as_bytes())?;
+        let value = http::header::HeaderValue::from_str(value)?;
+        map.append(name, value);
+    }
+
+    Ok(map)
+}
+
+// Our QPACK implementation only supports a single UTF-8 value per header.
+// Only the last value of a repeated header is kept, and non UTF-8 values are skipped.
+pub(crate) fn from_header_map(map: &http::HeaderMap) -> qpack::Headers {
+    let mut headers = qpack::Headers::default();
+
+    for (name, value) in map {
+        if let Ok(value) = value.to_str() {
+            headers.set(name.as_str(), value);
+        }
+    }
+
+    headers
+}
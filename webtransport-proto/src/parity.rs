@@ -0,0 +1,46 @@
+use bytes::{BufMut, Bytes};
+
+use super::{VarInt, VarIntUnexpectedEnd};
+
+// A datagram protected by webtransport-quinn's XOR parity, which isn't part of any standard.
+// Each group of `count` data shards (index < count) is followed by a parity shard (index == count)
+// containing the XOR of every data shard's length (u16) and payload, so a single loss can be recovered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParityShard {
+    pub group: VarInt,
+    pub index: VarInt,
+    pub count: VarInt,
+    pub payload: Bytes,
+}
+
+impl ParityShard {
+    // Decode a shard without copying the payload.
+    pub fn decode(mut buf: Bytes) -> Result<Self, VarIntUnexpectedEnd> {
+        let group = VarInt::decode(&mut buf)?;
+        let index = VarInt::decode(&mut buf)?;
+        let count = VarInt::decode(&mut buf)?;
+
+        Ok(Self {
+            group,
+            index,
+            count,
+            payload: buf,
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        self.group.encode(buf);
+        self.index.encode(buf);
+        self.count.encode(buf);
+        buf.put_slice(&self.payload);
+    }
+
+    pub fn is_parity(&self) -> bool {
+        self.index == self.count
+    }
+
+    // The size of everything before the payload.
+    pub fn header_size(&self) -> usize {
+        self.group.size() + self.index.size() + self.count.size()
+    }
+}
@@ -30,6 +30,93 @@ pub enum DecodeError {
     Utf8Error(#[from] std::str::Utf8Error),
 }
 
+// A decode error, with a dump of the header block when the `debug` feature is enabled.
+#[derive(Debug)]
+pub struct HeadersError {
+    pub error: DecodeError,
+    pub dump: Option<Box<HeaderDump>>,
+}
+
+impl std::error::Error for HeadersError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl std::fmt::Display for HeadersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+
+        if let Some(dump) = &self.dump {
+            write!(f, " ({})", dump)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<DecodeError> for HeadersError {
+    fn from(error: DecodeError) -> Self {
+        Self { error, dump: None }
+    }
+}
+
+// The maximum number of bytes of the header block included in a dump.
+#[cfg(feature = "debug")]
+const MAX_SNIPPET: usize = 64;
+
+// The state of the decoder when a header block failed to decode, used to diagnose misbehaving clients.
+// Only captured when the `debug` feature is enabled, since it copies the header block before decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderDump {
+    // The size of the header block.
+    pub size: usize,
+
+    // The number of bytes consumed when decoding failed.
+    pub offset: usize,
+
+    // The position of the snippet within the header block.
+    pub snippet_offset: usize,
+
+    // Up to 64 bytes of the header block around the offset, hex encoded.
+    pub snippet: String,
+
+    // The names of the fields that were decoded before the failure, in order.
+    pub fields: Vec<String>,
+}
+
+#[cfg(feature = "debug")]
+impl HeaderDump {
+    fn new(block: &[u8], size: usize, offset: usize, fields: Vec<String>) -> Self {
+        // Show a little context before the failure, but mostly what follows it.
+        let start = offset.saturating_sub(MAX_SNIPPET / 4).min(block.len());
+        let end = (start + MAX_SNIPPET).min(block.len());
+
+        let snippet = block[start..end]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Self {
+            size,
+            offset,
+            snippet_offset: start,
+            snippet,
+            fields,
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at byte {} of {}, after {:?}, bytes {}..: {}",
+            self.offset, self.size, self.fields, self.snippet_offset, self.snippet
+        )
+    }
+}
+
 #[cfg(target_pointer_width = "64")]
 const MAX_POWER: usize = 10 * 7;
 
@@ -51,7 +138,50 @@ impl Headers {
         self.fields.insert(name.to_string(), value.to_string());
     }
 
-    pub fn decode<B: Buf>(mut buf: &mut B) -> Result<Self, DecodeError> {
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    // The uncompressed size of the field section, as limited by SETTINGS_MAX_FIELD_SECTION_SIZE.
+    // Each field counts its name and value plus 32 bytes of overhead, see RFC 9114 section 4.2.2.
+    pub fn size(&self) -> u64 {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.len() + value.len() + 32) as u64)
+            .sum()
+    }
+
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Self, HeadersError> {
+        // Copy the header block up front, since the decoder consumes it.
+        #[cfg(feature = "debug")]
+        let (block, size) = (buf.chunk().to_vec(), buf.remaining());
+
+        let mut names = Vec::new();
+
+        match Self::decode_fields(buf, &mut names) {
+            Ok(fields) => Ok(Self { fields }),
+            Err(error) => {
+                #[cfg(feature = "debug")]
+                let dump = Some(Box::new(HeaderDump::new(
+                    &block,
+                    size,
+                    size - buf.remaining(),
+                    names,
+                )));
+
+                #[cfg(not(feature = "debug"))]
+                let dump = None;
+
+                Err(HeadersError { error, dump })
+            }
+        }
+    }
+
+    // Decode the fields, recording the name of each one as it's decoded.
+    fn decode_fields<B: Buf>(
+        mut buf: &mut B,
+        names: &mut Vec<String>,
+    ) -> Result<HashMap<String, String>, DecodeError> {
         // We don't support dynamic entries so we can skip these.
         let (_, _insert_count) = decode_prefix(buf, 8)?;
         let (_sign, _delta_base) = decode_prefix(buf, 7)?;
@@ -97,13 +227,14 @@ impl Headers {
                 },
             };
 
+            names.push(name.clone());
             fields.insert(name, value);
 
             // Get the buffer back.
             (_, buf) = chain.into_inner();
         }
 
-        Ok(Self { fields })
+        Ok(fields)
     }
 
     fn decode_index<B: Buf>(buf: &mut B) -> Result<(String, String), DecodeError> {
@@ -0,0 +1,229 @@
+use std::io::Cursor;
+
+use thiserror::Error;
+
+use super::{
+    Capsule, CapsuleError, ConnectError, ConnectRequest, ConnectResponse, Frame, GoAway, Settings,
+    SettingsError,
+};
+
+// Replays a captured byte sequence through the decoders, so a real-world failure can be turned into a regression test.
+// Feed the bytes with `push`, using the same chunks as the capture to reproduce bugs around partial reads,
+// then call `step` until it returns None to decode each message and inspect the state in between.
+
+// The stream a capture came from, which determines the messages expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayKind {
+    // A control stream: the stream type and SETTINGS, followed by any other frames (ex. GOAWAY).
+    Control,
+
+    // The client side of a CONNECT stream: the request headers, followed by capsules.
+    Request,
+
+    // The server side of a CONNECT stream: the response headers, followed by capsules.
+    Response,
+
+    // Only capsules, for a capture that starts after the headers.
+    Capsules,
+}
+
+// What the replay expects to decode next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayState {
+    // The SETTINGS frame at the start of a control stream.
+    Settings,
+
+    // Any frame on the control stream after SETTINGS.
+    Frames,
+
+    // The HEADERS frame at the start of a CONNECT stream.
+    Headers,
+
+    // A capsule on the CONNECT stream.
+    Capsules,
+
+    // Nothing, because the session was closed by a CLOSE_WEBTRANSPORT_SESSION capsule.
+    Closed,
+}
+
+// A message decoded from the capture.
+#[derive(Debug)]
+pub enum ReplayEvent {
+    Settings(Settings),
+    GoAway(GoAway),
+
+    // A frame on the control stream that we ignore.
+    Frame(Frame),
+
+    Request(ConnectRequest),
+    Response(ConnectResponse),
+    Capsule(Capsule),
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("settings error at offset {offset}: {error}")]
+    Settings { offset: u64, error: SettingsError },
+
+    #[error("connect error at offset {offset}: {error}")]
+    Connect { offset: u64, error: ConnectError },
+
+    #[error("capsule error at offset {offset}: {error}")]
+    Capsule { offset: u64, error: CapsuleError },
+
+    #[error("data after the session was closed at offset {offset}")]
+    AfterClose { offset: u64 },
+
+    #[error("capture ended with {remaining} bytes of an incomplete message at offset {offset}")]
+    Truncated { offset: u64, remaining: usize },
+}
+
+pub struct Replay {
+    kind: ReplayKind,
+    state: ReplayState,
+
+    // Data that was pushed but not decoded yet.
+    buf: Vec<u8>,
+
+    // The number of bytes decoded so far.
+    offset: u64,
+}
+
+impl Replay {
+    pub fn new(kind: ReplayKind) -> Self {
+        let state = match kind {
+            ReplayKind::Control => ReplayState::Settings,
+            ReplayKind::Request | ReplayKind::Response => ReplayState::Headers,
+            ReplayKind::Capsules => ReplayState::Capsules,
+        };
+
+        Self {
+            kind,
+            state,
+            buf: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    // Decode every chunk of a capture, returning the messages in order.
+    pub fn run<'a, I>(kind: ReplayKind, chunks: I) -> Result<Vec<ReplayEvent>, ReplayError>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut replay = Self::new(kind);
+        let mut events = Vec::new();
+
+        for chunk in chunks {
+            replay.push(chunk);
+            while let Some(event) = replay.step()? {
+                events.push(event);
+            }
+        }
+
+        replay.finish()?;
+
+        Ok(events)
+    }
+
+    // Append the next chunk of the capture.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    // Decode the next message, returning None when more data is needed.
+    // The buffered data is only consumed once a message decodes, just like the real stream handling.
+    pub fn step(&mut self) -> Result<Option<ReplayEvent>, ReplayError> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let offset = self.offset;
+        let mut cursor = Cursor::new(self.buf.as_slice());
+
+        let event = match self.state {
+            ReplayState::Settings => match Settings::decode(&mut cursor) {
+                Ok(settings) => {
+                    self.state = ReplayState::Frames;
+                    ReplayEvent::Settings(settings)
+                }
+                Err(SettingsError::UnexpectedEnd) => return Ok(None),
+                Err(error) => return Err(ReplayError::Settings { offset, error }),
+            },
+            ReplayState::Frames => {
+                // Peek at the frame type, since GoAway::decode skips the others.
+                let typ = Frame::decode(&mut cursor.clone()).ok();
+
+                match GoAway::decode(&mut cursor) {
+                    Ok(Some(goaway)) => ReplayEvent::GoAway(goaway),
+                    Ok(None) => ReplayEvent::Frame(typ.unwrap()),
+                    Err(SettingsError::UnexpectedEnd) => return Ok(None),
+                    Err(error) => return Err(ReplayError::Settings { offset, error }),
+                }
+            }
+            ReplayState::Headers => {
+                let res = match self.kind {
+                    ReplayKind::Response => {
+                        ConnectResponse::decode(&mut cursor).map(ReplayEvent::Response)
+                    }
+                    _ => ConnectRequest::decode(&mut cursor).map(ReplayEvent::Request),
+                };
+
+                match res {
+                    Ok(event) => {
+                        self.state = ReplayState::Capsules;
+                        event
+                    }
+                    Err(ConnectError::UnexpectedEnd) => return Ok(None),
+                    Err(error) => return Err(ReplayError::Connect { offset, error }),
+                }
+            }
+            ReplayState::Capsules => match Capsule::decode(&mut cursor) {
+                Ok(capsule) => {
+                    if let Capsule::CloseWebTransportSession { .. } = capsule {
+                        self.state = ReplayState::Closed;
+                    }
+                    ReplayEvent::Capsule(capsule)
+                }
+                Err(CapsuleError::UnexpectedEnd) => return Ok(None),
+                Err(error) => return Err(ReplayError::Capsule { offset, error }),
+            },
+            ReplayState::Closed => return Err(ReplayError::AfterClose { offset }),
+        };
+
+        let size = cursor.position() as usize;
+        self.buf.drain(..size);
+        self.offset += size as u64;
+
+        Ok(Some(event))
+    }
+
+    // Check that the capture didn't end in the middle of a message.
+    pub fn finish(&self) -> Result<(), ReplayError> {
+        match self.buf.len() {
+            0 => Ok(()),
+            remaining => Err(ReplayError::Truncated {
+                offset: self.offset,
+                remaining,
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> ReplayKind {
+        self.kind
+    }
+
+    // What the replay expects to decode next.
+    pub fn state(&self) -> ReplayState {
+        self.state
+    }
+
+    // The number of bytes decoded so far, which is where the next message starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // The data that was pushed but not decoded yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+}
@@ -47,6 +47,22 @@ settings! {
 
     // New way to enable WebTransport
     WEBTRANSPORT_MAX_SESSIONS = 0xc671706a,
+
+    // The initial flow control limits of each session, see draft-ietf-webtrans-http3 section 5.
+    WEBTRANSPORT_INITIAL_MAX_DATA = 0x2b61,
+    WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI = 0x2b64,
+    WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI = 0x2b65,
+}
+
+// The revisions of the WebTransport draft that we support.
+// They mostly differ in the settings used to enable WebTransport and a few headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Draft {
+    // Used by Chrome, which enables WebTransport with the deprecated settings.
+    Draft02,
+
+    // Used by Firefox and newer implementations, which send WEBTRANSPORT_MAX_SESSIONS.
+    Draft07,
 }
 
 #[derive(Error, Debug)]
@@ -113,17 +129,35 @@ impl Settings {
         buf.put_slice(&tmp);
     }
 
-    pub fn enable_webtransport(&mut self, max_sessions: u32) {
+    // Enable WebTransport using the settings for a specific draft, or all of them if None.
+    // We send our settings before receiving the peer's, so by default we include the deprecated ones too.
+    pub fn enable_webtransport(&mut self, max_sessions: u32, draft: Option<Draft>) {
         let max = VarInt::from_u32(max_sessions);
 
         self.insert(Setting::ENABLE_CONNECT_PROTOCOL, VarInt::from_u32(1));
-        self.insert(Setting::ENABLE_DATAGRAM, VarInt::from_u32(1));
-        self.insert(Setting::ENABLE_DATAGRAM_DEPRECATED, VarInt::from_u32(1));
-        self.insert(Setting::WEBTRANSPORT_MAX_SESSIONS, max);
 
-        // TODO remove when 07 is in the wild
-        self.insert(Setting::WEBTRANSPORT_MAX_SESSIONS_DEPRECATED, max);
-        self.insert(Setting::WEBTRANSPORT_ENABLE_DEPRECATED, VarInt::from_u32(1));
+        if draft != Some(Draft::Draft02) {
+            self.insert(Setting::ENABLE_DATAGRAM, VarInt::from_u32(1));
+            self.insert(Setting::WEBTRANSPORT_MAX_SESSIONS, max);
+        }
+
+        if draft != Some(Draft::Draft07) {
+            self.insert(Setting::ENABLE_DATAGRAM_DEPRECATED, VarInt::from_u32(1));
+            self.insert(Setting::WEBTRANSPORT_MAX_SESSIONS_DEPRECATED, max);
+            self.insert(Setting::WEBTRANSPORT_ENABLE_DEPRECATED, VarInt::from_u32(1));
+        }
+    }
+
+    // Returns the draft used by the peer, or None if WebTransport isn't enabled.
+    pub fn draft(&self) -> Option<Draft> {
+        if self.supports_webtransport() == 0 {
+            return None;
+        }
+
+        match self.get(&Setting::WEBTRANSPORT_MAX_SESSIONS) {
+            Some(_) => Some(Draft::Draft07),
+            None => Some(Draft::Draft02),
+        }
     }
 
     // Returns the maximum number of sessions supported.
@@ -21,7 +21,7 @@ impl StreamUni {
             return false;
         }
 
-        (val - 0x21) % 0x1f == 0
+        (val - 0x21).is_multiple_of(0x1f)
     }
 }
 
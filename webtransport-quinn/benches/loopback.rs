@@ -0,0 +1,96 @@
+//! Compare the WebTransport wrappers against raw Quinn over localhost, see [`webtransport_quinn::bench`].
+//! Run with `cargo bench --features bench-bin`.
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+use webtransport_quinn::bench::{Loopback, Transport};
+
+const TRANSPORTS: [Transport; 2] = [Transport::WebTransport, Transport::Quic];
+
+fn loopbacks(runtime: &Runtime) -> Vec<Loopback> {
+    TRANSPORTS
+        .iter()
+        .map(|transport| runtime.block_on(Loopback::new(*transport)).unwrap())
+        .collect()
+}
+
+fn open(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("open");
+
+    for loopback in &loopbacks(&runtime) {
+        group.bench_function(loopback.transport().to_string(), |b| {
+            b.to_async(&runtime).iter_custom(|iters| async move {
+                loopback.open_streams(iters as usize).await.unwrap()
+            })
+        });
+    }
+}
+
+fn accept(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("accept");
+
+    for loopback in &loopbacks(&runtime) {
+        for acceptors in [1, 16, 256] {
+            let id = BenchmarkId::new(loopback.transport().to_string(), acceptors);
+            group.bench_with_input(id, &acceptors, |b, &acceptors| {
+                b.to_async(&runtime).iter_custom(|iters| async move {
+                    loopback
+                        .accept_streams(acceptors, iters as usize)
+                        .await
+                        .unwrap()
+                })
+            });
+        }
+    }
+}
+
+fn transfer(c: &mut Criterion) {
+    const BYTES: usize = 1024 * 1024;
+    const CHUNK: usize = 16 * 1024;
+
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("transfer");
+
+    for loopback in &loopbacks(&runtime) {
+        for streams in [1, 4, 16] {
+            let id = BenchmarkId::new(loopback.transport().to_string(), streams);
+            group.throughput(Throughput::Bytes((streams * BYTES) as u64));
+            group.bench_with_input(id, &streams, |b, &streams| {
+                b.to_async(&runtime)
+                    .iter(|| async move { loopback.transfer(streams, BYTES, CHUNK).await.unwrap() })
+            });
+        }
+    }
+}
+
+fn datagrams(c: &mut Criterion) {
+    const COUNT: usize = 1000;
+
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("datagrams");
+    group.throughput(Throughput::Elements(COUNT as u64));
+
+    for loopback in &loopbacks(&runtime) {
+        for size in [100, 1000] {
+            let id = BenchmarkId::new(loopback.transport().to_string(), size);
+
+            // Only count the time until the last datagram arrived, not the wait for any that were lost.
+            group.bench_with_input(id, &size, |b, &size| {
+                b.to_async(&runtime).iter_custom(|iters| async move {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        total += loopback.datagrams(COUNT, size).await.unwrap().elapsed;
+                    }
+                    total
+                })
+            });
+        }
+    }
+}
+
+criterion_group!(benches, open, accept, transfer, datagrams);
+criterion_main!(benches);
@@ -9,19 +9,16 @@ async fn main() -> anyhow::Result<()> {
     let env = env_logger::Env::default().default_filter_or("info");
     env_logger::init_from_env(env);
 
-    // Standard quinn setup.
-    let mut tls_config = rustls::ClientConfig::builder()
+    // Skip certificate verification, since the example server uses a self-signed certificate.
+    let tls_config = rustls::ClientConfig::builder()
         .with_safe_defaults()
         .with_custom_certificate_verifier(SkipServerVerification::new()) // WARNING: Don't use this in production
         .with_no_client_auth();
 
-    tls_config.alpn_protocols = vec![webtransport_quinn::ALPN.to_vec()]; // this one is important
-
-    let config = quinn::ClientConfig::new(std::sync::Arc::new(tls_config));
-
-    let addr = "[::]:0".parse()?;
-    let mut client = quinn::Endpoint::client(addr)?;
-    client.set_default_client_config(config);
+    // The builder sets the ALPN and binds the QUIC endpoint.
+    let client = webtransport_quinn::ClientBuilder::new()
+        .tls_config(tls_config)
+        .build()?;
 
     //	Create the WebTransport URL.
     let batons = 1;
@@ -32,7 +29,7 @@ async fn main() -> anyhow::Result<()> {
     log::info!("connecting to {}", uri);
 
     // Connect to the given URI.
-    let session = webtransport_quinn::connect(&client, &uri).await?;
+    let session = client.connect(&uri).await?;
 
     // Run the baton code.
     baton::run(session, None, batons).await?;
@@ -16,31 +16,20 @@ async fn main() -> anyhow::Result<()> {
     let cert = rustls::Certificate(gen.serialize_der().unwrap());
     let key = rustls::PrivateKey(gen.serialize_private_key_der());
 
-    // Standard Quinn setup
-    let mut tls_config = rustls::ServerConfig::builder()
-        .with_safe_default_cipher_suites()
-        .with_safe_default_kx_groups()
-        .with_protocol_versions(&[&rustls::version::TLS13])
-        .unwrap()
-        .with_no_client_auth()
-        .with_single_cert(vec![cert], key)?;
-
-    tls_config.max_early_data_size = u32::MAX;
-    tls_config.alpn_protocols = vec![webtransport_quinn::ALPN.to_vec()]; // this one is important
-
-    let config = quinn::ServerConfig::with_crypto(std::sync::Arc::new(tls_config));
-
+    // The builder sets the ALPN and binds the QUIC endpoint.
     let addr = "[::]:4443".parse()?;
-    let server = quinn::Endpoint::server(config, addr)?;
+    let mut server = webtransport_quinn::ServerBuilder::new(vec![cert], key)
+        .bind(addr)
+        .build()?;
 
     log::info!("listening on {}", addr);
 
-    // Accept new connections.
-    while let Some(conn) = server.accept().await {
+    // Accept new WebTransport requests, after the QUIC and HTTP/3 handshakes.
+    while let Some(request) = server.accept().await {
         tokio::spawn(async move {
-            let err = run_conn(conn).await;
+            let err = run_session(request).await;
             if let Err(err) = err {
-                log::error!("connection failed: {}", err)
+                log::error!("session failed: {}", err)
             }
         });
     }
@@ -50,15 +39,7 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn run_conn(conn: quinn::Connecting) -> anyhow::Result<()> {
-    log::info!("received new QUIC connection");
-
-    // Wait for the QUIC handshake to complete.
-    let conn = conn.await.context("failed to accept connection")?;
-    log::info!("established QUIC connection");
-
-    // Perform the WebTransport handshake.
-    let request = webtransport_quinn::accept(conn).await?;
+async fn run_session(request: webtransport_quinn::Request) -> anyhow::Result<()> {
     log::info!("received WebTransport request: {}", request.uri());
 
     // Parse the request URI to decide if we should accept the session.
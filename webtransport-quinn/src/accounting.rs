@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    ops::{Add, AddAssign, Sub},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, SystemClock};
+
+/// The number of bytes sent and received, including QUIC and UDP overhead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Usage {
+    fn from_stats(stats: &quinn_proto::ConnectionStats) -> Self {
+        Self {
+            sent: stats.udp_tx.bytes,
+            received: stats.udp_rx.bytes,
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            sent: self.sent + other.sent,
+            received: self.received + other.received,
+        }
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other
+    }
+}
+
+impl Sub for Usage {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            sent: self.sent.saturating_sub(other.sent),
+            received: self.received.saturating_sub(other.received),
+        }
+    }
+}
+
+/// The usage of a single session, see [`Accounting::sessions`].
+#[derive(Clone, Copy, Debug)]
+pub struct SessionUsage {
+    /// The ID of the connection, see [`quinn::Connection::stable_id`].
+    pub id: usize,
+
+    /// The current address of the peer.
+    pub remote: SocketAddr,
+
+    /// The usage since the session was tracked or the last reset.
+    pub usage: Usage,
+}
+
+/// A report of a single session's usage, passed to the hook in [`Accounting::set_hook`].
+#[derive(Clone, Copy, Debug)]
+pub struct SessionReport {
+    /// The ID of the connection, see [`quinn::Connection::stable_id`].
+    pub id: usize,
+
+    /// The current address of the peer.
+    pub remote: SocketAddr,
+
+    /// The usage over the entire life of the session, unaffected by [`Accounting::reset`].
+    pub usage: Usage,
+
+    /// The time since the session was tracked.
+    pub duration: Duration,
+
+    /// True if this is the final report, because the session has closed.
+    pub closed: bool,
+}
+
+type Hook = Arc<dyn Fn(&SessionReport) + Send + Sync>;
+
+/// Tracks the bytes sent and received by every session on an endpoint, per session and per remote IP.
+///
+/// Register each session with [`Self::track`] after it's accepted.
+/// The counts are sampled from Quinn when queried, so tracking is cheap.
+/// Closed sessions are folded into the per IP totals.
+///
+/// This is a cheap handle; clone it to share between the accept loop and whatever enforces quotas.
+#[derive(Clone, Default)]
+pub struct Accounting {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    // Open sessions, keyed by the stable ID.
+    open: HashMap<usize, Open>,
+
+    // The usage of closed sessions since the last reset.
+    closed: HashMap<IpAddr, Usage>,
+
+    // Called with reports about each session, see Accounting::run.
+    hook: Option<Hook>,
+
+    // Used for the duration of each session and the interval of Accounting::run.
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            open: HashMap::new(),
+            closed: HashMap::new(),
+            hook: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+struct Open {
+    conn: quinn::Connection,
+
+    // The usage at the last reset.
+    base: Usage,
+
+    // When the session was tracked.
+    started: Instant,
+}
+
+impl Open {
+    fn usage(&self) -> Usage {
+        Usage::from_stats(&self.conn.stats()) - self.base
+    }
+
+    fn report(&self, closed: bool, now: Instant) -> SessionReport {
+        SessionReport {
+            id: self.conn.stable_id(),
+            remote: self.conn.remote_address(),
+            usage: Usage::from_stats(&self.conn.stats()),
+            duration: now.saturating_duration_since(self.started),
+            closed,
+        }
+    }
+}
+
+impl Accounting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a session or connection.
+    pub fn track(&self, conn: &quinn::Connection) {
+        let mut state = self.state.lock().unwrap();
+        let open = Open {
+            conn: conn.clone(),
+            base: Usage::default(),
+            started: state.clock.now(),
+        };
+
+        let closed = state.collect();
+        state.open.insert(conn.stable_id(), open);
+        notify(state, closed);
+    }
+
+    /// Return the usage of a single session, or None if it's not tracked or has closed.
+    pub fn session(&self, conn: &quinn::Connection) -> Option<Usage> {
+        let state = self.state.lock().unwrap();
+        Some(state.open.get(&conn.stable_id())?.usage())
+    }
+
+    /// Return the usage of every open session.
+    pub fn sessions(&self) -> Vec<SessionUsage> {
+        let mut state = self.state.lock().unwrap();
+        let closed = state.collect();
+
+        let sessions = state
+            .open
+            .iter()
+            .map(|(id, open)| SessionUsage {
+                id: *id,
+                remote: open.conn.remote_address(),
+                usage: open.usage(),
+            })
+            .collect();
+
+        notify(state, closed);
+        sessions
+    }
+
+    /// Return the usage of every remote IP, including closed sessions.
+    pub fn ips(&self) -> HashMap<IpAddr, Usage> {
+        let mut state = self.state.lock().unwrap();
+        let closed = state.collect();
+
+        let mut ips = state.closed.clone();
+        for open in state.open.values() {
+            *ips.entry(open.conn.remote_address().ip()).or_default() += open.usage();
+        }
+
+        notify(state, closed);
+        ips
+    }
+
+    /// Return the usage of a single remote IP, including closed sessions.
+    pub fn ip(&self, ip: IpAddr) -> Usage {
+        self.ips().get(&ip).copied().unwrap_or_default()
+    }
+
+    /// Return the usage of the entire endpoint.
+    pub fn total(&self) -> Usage {
+        self.ips()
+            .values()
+            .fold(Usage::default(), |total, usage| total + *usage)
+    }
+
+    /// Reset all of the counts to zero, without untracking the open sessions.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        let closed = state.collect();
+        state.closed.clear();
+
+        for open in state.open.values_mut() {
+            open.base = Usage::from_stats(&open.conn.stats());
+        }
+
+        notify(state, closed);
+    }
+
+    /// Call the hook with a report for each session, replacing any previous hook.
+    ///
+    /// The hook is called for every open session each interval of [`Self::run`],
+    /// and once more when each session is found to be closed, for metering and audit logs.
+    /// It's called from whichever task noticed, so it should be quick and must not block.
+    pub fn set_hook<F>(&self, hook: F)
+    where
+        F: Fn(&SessionReport) + Send + Sync + 'static,
+    {
+        self.state.lock().unwrap().hook = Some(Arc::new(hook));
+    }
+
+    /// Use the given clock for the duration of each session and the interval of [`Self::run`], instead of the system time.
+    ///
+    /// Use the same clock as the sessions (see [`crate::Server::set_clock`]) so tests can advance both at once.
+    pub fn set_clock<C: Clock + 'static>(&self, clock: C) {
+        self.state.lock().unwrap().clock = Arc::new(clock);
+    }
+
+    /// Stop calling the hook.
+    pub fn clear_hook(&self) {
+        self.state.lock().unwrap().hook = None;
+    }
+
+    /// Call the hook every interval, forever, see [`Self::set_hook`].
+    ///
+    /// This crate doesn't spawn tasks, so spawn this future instead.
+    /// Closed sessions are reported within one interval of closing.
+    pub async fn run(&self, interval: Duration) {
+        loop {
+            let sleep = self.state.lock().unwrap().clock.sleep(interval);
+            sleep.await;
+
+            let mut state = self.state.lock().unwrap();
+            let mut reports = state.collect();
+            let now = state.clock.now();
+            reports.extend(state.open.values().map(|open| open.report(false, now)));
+            notify(state, reports);
+        }
+    }
+}
+
+impl State {
+    // Fold any closed sessions into the per IP totals, so we don't hold onto them forever.
+    // Returns the final report for each closed session.
+    fn collect(&mut self) -> Vec<SessionReport> {
+        let closed = &mut self.closed;
+        let now = self.clock.now();
+        let mut reports = Vec::new();
+
+        self.open.retain(|_, open| {
+            if open.conn.close_reason().is_none() {
+                return true;
+            }
+
+            *closed.entry(open.conn.remote_address().ip()).or_default() += open.usage();
+            reports.push(open.report(true, now));
+            false
+        });
+
+        reports
+    }
+}
+
+// Release the lock and call the hook, so it's free to use the accounting handle.
+fn notify(state: MutexGuard<'_, State>, reports: Vec<SessionReport>) {
+    let hook = state.hook.clone();
+    drop(state);
+
+    if let Some(hook) = hook {
+        for report in &reports {
+            hook(report);
+        }
+    }
+}
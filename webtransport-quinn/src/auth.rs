@@ -0,0 +1,95 @@
+use crate::{Extensions, Request};
+
+/// The parts of a CONNECT request given to an authorizer, see [`crate::Server::set_authorizer`].
+///
+/// The extensions are shared with the request, so the authorizer can attach the identity it found for the session.
+#[derive(Clone)]
+pub struct AuthRequest {
+    uri: http::Uri,
+    headers: http::HeaderMap,
+    certificates: Option<Vec<rustls::Certificate>>,
+    extensions: Extensions,
+}
+
+impl AuthRequest {
+    pub(crate) fn new(request: &Request) -> Self {
+        Self {
+            uri: request.uri().clone(),
+            headers: request.headers().clone(),
+            certificates: request.peer_identity(),
+            extensions: request.extensions().clone(),
+        }
+    }
+
+    /// Returns the URI provided by the client, ex. for a token in the query.
+    pub fn uri(&self) -> &http::Uri {
+        &self.uri
+    }
+
+    /// Returns the headers of the request, excluding the pseudo-headers.
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the `authorization` header, ex. `Bearer <token>`.
+    pub fn authorization(&self) -> Option<&str> {
+        self.header(http::header::AUTHORIZATION)
+    }
+
+    /// Returns the value of the named cookie from the `cookie` header.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header(http::header::COOKIE)?
+            .split(';')
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the client's certificate chain, when client authentication is enabled, see [`crate::Session::peer_identity`].
+    pub fn peer_identity(&self) -> Option<&[rustls::Certificate]> {
+        self.certificates.as_deref()
+    }
+
+    /// Returns the state attached to the request, which is handed over to the session if it's accepted.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    fn header(&self, name: http::header::HeaderName) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+}
+
+/// The decision of an authorizer, see [`crate::Server::set_authorizer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Authorization {
+    /// Let the request through to the application.
+    Allow,
+
+    /// Refuse with a 401 Unauthorized, asking for credentials with the `www-authenticate` challenge (ex. `Bearer realm="chat"`).
+    Unauthorized(String),
+
+    /// Refuse with a 403 Forbidden, since the client authenticated but isn't allowed.
+    Forbidden,
+}
+
+impl Authorization {
+    // The response to refuse the request with, or None if it's allowed.
+    pub(crate) fn response(&self) -> Option<(http::StatusCode, http::HeaderMap)> {
+        let mut headers = http::HeaderMap::new();
+
+        let status = match self {
+            Self::Allow => return None,
+            Self::Unauthorized(challenge) => {
+                if let Ok(challenge) = http::HeaderValue::from_str(challenge) {
+                    headers.insert(http::header::WWW_AUTHENTICATE, challenge);
+                }
+
+                http::StatusCode::UNAUTHORIZED
+            }
+            Self::Forbidden => http::StatusCode::FORBIDDEN,
+        };
+
+        Some((status, headers))
+    }
+}
@@ -0,0 +1,527 @@
+//! Loopback benchmarks for this crate, compared against raw Quinn on the same machine.
+//!
+//! This is enabled with the `bench-bin` feature, which is used by the criterion benchmarks (`cargo bench --features bench-bin`)
+//! and the `webtransport-bench` binary (`cargo run --release --features bench-bin --bin webtransport-bench -- --help`).
+//! Each scenario runs against a [`Loopback`] connection using either [`Transport`], so the difference is the overhead of the wrappers.
+//! Both endpoints run in the calling task, so the numbers don't depend on how tasks are scheduled.
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{future, stream, StreamExt, TryFutureExt, TryStreamExt};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    clock::timeout, ClientError, Clock, SendDatagramError, ServerError, Session, SessionError,
+    SystemClock,
+};
+
+// How long the receiver waits for another datagram before assuming the rest were lost.
+const DATAGRAM_IDLE: Duration = Duration::from_millis(100);
+
+// The number of streams the server echoes at once in the open benchmark.
+const ECHO_CONCURRENCY: usize = 32;
+
+/// An error while setting up or running a benchmark.
+#[derive(Error, Debug)]
+pub enum BenchError {
+    #[error("tls error: {0}")]
+    Tls(String),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("connect error: {0}")]
+    Connect(#[from] quinn::ConnectError),
+
+    #[error("connection error: {0}")]
+    Connection(#[from] quinn::ConnectionError),
+
+    #[error("client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("server error: {0}")]
+    Server(#[from] ServerError),
+
+    #[error("session error: {0}")]
+    Session(#[from] SessionError),
+
+    #[error("datagram error: {0}")]
+    Datagram(#[from] SendDatagramError),
+}
+
+/// The protocol used by a [`Loopback`] connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// A [`Session`] from this crate.
+    WebTransport,
+
+    /// A raw [`quinn::Connection`], as the baseline.
+    Quic,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::WebTransport => write!(f, "webtransport"),
+            Transport::Quic => write!(f, "quic"),
+        }
+    }
+}
+
+/// The parameters of [`Loopback::run`], which runs every scenario once.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    /// The number of streams opened one after another, see [`Loopback::open_streams`].
+    pub opens: usize,
+
+    /// The number of tasks accepting streams at once, see [`Loopback::accept_streams`].
+    pub acceptors: usize,
+
+    /// The number of streams accepted by them in total.
+    pub accepts: usize,
+
+    /// The number of concurrent streams, see [`Loopback::transfer`].
+    pub streams: usize,
+
+    /// The number of bytes written to each stream.
+    pub bytes: usize,
+
+    /// The size of each write.
+    pub chunk: usize,
+
+    /// The number of datagrams sent, see [`Loopback::datagrams`].
+    pub datagrams: usize,
+
+    /// The payload size of each datagram.
+    pub datagram_size: usize,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            opens: 1000,
+            acceptors: 256,
+            accepts: 1000,
+            streams: 4,
+            bytes: 16 * 1024 * 1024,
+            chunk: 16 * 1024,
+            datagrams: 10_000,
+            datagram_size: 1000,
+        }
+    }
+}
+
+/// The results of [`Loopback::run`].
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub transport: Transport,
+
+    /// The average round trip to open a stream and echo a byte.
+    pub open: Duration,
+
+    /// The average time to accept a stream while many tasks are waiting for one.
+    pub accept: Duration,
+
+    pub transfer: Transfer,
+    pub datagrams: Datagrams,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.transport)?;
+        writeln!(f, "  open:      {:?} per stream", self.open)?;
+        writeln!(f, "  accept:    {:?} per stream", self.accept)?;
+        writeln!(
+            f,
+            "  transfer:  {:.1} MB/s ({} bytes in {:?})",
+            self.transfer.bytes_per_sec() / 1_000_000.0,
+            self.transfer.bytes,
+            self.transfer.elapsed
+        )?;
+        write!(
+            f,
+            "  datagrams: {:.0}/s ({} of {} received in {:?})",
+            self.datagrams.per_sec(),
+            self.datagrams.received,
+            self.datagrams.sent,
+            self.datagrams.elapsed
+        )
+    }
+}
+
+/// The result of [`Loopback::transfer`].
+#[derive(Clone, Copy, Debug)]
+pub struct Transfer {
+    /// The number of bytes received over every stream.
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Transfer {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// The result of [`Loopback::datagrams`].
+#[derive(Clone, Copy, Debug)]
+pub struct Datagrams {
+    pub sent: usize,
+    pub received: usize,
+
+    /// The time until the last datagram was received.
+    pub elapsed: Duration,
+}
+
+impl Datagrams {
+    /// The number of datagrams received per second.
+    pub fn per_sec(&self) -> f64 {
+        self.received as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// A client and server connected over localhost.
+pub struct Loopback {
+    transport: Transport,
+    client: Conn,
+    server: Conn,
+
+    // Keep the sockets open.
+    _endpoints: (quinn::Endpoint, quinn::Endpoint),
+}
+
+impl Loopback {
+    /// Connect a client and server over localhost with a self-signed certificate.
+    ///
+    /// This needs Quinn's runtime (Tokio by default) to create the sockets.
+    pub async fn new(transport: Transport) -> Result<Self, BenchError> {
+        let gen = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+            .map_err(|err| BenchError::Tls(err.to_string()))?;
+        let der = gen
+            .serialize_der()
+            .map_err(|err| BenchError::Tls(err.to_string()))?;
+        let cert = rustls::Certificate(der);
+        let key = rustls::PrivateKey(gen.serialize_private_key_der());
+
+        let mut tls = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .map_err(|err| BenchError::Tls(err.to_string()))?;
+        tls.alpn_protocols = vec![crate::ALPN.to_vec()];
+
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let server =
+            quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(tls)), localhost)?;
+        let addr = server.local_addr()?;
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&cert)
+            .map_err(|err| BenchError::Tls(err.to_string()))?;
+
+        let mut tls = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![crate::ALPN.to_vec()];
+
+        let mut client = quinn::Endpoint::client(localhost)?;
+        client.set_default_client_config(quinn::ClientConfig::new(Arc::new(tls)));
+
+        let accept = async {
+            let connecting = server
+                .accept()
+                .await
+                .ok_or(quinn::ConnectionError::LocallyClosed)?;
+            Ok::<_, BenchError>(connecting.await?)
+        };
+        let connect = async { Ok::<_, BenchError>(client.connect(addr, "localhost")?.await?) };
+        let (client_conn, server_conn) = future::try_join(connect, accept).await?;
+
+        let (client_conn, server_conn) = match transport {
+            Transport::Quic => (Conn::Quic(client_conn), Conn::Quic(server_conn)),
+            Transport::WebTransport => {
+                let uri = format!("https://localhost:{}/", addr.port())
+                    .parse()
+                    .unwrap();
+                let connect = crate::connect_with(client_conn, &uri).err_into::<BenchError>();
+                let accept = crate::accept(server_conn)
+                    .and_then(|request| request.ok())
+                    .err_into::<BenchError>();
+                let (client, server) = future::try_join(connect, accept).await?;
+
+                (
+                    Conn::WebTransport(Box::new(client)),
+                    Conn::WebTransport(Box::new(server)),
+                )
+            }
+        };
+
+        Ok(Self {
+            transport,
+            client: client_conn,
+            server: server_conn,
+            _endpoints: (client, server),
+        })
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Run every scenario once.
+    pub async fn run(&self, scenario: &Scenario) -> Result<Report, BenchError> {
+        let open = self.open_streams(scenario.opens).await? / scenario.opens.max(1) as u32;
+        let accept = self
+            .accept_streams(scenario.acceptors, scenario.accepts)
+            .await?
+            / scenario.accepts.max(1) as u32;
+        let transfer = self
+            .transfer(scenario.streams, scenario.bytes, scenario.chunk)
+            .await?;
+        let datagrams = self
+            .datagrams(scenario.datagrams, scenario.datagram_size)
+            .await?;
+
+        Ok(Report {
+            transport: self.transport,
+            open,
+            accept,
+            transfer,
+            datagrams,
+        })
+    }
+
+    /// Open bidirectional streams one after another, each echoing a single byte, and return the total time.
+    pub async fn open_streams(&self, count: usize) -> Result<Duration, BenchError> {
+        let start = Instant::now();
+
+        let client = async {
+            for _ in 0..count {
+                let (mut send, mut recv) = self.client.open_bi().await?;
+                send.write_all(&[0]).await?;
+
+                // Finishing waits for an acknowledgement, so don't wait for it before the echo.
+                let mut echo = Vec::new();
+                future::try_join(send.shutdown(), recv.read_to_end(&mut echo)).await?;
+            }
+
+            Ok::<_, BenchError>(())
+        };
+
+        // Echo concurrently, so waiting to finish one stream doesn't delay the next.
+        let server = stream::iter(0..count)
+            .then(|_| self.server.accept_bi())
+            .map_ok(|(mut send, mut recv)| async move {
+                let mut buf = Vec::new();
+                recv.read_to_end(&mut buf).await?;
+                send.write_all(&buf).await?;
+                send.shutdown().await?;
+                Ok::<_, BenchError>(())
+            })
+            .try_buffer_unordered(ECHO_CONCURRENCY)
+            .try_collect::<()>();
+
+        future::try_join(client, server).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Accept unidirectional streams with `acceptors` concurrent tasks, each waiting for its share of `count`, and return the total time.
+    ///
+    /// Each stream should only wake one of the waiting tasks, so this shouldn't get slower with more of them.
+    pub async fn accept_streams(
+        &self,
+        acceptors: usize,
+        count: usize,
+    ) -> Result<Duration, BenchError> {
+        let start = Instant::now();
+        let acceptors = acceptors.max(1);
+
+        let client = async {
+            for _ in 0..count {
+                let mut send = self.client.open_uni().await?;
+                send.write_all(&[0]).await?;
+                send.shutdown().await?;
+            }
+
+            Ok::<_, BenchError>(())
+        };
+
+        // Split the streams so every acceptor returns once they've all arrived.
+        let server = future::try_join_all((0..acceptors).map(|i| async move {
+            let share = count / acceptors + usize::from(i < count % acceptors);
+            for _ in 0..share {
+                // Read the stream so it's not stopped before the client finishes it.
+                let mut recv = self.server.accept_uni().await?;
+                recv.read_to_end(&mut Vec::new()).await?;
+            }
+
+            Ok::<_, BenchError>(())
+        }));
+
+        future::try_join(client, server).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Write `bytes` to each of `streams` concurrent unidirectional streams, in writes of `chunk` bytes.
+    pub async fn transfer(
+        &self,
+        streams: usize,
+        bytes: usize,
+        chunk: usize,
+    ) -> Result<Transfer, BenchError> {
+        let start = Instant::now();
+        let payload = vec![0; chunk.clamp(1, bytes.max(1))];
+
+        let client = stream::iter(0..streams)
+            .map(|_| async {
+                let mut send = self.client.open_uni().await?;
+                let mut remaining = bytes;
+
+                while remaining > 0 {
+                    let size = remaining.min(payload.len());
+                    send.write_all(&payload[..size]).await?;
+                    remaining -= size;
+                }
+
+                send.shutdown().await?;
+                Ok::<_, BenchError>(())
+            })
+            .buffer_unordered(streams.max(1))
+            .try_collect::<()>();
+
+        let server = stream::iter(0..streams)
+            .then(|_| self.server.accept_uni())
+            .map_ok(|mut recv| async move {
+                Ok::<_, BenchError>(tokio::io::copy(&mut recv, &mut tokio::io::sink()).await?)
+            })
+            .try_buffer_unordered(streams.max(1))
+            .try_fold(0, |total, size| future::ok(total + size));
+
+        let ((), bytes) = future::try_join(client, server).await?;
+
+        Ok(Transfer {
+            bytes,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Send datagrams as fast as possible and count how many arrived.
+    ///
+    /// Datagrams are unreliable, so the receiver gives up once none have arrived for a short while.
+    pub async fn datagrams(&self, count: usize, size: usize) -> Result<Datagrams, BenchError> {
+        let start = Instant::now();
+        let payload = Bytes::from(vec![0; size]);
+
+        let client = async {
+            for _ in 0..count {
+                self.client.send_datagram(payload.clone())?;
+            }
+
+            Ok::<_, BenchError>(())
+        };
+
+        let server = async {
+            let mut received = 0;
+            let mut last = start;
+
+            while received < count {
+                let recv = self.server.recv_datagram();
+                match timeout(SystemClock.sleep(DATAGRAM_IDLE), recv).await {
+                    Some(res) => res?,
+                    None => break,
+                };
+
+                received += 1;
+                last = Instant::now();
+            }
+
+            Ok::<_, BenchError>((received, last))
+        };
+
+        let ((), (received, last)) = future::try_join(client, server).await?;
+
+        Ok(Datagrams {
+            sent: count,
+            received,
+            elapsed: last - start,
+        })
+    }
+}
+
+type Writer = Box<dyn AsyncWrite + Unpin + Send>;
+type Reader = Box<dyn AsyncRead + Unpin + Send>;
+
+// One side of the connection, using boxed streams for either transport so the overhead is the same.
+enum Conn {
+    WebTransport(Box<Session>),
+    Quic(quinn::Connection),
+}
+
+impl Conn {
+    async fn open_bi(&self) -> Result<(Writer, Reader), BenchError> {
+        Ok(match self {
+            Conn::WebTransport(session) => {
+                let (send, recv) = session.open_bi().await?;
+                (Box::new(send), Box::new(recv))
+            }
+            Conn::Quic(conn) => {
+                let (send, recv) = conn.open_bi().await?;
+                (Box::new(send), Box::new(recv))
+            }
+        })
+    }
+
+    async fn accept_bi(&self) -> Result<(Writer, Reader), BenchError> {
+        Ok(match self {
+            Conn::WebTransport(session) => {
+                let (send, recv) = session.accept_bi().await?;
+                (Box::new(send), Box::new(recv))
+            }
+            Conn::Quic(conn) => {
+                let (send, recv) = conn.accept_bi().await?;
+                (Box::new(send), Box::new(recv))
+            }
+        })
+    }
+
+    async fn open_uni(&self) -> Result<Writer, BenchError> {
+        Ok(match self {
+            Conn::WebTransport(session) => Box::new(session.open_uni().await?),
+            Conn::Quic(conn) => Box::new(conn.open_uni().await?),
+        })
+    }
+
+    async fn accept_uni(&self) -> Result<Reader, BenchError> {
+        Ok(match self {
+            Conn::WebTransport(session) => Box::new(session.accept_uni().await?),
+            Conn::Quic(conn) => Box::new(conn.accept_uni().await?),
+        })
+    }
+
+    fn send_datagram(&self, payload: Bytes) -> Result<(), BenchError> {
+        match self {
+            Conn::WebTransport(session) => session.send_datagram(payload)?,
+            Conn::Quic(conn) => conn
+                .send_datagram(payload)
+                .map_err(SendDatagramError::from)?,
+        };
+
+        Ok(())
+    }
+
+    async fn recv_datagram(&self) -> Result<Bytes, BenchError> {
+        Ok(match self {
+            Conn::WebTransport(session) => session.recv_datagram().await?,
+            Conn::Quic(conn) => conn.read_datagram().await?,
+        })
+    }
+}
@@ -0,0 +1,70 @@
+//! Run the loopback benchmarks once and print the results, see [`webtransport_quinn::bench`].
+//! Build with `--release`, since debug builds are much slower.
+
+use webtransport_quinn::bench::{Loopback, Scenario, Transport};
+
+const USAGE: &str = "usage: webtransport-bench [options]
+
+options:
+  --transport <webtransport|quic|both>  the protocols to measure (default: both)
+  --opens <n>                           streams opened one after another (default: 1000)
+  --acceptors <n>                       tasks accepting streams at once (default: 256)
+  --accepts <n>                         streams accepted by them in total (default: 1000)
+  --streams <n>                         concurrent streams for the transfer (default: 4)
+  --bytes <n>                           bytes written to each stream (default: 16777216)
+  --chunk <n>                           size of each write (default: 16384)
+  --datagrams <n>                       datagrams sent (default: 10000)
+  --datagram-size <n>                   payload size of each datagram (default: 1000)";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut scenario = Scenario::default();
+    let mut transports = vec![Transport::WebTransport, Transport::Quic];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            return Ok(());
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {arg}\n\n{USAGE}"))?;
+
+        let count = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid value for {arg}: {value}"))
+        };
+
+        match arg.as_str() {
+            "--transport" => {
+                transports = match value.as_str() {
+                    "webtransport" => vec![Transport::WebTransport],
+                    "quic" => vec![Transport::Quic],
+                    "both" => vec![Transport::WebTransport, Transport::Quic],
+                    _ => return Err(format!("unknown transport: {value}").into()),
+                }
+            }
+            "--opens" => scenario.opens = count()?,
+            "--acceptors" => scenario.acceptors = count()?,
+            "--accepts" => scenario.accepts = count()?,
+            "--streams" => scenario.streams = count()?,
+            "--bytes" => scenario.bytes = count()?,
+            "--chunk" => scenario.chunk = count()?,
+            "--datagrams" => scenario.datagrams = count()?,
+            "--datagram-size" => scenario.datagram_size = count()?,
+            _ => return Err(format!("unknown option: {arg}\n\n{USAGE}").into()),
+        }
+    }
+
+    println!("{scenario:?}");
+
+    for transport in transports {
+        let loopback = Loopback::new(transport).await?;
+        println!("{}", loopback.run(&scenario).await?);
+    }
+
+    Ok(())
+}
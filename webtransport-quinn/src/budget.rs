@@ -0,0 +1,44 @@
+/// A cap on the memory used to buffer data for each QUIC connection, see [`Self::apply`].
+///
+/// Quinn enforces the limits with flow control, so a slow or malicious peer is held back instead of buffered:
+/// writes wait once the peer hasn't acknowledged `send` bytes, and the peer can't send more than `recv` unread bytes.
+/// A peer that ignores flow control is a protocol violation, which closes the connection.
+/// Datagrams are not flow controlled, so the oldest are dropped instead once `datagrams` bytes are buffered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// The maximum number of bytes of incoming stream data that haven't been read.
+    pub recv: u64,
+
+    /// The maximum number of bytes of outgoing stream data that haven't been acknowledged.
+    pub send: u64,
+
+    /// The maximum number of bytes of datagrams to buffer, in each direction.
+    pub datagrams: usize,
+}
+
+impl MemoryBudget {
+    /// Split the total number of bytes between incoming and outgoing data, reserving 1/8 for datagrams.
+    pub fn new(total: u64) -> Self {
+        let datagrams = total / 16;
+        let streams = (total - 2 * datagrams) / 2;
+
+        Self {
+            recv: streams,
+            send: streams,
+            datagrams: datagrams as usize,
+        }
+    }
+
+    /// Configure the flow control windows and datagram buffers used by each connection.
+    /// The cap applies per connection, which may carry several sessions when it's shared, so they split the budget between them.
+    pub fn apply(&self, config: &mut quinn::TransportConfig) {
+        let recv = quinn::VarInt::from_u64(self.recv).unwrap_or(quinn::VarInt::MAX);
+
+        config
+            .receive_window(recv)
+            .stream_receive_window(recv)
+            .send_window(self.send)
+            .datagram_receive_buffer_size(Some(self.datagrams))
+            .datagram_send_buffer_size(self.datagrams);
+    }
+}
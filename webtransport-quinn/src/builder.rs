@@ -0,0 +1,667 @@
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::{
+    CertificateHash, Client, Compat, Server, SessionCache, SessionLimits, SessionListener,
+    SessionMetrics, ALPN,
+};
+
+/// An error returned when building a [`Client`] or [`Server`], see [`ClientBuilder`] and [`ServerBuilder`].
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A certificate or key was rejected, or no root certificates were found.
+    #[error("tls error: {0}")]
+    Tls(String),
+}
+
+/// The congestion controller used by every connection of an endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CongestionControl {
+    /// CUBIC, as used by TCP on most platforms.
+    #[default]
+    Cubic,
+
+    /// NewReno, which is more conservative than CUBIC.
+    NewReno,
+
+    /// BBR, which probes for bandwidth instead of backing off on loss. Quinn considers it experimental.
+    Bbr,
+}
+
+impl CongestionControl {
+    pub(crate) fn apply(self, transport: &mut quinn::TransportConfig) {
+        use quinn::congestion;
+
+        // Quinn takes the factory by type rather than as a trait object, so each arm installs its own.
+        match self {
+            Self::Cubic => {
+                let config = congestion::CubicConfig::default();
+                transport.congestion_controller_factory(Arc::new(config));
+            }
+            Self::NewReno => {
+                let config = congestion::NewRenoConfig::default();
+                transport.congestion_controller_factory(Arc::new(config));
+            }
+            Self::Bbr => {
+                let config = congestion::BbrConfig::default();
+                transport.congestion_controller_factory(Arc::new(config));
+            }
+        }
+    }
+}
+
+// The QUIC transport parameters shared by both builders, with our options applied on top.
+#[derive(Default)]
+struct Transport {
+    config: quinn::TransportConfig,
+    congestion: Option<CongestionControl>,
+    keep_alive: Option<Duration>,
+}
+
+impl Transport {
+    fn build(self) -> Arc<quinn::TransportConfig> {
+        let mut config = self.config;
+        if let Some(congestion) = self.congestion {
+            congestion.apply(&mut config);
+        }
+
+        if let Some(interval) = self.keep_alive {
+            config.keep_alive_interval(Some(interval));
+        }
+
+        Arc::new(config)
+    }
+}
+
+/// How a [`Server`] authenticates clients with certificates (mTLS), see [`ServerBuilder::client_auth`].
+///
+/// This is meant for service-to-service deployments, since browsers prompt the user to pick a certificate.
+#[derive(Clone, Debug)]
+pub enum ClientAuth {
+    /// Require a certificate signed by one of the given roots, refusing the handshake otherwise.
+    ///
+    /// The roots can't be empty, since no client could connect; [`ServerBuilder::build`] fails instead.
+    Required(Vec<rustls::Certificate>),
+
+    /// Verify the certificate against the given roots if the client sends one, but accept anonymous clients too.
+    Optional(Vec<rustls::Certificate>),
+}
+
+impl ClientAuth {
+    // Fails without any roots, since no client could be verified.
+    // The verifier is passed straight to the builder, since rustls only exports its trait with dangerous_configuration.
+    fn apply(
+        &self,
+        builder: rustls::ConfigBuilder<rustls::ServerConfig, rustls::WantsVerifier>,
+    ) -> Result<
+        rustls::ConfigBuilder<rustls::ServerConfig, rustls::server::WantsServerCert>,
+        BuildError,
+    > {
+        let (Self::Required(certs) | Self::Optional(certs)) = self;
+        if certs.is_empty() {
+            return Err(BuildError::Tls(
+                "client authentication requires a root certificate".to_string(),
+            ));
+        }
+
+        Ok(match self {
+            Self::Required(certs) => builder.with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(roots(false, certs)?).boxed(),
+            ),
+            Self::Optional(certs) => builder.with_client_cert_verifier(
+                rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(roots(false, certs)?)
+                    .boxed(),
+            ),
+        })
+    }
+}
+
+/// Configures the TLS and QUIC setup of a [`Client`] once, instead of assembling a [`quinn::Endpoint`] by hand.
+///
+/// By default the client binds to an ephemeral port on every interface, trusts the platform's root certificates, and offers the `h3` ALPN.
+/// [`Self::build`] creates the endpoint with Quinn's default runtime, so it has to be called within a Tokio runtime.
+/// The settings of the [`Client`] itself (ex. [`Client::set_pooling`]) can still be changed afterwards.
+pub struct ClientBuilder {
+    bind: SocketAddr,
+    native_roots: bool,
+    roots: Vec<rustls::Certificate>,
+    hashes: Vec<CertificateHash>,
+    tls: Option<rustls::ClientConfig>,
+    identity: Option<(Vec<rustls::Certificate>, rustls::PrivateKey)>,
+    alpn: Vec<Vec<u8>>,
+    key_log: bool,
+    zero_rtt: bool,
+    transport: Transport,
+    cache: SessionCache,
+    compat: Compat,
+    metrics: Option<Arc<dyn SessionMetrics>>,
+    listener: Option<Arc<dyn SessionListener>>,
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self {
+            bind: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            native_roots: true,
+            roots: Vec::new(),
+            hashes: Vec::new(),
+            tls: None,
+            identity: None,
+            alpn: vec![ALPN.to_vec()],
+            key_log: false,
+            zero_rtt: false,
+            transport: Transport::default(),
+            cache: SessionCache::default(),
+            compat: Compat::default(),
+            metrics: None,
+            listener: None,
+        }
+    }
+
+    /// Bind the endpoint to the given address, ex. `0.0.0.0:0` on hosts without IPv6.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = addr;
+        self
+    }
+
+    /// Trust the given root certificate, in addition to the platform's unless [`Self::without_native_roots`] is used.
+    pub fn add_root_certificate(mut self, cert: rustls::Certificate) -> Self {
+        self.roots.push(cert);
+        self
+    }
+
+    /// Don't trust the platform's root certificates, only those added with [`Self::add_root_certificate`].
+    pub fn without_native_roots(mut self) -> Self {
+        self.native_roots = false;
+        self
+    }
+
+    /// Trust servers by the hash of their certificate instead of the roots, like the `serverCertificateHashes` option of browsers.
+    ///
+    /// As with browsers, the certificate has to be valid for at most [`CertificateHash::MAX_VALIDITY`], and the server name and chain aren't checked.
+    /// This is ignored with [`Self::tls_config`].
+    ///
+    /// This requires the `cert-hashes` feature, which enables the `dangerous_configuration` feature of rustls.
+    #[cfg(feature = "cert-hashes")]
+    pub fn server_certificate_hashes(mut self, hashes: Vec<CertificateHash>) -> Self {
+        self.hashes = hashes;
+        self
+    }
+
+    /// Use the given TLS config instead of building one from the roots, ex. for a custom certificate verifier.
+    ///
+    /// The ALPN protocols, key log and session cache of this builder are still applied.
+    pub fn tls_config(mut self, tls: rustls::ClientConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Authenticate to servers that ask for a client certificate (mTLS) with the given chain, starting with the end-entity certificate, and its private key.
+    ///
+    /// This is ignored with [`Self::tls_config`], which should include the certificate itself. See [`ServerBuilder::client_auth`].
+    pub fn client_certificate(
+        mut self,
+        certs: Vec<rustls::Certificate>,
+        key: rustls::PrivateKey,
+    ) -> Self {
+        self.identity = Some((certs, key));
+        self
+    }
+
+    /// Offer the given ALPN protocols during the handshake, only `h3` by default.
+    ///
+    /// WebTransport requires `h3`, so this is only useful to offer other protocols first to a server that speaks them.
+    pub fn alpn(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn = protocols;
+        self
+    }
+
+    /// Write the TLS secrets to the file named by the `SSLKEYLOGFILE` environment variable, if set, so captured traffic can be decrypted in Wireshark.
+    ///
+    /// Anyone with the file can decrypt the traffic, so only use this during development.
+    pub fn key_log(mut self) -> Self {
+        self.key_log = true;
+        self
+    }
+
+    /// Use the given QUIC transport parameters (ex. idle timeout, stream limits) instead of Quinn's defaults.
+    ///
+    /// The congestion control and keep-alive of this builder are applied on top.
+    pub fn transport_config(mut self, transport: quinn::TransportConfig) -> Self {
+        self.transport.config = transport;
+        self
+    }
+
+    /// Use the given congestion controller, CUBIC by default.
+    pub fn congestion_control(mut self, congestion: CongestionControl) -> Self {
+        self.transport.congestion = Some(congestion);
+        self
+    }
+
+    /// Send a keep-alive at the given interval, so idle connections aren't closed by the idle timeout or a NAT.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.transport.keep_alive = Some(interval);
+        self
+    }
+
+    /// Enable TLS early data, so [`Client::connect_0rtt`] can send the CONNECT request in 0-RTT when resuming a session.
+    pub fn zero_rtt(mut self) -> Self {
+        self.zero_rtt = true;
+        self
+    }
+
+    /// Cache TLS sessions so later connections can do a resumed handshake, in memory by default.
+    pub fn session_cache(mut self, cache: SessionCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Choose which revision of the WebTransport draft to speak, see [`Client::set_compat`].
+    pub fn compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Report the events of each session to the given metrics, see [`Client::set_metrics`].
+    pub fn metrics<M: SessionMetrics + 'static>(mut self, metrics: M) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Report the lifecycle of each session to the given listener, see [`Client::set_listener`].
+    pub fn listener<L: SessionListener + 'static>(mut self, listener: L) -> Self {
+        self.listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Bind the endpoint and create the client.
+    pub fn build(self) -> Result<Client, BuildError> {
+        let mut tls = match self.tls {
+            Some(tls) => tls,
+            None => {
+                // The roots are replaced by the hashes below, but rustls needs some to build the config.
+                let roots = match self.hashes.is_empty() {
+                    true => roots(self.native_roots, &self.roots)?,
+                    false => rustls::RootCertStore::empty(),
+                };
+                let builder = rustls::ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_root_certificates(roots);
+
+                let tls = match self.identity {
+                    Some((certs, key)) => builder
+                        .with_client_auth_cert(certs, key)
+                        .map_err(|err| BuildError::Tls(err.to_string()))?,
+                    None => builder.with_no_client_auth(),
+                };
+
+                #[cfg(feature = "cert-hashes")]
+                let tls = {
+                    let mut tls = tls;
+                    if !self.hashes.is_empty() {
+                        let verifier = crate::verify::HashVerifier::new(self.hashes);
+                        tls.dangerous().set_certificate_verifier(Arc::new(verifier));
+                    }
+                    tls
+                };
+
+                tls
+            }
+        };
+
+        tls.alpn_protocols = self.alpn;
+        self.cache.apply(&mut tls);
+
+        if self.zero_rtt {
+            tls.enable_early_data = true;
+        }
+
+        if self.key_log {
+            tls.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+
+        let mut config = quinn::ClientConfig::new(Arc::new(tls));
+        config.transport_config(self.transport.build());
+
+        let mut endpoint = quinn::Endpoint::client(self.bind)?;
+        endpoint.set_default_client_config(config);
+
+        let mut client = Client::new(endpoint);
+        client.set_compat(self.compat);
+
+        if let Some(metrics) = self.metrics {
+            client.set_metrics(metrics);
+        }
+
+        if let Some(listener) = self.listener {
+            client.set_listener(listener);
+        }
+
+        Ok(client)
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configures the TLS and QUIC setup of a [`Server`] once, instead of assembling a [`quinn::ServerConfig`] by hand.
+///
+/// By default the server listens on port 443 of every interface and offers the `h3` ALPN.
+/// [`Self::build`] creates the endpoint with Quinn's default runtime, so it has to be called within a Tokio runtime.
+/// The policies of the [`Server`] itself (ex. [`Server::set_origin_policy`]) can still be changed afterwards.
+pub struct ServerBuilder {
+    bind: SocketAddr,
+    tls: Tls,
+    client_auth: Option<ClientAuth>,
+    alpn: Vec<Vec<u8>>,
+    key_log: bool,
+    zero_rtt: bool,
+    transport: Transport,
+    compat: Compat,
+    max_sessions: Option<usize>,
+    max_sessions_per_connection: u32,
+    limits: Option<SessionLimits>,
+    metrics: Option<Arc<dyn SessionMetrics>>,
+    listener: Option<Arc<dyn SessionListener>>,
+}
+
+enum Tls {
+    Cert(Vec<rustls::Certificate>, rustls::PrivateKey),
+    Config(rustls::ServerConfig),
+}
+
+impl ServerBuilder {
+    /// Serve the given certificate chain, starting with the end-entity certificate, and its private key, both DER encoded.
+    pub fn new(certs: Vec<rustls::Certificate>, key: rustls::PrivateKey) -> Self {
+        Self::with_tls(Tls::Cert(certs, key))
+    }
+
+    /// Serve the certificate chain and private key (PKCS#8, PKCS#1 or SEC1) in the given PEM files, ex. as written by certbot.
+    pub fn from_pem(certs: &[u8], key: &[u8]) -> Result<Self, BuildError> {
+        let certs: Vec<_> = rustls_pemfile::certs(&mut &*certs)?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+
+        if certs.is_empty() {
+            return Err(BuildError::Tls("no certificates".to_string()));
+        }
+
+        let mut key = key;
+        let key = loop {
+            match rustls_pemfile::read_one(&mut key)? {
+                Some(
+                    rustls_pemfile::Item::PKCS8Key(key)
+                    | rustls_pemfile::Item::RSAKey(key)
+                    | rustls_pemfile::Item::ECKey(key),
+                ) => break rustls::PrivateKey(key),
+                Some(_) => continue,
+                None => return Err(BuildError::Tls("no private key".to_string())),
+            }
+        };
+
+        Ok(Self::new(certs, key))
+    }
+
+    /// Use the given TLS config instead of building one from a certificate, ex. to resolve certificates by SNI or verify clients with a custom verifier.
+    ///
+    /// The ALPN protocols and key log of this builder are still applied.
+    pub fn with_tls_config(tls: rustls::ServerConfig) -> Self {
+        Self::with_tls(Tls::Config(tls))
+    }
+
+    fn with_tls(tls: Tls) -> Self {
+        Self {
+            bind: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 443)),
+            tls,
+            client_auth: None,
+            alpn: vec![ALPN.to_vec()],
+            key_log: false,
+            zero_rtt: false,
+            transport: Transport::default(),
+            compat: Compat::default(),
+            max_sessions: None,
+            max_sessions_per_connection: 1,
+            limits: None,
+            metrics: None,
+            listener: None,
+        }
+    }
+
+    /// Listen on the given address, ex. `0.0.0.0:4443` on hosts without IPv6.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = addr;
+        self
+    }
+
+    /// Accept the given ALPN protocols during the handshake, only `h3` by default.
+    ///
+    /// Every connection is treated as WebTransport, so this is only useful to accept another spelling used by old clients.
+    pub fn alpn(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn = protocols;
+        self
+    }
+
+    /// Write the TLS secrets to the file named by `SSLKEYLOGFILE`, see [`ClientBuilder::key_log`].
+    pub fn key_log(mut self) -> Self {
+        self.key_log = true;
+        self
+    }
+
+    /// Accept TLS early data from clients resuming a session, see [`Server::set_zero_rtt`].
+    pub fn zero_rtt(mut self) -> Self {
+        self.zero_rtt = true;
+        self
+    }
+
+    /// Use the given QUIC transport parameters (ex. idle timeout, stream limits) instead of Quinn's defaults.
+    ///
+    /// The congestion control and keep-alive of this builder are applied on top.
+    pub fn transport_config(mut self, transport: quinn::TransportConfig) -> Self {
+        self.transport.config = transport;
+        self
+    }
+
+    /// Use the given congestion controller, CUBIC by default.
+    pub fn congestion_control(mut self, congestion: CongestionControl) -> Self {
+        self.transport.congestion = Some(congestion);
+        self
+    }
+
+    /// Send a keep-alive at the given interval, so idle connections aren't closed by the idle timeout or a NAT.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.transport.keep_alive = Some(interval);
+        self
+    }
+
+    /// Choose which revision of the WebTransport draft to speak, see [`Server::set_compat`].
+    pub fn compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Limit the number of open sessions, see [`Server::set_max_sessions`].
+    pub fn max_sessions(mut self, max: usize) -> Self {
+        self.max_sessions = Some(max);
+        self
+    }
+
+    /// Allow clients to establish multiple sessions on the same connection, see [`Server::set_max_sessions_per_connection`].
+    pub fn max_sessions_per_connection(mut self, max: u32) -> Self {
+        self.max_sessions_per_connection = max;
+        self
+    }
+
+    /// Advertise flow control limits for each session, see [`Server::set_session_limits`].
+    pub fn session_limits(mut self, limits: SessionLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Authenticate clients with certificates (mTLS), see [`ClientAuth`].
+    ///
+    /// This is ignored with [`Self::with_tls_config`], which should include its own verifier, and can't be combined with [`Self::zero_rtt`],
+    /// since requests in 0-RTT would be handled before the client's certificate is verified.
+    pub fn client_auth(mut self, auth: ClientAuth) -> Self {
+        self.client_auth = Some(auth);
+        self
+    }
+
+    /// Report the events of each session to the given metrics, see [`Server::set_metrics`].
+    pub fn metrics<M: SessionMetrics + 'static>(mut self, metrics: M) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Report the lifecycle of each session to the given listener, see [`Server::set_listener`].
+    pub fn listener<L: SessionListener + 'static>(mut self, listener: L) -> Self {
+        self.listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Bind the endpoint and create the server.
+    pub fn build(self) -> Result<Server, BuildError> {
+        let mut tls = match self.tls {
+            Tls::Config(tls) => tls,
+            Tls::Cert(certs, key) => {
+                if self.zero_rtt && self.client_auth.is_some() {
+                    return Err(BuildError::Tls(
+                        "0-RTT can't be combined with client authentication".to_string(),
+                    ));
+                }
+
+                let builder = rustls::ServerConfig::builder().with_safe_defaults();
+                let builder = match &self.client_auth {
+                    Some(auth) => auth.apply(builder)?,
+                    None => builder.with_no_client_auth(),
+                };
+
+                builder
+                    .with_single_cert(certs, key)
+                    .map_err(|err| BuildError::Tls(err.to_string()))?
+            }
+        };
+
+        tls.alpn_protocols = self.alpn;
+
+        if self.key_log {
+            tls.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+
+        // Quinn only accepts 0-RTT with this exact value, since QUIC doesn't limit the amount of early data like TLS does.
+        if self.zero_rtt {
+            tls.max_early_data_size = u32::MAX;
+        }
+
+        let mut config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+        config.transport_config(self.transport.build());
+
+        let mut server = Server::bind(self.bind, config)?;
+        server.set_compat(self.compat);
+        server.set_max_sessions(self.max_sessions);
+        server.set_max_sessions_per_connection(self.max_sessions_per_connection);
+        server.set_session_limits(self.limits);
+        server.set_zero_rtt(self.zero_rtt);
+
+        if let Some(metrics) = self.metrics {
+            server.set_metrics(metrics);
+        }
+
+        if let Some(listener) = self.listener {
+            server.set_listener(listener);
+        }
+
+        Ok(server)
+    }
+}
+
+fn roots(native: bool, certs: &[rustls::Certificate]) -> Result<rustls::RootCertStore, BuildError> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    if native {
+        // Skip any platform certificates that rustls can't parse, like a browser would.
+        for cert in rustls_native_certs::load_native_certs()? {
+            roots.add(&rustls::Certificate(cert.0)).ok();
+        }
+    }
+
+    for cert in certs {
+        roots
+            .add(cert)
+            .map_err(|err| BuildError::Tls(err.to_string()))?;
+    }
+
+    if roots.is_empty() {
+        return Err(BuildError::Tls("no root certificates".to_string()));
+    }
+
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cert() -> (rustls::Certificate, rustls::PrivateKey) {
+        let gen = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = rustls::Certificate(gen.serialize_der().unwrap());
+        (cert, rustls::PrivateKey(gen.serialize_private_key_der()))
+    }
+
+    fn server(auth: ClientAuth) -> ServerBuilder {
+        let (cert, key) = cert();
+        ServerBuilder::new(vec![cert], key)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .client_auth(auth)
+    }
+
+    #[tokio::test]
+    async fn client_auth() {
+        let (root, _) = cert();
+        assert!(server(ClientAuth::Required(vec![root.clone()]))
+            .build()
+            .is_ok());
+        assert!(server(ClientAuth::Optional(vec![root])).build().is_ok());
+    }
+
+    #[test]
+    fn client_auth_without_roots() {
+        assert!(server(ClientAuth::Required(Vec::new())).build().is_err());
+        assert!(server(ClientAuth::Optional(Vec::new())).build().is_err());
+    }
+
+    #[test]
+    fn client_auth_zero_rtt() {
+        let (root, _) = cert();
+        let server = server(ClientAuth::Required(vec![root])).zero_rtt();
+        assert!(server.build().is_err());
+    }
+
+    #[tokio::test]
+    async fn tls_config_ignores_client_auth() {
+        let (cert, key) = cert();
+        let tls = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+
+        // The client auth is ignored, so it doesn't conflict with 0-RTT either.
+        let server = ServerBuilder::with_tls_config(tls)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .client_auth(ClientAuth::Required(Vec::new()))
+            .zero_rtt();
+        assert!(server.build().is_ok());
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use futures::future::Either;
+
+/// A future returned by [`Clock::sleep`].
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// A source of time for the timeouts and policies in this crate, so tests can control it.
+///
+/// This is used by [`crate::Session::with_timeout`], the [`crate::HandlerPolicy`] timeout, the [`crate::IdlePolicy`],
+/// and by the [`crate::Client`] when racing addresses and expiring preconnected connections.
+/// Each session also uses it for stall detection, rate limits, scheduling rounds, [`crate::Session::path_events`],
+/// and the durations in its stats, journal and metrics.
+/// QUIC itself (ex. the idle timeout and loss detection) uses Quinn's runtime instead.
+///
+/// The default is [`SystemClock`]. Use [`ManualClock`] to advance time by hand,
+/// or implement it with `tokio::time::Instant::now` and `tokio::time::sleep` to work with `tokio::time::pause`.
+pub trait Clock: Send + Sync {
+    /// Return the current time.
+    fn now(&self) -> Instant;
+
+    /// Return a future that completes once the duration has elapsed.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// The real time, using [`async_std::task::sleep`] which works with any runtime.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// A clock that only moves when [`Self::advance`] is called, so time-dependent behavior can be tested deterministically.
+///
+/// Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    inner: Arc<Mutex<Manual>>,
+}
+
+struct Manual {
+    now: Instant,
+
+    // The deadline of each pending sleep and the task to wake.
+    sleepers: Vec<(Instant, Waker)>,
+}
+
+impl ManualClock {
+    /// Create a clock starting at the current time.
+    pub fn new() -> Self {
+        let manual = Manual {
+            now: Instant::now(),
+            sleepers: Vec::new(),
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(manual)),
+        }
+    }
+
+    /// Move the clock forward, completing any sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let mut manual = self.inner.lock().unwrap();
+        manual.now += duration;
+
+        let now = manual.now;
+        let (due, pending) = manual
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= now);
+        manual.sleepers = pending;
+        drop(manual);
+
+        for (_, waker) in due {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let inner = self.inner.clone();
+        let deadline = self.now() + duration;
+
+        Box::pin(futures::future::poll_fn(move |cx: &mut Context<'_>| {
+            let mut manual = inner.lock().unwrap();
+            if manual.now >= deadline {
+                return Poll::Ready(());
+            }
+
+            let registered = manual
+                .sleepers
+                .iter()
+                .any(|(at, waker)| *at == deadline && waker.will_wake(cx.waker()));
+            if !registered {
+                manual.sleepers.push((deadline, cx.waker().clone()));
+            }
+
+            Poll::Pending
+        }))
+    }
+}
+
+// Run the future until it completes, or return None if the sleep completes first.
+pub(crate) async fn timeout<F: Future>(sleep: Sleep, fut: F) -> Option<F::Output> {
+    match futures::future::select(pin!(fut), sleep).await {
+        Either::Left((res, _)) => Some(res),
+        Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,38 @@
+/// A revision of the WebTransport draft, see [`Compat`].
+///
+/// - `Draft02` is used by Chrome, which enables WebTransport with the deprecated settings and expects a draft header in the response.
+/// - `Draft07` is used by Firefox and newer implementations.
+pub use webtransport_proto::Draft;
+
+/// Which revision of the WebTransport draft to speak, see [`crate::Server::set_compat`] and [`crate::Client::set_compat`].
+///
+/// Browsers implement different drafts, which differ in the HTTP/3 settings and headers used to negotiate the session.
+/// By default we send the settings for every draft we support, and adapt to whatever the peer sends back.
+/// The draft that was used is available via [`crate::Session::draft`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compat {
+    /// Detect the draft from the peer's settings.
+    #[default]
+    Auto,
+
+    /// Only speak the given draft, even if the peer claims to support another.
+    Force(Draft),
+}
+
+impl Compat {
+    // The draft we tell the peer, or None to advertise all of them.
+    pub(crate) fn advertise(self) -> Option<Draft> {
+        match self {
+            Self::Auto => None,
+            Self::Force(draft) => Some(draft),
+        }
+    }
+
+    // The draft to use, given what the peer advertised.
+    pub(crate) fn resolve(self, peer: Draft) -> Draft {
+        match self {
+            Self::Auto => peer,
+            Self::Force(draft) => draft,
+        }
+    }
+}
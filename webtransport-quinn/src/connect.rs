@@ -1,9 +1,20 @@
 use std::io;
 
-use webtransport_proto::{ConnectRequest, ConnectResponse, VarInt};
+use webtransport_proto::{ConnectRequest, ConnectResponse, Draft, VarInt};
 
 use thiserror::Error;
 
+use crate::{trace::event, MAX_FIELD_SECTION_SIZE};
+
+// The HTTP/3 error code used to refuse a request without processing it.
+pub const H3_REQUEST_REJECTED: quinn::VarInt = quinn::VarInt::from_u32(0x10b);
+
+// The HTTP/3 error code used to abandon a request that was never answered.
+pub const H3_REQUEST_CANCELLED: quinn::VarInt = quinn::VarInt::from_u32(0x10c);
+
+// The largest frame type and length, each a VarInt.
+const MAX_FRAME_HEADER: u64 = 16;
+
 #[derive(Error, Debug)]
 pub enum ConnectError {
     #[error("quic stream was closed early")]
@@ -23,6 +34,26 @@ pub enum ConnectError {
 
     #[error("http error status: {0}")]
     ErrorStatus(http::StatusCode),
+
+    /// The server redirected the request with a 3xx status, to the `location` resolved against the request URI.
+    #[error("redirected with {status} to {location}")]
+    Redirect {
+        status: http::StatusCode,
+        location: http::Uri,
+    },
+}
+
+// The result of reading the first request on a stream.
+pub enum Accepted {
+    Connect(Connect),
+
+    // A plain HTTP/3 request, including the bytes read so far.
+    Request {
+        method: http::Method,
+        buf: Vec<u8>,
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+    },
 }
 
 pub struct Connect {
@@ -30,19 +61,45 @@ pub struct Connect {
     request: ConnectRequest,
 
     // A reference to the send/recv stream, so we don't close it until dropped.
-    send: quinn::SendStream,
+    // The session takes both to send and receive capsules, leaving None.
+    streams: Option<(quinn::SendStream, quinn::RecvStream)>,
 
-    #[allow(dead_code)]
-    recv: quinn::RecvStream,
+    // The response to the request, once it was sent or received.
+    // Boxed so a pending request stays small, since it waits in queues.
+    response: Option<Box<http::Response<()>>>,
+
+    // Set once there's a response, so the request isn't cancelled when dropped.
+    answered: bool,
 }
 
 impl Connect {
     pub async fn accept(conn: &quinn::Connection) -> Result<Self, ConnectError> {
         // Accept the stream that will be used to send the HTTP CONNECT request.
         // If they try to send any other type of HTTP request, we will error out.
-        let (send, mut recv) = conn.accept_bi().await?;
-        let mut buf = Vec::new();
+        let (send, recv) = conn.accept_bi().await?;
+
+        match Self::read(send, recv).await? {
+            Accepted::Connect(connect) => Ok(connect),
+            Accepted::Request { method, .. } => {
+                Err(webtransport_proto::ConnectError::WrongMethod(Some(method)).into())
+            }
+        }
+    }
 
+    // Read the request on a newly accepted stream, which might not be a CONNECT.
+    pub async fn read(
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+    ) -> Result<Accepted, ConnectError> {
+        Self::read_after(Vec::new(), send, recv).await
+    }
+
+    // Like read, but the caller already read the start of the stream (ex. the frame type) into the buffer.
+    pub async fn read_after(
+        mut buf: Vec<u8>,
+        send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+    ) -> Result<Accepted, ConnectError> {
         // Read the request from the client, buffering more data until we get a full response.
         loop {
             // Read more data into the buffer.
@@ -59,46 +116,102 @@ impl Connect {
                 // It worked, return it.
                 Ok(req) => req,
 
+                // We advertised MAX_FIELD_SECTION_SIZE, so don't buffer a HEADERS frame that's any larger.
+                // The encoded block is never larger than the field section it decodes to, except for the frame header.
+                Err(webtransport_proto::ConnectError::UnexpectedEnd)
+                    if buf.len() as u64 > MAX_FIELD_SECTION_SIZE + MAX_FRAME_HEADER =>
+                {
+                    return Err(webtransport_proto::ConnectError::HeadersTooLarge {
+                        size: buf.len() as u64,
+                        max: MAX_FIELD_SECTION_SIZE,
+                    }
+                    .into())
+                }
+
                 // We didn't have enough data in the buffer, so we'll read more and try again.
                 Err(webtransport_proto::ConnectError::UnexpectedEnd) => continue,
 
+                // A plain HTTP/3 request, which is up to the caller to handle.
+                Err(webtransport_proto::ConnectError::WrongMethod(Some(method))) => {
+                    return Ok(Accepted::Request {
+                        method,
+                        buf,
+                        send,
+                        recv,
+                    })
+                }
+
                 // Some other fatal error.
                 Err(e) => return Err(e.into()),
             };
 
+            event!(debug, uri = %request.uri, "received CONNECT");
+
             // The request was successfully decoded, so we can send a response.
-            return Ok(Self {
+            return Ok(Accepted::Connect(Self {
                 request,
-                send,
-                recv,
-            });
+                streams: Some((send, recv)),
+                response: None,
+                answered: false,
+            }));
         }
     }
 
     // Called by the server to send a response to the client.
-    pub async fn respond(&mut self, status: http::StatusCode) -> Result<(), quinn::WriteError> {
-        let resp = ConnectResponse { status };
+    // Fails without writing anything if the response exceeds the client's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    pub async fn respond(
+        &mut self,
+        status: http::StatusCode,
+        headers: http::HeaderMap,
+        draft: Draft,
+        max_field_section_size: u64,
+    ) -> Result<(), ConnectError> {
+        let resp = ConnectResponse {
+            status,
+            headers,
+            draft,
+        };
 
         let mut buf = Vec::new();
-        resp.encode(&mut buf);
+        resp.encode_max(&mut buf, max_field_section_size)?;
 
-        self.send.write_all(&buf).await?;
+        self.send().write_all(&buf).await?;
+        event!(debug, status = status.as_u16(), "sent CONNECT response");
+
+        self.response = Some(into_response(resp));
+        self.answered = true;
 
         Ok(())
     }
 
-    pub async fn open(conn: &quinn::Connection, uri: &http::Uri) -> Result<Self, ConnectError> {
-        // Create a new stream that will be used to send the CONNECT frame.
-        let (mut send, mut recv) = conn.open_bi().await?;
+    // Finish the stream after a response, waiting until the peer has acknowledged it.
+    pub async fn finish(&mut self) -> Result<(), quinn::WriteError> {
+        self.send().finish().await
+    }
 
+    // Send the CONNECT request on a new stream and wait for the response.
+    // The stream is opened by the caller so it knows the ID, which is used to interpret a GOAWAY.
+    pub async fn open(
+        mut send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+        uri: &http::Uri,
+        headers: &http::HeaderMap,
+        max_field_section_size: u64,
+    ) -> Result<Self, ConnectError> {
         // Create a new CONNECT request that we'll send using HTTP/3
-        let request = ConnectRequest { uri: uri.clone() };
+        let request = ConnectRequest {
+            uri: uri.clone(),
+            headers: headers.clone(),
+        };
 
         // Encode our connect request into a buffer and write it to the stream.
+        // This fails if the URI is too long for the server's SETTINGS_MAX_FIELD_SECTION_SIZE.
         let mut buf = Vec::new();
-        request.encode(&mut buf);
+        request.encode_max(&mut buf, max_field_section_size)?;
         send.write_all(&buf).await?;
 
+        event!(debug, %uri, "sent CONNECT");
+
         buf.clear();
 
         // Read the response from the server, buffering more data until we get a full response.
@@ -124,6 +237,25 @@ impl Connect {
                 Err(e) => return Err(e.into()),
             };
 
+            event!(
+                debug,
+                status = res.status.as_u16(),
+                "received CONNECT response"
+            );
+
+            if res.status.is_redirection() {
+                if let Some(location) = redirect_location(uri, &res.headers) {
+                    return Err(ConnectError::Redirect {
+                        status: res.status,
+                        location,
+                    });
+                }
+            }
+
+            if !res.status.is_success() {
+                return Err(webtransport_proto::ConnectError::WrongStatus(Some(res.status)).into());
+            }
+
             // Throw an error if we didn't get a 200 OK.
             if res.status != http::StatusCode::OK {
                 return Err(ConnectError::ErrorStatus(res.status));
@@ -131,17 +263,43 @@ impl Connect {
 
             return Ok(Self {
                 request,
-                send,
-                recv,
+                streams: Some((send, recv)),
+                response: Some(into_response(res)),
+                answered: true,
             });
         }
     }
 
+    // Refuse the request without processing it, so the client knows it's safe to retry, see RFC 9114 section 8.1.
+    pub fn reject(&mut self) {
+        event!(debug, uri = %self.request.uri, "refused CONNECT");
+
+        self.reset(H3_REQUEST_REJECTED);
+        self.answered = true;
+    }
+
+    fn reset(&mut self, code: quinn::VarInt) {
+        if let Some((send, recv)) = &mut self.streams {
+            send.reset(code).ok();
+            recv.stop(code).ok();
+        }
+    }
+
+    // Hand the stream over to the session, which uses it to send and receive capsules.
+    pub fn into_streams(mut self) -> (quinn::SendStream, quinn::RecvStream) {
+        self.streams.take().expect("streams already taken")
+    }
+
+    fn send(&mut self) -> &mut quinn::SendStream {
+        &mut self.streams.as_mut().expect("streams already taken").0
+    }
+
     // The session ID is the stream ID of the CONNECT request.
     pub fn session_id(&self) -> VarInt {
         // We gotta convert from the Quinn VarInt to the (forked) WebTransport VarInt.
         // We don't use the quinn::VarInt because that would mean a quinn dependency in webtransport-proto
-        let stream_id = quinn::VarInt::from(self.send.id());
+        let (send, _) = self.streams.as_ref().expect("streams already taken");
+        let stream_id = quinn::VarInt::from(send.id());
         VarInt::try_from(stream_id.into_inner()).unwrap()
     }
 
@@ -149,4 +307,49 @@ impl Connect {
     pub fn uri(&self) -> &http::Uri {
         &self.request.uri
     }
+
+    // The headers in the CONNECT request, excluding the pseudo-headers.
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.request.headers
+    }
+
+    // Take the response that was sent or received, for the session.
+    pub fn take_response(&mut self) -> Box<http::Response<()>> {
+        self.response.take().unwrap_or_default()
+    }
+}
+
+// Resolve the location header of a redirect, which is either an absolute URI or an absolute path on the same origin.
+fn redirect_location(uri: &http::Uri, headers: &http::HeaderMap) -> Option<http::Uri> {
+    let location = headers.get(http::header::LOCATION)?.to_str().ok()?;
+    if location.starts_with('/') && !location.starts_with("//") {
+        let mut parts = http::uri::Parts::default();
+        parts.scheme = uri.scheme().cloned();
+        parts.authority = uri.authority().cloned();
+        parts.path_and_query = Some(location.parse().ok()?);
+        return http::Uri::from_parts(parts).ok();
+    }
+
+    let location: http::Uri = location.parse().ok()?;
+    location.scheme()?;
+    location.authority()?;
+    Some(location)
+}
+
+fn into_response(resp: ConnectResponse) -> Box<http::Response<()>> {
+    let mut response = http::Response::new(());
+    *response.version_mut() = http::Version::HTTP_3;
+    *response.status_mut() = resp.status;
+    *response.headers_mut() = resp.headers;
+    Box::new(response)
+}
+
+impl Drop for Connect {
+    fn drop(&mut self) {
+        // The request was abandoned without a response (ex. the server dropped it), so tell the client instead of finishing the stream.
+        if !self.answered {
+            event!(debug, uri = %self.request.uri, "cancelled CONNECT");
+            self.reset(H3_REQUEST_CANCELLED);
+        }
+    }
 }
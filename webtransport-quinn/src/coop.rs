@@ -0,0 +1,74 @@
+use std::{
+    future::poll_fn,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+// The number of items an internal loop handles before yielding, matching Tokio's budget per task.
+pub(crate) const DEFAULT_YIELD_BUDGET: usize = 128;
+
+// A configurable yield budget, shared by everything that reads it.
+// A budget of 0 disables yielding.
+pub(crate) struct YieldBudget(AtomicUsize);
+
+impl YieldBudget {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, budget: usize) {
+        self.0.store(budget, Ordering::Relaxed)
+    }
+
+    // Start counting the work done by a loop.
+    pub fn coop(&self) -> Coop {
+        Coop::new(self.get())
+    }
+}
+
+impl Default for YieldBudget {
+    fn default() -> Self {
+        Self(AtomicUsize::new(DEFAULT_YIELD_BUDGET))
+    }
+}
+
+// Counts the work done by a loop, yielding to the runtime once the budget is spent.
+//
+// Quinn's futures are often ready immediately under load (ex. a flood of streams or datagrams),
+// so a loop that only awaits them never returns to the runtime and starves the other tasks on the worker thread.
+// We can't use Tokio's cooperative budget without depending on Tokio, so each loop keeps its own.
+pub(crate) struct Coop {
+    budget: usize,
+    remaining: usize,
+}
+
+impl Coop {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            remaining: budget,
+        }
+    }
+
+    // Spend one unit of the budget, or wake the task and return Pending once it's exhausted.
+    // The budget is refilled, so the next poll proceeds.
+    pub fn poll_proceed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.budget == 0 {
+            return Poll::Ready(());
+        }
+
+        if self.remaining == 0 {
+            self.remaining = self.budget;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        self.remaining -= 1;
+        Poll::Ready(())
+    }
+
+    // Spend one unit of the budget, yielding to the runtime once it's exhausted.
+    pub async fn proceed(&mut self) {
+        poll_fn(|cx| self.poll_proceed(cx)).await
+    }
+}
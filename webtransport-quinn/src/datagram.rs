@@ -0,0 +1,586 @@
+//! Datagrams for a session or connection, and optional layers such as [`ParityDatagrams`] on top of them.
+//! Everything here is also re-exported at the crate root.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{future::BoxFuture, FutureExt, Sink, Stream};
+
+use webtransport_proto::{Datagram, VarInt};
+
+use crate::{
+    coop::{Coop, DEFAULT_YIELD_BUDGET},
+    trace::event,
+    SendDatagramError, Session, SessionError,
+};
+
+mod parity;
+
+pub use parity::*;
+
+/// Raw HTTP/3 datagrams on a QUIC connection, each prefixed with a caller-chosen quarter stream ID.
+///
+/// This is for experimenting with HTTP/3 extensions that use datagrams (ex. CONNECT-IP) where the caller manages the requests themselves.
+/// The quarter stream ID identifies the request associated with each datagram; see [`Self::quarter_stream_id`].
+/// Nothing is validated, so the peer needs to have enabled HTTP/3 datagrams and the request needs to exist.
+///
+/// Beware that every datagram on the connection is received here, so don't mix this with another reader on the same connection.
+#[derive(Clone)]
+pub struct H3Datagrams {
+    conn: quinn::Connection,
+    congestion: Arc<Congestion>,
+}
+
+// Used to drop datagrams instead of queueing them, shared between clones.
+struct Congestion {
+    // The size of the send buffer when nothing is queued.
+    capacity: usize,
+
+    // The maximum number of queued bytes before dropping, or usize::MAX if disabled.
+    max_queued: AtomicUsize,
+
+    // Mirrors the datagrams queued by Quinn, so we can count the ones it drops.
+    queue: Mutex<Queue>,
+
+    overflow: AtomicU64,
+    expired: AtomicU64,
+    rate_limited: AtomicU64,
+    too_large: AtomicU64,
+    unsupported: AtomicU64,
+
+    // Told about each datagram that isn't sent, set by the session for its listener.
+    listener: OnceLock<DropListener>,
+}
+
+type DropListener = Box<dyn Fn(DatagramDropReason) + Send + Sync>;
+
+// The size of each datagram queued by Quinn, oldest first.
+#[derive(Default)]
+struct Queue {
+    sizes: VecDeque<usize>,
+    total: usize,
+}
+
+impl Queue {
+    // Forget the oldest datagrams until only `queued` bytes are left, since Quinn sends them in order.
+    fn sent(&mut self, queued: usize) {
+        while self.total > queued {
+            self.pop();
+        }
+    }
+
+    // Queue a datagram, returning how many Quinn dropped to make room, which it does while over capacity.
+    fn push(&mut self, size: usize, capacity: usize) -> u64 {
+        let mut dropped = 0;
+        while self.total > capacity {
+            self.pop();
+            dropped += 1;
+        }
+
+        self.sizes.push_back(size);
+        self.total += size;
+
+        dropped
+    }
+
+    fn pop(&mut self) {
+        let size = self.sizes.pop_front().unwrap_or(self.total);
+        self.total -= size;
+    }
+}
+
+/// The number of datagrams that weren't sent, by reason, returned by [`H3Datagrams::drops`] and [`Session::datagram_drops`].
+///
+/// The counters are cumulative since the session was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DatagramDrops {
+    /// Dropped because the outgoing queue was full, see [`DatagramQueue`].
+    /// For [`DatagramOverflow::DropOldest`] this is estimated, since Quinn drops them without telling us.
+    pub overflow: u64,
+
+    /// Dropped because they would have been sent after their deadline, see [`DatagramOptions::max_age`].
+    pub expired: u64,
+
+    /// Dropped because of the session's rate limit, see [`Session::set_rate_limit`].
+    pub rate_limited: u64,
+
+    /// Rejected with [`SendDatagramError::TooLarge`].
+    pub too_large: u64,
+
+    /// Rejected because the peer doesn't support datagrams or they're disabled locally.
+    pub unsupported: u64,
+}
+
+/// Why a datagram wasn't sent, with the same meaning as the counters of [`DatagramDrops`].
+///
+/// Reported for each datagram to [`crate::SessionListener::on_datagram_dropped`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatagramDropReason {
+    /// The outgoing queue was full.
+    Overflow,
+
+    /// It would have been sent after its deadline.
+    Expired,
+
+    /// The session's rate limit was exceeded.
+    RateLimited,
+
+    /// It was larger than the peer allows.
+    TooLarge,
+
+    /// The peer doesn't support datagrams or they're disabled locally.
+    Unsupported,
+}
+
+impl H3Datagrams {
+    pub fn new(conn: quinn::Connection) -> Self {
+        let congestion = Congestion {
+            capacity: conn.datagram_send_buffer_space(),
+            max_queued: AtomicUsize::new(usize::MAX),
+            queue: Default::default(),
+            overflow: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            too_large: AtomicU64::new(0),
+            unsupported: AtomicU64::new(0),
+            listener: OnceLock::new(),
+        };
+
+        Self {
+            conn,
+            congestion: Arc::new(congestion),
+        }
+    }
+
+    /// Return the quarter stream ID for the request using the given stream.
+    pub fn quarter_stream_id(stream_id: quinn::StreamId) -> quinn::VarInt {
+        let id = Datagram::quarter_stream_id(convert(stream_id.into()));
+        quinn::VarInt::from_u64(id.into_inner()).unwrap()
+    }
+
+    /// Send a datagram associated with the given quarter stream ID. See [`quinn::Connection::send_datagram`].
+    ///
+    /// The datagram is silently dropped if it would be queued behind too much data, see [`Self::set_queue`].
+    /// Every datagram that isn't sent is counted by [`Self::drops`].
+    pub fn send(
+        &self,
+        quarter_stream_id: quinn::VarInt,
+        payload: Bytes,
+    ) -> Result<(), SendDatagramError> {
+        let buf = encode(quarter_stream_id, payload);
+
+        let mut queue = self.congestion.queue.lock().unwrap();
+        self.enqueue(&mut queue, buf)
+    }
+
+    /// Send several datagrams back to back, otherwise the same as [`Self::send`].
+    ///
+    /// They're encoded before any are queued, so Quinn can transmit them together (with GSO where supported)
+    /// instead of waking up for each one. A failure stops the batch, leaving the earlier datagrams queued.
+    pub fn send_batch(
+        &self,
+        quarter_stream_id: quinn::VarInt,
+        payloads: &[Bytes],
+    ) -> Result<(), SendDatagramError> {
+        let bufs: Vec<_> = payloads
+            .iter()
+            .map(|payload| encode(quarter_stream_id, payload.clone()))
+            .collect();
+
+        let mut queue = self.congestion.queue.lock().unwrap();
+        for buf in bufs {
+            self.enqueue(&mut queue, buf)?;
+        }
+
+        Ok(())
+    }
+
+    // Hand an encoded datagram to Quinn, mirroring it in the queue.
+    fn enqueue(&self, queue: &mut Queue, buf: Bytes) -> Result<(), SendDatagramError> {
+        let congestion = &self.congestion;
+
+        if self.queued() > congestion.max_queued.load(Ordering::Relaxed) {
+            self.count_drop(DatagramDropReason::Overflow, 1);
+            return Ok(());
+        }
+
+        // Quinn only reports the space left, so once it's over capacity we can't tell if anything was sent.
+        let queued = self.queued();
+        if queued < congestion.capacity {
+            queue.sent(queued);
+        }
+
+        let size = buf.len();
+        match self.conn.send_datagram(buf) {
+            Ok(()) => {
+                let dropped = queue.push(size, congestion.capacity);
+                self.count_drop(DatagramDropReason::Overflow, dropped);
+                Ok(())
+            }
+            Err(err) => {
+                self.rejected(&err);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Send a datagram with the given options, otherwise the same as [`Self::send`].
+    pub fn send_with(
+        &self,
+        quarter_stream_id: quinn::VarInt,
+        payload: Bytes,
+        options: &DatagramOptions,
+    ) -> Result<(), SendDatagramError> {
+        if self.expire(options) {
+            return Ok(());
+        }
+
+        self.send(quarter_stream_id, payload)
+    }
+
+    // Return true and count the datagram if it would wait longer than its max age.
+    pub(crate) fn expire(&self, options: &DatagramOptions) -> bool {
+        let expired = match options.max_age {
+            Some(max_age) => self.delay() > max_age,
+            None => false,
+        };
+
+        if expired {
+            self.count_drop(DatagramDropReason::Expired, 1);
+        }
+
+        expired
+    }
+
+    // Estimate how long a new datagram would wait behind the queued ones, sent one congestion window per RTT.
+    fn delay(&self) -> Duration {
+        let queued = self.queued();
+        if queued == 0 {
+            return Duration::ZERO;
+        }
+
+        let path = self.conn.stats().path;
+        path.rtt.mul_f64(queued as f64 / path.cwnd.max(1) as f64)
+    }
+
+    // Count a datagram that Quinn refused to send.
+    fn rejected(&self, err: &quinn::SendDatagramError) {
+        let reason = match err {
+            quinn::SendDatagramError::TooLarge => DatagramDropReason::TooLarge,
+            quinn::SendDatagramError::UnsupportedByPeer | quinn::SendDatagramError::Disabled => {
+                DatagramDropReason::Unsupported
+            }
+            quinn::SendDatagramError::ConnectionLost(_) => return,
+        };
+
+        self.count_drop(reason, 1);
+    }
+
+    // Count datagrams that weren't sent, and tell the listener about each of them.
+    fn count_drop(&self, reason: DatagramDropReason, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        let congestion = &self.congestion;
+        let counter = match reason {
+            DatagramDropReason::Overflow => &congestion.overflow,
+            DatagramDropReason::Expired => &congestion.expired,
+            DatagramDropReason::RateLimited => &congestion.rate_limited,
+            DatagramDropReason::TooLarge => &congestion.too_large,
+            DatagramDropReason::Unsupported => &congestion.unsupported,
+        };
+
+        counter.fetch_add(count, Ordering::Relaxed);
+        event!(trace, ?reason, count, "datagrams dropped");
+
+        if let Some(listener) = congestion.listener.get() {
+            for _ in 0..count {
+                listener(reason);
+            }
+        }
+    }
+
+    // Report each datagram that isn't sent from now on, only the first time it's called.
+    pub(crate) fn set_listener(&self, listener: DropListener) {
+        self.congestion.listener.set(listener).ok();
+    }
+
+    // Reject a payload that doesn't fit, before it's charged against a rate limit.
+    pub(crate) fn check_size(
+        &self,
+        quarter_stream_id: quinn::VarInt,
+        size: usize,
+    ) -> Result<(), SendDatagramError> {
+        match self.max_size(quarter_stream_id) {
+            Some(max) if size > max => {
+                self.count_drop(DatagramDropReason::TooLarge, 1);
+                Err(SendDatagramError::TooLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Receive the next datagram, returning the quarter stream ID and payload. See [`quinn::Connection::read_datagram`].
+    ///
+    /// Datagrams that are too short to contain a quarter stream ID are skipped.
+    pub async fn recv(&self) -> Result<(quinn::VarInt, Bytes), SessionError> {
+        // Don't spin forever on a flood of malformed datagrams.
+        let mut coop = Coop::new(DEFAULT_YIELD_BUDGET);
+
+        loop {
+            coop.proceed().await;
+            let buf = self.conn.read_datagram().await?;
+
+            if let Ok(datagram) = Datagram::decode(buf) {
+                let id = quinn::VarInt::from_u64(datagram.quarter_stream_id.into_inner()).unwrap();
+                return Ok((id, datagram.payload));
+            }
+        }
+    }
+
+    /// Return the maximum payload size for the given quarter stream ID, or None if datagrams are unsupported.
+    /// See [`quinn::Connection::max_datagram_size`].
+    pub fn max_size(&self, quarter_stream_id: quinn::VarInt) -> Option<usize> {
+        let header = convert(quarter_stream_id).size();
+        self.conn
+            .max_datagram_size()
+            .map(|max| max.saturating_sub(header))
+    }
+
+    /// Drop new datagrams while more than the given number of bytes are queued, or always queue them with None (the default).
+    ///
+    /// Datagrams are queued when the congestion window is exhausted, so under load they wait behind everything sent before them.
+    /// For real-time data it's better to skip a datagram than deliver it late, so this bounds the latency instead.
+    /// A limit of 0 drops datagrams whenever the congestion controller has any queued.
+    /// This applies to every clone and is counted by [`Self::drops`].
+    pub fn set_max_queued(&self, max: Option<usize>) {
+        let max = max.unwrap_or(usize::MAX);
+        self.congestion.max_queued.store(max, Ordering::Relaxed);
+    }
+
+    /// Return the policy set by [`Self::set_max_queued`].
+    pub fn max_queued(&self) -> Option<usize> {
+        match self.congestion.max_queued.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            max => Some(max),
+        }
+    }
+
+    /// Choose what happens to datagrams that don't fit in the outgoing queue, see [`DatagramQueue`].
+    ///
+    /// This applies to every clone. [`Self::set_max_queued`] is shorthand for [`DatagramOverflow::RejectNew`].
+    pub fn set_queue(&self, queue: DatagramQueue) {
+        let max = match queue.overflow {
+            DatagramOverflow::RejectNew => queue.capacity,
+            DatagramOverflow::DropOldest => usize::MAX,
+        };
+
+        self.congestion.max_queued.store(max, Ordering::Relaxed);
+    }
+
+    /// Return the policy set by [`Self::set_queue`], where the capacity of [`DatagramOverflow::DropOldest`] is the connection's send buffer.
+    pub fn queue(&self) -> DatagramQueue {
+        match self.max_queued() {
+            Some(capacity) => DatagramQueue::reject_new(capacity),
+            None => DatagramQueue::drop_oldest(self.congestion.capacity),
+        }
+    }
+
+    /// Return the number of datagrams silently dropped instead of being sent, because the queue overflowed, they expired, or of a rate limit.
+    pub fn dropped(&self) -> u64 {
+        let drops = self.drops();
+        drops.overflow + drops.expired + drops.rate_limited
+    }
+
+    /// Return the number of datagrams that weren't sent for each reason, including those that returned an error.
+    pub fn drops(&self) -> DatagramDrops {
+        let congestion = &self.congestion;
+
+        DatagramDrops {
+            overflow: congestion.overflow.load(Ordering::Relaxed),
+            expired: congestion.expired.load(Ordering::Relaxed),
+            rate_limited: congestion.rate_limited.load(Ordering::Relaxed),
+            too_large: congestion.too_large.load(Ordering::Relaxed),
+            unsupported: congestion.unsupported.load(Ordering::Relaxed),
+        }
+    }
+
+    // Count a datagram that was dropped by the session's rate limit.
+    pub(crate) fn rate_limited(&self) {
+        self.count_drop(DatagramDropReason::RateLimited, 1);
+    }
+
+    // Return the number of bytes of datagrams waiting to be sent.
+    fn queued(&self) -> usize {
+        let space = self.conn.datagram_send_buffer_space();
+        self.congestion.capacity.saturating_sub(space)
+    }
+
+    /// Return the underlying QUIC connection.
+    pub fn conn(&self) -> &quinn::Connection {
+        &self.conn
+    }
+}
+
+/// Options for sending a single datagram, see [`Session::send_datagram_with`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DatagramOptions {
+    /// Drop the datagram instead of sending it after this long, or None (the default) to wait as long as it takes.
+    ///
+    /// A late datagram is often worse than none (ex. telemetry or the position of a player), since it delays the fresh ones.
+    /// Quinn owns the queue, so the wait is estimated when the datagram is sent, from the bytes queued ahead of it,
+    /// the congestion window and the RTT. Datagrams that would wait too long are dropped immediately and counted as expired.
+    pub max_age: Option<Duration>,
+}
+
+/// The policy for datagrams waiting to be sent, see [`Session::set_datagram_queue`].
+///
+/// Datagrams are queued when the congestion window is exhausted, so under load they wait behind everything sent before them.
+/// Realtime senders (ex. media or games) should bound the queue so stale datagrams are shed instead of delivered late.
+///
+/// Quinn owns the queue, so [`DatagramOverflow::DropOldest`] is done by Quinn once the connection's send buffer is full.
+/// That buffer is fixed when the connection is created, so use [`Self::configure`] on the transport config used to connect or accept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DatagramQueue {
+    /// The maximum number of bytes of datagrams waiting to be sent.
+    pub capacity: usize,
+
+    /// What happens to a datagram that doesn't fit.
+    pub overflow: DatagramOverflow,
+}
+
+/// What happens to a datagram that doesn't fit in the [`DatagramQueue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatagramOverflow {
+    /// Drop queued datagrams, oldest first, to make room for the new one, which is Quinn's behavior.
+    /// This is best when only the latest data matters (ex. the position of a player).
+    DropOldest,
+
+    /// Drop the new datagram and keep what's queued.
+    /// This is best when every datagram is equally useful, since no work is spent on the ones already queued.
+    RejectNew,
+}
+
+impl DatagramQueue {
+    pub fn drop_oldest(capacity: usize) -> Self {
+        Self {
+            capacity,
+            overflow: DatagramOverflow::DropOldest,
+        }
+    }
+
+    pub fn reject_new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            overflow: DatagramOverflow::RejectNew,
+        }
+    }
+
+    /// Size Quinn's datagram send buffer for the queue, see [`quinn::TransportConfig::datagram_send_buffer_size`].
+    ///
+    /// This is required for [`DatagramOverflow::DropOldest`] to use the capacity.
+    /// For [`DatagramOverflow::RejectNew`], it leaves room for one more datagram so Quinn never drops a queued one first.
+    pub fn configure(&self, transport: &mut quinn::TransportConfig) {
+        let size = match self.overflow {
+            DatagramOverflow::DropOldest => self.capacity,
+            DatagramOverflow::RejectNew => self.capacity.saturating_add(u16::MAX as usize),
+        };
+
+        transport.datagram_send_buffer_size(size);
+    }
+}
+
+/// The datagrams of a [`Session`] as a [`Stream`] and [`Sink`], returned by [`Session::datagrams`].
+///
+/// This is for plugging datagrams into combinators or splitting them (ex. with [`futures::StreamExt::split`]).
+/// The stream ends once the session is closed, see [`Session::close_reason`] for why.
+/// The sink is always ready, since datagrams are dropped instead of waiting for capacity (see [`Session::send_datagram`]),
+/// and closing it doesn't close the session.
+pub struct SessionDatagrams {
+    session: Session,
+    recv: Option<BoxFuture<'static, Result<Bytes, SessionError>>>,
+    done: bool,
+}
+
+impl SessionDatagrams {
+    pub(crate) fn new(session: Session) -> Self {
+        Self {
+            session,
+            recv: None,
+            done: false,
+        }
+    }
+}
+
+impl Stream for SessionDatagrams {
+    type Item = Bytes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let session = self.session.clone();
+        let recv = self
+            .recv
+            .get_or_insert_with(|| async move { session.recv_datagram().await }.boxed());
+
+        let res = ready!(recv.poll_unpin(cx));
+        self.recv = None;
+
+        match res {
+            Ok(payload) => Poll::Ready(Some(payload)),
+            Err(_) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl Sink<Bytes> for SessionDatagrams {
+    type Error = SendDatagramError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, payload: Bytes) -> Result<(), Self::Error> {
+        self.session.send_datagram(payload)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Convert from the Quinn VarInt to the (forked) WebTransport VarInt, which have the same range.
+// Prefix the payload with the quarter stream ID.
+fn encode(quarter_stream_id: quinn::VarInt, payload: Bytes) -> Bytes {
+    let datagram = Datagram {
+        quarter_stream_id: convert(quarter_stream_id),
+        payload,
+    };
+
+    let mut buf = BytesMut::with_capacity(8 + datagram.payload.len());
+    datagram.encode(&mut buf);
+    buf.freeze()
+}
+
+fn convert(v: quinn::VarInt) -> VarInt {
+    VarInt::try_from(v.into_inner()).unwrap()
+}
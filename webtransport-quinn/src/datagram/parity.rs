@@ -0,0 +1,479 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+
+use webtransport_proto::{ParityShard, VarInt};
+
+use crate::{SendDatagramError, Session, SessionError};
+
+// The maximum number of data shards in a group, so the index and count fit in a single byte.
+const MAX_GROUP_SIZE: usize = 63;
+
+// The size of the index and count varints, given the maximum group size.
+const INDEX_SIZE: usize = 2;
+
+// Keep this many groups before the newest, to tolerate some reordering.
+const MAX_PENDING_GROUPS: u64 = 8;
+
+// Each payload is prefixed with its length in the parity, so it can be recovered exactly.
+const LENGTH_SIZE: usize = 2;
+
+/// Datagrams protected by XOR parity, so a single loss in each group is recovered without waiting for a retransmission.
+///
+/// After every `group_size` datagrams, a parity datagram is sent containing the XOR of the group.
+/// The receiver uses it to rebuild any one missing datagram of the group, at the cost of one extra datagram per group.
+/// Two or more losses in the same group can't be recovered, so smaller groups tolerate more loss but cost more.
+///
+/// Datagrams are received as soon as they arrive, so only recovered datagrams are delayed, until the parity arrives.
+/// Call [`Self::flush`] before going idle, otherwise the last datagrams can't be recovered until the group fills.
+///
+/// This is a custom framing, so both endpoints need to use it, and it shouldn't be mixed with [`Session::recv_datagram`] on the same session.
+/// Create one per session and clone it, since group IDs are only unique per instance.
+#[derive(Clone)]
+pub struct ParityDatagrams {
+    session: Session,
+    encoder: Arc<Mutex<Encoder>>,
+    decoder: Arc<Mutex<Decoder>>,
+}
+
+impl ParityDatagrams {
+    /// Send a parity datagram after every `group_size` datagrams, clamped to 1-63.
+    pub fn new(session: Session, group_size: usize) -> Self {
+        let encoder = Encoder {
+            group_size: group_size.clamp(1, MAX_GROUP_SIZE),
+            group: 0,
+            count: 0,
+            parity: BytesMut::new(),
+        };
+
+        Self {
+            session,
+            encoder: Arc::new(Mutex::new(encoder)),
+            decoder: Default::default(),
+        }
+    }
+
+    /// Send a datagram, followed by the parity datagram if it completes the group. See [`Session::send_datagram`].
+    pub fn send(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        let mut encoder = self.encoder.lock().unwrap();
+
+        let size = self
+            .max_payload_size_for(encoder.group)
+            .ok_or(SendDatagramError::UnsupportedByPeer)?;
+        if payload.len() > size {
+            return Err(SendDatagramError::TooLarge);
+        }
+
+        let group = VarInt::try_from(encoder.group).map_err(|_| SendDatagramError::TooLarge)?;
+        let shard = ParityShard {
+            group,
+            index: VarInt::from_u32(encoder.count as u32),
+            count: VarInt::from_u32(encoder.group_size as u32),
+            payload,
+        };
+
+        self.session.send_datagram(encode(&shard))?;
+        encoder.add(&shard.payload);
+
+        if encoder.count == encoder.group_size {
+            self.send_parity(&mut encoder)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the parity datagram for an incomplete group now, so its datagrams can be recovered without waiting for more.
+    pub fn flush(&self) -> Result<(), SendDatagramError> {
+        let mut encoder = self.encoder.lock().unwrap();
+        if encoder.count == 0 {
+            return Ok(());
+        }
+
+        self.send_parity(&mut encoder)
+    }
+
+    /// Receive the next datagram, either as it arrived or recovered from the parity.
+    ///
+    /// Datagrams may be received out of order, but never more than once.
+    pub async fn recv(&self) -> Result<Bytes, SessionError> {
+        let mut coop = self.session.coop();
+
+        loop {
+            if let Some(payload) = self.decoder.lock().unwrap().ready.pop_front() {
+                return Ok(payload);
+            }
+
+            coop.proceed().await;
+
+            let datagram = self.session.recv_datagram().await?;
+            let shard = match ParityShard::decode(datagram) {
+                Ok(shard) => shard,
+                Err(_) => continue,
+            };
+
+            if let Some(payload) = self.decoder.lock().unwrap().push(shard) {
+                return Ok(payload);
+            }
+        }
+    }
+
+    /// The largest payload that can currently be sent, or None if datagrams are unsupported by the peer.
+    ///
+    /// This is [`Session::max_datagram_size`] minus the shard header and the length prefix needed for the parity.
+    pub fn max_payload_size(&self) -> Option<usize> {
+        let group = self.encoder.lock().unwrap().group;
+        self.max_payload_size_for(group)
+    }
+
+    /// The number of lost datagrams that were recovered from the parity.
+    pub fn recovered(&self) -> u64 {
+        self.decoder.lock().unwrap().recovered
+    }
+
+    /// The number of groups discarded with a loss that couldn't be recovered.
+    pub fn unrecovered(&self) -> u64 {
+        self.decoder.lock().unwrap().unrecovered
+    }
+
+    fn max_payload_size_for(&self, group: u64) -> Option<usize> {
+        let group = VarInt::try_from(group).ok()?;
+        let overhead = group.size() + INDEX_SIZE + LENGTH_SIZE;
+
+        self.session.max_datagram_size()?.checked_sub(overhead)
+    }
+
+    fn send_parity(&self, encoder: &mut Encoder) -> Result<(), SendDatagramError> {
+        let group = VarInt::try_from(encoder.group).map_err(|_| SendDatagramError::TooLarge)?;
+        let count = VarInt::from_u32(encoder.count as u32);
+
+        let shard = ParityShard {
+            group,
+            index: count,
+            count,
+            payload: std::mem::take(&mut encoder.parity).freeze(),
+        };
+
+        encoder.group += 1;
+        encoder.count = 0;
+
+        self.session.send_datagram(encode(&shard))
+    }
+}
+
+fn encode(shard: &ParityShard) -> Bytes {
+    let mut buf = BytesMut::with_capacity(shard.header_size() + shard.payload.len());
+    shard.encode(&mut buf);
+    buf.freeze()
+}
+
+// XOR the payload into the parity, prefixed with its length and zero padded.
+fn xor(parity: &mut BytesMut, payload: &[u8]) {
+    let size = LENGTH_SIZE + payload.len();
+    if parity.len() < size {
+        parity.resize(size, 0);
+    }
+
+    let length = (payload.len() as u16).to_be_bytes();
+    for (dst, src) in parity.iter_mut().zip(length.iter().chain(payload)) {
+        *dst ^= src;
+    }
+}
+
+// The parity of the group currently being sent.
+struct Encoder {
+    group_size: usize,
+    group: u64,
+
+    // The number of data shards sent in the current group.
+    count: usize,
+
+    parity: BytesMut,
+}
+
+impl Encoder {
+    fn add(&mut self, payload: &[u8]) {
+        xor(&mut self.parity, payload);
+        self.count += 1;
+    }
+}
+
+// The shards received for recent groups, keyed by the group ID.
+#[derive(Default)]
+struct Decoder {
+    groups: BTreeMap<u64, Group>,
+
+    // The newest group ID seen, used to discard older groups.
+    newest: u64,
+
+    // Shards for groups older than this are ignored, since they were discarded.
+    floor: u64,
+
+    // Recovered payloads, returned before receiving any more datagrams.
+    ready: VecDeque<Bytes>,
+
+    recovered: u64,
+    unrecovered: u64,
+}
+
+#[derive(Default)]
+struct Group {
+    // The data shards received, by index, kept until the group is complete.
+    data: Vec<(u64, Bytes)>,
+
+    // The number of data shards, according to the data shards until the parity arrives.
+    count: u64,
+
+    // A bitmask of the indexes returned, including any recovered.
+    delivered: u64,
+
+    // The number of data shards and their parity, once it arrives.
+    parity: Option<(u64, Bytes)>,
+
+    done: bool,
+}
+
+impl Decoder {
+    // Add a shard, returning its payload if it's new data; recovered payloads are queued instead.
+    fn push(&mut self, shard: ParityShard) -> Option<Bytes> {
+        let group = shard.group.into_inner();
+        let index = shard.index.into_inner();
+        let count = shard.count.into_inner();
+
+        if count > MAX_GROUP_SIZE as u64 || index > count || group < self.floor {
+            return None;
+        }
+
+        if group > self.newest {
+            self.newest = group;
+            self.expire();
+
+            if group < self.floor {
+                return None;
+            }
+        }
+
+        let entry = self.groups.entry(group).or_default();
+        let mut data = None;
+
+        if shard.is_parity() {
+            if entry.done || entry.parity.is_some() {
+                return None;
+            }
+
+            entry.count = count;
+            entry.parity = Some((count, shard.payload));
+        } else {
+            // Ignore duplicates, including a late shard that was already recovered.
+            if entry.delivered & (1 << index) != 0 {
+                return None;
+            }
+
+            entry.delivered |= 1 << index;
+            if entry.parity.is_none() {
+                entry.count = count;
+            }
+
+            if !entry.done {
+                entry.data.push((index, shard.payload.clone()));
+            }
+
+            data = Some(shard.payload);
+        }
+
+        if !entry.done {
+            if let Some(payload) = entry.recover() {
+                self.ready.push_back(payload);
+                self.recovered += 1;
+            }
+        }
+
+        data
+    }
+
+    // Discard groups too far behind the newest, counting the ones still missing data.
+    fn expire(&mut self) {
+        self.floor = self
+            .floor
+            .max(self.newest.saturating_sub(MAX_PENDING_GROUPS));
+
+        while let Some(entry) = self.groups.first_entry() {
+            if *entry.key() >= self.floor {
+                break;
+            }
+
+            let group = entry.remove();
+            if !group.done && group.missing() != 0 {
+                self.unrecovered += 1;
+            }
+        }
+    }
+}
+
+impl Group {
+    // Rebuild the single missing data shard once the parity and every other shard arrived.
+    fn recover(&mut self) -> Option<Bytes> {
+        let (count, parity) = self.parity.as_ref()?;
+        let missing = self.missing();
+
+        if missing == 0 {
+            self.done();
+            return None;
+        }
+
+        if missing.count_ones() > 1 {
+            return None;
+        }
+
+        let mut buf = BytesMut::from(&parity[..]);
+        for (index, payload) in &self.data {
+            if *index < *count {
+                xor(&mut buf, payload);
+            }
+        }
+
+        self.delivered |= missing;
+        self.done();
+
+        if buf.len() < LENGTH_SIZE {
+            return None;
+        }
+
+        let size = buf.get_u16() as usize;
+        if size > buf.len() {
+            return None;
+        }
+
+        buf.truncate(size);
+        Some(buf.freeze())
+    }
+
+    // A bitmask of the data shards not yet returned.
+    fn missing(&self) -> u64 {
+        ((1u64 << self.count) - 1) & !self.delivered
+    }
+
+    fn done(&mut self) {
+        self.done = true;
+        self.data.clear();
+        self.parity = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOADS: [&[u8]; 4] = [b"a", b"hello", b"", b"a longer payload"];
+
+    // The shards sent for the payloads, like ParityDatagrams::send followed by flush, with the parity last.
+    fn shards(group: u64, group_size: usize, payloads: &[&'static [u8]]) -> Vec<ParityShard> {
+        let mut encoder = Encoder {
+            group_size,
+            group,
+            count: 0,
+            parity: BytesMut::new(),
+        };
+
+        let mut shards = Vec::new();
+        for payload in payloads {
+            shards.push(ParityShard {
+                group: VarInt::try_from(group).unwrap(),
+                index: VarInt::from_u32(encoder.count as u32),
+                count: VarInt::from_u32(group_size as u32),
+                payload: Bytes::from_static(payload),
+            });
+            encoder.add(payload);
+        }
+
+        let count = VarInt::from_u32(encoder.count as u32);
+        shards.push(ParityShard {
+            group: VarInt::try_from(group).unwrap(),
+            index: count,
+            count,
+            payload: encoder.parity.freeze(),
+        });
+
+        shards
+    }
+
+    #[test]
+    fn recover_each() {
+        for (lost, expected) in PAYLOADS.iter().enumerate() {
+            let mut decoder = Decoder::default();
+
+            for (index, shard) in shards(0, 4, &PAYLOADS).into_iter().enumerate() {
+                let payload = shard.payload.clone();
+                match index {
+                    index if index == lost => continue,
+                    4 => assert_eq!(decoder.push(shard), None),
+                    _ => assert_eq!(decoder.push(shard), Some(payload)),
+                }
+            }
+
+            assert_eq!(decoder.ready.pop_front().unwrap(), expected);
+            assert!(decoder.ready.is_empty());
+            assert_eq!(decoder.recovered, 1);
+        }
+    }
+
+    #[test]
+    fn parity_first() {
+        let mut decoder = Decoder::default();
+        let mut shards = shards(0, 4, &PAYLOADS);
+
+        assert_eq!(decoder.push(shards.pop().unwrap()), None);
+        shards.remove(1);
+
+        for shard in shards {
+            assert!(decoder.push(shard).is_some());
+        }
+
+        assert_eq!(decoder.ready.pop_front().unwrap(), "hello");
+
+        // The lost shard arriving late isn't received twice.
+        let late = self::shards(0, 4, &PAYLOADS).remove(1);
+        assert_eq!(decoder.push(late), None);
+        assert_eq!(decoder.recovered, 1);
+    }
+
+    #[test]
+    fn recover_flushed() {
+        let mut decoder = Decoder::default();
+
+        // The group was flushed after two of four datagrams, so the parity only covers those.
+        let mut shards = shards(0, 4, &PAYLOADS[..2]);
+        shards.remove(0);
+
+        for shard in shards {
+            decoder.push(shard);
+        }
+
+        assert_eq!(decoder.ready.pop_front().unwrap(), "a");
+        assert_eq!(decoder.recovered, 1);
+    }
+
+    #[test]
+    fn two_losses() {
+        let mut decoder = Decoder::default();
+
+        for (index, shard) in shards(0, 4, &PAYLOADS).into_iter().enumerate() {
+            if index != 0 && index != 2 {
+                decoder.push(shard);
+            }
+        }
+
+        assert!(decoder.ready.is_empty());
+        assert_eq!(decoder.unrecovered, 0);
+
+        // Counted once the group falls behind.
+        for shard in shards(MAX_PENDING_GROUPS + 1, 4, &PAYLOADS) {
+            decoder.push(shard);
+        }
+
+        assert_eq!(decoder.recovered, 0);
+        assert_eq!(decoder.unrecovered, 1);
+    }
+}
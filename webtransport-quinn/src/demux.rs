@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+
+use webtransport_proto::VarInt;
+
+use crate::coop::YieldBudget;
+
+type Parser<K> = dyn Fn(&mut Bytes) -> Option<K> + Send + Sync;
+
+/// Routes incoming datagrams to a channel per application key, so each consumer only wakes for its own datagrams.
+///
+/// The key is parsed from the start of each datagram by a caller-provided function, which may also strip the prefix.
+/// Use [`Self::subscribe`] to receive the datagrams for a key, then drive [`Self::run`] (ex. in a spawned task) to read and route them.
+/// A datagram is dropped when nobody subscribed to its key or the subscriber's channel is full, since a slow consumer shouldn't hold up the others.
+///
+/// This is a cheap handle; clone it to subscribe from elsewhere.
+pub struct DatagramDemux<K> {
+    parser: Arc<Parser<K>>,
+    state: Arc<State<K>>,
+}
+
+struct State<K> {
+    routes: Mutex<HashMap<K, mpsc::Sender<Bytes>>>,
+    unrouted: AtomicU64,
+    dropped: AtomicU64,
+    yield_budget: YieldBudget,
+}
+
+impl<K: Hash + Eq + Send + 'static> DatagramDemux<K> {
+    /// Create a demultiplexer using the given function to parse the key, returning None for a malformed datagram.
+    /// The function can advance the buffer to strip the key before the datagram is delivered.
+    pub fn new<F>(parser: F) -> Self
+    where
+        F: Fn(&mut Bytes) -> Option<K> + Send + Sync + 'static,
+    {
+        let state = State {
+            routes: Mutex::new(HashMap::new()),
+            unrouted: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            yield_budget: YieldBudget::default(),
+        };
+
+        Self {
+            parser: Arc::new(parser),
+            state: Arc::new(state),
+        }
+    }
+
+    /// Receive the datagrams for the given key, buffering at least `capacity` before new ones are dropped.
+    ///
+    /// This replaces any previous subscriber for the key, and the key is unsubscribed once the receiver is dropped.
+    pub fn subscribe(&self, key: K, capacity: usize) -> mpsc::Receiver<Bytes> {
+        let (send, recv) = mpsc::channel(capacity);
+        self.state.routes.lock().unwrap().insert(key, send);
+        recv
+    }
+
+    /// Stop routing datagrams for the given key, which ends the subscriber's stream.
+    pub fn unsubscribe(&self, key: &K) {
+        self.state.routes.lock().unwrap().remove(key);
+    }
+
+    /// Route a single datagram, returning true if it was queued for a subscriber.
+    pub fn dispatch(&self, mut datagram: Bytes) -> bool {
+        let key = match (self.parser)(&mut datagram) {
+            Some(key) => key,
+            None => {
+                self.state.unrouted.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+
+        let mut routes = self.state.routes.lock().unwrap();
+
+        let send = match routes.get_mut(&key) {
+            Some(send) => send,
+            None => {
+                self.state.unrouted.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+
+        match send.try_send(datagram) {
+            Ok(()) => true,
+            Err(err) if err.is_full() => {
+                self.state.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Err(_) => {
+                // The receiver was dropped, so forget about the key.
+                routes.remove(&key);
+                self.state.unrouted.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Read datagrams with the given function and route each of them, until it returns an error.
+    ///
+    /// The function is called for each datagram, ex. `|| async { h3.recv().await.map(|(_, payload)| payload) }` with [`crate::H3Datagrams`].
+    /// This crate doesn't spawn tasks, so run this future yourself.
+    /// It yields to the runtime after routing a batch, see [`Self::set_yield_budget`].
+    pub async fn run<F, Fut, E>(&self, mut recv: F) -> E
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Bytes, E>>,
+    {
+        let mut coop = self.state.yield_budget.coop();
+
+        loop {
+            coop.proceed().await;
+
+            match recv().await {
+                Ok(datagram) => {
+                    self.dispatch(datagram);
+                }
+                Err(err) => return err,
+            }
+        }
+    }
+
+    /// Limit how many datagrams [`Self::run`] routes before yielding to the runtime, or 0 to never yield.
+    ///
+    /// Datagrams are usually ready immediately under load, so otherwise a flood would monopolize the worker thread. The default is 128.
+    /// This only applies to calls to [`Self::run`] made afterwards.
+    pub fn set_yield_budget(&self, budget: usize) {
+        self.state.yield_budget.set(budget)
+    }
+
+    /// Return the current yield budget, see [`Self::set_yield_budget`].
+    pub fn yield_budget(&self) -> usize {
+        self.state.yield_budget.get()
+    }
+
+    /// Return the number of datagrams dropped because the key couldn't be parsed or nobody subscribed to it.
+    pub fn unrouted(&self) -> u64 {
+        self.state.unrouted.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of datagrams dropped because the subscriber's channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl DatagramDemux<u64> {
+    /// Create a demultiplexer keyed by a VarInt at the start of each datagram (ex. a channel ID), which is stripped.
+    pub fn varint() -> Self {
+        Self::new(|datagram| {
+            let key = VarInt::decode(datagram).ok()?;
+            Some(key.into_inner())
+        })
+    }
+}
+
+impl<K> Clone for DatagramDemux<K> {
+    fn clone(&self) -> Self {
+        Self {
+            parser: self.parser.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{BuildError, CertificateHash};
+
+/// A self-signed certificate for local development, which browsers trust by its [`CertificateHash`] instead of a CA.
+///
+/// This is enabled with the `dev` feature. Serve it and hand the hash to the page, which passes it to `serverCertificateHashes`:
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let dev = webtransport_quinn::DevCertificate::generate(vec!["localhost".to_string()])?;
+/// println!("serverCertificateHashes: {}", dev.hash);
+///
+/// let server = webtransport_quinn::ServerBuilder::new(vec![dev.cert], dev.key)
+///     .bind("[::]:4443".parse()?)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Browsers refuse certificates that are valid for longer than [`CertificateHash::MAX_VALIDITY`],
+/// so this one expires after 13 days and a long-running server has to generate a new one before then.
+#[derive(Clone, Debug)]
+pub struct DevCertificate {
+    /// The certificate, with the given names as its subject alternative names.
+    pub cert: rustls::Certificate,
+
+    /// The ECDSA P-256 private key of the certificate.
+    pub key: rustls::PrivateKey,
+
+    /// The hash of the certificate, which a client can trust instead of a CA (ex. with `serverCertificateHashes` in the browser).
+    pub hash: CertificateHash,
+
+    /// When the certificate expires.
+    pub expires: SystemTime,
+}
+
+impl DevCertificate {
+    /// Generate a certificate for the given names (ex. `localhost`), valid from an hour ago to tolerate clock skew.
+    pub fn generate(names: Vec<String>) -> Result<Self, BuildError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| BuildError::Tls(err.to_string()))?;
+
+        let not_before = now.saturating_sub(Duration::from_secs(60 * 60));
+        let not_after = not_before + Duration::from_secs(13 * 24 * 60 * 60);
+
+        let mut params = rcgen::CertificateParams::new(names);
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+
+        // rcgen takes a time::OffsetDateTime, so offset the epoch to avoid depending on time directly.
+        let epoch = rcgen::date_time_ymd(1970, 1, 1);
+        params.not_before = epoch + not_before;
+        params.not_after = epoch + not_after;
+
+        let gen = rcgen::Certificate::from_params(params)
+            .map_err(|err| BuildError::Tls(err.to_string()))?;
+        let der = gen
+            .serialize_der()
+            .map_err(|err| BuildError::Tls(err.to_string()))?;
+
+        let cert = rustls::Certificate(der);
+        let key = rustls::PrivateKey(gen.serialize_private_key_der());
+        let hash = CertificateHash::new(&cert);
+
+        Ok(Self {
+            cert,
+            key,
+            hash,
+            expires: UNIX_EPOCH + not_after,
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use thiserror::Error;
 
 /// An errors returned by [`crate::Session`], split based on if they are underlying QUIC errors or WebTransport errors.
@@ -10,6 +12,90 @@ pub enum SessionError {
     WebTransportError(#[from] WebTransportError),
 }
 
+/// Why a session ended, returned by [`crate::Session::closed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionClose {
+    /// The application error code, from a CLOSE_WEBTRANSPORT_SESSION capsule or a CONNECTION_CLOSE.
+    ///
+    /// This is 0 if the session ended without one, ex. the connection was lost or the peer finished the CONNECT stream.
+    pub code: u32,
+
+    /// The human-readable reason, which may be empty.
+    pub reason: String,
+
+    /// Who or what ended the session.
+    pub source: CloseSource,
+}
+
+/// Who or what ended a session, see [`SessionClose`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseSource {
+    /// The peer closed the session or the QUIC connection.
+    Peer,
+
+    /// We closed the session or the QUIC connection, ex. with [`crate::Session::close`].
+    Local,
+
+    /// The QUIC connection failed, ex. it was reset or the peer closed it with a transport error.
+    ConnectionLost,
+
+    /// The QUIC connection was idle for too long.
+    TimedOut,
+}
+
+impl fmt::Display for SessionClose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self.source {
+            CloseSource::Peer => "closed by peer",
+            CloseSource::Local => "closed locally",
+            CloseSource::ConnectionLost => "connection lost",
+            CloseSource::TimedOut => "timed out",
+        };
+
+        write!(f, "{}: {} (code {})", source, self.reason, self.code)
+    }
+}
+
+// Implemented by hand, since thiserror would treat the `source` field as the underlying error.
+impl std::error::Error for SessionClose {}
+
+impl SessionClose {
+    // Interpret the reason the session was closed, given the code and reason we used if we closed it ourselves.
+    pub(crate) fn new(err: &quinn::ConnectionError, local: Option<(u32, String)>) -> Self {
+        let lost = |reason: String| Self {
+            code: 0,
+            reason,
+            source: CloseSource::ConnectionLost,
+        };
+
+        match err {
+            quinn::ConnectionError::ApplicationClosed(close) => Self {
+                code: webtransport_proto::error_from_http3(close.error_code.into_inner())
+                    .unwrap_or_default(),
+                reason: String::from_utf8_lossy(&close.reason).into_owned(),
+                source: CloseSource::Peer,
+            },
+            quinn::ConnectionError::LocallyClosed => {
+                let (code, reason) = local.unwrap_or_default();
+                Self {
+                    code,
+                    reason,
+                    source: CloseSource::Local,
+                }
+            }
+            quinn::ConnectionError::TimedOut => Self {
+                code: 0,
+                reason: String::new(),
+                source: CloseSource::TimedOut,
+            },
+            quinn::ConnectionError::ConnectionClosed(close) => {
+                lost(String::from_utf8_lossy(&close.reason).into_owned())
+            }
+            err => lost(err.to_string()),
+        }
+    }
+}
+
 /// An error that can occur when reading/writing the WebTransport stream header.
 #[derive(Error, Debug)]
 pub enum WebTransportError {
@@ -49,6 +135,10 @@ pub enum WriteError {
 
     #[error("stream closed")]
     Closed,
+
+    /// The stream was reset with the code after being blocked for too long, see [`crate::StallPolicy`].
+    #[error("stalled: {0}")]
+    Stalled(u32),
 }
 
 impl From<quinn::WriteError> for WriteError {
@@ -62,7 +152,9 @@ impl From<quinn::WriteError> for WriteError {
             }
             quinn::WriteError::UnknownStream => WriteError::Closed,
             quinn::WriteError::ConnectionLost(e) => WriteError::SessionError(e.into()),
-            quinn::WriteError::ZeroRttRejected => unreachable!("0-RTT not supported"),
+            quinn::WriteError::ZeroRttRejected => {
+                unreachable!("sessions are only returned once 0-RTT is accepted or rejected")
+            }
         }
     }
 }
@@ -118,7 +210,9 @@ impl From<quinn::ReadError> for ReadError {
             quinn::ReadError::ConnectionLost(e) => ReadError::SessionError(e.into()),
             quinn::ReadError::IllegalOrderedRead => ReadError::IllegalOrderedRead,
             quinn::ReadError::UnknownStream => ReadError::Closed,
-            quinn::ReadError::ZeroRttRejected => unreachable!("0-RTT not supported"),
+            quinn::ReadError::ZeroRttRejected => {
+                unreachable!("sessions are only returned once 0-RTT is accepted or rejected")
+            }
         }
     }
 }
@@ -207,7 +301,38 @@ impl From<quinn::StoppedError> for StoppedError {
         match e {
             quinn::StoppedError::ConnectionLost(e) => StoppedError::SessionError(e.into()),
             quinn::StoppedError::UnknownStream => StoppedError::Closed,
-            quinn::StoppedError::ZeroRttRejected => unreachable!("0-RTT not supported"),
+            quinn::StoppedError::ZeroRttRejected => {
+                unreachable!("sessions are only returned once 0-RTT is accepted or rejected")
+            }
+        }
+    }
+}
+
+/// An error when sending a datagram. Similar to [`quinn::SendDatagramError`].
+#[derive(Error, Debug)]
+pub enum SendDatagramError {
+    #[error("datagrams not supported by peer")]
+    UnsupportedByPeer,
+
+    #[error("datagram support disabled")]
+    Disabled,
+
+    #[error("datagram too large")]
+    TooLarge,
+
+    #[error("session error: {0}")]
+    SessionError(#[from] SessionError),
+}
+
+impl From<quinn::SendDatagramError> for SendDatagramError {
+    fn from(e: quinn::SendDatagramError) -> Self {
+        match e {
+            quinn::SendDatagramError::UnsupportedByPeer => SendDatagramError::UnsupportedByPeer,
+            quinn::SendDatagramError::Disabled => SendDatagramError::Disabled,
+            quinn::SendDatagramError::TooLarge => SendDatagramError::TooLarge,
+            quinn::SendDatagramError::ConnectionLost(e) => {
+                SendDatagramError::SessionError(e.into())
+            }
         }
     }
 }
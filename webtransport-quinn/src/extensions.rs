@@ -0,0 +1,58 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// A map of values keyed by type, used to attach per-session state. Similar to [`http::Extensions`].
+///
+/// Middleware (ex. auth or routing) can insert values into [`crate::Request::extensions`] before accepting the session,
+/// and handlers can later retrieve them from [`crate::Session::extensions`].
+/// Values are stored in an [`Arc`] and the map is shared by every clone of the session.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: Arc<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value, returning the previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) -> Option<Arc<T>> {
+        let prev = self
+            .map
+            .lock()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Arc::new(value));
+
+        prev.and_then(downcast)
+    }
+
+    /// Return the value of the given type.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self.map.lock().unwrap().get(&TypeId::of::<T>()).cloned();
+        value.and_then(downcast)
+    }
+
+    /// Remove and return the value of the given type.
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let prev = self.map.lock().unwrap().remove(&TypeId::of::<T>());
+        prev.and_then(downcast)
+    }
+
+    /// Return true if there's a value of the given type.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.lock().unwrap().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove every value.
+    pub fn clear(&self) {
+        self.map.lock().unwrap().clear()
+    }
+}
+
+fn downcast<T: Send + Sync + 'static>(value: Arc<dyn Any + Send + Sync>) -> Option<Arc<T>> {
+    value.downcast().ok()
+}
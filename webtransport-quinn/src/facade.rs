@@ -0,0 +1,433 @@
+//! A facade for language bindings (ex. uniffi or napi), which can't express borrowed futures, generics or tuples.
+//!
+//! Every handle is cheaply cloneable and `'static`, and every async method takes `&self` and returns an owned future,
+//! so it can be handed to a foreign executor without borrowing anything.
+//! Buffers are owned [`Vec<u8>`] and errors are a single cloneable [`Error`].
+//! Instead of separate accept loops, the peer's streams and datagrams are pulled one [`Event`] at a time with [`Session::next_event`].
+//!
+//! Operations on a stream run in the order their methods were called, even if the executor polls the futures in a different order,
+//! since each one takes its place in line when it's created and waits for the previous to finish.
+//! An operation that's neither polled nor dropped holds up every later operation on the same stream.
+
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use futures::{
+    channel::oneshot,
+    lock::{Mutex, OwnedMutexGuard},
+    FutureExt,
+};
+use thiserror::Error;
+use webtransport_generic::{SessionError as _, StreamError as _};
+
+use crate::{
+    ClientError, ReadError, ReadToEndError, SendDatagramError, SessionError, StreamClosed,
+    WriteError,
+};
+
+/// An error returned by the facade, flattened so it can be mapped to a foreign exception.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The session is closed, with the peer's error code if it closed the session itself.
+    #[error("session closed: {message}")]
+    SessionClosed { code: Option<u32>, message: String },
+
+    /// The peer reset the stream with an error code.
+    #[error("stream reset: {code}")]
+    Reset { code: u32 },
+
+    /// The peer asked us to stop sending with an error code.
+    #[error("stream stopped: {code}")]
+    Stopped { code: u32 },
+
+    /// The stream was already finished, reset or stopped.
+    #[error("stream closed")]
+    StreamClosed,
+
+    /// A datagram couldn't be sent, ex. because it's too large.
+    #[error("datagram error: {message}")]
+    Datagram { message: String },
+
+    /// The session couldn't be established.
+    #[error("connect error: {message}")]
+    Connect { message: String },
+
+    /// Anything else, ex. a protocol violation.
+    #[error("{message}")]
+    Other { message: String },
+}
+
+impl From<SessionError> for Error {
+    fn from(err: SessionError) -> Self {
+        Error::SessionClosed {
+            code: err.session_error(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<WriteError> for Error {
+    fn from(err: WriteError) -> Self {
+        match err {
+            WriteError::Stopped(code) => Error::Stopped { code },
+            WriteError::SessionError(err) => err.into(),
+            WriteError::Closed => Error::StreamClosed,
+            err => Error::Other {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl From<ReadError> for Error {
+    fn from(err: ReadError) -> Self {
+        match err.stream_error() {
+            Some(code) => Error::Reset { code },
+            None => match err {
+                ReadError::SessionError(err) => err.into(),
+                ReadError::Closed => Error::StreamClosed,
+                err => Error::Other {
+                    message: err.to_string(),
+                },
+            },
+        }
+    }
+}
+
+impl From<ReadToEndError> for Error {
+    fn from(err: ReadToEndError) -> Self {
+        match err {
+            ReadToEndError::ReadError(err) => err.into(),
+            err => Error::Other {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl From<SendDatagramError> for Error {
+    fn from(err: SendDatagramError) -> Self {
+        match err {
+            SendDatagramError::SessionError(err) => err.into(),
+            err => Error::Datagram {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl From<StreamClosed> for Error {
+    fn from(_: StreamClosed) -> Self {
+        Error::StreamClosed
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(err: ClientError) -> Self {
+        Error::Connect {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Something the peer did, returned by [`Session::next_event`].
+#[derive(Clone)]
+pub enum Event {
+    /// The peer opened a unidirectional stream.
+    UniStream(RecvStream),
+
+    /// The peer opened a bidirectional stream.
+    BiStream(BiStream),
+
+    /// The peer sent a datagram.
+    Datagram(Vec<u8>),
+
+    /// The session is closed; every later call returns this too.
+    Closed(Error),
+}
+
+/// A client that dials sessions by URL, see [`crate::Client`].
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+}
+
+impl Client {
+    pub fn new(client: crate::Client) -> Self {
+        Self { inner: client }
+    }
+
+    /// Connect to the `https://` URL. See [`crate::Client::connect`].
+    pub fn connect(
+        &self,
+        url: String,
+    ) -> impl Future<Output = Result<Session, Error>> + Send + 'static {
+        let client = self.inner.clone();
+
+        async move {
+            let uri = match http::Uri::try_from(url) {
+                Ok(uri) => uri,
+                Err(err) => {
+                    return Err(Error::Connect {
+                        message: err.to_string(),
+                    })
+                }
+            };
+
+            Ok(client.connect(&uri).await?.into())
+        }
+    }
+}
+
+impl From<crate::Client> for Client {
+    fn from(client: crate::Client) -> Self {
+        Self::new(client)
+    }
+}
+
+/// An established session, see [`crate::Session`].
+#[derive(Clone)]
+pub struct Session {
+    inner: crate::Session,
+}
+
+impl Session {
+    pub fn new(session: crate::Session) -> Self {
+        Self { inner: session }
+    }
+
+    /// Wait for the next stream or datagram from the peer, or for the session to close.
+    ///
+    /// Concurrent calls each receive a different event, except for [`Event::Closed`] which every call receives.
+    pub fn next_event(&self) -> impl Future<Output = Event> + Send + 'static {
+        let session = self.inner.clone();
+
+        async move {
+            loop {
+                let res = futures::select! {
+                    res = session.accept_uni().fuse() => res.map(|recv| Event::UniStream(recv.into())),
+                    res = session.accept_bi().fuse() => res.map(|bi| Event::BiStream(bi.into())),
+                    res = session.recv_datagram().fuse() => res.map(|payload| Event::Datagram(payload.to_vec())),
+                };
+
+                if let Ok(event) = res {
+                    return event;
+                }
+
+                // Otherwise a single stream may have failed before its header was read (ex. it was reset), so skip it.
+                if let Some(reason) = session.close_reason() {
+                    return Event::Closed(reason.into());
+                }
+            }
+        }
+    }
+
+    /// Open a unidirectional stream. See [`crate::Session::open_uni`].
+    pub fn open_uni(&self) -> impl Future<Output = Result<SendStream, Error>> + Send + 'static {
+        let session = self.inner.clone();
+        async move { Ok(session.open_uni().await?.into()) }
+    }
+
+    /// Open a bidirectional stream. See [`crate::Session::open_bi`].
+    pub fn open_bi(&self) -> impl Future<Output = Result<BiStream, Error>> + Send + 'static {
+        let session = self.inner.clone();
+        async move { Ok(session.open_bi().await?.into()) }
+    }
+
+    /// Send an unreliable datagram. See [`crate::Session::send_datagram`].
+    pub fn send_datagram(&self, payload: Vec<u8>) -> Result<(), Error> {
+        Ok(self.inner.send_datagram(Bytes::from(payload))?)
+    }
+
+    /// The largest datagram payload that can currently be sent, or [`None`] if datagrams are unsupported.
+    pub fn max_datagram_size(&self) -> Option<u64> {
+        self.inner.max_datagram_size().map(|size| size as u64)
+    }
+
+    /// Close the session without waiting for the peer. See [`crate::Session::close`].
+    pub fn close(&self, code: u32, reason: String) {
+        self.inner.close(code, &reason)
+    }
+
+    /// Wait until the session is closed, returning the reason.
+    pub fn closed(&self) -> impl Future<Output = Error> + Send + 'static {
+        let session = self.inner.clone();
+        async move { crate::SessionError::from(session.closed_error().await).into() }
+    }
+}
+
+impl From<crate::Session> for Session {
+    fn from(session: crate::Session) -> Self {
+        Self::new(session)
+    }
+}
+
+// Runs the operations on a stream in the order they were created, rather than the order they're first polled.
+struct Queue<T> {
+    stream: Arc<Mutex<T>>,
+
+    // Resolves once the most recently created operation is done or dropped.
+    last: std::sync::Mutex<Option<oneshot::Receiver<()>>>,
+}
+
+impl<T> Queue<T> {
+    fn new(stream: T) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(stream)),
+            last: Default::default(),
+        }
+    }
+
+    // Take a place in line now, returning a future that waits for every earlier operation.
+    fn turn(&self) -> impl Future<Output = Turn<T>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        let (done, next) = oneshot::channel();
+        let prev = self.last.lock().unwrap().replace(next);
+        let stream = self.stream.clone();
+
+        async move {
+            if let Some(prev) = prev {
+                // Cancelled just means the previous operation is done.
+                prev.await.ok();
+            }
+
+            Turn {
+                stream: stream.lock_owned().await,
+                _done: done,
+            }
+        }
+    }
+}
+
+// Exclusive access to the stream, letting the next operation run once it's dropped.
+struct Turn<T> {
+    stream: OwnedMutexGuard<T>,
+    _done: oneshot::Sender<()>,
+}
+
+impl<T> Deref for Turn<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.stream
+    }
+}
+
+impl<T> DerefMut for Turn<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.stream
+    }
+}
+
+/// The two halves of a bidirectional stream, instead of a tuple.
+#[derive(Clone)]
+pub struct BiStream {
+    pub send: SendStream,
+    pub recv: RecvStream,
+}
+
+impl From<(crate::SendStream, crate::RecvStream)> for BiStream {
+    fn from((send, recv): (crate::SendStream, crate::RecvStream)) -> Self {
+        Self {
+            send: send.into(),
+            recv: recv.into(),
+        }
+    }
+}
+
+/// The sending half of a stream, see [`crate::SendStream`].
+///
+/// The stream is reset when the last clone is dropped, unless it was finished.
+#[derive(Clone)]
+pub struct SendStream {
+    inner: Arc<Queue<crate::SendStream>>,
+}
+
+impl SendStream {
+    /// Write all of the bytes.
+    pub fn write(&self, data: Vec<u8>) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        async move { Ok(turn.await.write_chunk(Bytes::from(data)).await?) }
+    }
+
+    /// Finish the stream once everything written was sent.
+    pub fn finish(&self) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        async move { Ok(turn.await.finish().await?) }
+    }
+
+    /// Abandon the stream with an error code, once pending writes are done.
+    pub fn reset(&self, code: u32) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        async move { Ok(turn.await.reset(code)?) }
+    }
+
+    /// Set the priority of the stream. See [`crate::SendStream::set_priority`].
+    pub fn set_priority(
+        &self,
+        order: i32,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        async move { Ok(turn.await.set_priority(order)?) }
+    }
+}
+
+impl From<crate::SendStream> for SendStream {
+    fn from(stream: crate::SendStream) -> Self {
+        Self {
+            inner: Arc::new(Queue::new(stream)),
+        }
+    }
+}
+
+/// The receiving half of a stream, see [`crate::RecvStream`].
+#[derive(Clone)]
+pub struct RecvStream {
+    inner: Arc<Queue<crate::RecvStream>>,
+}
+
+impl RecvStream {
+    /// Read the next chunk of up to `max` bytes, or [`None`] once the stream is finished.
+    pub fn read(
+        &self,
+        max: u64,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        let max = usize::try_from(max).unwrap_or(usize::MAX);
+
+        async move {
+            let chunk = turn.await.read_chunk(max, true).await?;
+            Ok(chunk.map(|chunk| chunk.bytes.to_vec()))
+        }
+    }
+
+    /// Read until the stream is finished, failing if it's longer than `limit` bytes.
+    pub fn read_to_end(
+        &self,
+        limit: u64,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        async move { Ok(turn.await.read_to_end(limit).await?) }
+    }
+
+    /// Ask the peer to stop sending with an error code, once pending reads are done.
+    pub fn stop(&self, code: u32) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let turn = self.inner.turn();
+        async move { turn.await.stop(code).map_err(|_| Error::StreamClosed) }
+    }
+}
+
+impl From<crate::RecvStream> for RecvStream {
+    fn from(stream: crate::RecvStream) -> Self {
+        Self {
+            inner: Arc::new(Queue::new(stream)),
+        }
+    }
+}
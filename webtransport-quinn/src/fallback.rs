@@ -0,0 +1,186 @@
+use std::{future::Future, io, pin::Pin, sync::Arc};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use webtransport_proto::{HttpRequest, HttpResponse};
+
+/// The maximum size of a plain HTTP/3 request or response, including the headers and body.
+pub const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// An error returned when serving a plain HTTP/3 request.
+#[derive(Error, Debug)]
+pub enum FallbackError {
+    #[error("protocol error: {0}")]
+    ProtoError(#[from] webtransport_proto::ConnectError),
+
+    #[error("read error: {0}")]
+    ReadError(#[from] quinn::ReadToEndError),
+
+    #[error("write error: {0}")]
+    WriteError(#[from] quinn::WriteError),
+
+    #[error("invalid request: {0}")]
+    HttpError(#[from] http::Error),
+}
+
+/// An error returned by [`crate::Session::request`].
+#[derive(Error, Debug)]
+pub enum RequestError {
+    #[error("connection error: {0}")]
+    ConnectionError(#[from] quinn::ConnectionError),
+
+    #[error("protocol error: {0}")]
+    ProtoError(#[from] webtransport_proto::ConnectError),
+
+    #[error("read error: {0}")]
+    ReadError(#[from] quinn::ReadToEndError),
+
+    #[error("write error: {0}")]
+    WriteError(#[from] quinn::WriteError),
+
+    #[error("invalid request: {0}")]
+    HttpError(#[from] http::Error),
+}
+
+type Handler = dyn Fn(http::Request<Bytes>) -> Pin<Box<dyn Future<Output = http::Response<Bytes>> + Send>>
+    + Send
+    + Sync;
+
+// A plain HTTP/3 request that is currently being served.
+pub(crate) type Serving = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A handler for plain HTTP/3 requests (ex. `GET /healthz`) that arrive on a WebTransport connection.
+///
+/// Without a fallback, any request that isn't a WebTransport CONNECT is an error.
+/// The request body is buffered up to [`MAX_REQUEST_SIZE`] and the response is sent in a single DATA frame.
+#[derive(Clone)]
+pub struct Fallback {
+    handler: Arc<Handler>,
+}
+
+impl Fallback {
+    /// Create a fallback that calls the provided async function for each request.
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(http::Request<Bytes>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = http::Response<Bytes>> + Send + 'static,
+    {
+        Self {
+            handler: Arc::new(move |req| Box::pin(handler(req))),
+        }
+    }
+
+    /// Run the handler for a single request.
+    pub async fn handle(&self, req: http::Request<Bytes>) -> http::Response<Bytes> {
+        (self.handler)(req).await
+    }
+
+    // Serve a request stream in the background, ignoring any errors since they only affect the request.
+    pub(crate) fn serving(
+        &self,
+        buf: Vec<u8>,
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+    ) -> Serving {
+        let this = self.clone();
+        Box::pin(async move {
+            this.serve(buf, send, recv).await.ok();
+        })
+    }
+
+    // Serve a request stream, given any bytes that have already been read from it.
+    pub(crate) async fn serve(
+        &self,
+        mut buf: Vec<u8>,
+        mut send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+    ) -> Result<(), FallbackError> {
+        // The client finishes the stream after the body, so read until the end.
+        let limit = MAX_REQUEST_SIZE.saturating_sub(buf.len());
+        buf.extend_from_slice(&recv.read_to_end(limit).await?);
+
+        let mut cursor = io::Cursor::new(&buf);
+        let head = HttpRequest::decode(&mut cursor)?;
+        let body = webtransport_proto::decode_data(&mut cursor)?;
+
+        let mut req = http::Request::builder()
+            .method(head.method)
+            .uri(head.uri)
+            .body(body)?;
+        *req.headers_mut() = head.headers;
+
+        let (parts, body) = self.handle(req).await.into_parts();
+
+        let resp = HttpResponse {
+            status: parts.status,
+            headers: parts.headers,
+        };
+
+        let mut buf = Vec::new();
+        resp.encode(&mut buf);
+        if !body.is_empty() {
+            webtransport_proto::encode_data(&body, &mut buf);
+        }
+
+        send.write_all(&buf).await?;
+        send.finish().await?;
+
+        Ok(())
+    }
+}
+
+// Send a plain HTTP/3 request on a new stream and read the response.
+// A relative URI is resolved against the provided base URI.
+pub(crate) async fn request(
+    conn: &quinn::Connection,
+    base: &http::Uri,
+    req: http::Request<Bytes>,
+) -> Result<http::Response<Bytes>, RequestError> {
+    let (parts, body) = req.into_parts();
+
+    let uri = match parts.uri.authority() {
+        Some(_) => parts.uri,
+        None => {
+            let mut uri = parts.uri.into_parts();
+            uri.scheme = base.scheme().cloned();
+            uri.authority = base.authority().cloned();
+            http::Uri::from_parts(uri).map_err(http::Error::from)?
+        }
+    };
+
+    let head = HttpRequest {
+        method: parts.method,
+        uri,
+        headers: parts.headers,
+    };
+
+    let mut buf = Vec::new();
+    head.encode(&mut buf);
+    if !body.is_empty() {
+        webtransport_proto::encode_data(&body, &mut buf);
+    }
+
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(&buf).await?;
+    send.finish().await?;
+
+    // The server finishes the stream after the body, so read until the end.
+    let buf = recv.read_to_end(MAX_REQUEST_SIZE).await?;
+    let mut cursor = io::Cursor::new(&buf);
+
+    // Skip any informational (1xx) responses.
+    let head = loop {
+        let head = HttpResponse::decode(&mut cursor)?;
+        if !head.status.is_informational() {
+            break head;
+        }
+    };
+
+    let body = webtransport_proto::decode_data(&mut cursor)?;
+
+    let mut resp = http::Response::builder().status(head.status).body(body)?;
+    *resp.headers_mut() = head.headers;
+
+    Ok(resp)
+}
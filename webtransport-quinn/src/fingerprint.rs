@@ -0,0 +1,125 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use thiserror::Error;
+
+/// The SHA-256 hash of a certificate, which browsers trust instead of a CA with the `serverCertificateHashes` option.
+///
+/// This is meant for self-signed deployments (ex. development, or servers without a domain).
+/// Browsers only accept hashes of certificates that are valid for at most [`Self::MAX_VALIDITY`], so they have to be rotated regularly.
+///
+/// It's displayed and parsed as hex, which the page has to decode into the `value` of the hash:
+///
+/// ```js
+/// const value = Uint8Array.from(hex.match(/../g), (byte) => parseInt(byte, 16));
+/// const transport = new WebTransport(url, { serverCertificateHashes: [{ algorithm: "sha-256", value }] });
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CertificateHash([u8; 32]);
+
+impl CertificateHash {
+    /// The name of the hash algorithm, as the `algorithm` of the hash in the browser.
+    pub const ALGORITHM: &'static str = "sha-256";
+
+    /// The longest validity period of a certificate that browsers trust by hash.
+    pub const MAX_VALIDITY: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+    /// Compute the hash of the given certificate, over its DER encoding.
+    pub fn new(cert: &rustls::Certificate) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, &cert.0);
+
+        let mut hash = [0; 32];
+        hash.copy_from_slice(digest.as_ref());
+        Self(hash)
+    }
+
+    /// Return the raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for CertificateHash {
+    fn from(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+}
+
+impl fmt::Display for CertificateHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error returned when parsing a [`CertificateHash`] that isn't 32 bytes of hex.
+#[derive(Error, Debug)]
+#[error("invalid certificate hash")]
+pub struct InvalidCertificateHash;
+
+impl FromStr for CertificateHash {
+    type Err = InvalidCertificateHash;
+
+    // Colons are skipped, so the fingerprints printed by `openssl x509 -fingerprint -sha256` can be used as is.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: Vec<u8> = s.bytes().filter(|b| *b != b':').collect();
+        if digits.len() != 64 || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(InvalidCertificateHash);
+        }
+
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(digits.chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| InvalidCertificateHash)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| InvalidCertificateHash)?;
+        }
+
+        Ok(Self(hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4";
+
+    #[test]
+    fn parse_hex() {
+        let hash: CertificateHash = HEX.parse().unwrap();
+        assert_eq!(hash.as_bytes()[..2], [0x8f, 0x43]);
+        assert_eq!(hash.to_string(), HEX);
+
+        // Uppercase works too, but it's always displayed in lowercase.
+        let upper: CertificateHash = HEX.to_uppercase().parse().unwrap();
+        assert_eq!(upper, hash);
+    }
+
+    #[test]
+    fn parse_colons() {
+        // As printed by `openssl x509 -fingerprint -sha256`.
+        let pairs: Vec<_> = HEX
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap())
+            .collect();
+        let openssl = pairs.join(":").to_uppercase();
+
+        let hash: CertificateHash = openssl.parse().unwrap();
+        assert_eq!(hash, HEX.parse().unwrap());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("".parse::<CertificateHash>().is_err());
+        assert!(HEX[..62].parse::<CertificateHash>().is_err());
+        assert!(format!("{HEX}00").parse::<CertificateHash>().is_err());
+        assert!(HEX.replace('8', "g").parse::<CertificateHash>().is_err());
+
+        // from_str_radix accepts a sign, so make sure every digit is hex.
+        assert!(format!("+{}", &HEX[1..])
+            .parse::<CertificateHash>()
+            .is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::sync::Mutex;
+
+use webtransport_proto::{Capsule, VarInt};
+
+/// The flow control limits of each session, advertised with the SETTINGS_WT_INITIAL_MAX_* settings.
+///
+/// Sessions that share a connection also share QUIC's flow control, so these keep one session from starving the others.
+/// Each limit is a window that moves as the application accepts streams and reads data, so the peer can only get that far ahead.
+/// They're only enforced if both endpoints advertise them, see [`crate::Server::set_session_limits`] and [`crate::Client::set_session_limits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// The number of unidirectional streams the peer can open before the application accepts them.
+    pub max_streams_uni: u64,
+
+    /// The number of bidirectional streams the peer can open before the application accepts them.
+    pub max_streams_bidi: u64,
+
+    /// The number of bytes the peer can send on all of the session's streams before the application reads them.
+    pub max_data: u64,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            max_streams_uni: 100,
+            max_streams_bidi: 100,
+            max_data: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl SessionLimits {
+    // Clamp each limit to the largest VarInt, so they can be advertised.
+    pub(crate) fn clamp(self) -> Self {
+        let max = VarInt::MAX.into_inner();
+
+        Self {
+            max_streams_uni: self.max_streams_uni.min(max),
+            max_streams_bidi: self.max_streams_bidi.min(max),
+            max_data: self.max_data.min(max),
+        }
+    }
+}
+
+// Enforces the limits of a session once both endpoints advertised them, see draft-ietf-webtrans-http3 section 5.
+//
+// Every limit is cumulative like QUIC's, so the capsules only ever raise them.
+// This only does the accounting; the session writes the capsules that are returned and closes itself on a violation.
+//
+// Quinn doesn't say how much data arrived until the application reads it, so data is counted as it's read.
+// A peer that exceeds WT_MAX_DATA is caught once the excess is read; until then, it's bounded by QUIC's stream windows.
+pub(crate) struct Flow {
+    inner: Mutex<FlowState>,
+}
+
+struct FlowState {
+    // What we're allowed to send, raised by the peer's WT_MAX_STREAMS and WT_MAX_DATA.
+    open_uni: Credit,
+    open_bi: Credit,
+    send_data: Credit,
+
+    // What the peer is allowed to send, which we raise as the application catches up.
+    accept_uni: Window,
+    accept_bi: Window,
+    recv_data: Window,
+}
+
+// A limit set by the peer.
+struct Credit {
+    max: u64,
+    used: u64,
+
+    // The limit we last reported being blocked at, so we only report it once.
+    blocked: Option<u64>,
+}
+
+// A limit we set for the peer.
+struct Window {
+    // The size of the window, added to what's consumed whenever we raise the limit.
+    size: u64,
+    max: u64,
+
+    // How much the peer used, and how much the application consumed.
+    // These are the same for data, since it's only counted once it's read.
+    used: u64,
+    consumed: u64,
+}
+
+impl Flow {
+    pub fn new(local: SessionLimits, peer: SessionLimits) -> Self {
+        let state = FlowState {
+            open_uni: Credit::new(peer.max_streams_uni),
+            open_bi: Credit::new(peer.max_streams_bidi),
+            send_data: Credit::new(peer.max_data),
+            accept_uni: Window::new(local.max_streams_uni),
+            accept_bi: Window::new(local.max_streams_bidi),
+            recv_data: Window::new(local.max_data),
+        };
+
+        Self {
+            inner: Mutex::new(state),
+        }
+    }
+
+    // Take credit to open a stream, or return the WT_STREAMS_BLOCKED capsule to send if we haven't yet.
+    pub fn open(&self, bidi: bool) -> Result<(), Option<Capsule>> {
+        let mut state = self.inner.lock().unwrap();
+        let credit = match bidi {
+            true => &mut state.open_bi,
+            false => &mut state.open_uni,
+        };
+
+        credit
+            .take(1)
+            .map(|_| ())
+            .map_err(|max| max.map(|max| Capsule::WtStreamsBlocked { bidi, max }))
+    }
+
+    // Take credit to send up to the given number of bytes, returning how many we can send.
+    // Otherwise returns the WT_DATA_BLOCKED capsule to send if we haven't yet.
+    pub fn reserve(&self, size: usize) -> Result<usize, Option<Capsule>> {
+        let mut state = self.inner.lock().unwrap();
+
+        match state.send_data.take(size as u64) {
+            Ok(size) => Ok(size as usize),
+            Err(max) => Err(max.map(|max| Capsule::WtDataBlocked { max })),
+        }
+    }
+
+    // Return credit that was reserved but not used.
+    pub fn refund(&self, size: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.send_data.used -= size as u64;
+    }
+
+    // Raise a limit set by the peer, returning true if it's a capsule we handle.
+    pub fn update(&self, capsule: &Capsule) -> bool {
+        let mut state = self.inner.lock().unwrap();
+
+        let (credit, max) = match capsule {
+            Capsule::WtMaxStreams { bidi: true, max } => (&mut state.open_bi, max),
+            Capsule::WtMaxStreams { bidi: false, max } => (&mut state.open_uni, max),
+            Capsule::WtMaxData { max } => (&mut state.send_data, max),
+            _ => return false,
+        };
+
+        credit.max = credit.max.max(max.into_inner());
+        true
+    }
+
+    // Count a stream opened by the peer, returning false if it exceeded our limit.
+    pub fn opened(&self, bidi: bool) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        let window = match bidi {
+            true => &mut state.accept_bi,
+            false => &mut state.accept_uni,
+        };
+
+        window.used += 1;
+        window.used <= window.max
+    }
+
+    // Count a stream accepted by the application, returning the WT_MAX_STREAMS capsule to send if the limit was raised.
+    pub fn accepted(&self, bidi: bool) -> Option<Capsule> {
+        let mut state = self.inner.lock().unwrap();
+        let window = match bidi {
+            true => &mut state.accept_bi,
+            false => &mut state.accept_uni,
+        };
+
+        let max = window.consume(1)?;
+        Some(Capsule::WtMaxStreams { bidi, max })
+    }
+
+    // Count data read by the application, returning the WT_MAX_DATA capsule to send if the limit was raised.
+    // Returns an error if the peer sent more than our limit.
+    pub fn received(&self, size: usize) -> Result<Option<Capsule>, ()> {
+        let mut state = self.inner.lock().unwrap();
+        let window = &mut state.recv_data;
+
+        window.used += size as u64;
+        if window.used > window.max {
+            return Err(());
+        }
+
+        Ok(window
+            .consume(size as u64)
+            .map(|max| Capsule::WtMaxData { max }))
+    }
+}
+
+impl Credit {
+    fn new(max: u64) -> Self {
+        Self {
+            max,
+            used: 0,
+            blocked: None,
+        }
+    }
+
+    // Take up to the given amount, or return the limit if we haven't reported being blocked at it yet.
+    fn take(&mut self, size: u64) -> Result<u64, Option<VarInt>> {
+        let available = self.max - self.used;
+
+        if available == 0 {
+            return match self.blocked.replace(self.max) {
+                Some(blocked) if blocked == self.max => Err(None),
+                _ => Err(Some(VarInt::try_from(self.max).unwrap())),
+            };
+        }
+
+        let size = size.min(available);
+        self.used += size;
+        Ok(size)
+    }
+}
+
+impl Window {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            max: size,
+            used: 0,
+            consumed: 0,
+        }
+    }
+
+    // Consume part of the window, returning the new limit once less than half of it is left.
+    fn consume(&mut self, size: u64) -> Option<VarInt> {
+        self.consumed += size;
+
+        if self.size == 0 || self.max - self.consumed.min(self.max) > self.size / 2 {
+            return None;
+        }
+
+        self.max = (self.consumed + self.size).min(VarInt::MAX.into_inner());
+        Some(VarInt::try_from(self.max).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(streams: u64, data: u64) -> SessionLimits {
+        SessionLimits {
+            max_streams_uni: streams,
+            max_streams_bidi: streams,
+            max_data: data,
+        }
+    }
+
+    fn varint(v: u64) -> VarInt {
+        VarInt::try_from(v).unwrap()
+    }
+
+    #[test]
+    fn streams_blocked_once() {
+        let flow = Flow::new(limits(0, 0), limits(2, 0));
+
+        assert_eq!(flow.open(false), Ok(()));
+        assert_eq!(flow.open(false), Ok(()));
+
+        let blocked = Capsule::WtStreamsBlocked {
+            bidi: false,
+            max: varint(2),
+        };
+        assert_eq!(flow.open(false), Err(Some(blocked)));
+        assert_eq!(flow.open(false), Err(None));
+
+        // The other direction has its own limit.
+        assert_eq!(flow.open(true), Ok(()));
+
+        // Reported again once we're blocked at the new limit.
+        assert!(flow.update(&Capsule::WtMaxStreams {
+            bidi: false,
+            max: varint(3)
+        }));
+        assert_eq!(flow.open(false), Ok(()));
+
+        let blocked = Capsule::WtStreamsBlocked {
+            bidi: false,
+            max: varint(3),
+        };
+        assert_eq!(flow.open(false), Err(Some(blocked)));
+        assert_eq!(flow.open(false), Err(None));
+    }
+
+    #[test]
+    fn data_blocked_once() {
+        let flow = Flow::new(limits(0, 0), limits(0, 100));
+
+        assert_eq!(flow.reserve(60), Ok(60));
+        assert_eq!(flow.reserve(60), Ok(40));
+
+        let blocked = Capsule::WtDataBlocked { max: varint(100) };
+        assert_eq!(flow.reserve(1), Err(Some(blocked)));
+        assert_eq!(flow.reserve(1), Err(None));
+
+        // A lower limit is ignored, since they're cumulative.
+        assert!(flow.update(&Capsule::WtMaxData { max: varint(50) }));
+        assert_eq!(flow.reserve(1), Err(None));
+
+        assert!(flow.update(&Capsule::WtMaxData { max: varint(150) }));
+        assert_eq!(flow.reserve(100), Ok(50));
+
+        let blocked = Capsule::WtDataBlocked { max: varint(150) };
+        assert_eq!(flow.reserve(1), Err(Some(blocked)));
+    }
+
+    #[test]
+    fn refund_partial_reserve() {
+        let flow = Flow::new(limits(0, 0), limits(0, 100));
+
+        // Only part of the reservation was written.
+        assert_eq!(flow.reserve(80), Ok(80));
+        flow.refund(30);
+
+        assert_eq!(flow.reserve(100), Ok(50));
+        assert!(flow.reserve(1).is_err());
+    }
+
+    #[test]
+    fn raise_streams_at_half() {
+        let flow = Flow::new(limits(4, 0), limits(0, 0));
+
+        for _ in 0..4 {
+            assert!(flow.opened(true));
+        }
+        assert!(!flow.opened(true));
+
+        // More than half of the window is left after the first.
+        assert_eq!(flow.accepted(true), None);
+
+        let raised = Capsule::WtMaxStreams {
+            bidi: true,
+            max: varint(6),
+        };
+        assert_eq!(flow.accepted(true), Some(raised));
+        assert_eq!(flow.accepted(true), None);
+    }
+
+    #[test]
+    fn raise_data_at_half() {
+        let flow = Flow::new(limits(0, 100), limits(0, 0));
+
+        assert_eq!(flow.received(49), Ok(None));
+        assert_eq!(
+            flow.received(1),
+            Ok(Some(Capsule::WtMaxData { max: varint(150) }))
+        );
+
+        // The new limit is enforced, and raised again once half of it is left.
+        assert_eq!(flow.received(49), Ok(None));
+        assert_eq!(
+            flow.received(1),
+            Ok(Some(Capsule::WtMaxData { max: varint(200) }))
+        );
+    }
+
+    #[test]
+    fn data_exceeded() {
+        let flow = Flow::new(limits(0, 100), limits(0, 0));
+
+        // More than the peer was allowed before we raised the limit.
+        assert_eq!(flow.received(10), Ok(None));
+        assert_eq!(flow.received(91), Err(()));
+    }
+
+    #[test]
+    fn zero_size() {
+        let flow = Flow::new(limits(0, 0), limits(0, 0));
+
+        // Nothing is allowed and the limits are never raised.
+        assert!(!flow.opened(false));
+        assert_eq!(flow.accepted(false), None);
+        assert_eq!(flow.received(0), Ok(None));
+        assert_eq!(flow.received(1), Err(()));
+
+        let blocked = Capsule::WtStreamsBlocked {
+            bidi: true,
+            max: varint(0),
+        };
+        assert_eq!(flow.open(true), Err(Some(blocked)));
+        assert_eq!(
+            flow.reserve(1),
+            Err(Some(Capsule::WtDataBlocked { max: varint(0) }))
+        );
+    }
+}
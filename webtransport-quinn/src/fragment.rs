@@ -0,0 +1,352 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::{Bytes, BytesMut};
+
+use webtransport_proto::{Fragment, VarInt};
+
+use crate::{SendDatagramError, Session, SessionError};
+
+// The maximum number of fragments in a message, so the receiver's memory is bounded.
+const MAX_FRAGMENTS: u64 = 64;
+
+// The worst-case size of the index and count varints, since both are below 2^14.
+const MAX_INDEX_SIZE: usize = 4;
+
+/// Messages larger than a datagram, split into numbered fragments and reassembled by the peer's [`FragmentedDatagrams`].
+///
+/// Datagrams are limited by the path MTU (see [`Session::max_datagram_size`]), so larger messages are sent as up to 64 fragments.
+/// A message is only received once all of its fragments arrive, in any order; if any fragment is lost, so is the message.
+/// Since the chance of losing a message grows with the number of fragments, prefer small messages.
+///
+/// Incomplete messages are discarded once they fall too far behind the newest message, see [`Self::set_max_pending`].
+/// This is a custom framing, so both endpoints need to use it, and it shouldn't be mixed with [`Session::recv_datagram`] on the same session.
+/// Create one per session and clone it, since message IDs are only unique per instance.
+#[derive(Clone)]
+pub struct FragmentedDatagrams {
+    session: Session,
+    next: Arc<AtomicU64>,
+    reassembly: Arc<Mutex<Reassembly>>,
+}
+
+impl FragmentedDatagrams {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            next: Default::default(),
+            reassembly: Default::default(),
+        }
+    }
+
+    /// Send a message, split into as many datagrams as needed.
+    ///
+    /// Returns [`SendDatagramError::TooLarge`] if the message needs more than 64 fragments at the current MTU.
+    /// Fragments may still be dropped locally (ex. by [`Session::set_datagram_queue`]), losing the message.
+    pub fn send(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        let max = self
+            .max_fragment_size()
+            .ok_or(SendDatagramError::UnsupportedByPeer)?;
+
+        let count = payload.len().div_ceil(max).max(1) as u64;
+        if count > MAX_FRAGMENTS {
+            return Err(SendDatagramError::TooLarge);
+        }
+
+        let message = self.next.fetch_add(1, Ordering::Relaxed);
+        let message = VarInt::try_from(message).map_err(|_| SendDatagramError::TooLarge)?;
+
+        for index in 0..count {
+            let start = (index as usize) * max;
+            let end = payload.len().min(start + max);
+
+            let fragment = Fragment {
+                message,
+                index: VarInt::from_u32(index as u32),
+                count: VarInt::from_u32(count as u32),
+                payload: payload.slice(start..end),
+            };
+
+            let mut buf = BytesMut::with_capacity(fragment.header_size() + fragment.payload.len());
+            fragment.encode(&mut buf);
+            self.session.send_datagram(buf.freeze())?;
+        }
+
+        Ok(())
+    }
+
+    /// Receive the next complete message, skipping malformed fragments.
+    pub async fn recv(&self) -> Result<Bytes, SessionError> {
+        let mut coop = self.session.coop();
+
+        loop {
+            coop.proceed().await;
+
+            let datagram = self.session.recv_datagram().await?;
+            let fragment = match Fragment::decode(datagram) {
+                Ok(fragment) => fragment,
+                Err(_) => continue,
+            };
+
+            if let Some(message) = self.reassembly.lock().unwrap().push(fragment) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// The largest message that can currently be sent, or None if datagrams are unsupported by the peer.
+    ///
+    /// Like [`Session::max_datagram_size`], this depends on the path MTU, so check it for each message instead of caching it.
+    pub fn max_message_size(&self) -> Option<usize> {
+        Some(self.max_fragment_size()? * MAX_FRAGMENTS as usize)
+    }
+
+    /// Discard an incomplete message once a message this many IDs newer arrives, defaulting to 16.
+    ///
+    /// This bounds the memory used by lost fragments, but a larger window tolerates more reordering.
+    pub fn set_max_pending(&self, max: usize) {
+        let mut reassembly = self.reassembly.lock().unwrap();
+        reassembly.max_pending = max as u64;
+        reassembly.expire();
+    }
+
+    /// The number of incomplete messages discarded so far, because a fragment was lost or arrived too late.
+    pub fn discarded(&self) -> u64 {
+        self.reassembly.lock().unwrap().discarded
+    }
+
+    // The largest fragment payload that fits in a datagram, leaving room for the next message ID.
+    fn max_fragment_size(&self) -> Option<usize> {
+        let message = VarInt::try_from(self.next.load(Ordering::Relaxed)).ok()?;
+        let header = message.size() + MAX_INDEX_SIZE;
+
+        self.session
+            .max_datagram_size()?
+            .checked_sub(header)
+            .filter(|size| *size > 0)
+    }
+}
+
+// The fragments received for each incomplete message, keyed by the message ID.
+struct Reassembly {
+    pending: BTreeMap<u64, Partial>,
+
+    // Messages that were already returned, so a duplicate fragment doesn't start them again.
+    completed: BTreeSet<u64>,
+
+    // The newest message ID seen, used to discard older incomplete messages.
+    newest: u64,
+
+    // Fragments for messages older than this are ignored, since they were discarded.
+    floor: u64,
+
+    max_pending: u64,
+    discarded: u64,
+}
+
+impl Default for Reassembly {
+    fn default() -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            completed: BTreeSet::new(),
+            newest: 0,
+            floor: 0,
+            max_pending: 16,
+            discarded: 0,
+        }
+    }
+}
+
+struct Partial {
+    fragments: Vec<Option<Bytes>>,
+    missing: usize,
+}
+
+impl Reassembly {
+    // Add a fragment, returning the message if it's now complete.
+    fn push(&mut self, fragment: Fragment) -> Option<Bytes> {
+        let message = fragment.message.into_inner();
+        let index = fragment.index.into_inner();
+        let count = fragment.count.into_inner();
+
+        if count == 0 || count > MAX_FRAGMENTS || index >= count || message < self.floor {
+            return None;
+        }
+
+        if self.completed.contains(&message) {
+            return None;
+        }
+
+        if message > self.newest {
+            self.newest = message;
+            self.expire();
+
+            // The message itself may have been older than the new floor.
+            if message < self.floor {
+                return None;
+            }
+        }
+
+        if count == 1 {
+            self.completed.insert(message);
+            return Some(fragment.payload);
+        }
+
+        let partial = self.pending.entry(message).or_insert_with(|| Partial {
+            fragments: vec![None; count as usize],
+            missing: count as usize,
+        });
+
+        // Ignore fragments that disagree about the number of fragments.
+        if partial.fragments.len() != count as usize {
+            return None;
+        }
+
+        let slot = &mut partial.fragments[index as usize];
+        if slot.is_none() {
+            *slot = Some(fragment.payload);
+            partial.missing -= 1;
+        }
+
+        if partial.missing > 0 {
+            return None;
+        }
+
+        let partial = self.pending.remove(&message)?;
+        self.completed.insert(message);
+        let size = partial.fragments.iter().flatten().map(Bytes::len).sum();
+
+        let mut buf = BytesMut::with_capacity(size);
+        for payload in partial.fragments.into_iter().flatten() {
+            buf.extend_from_slice(&payload);
+        }
+
+        Some(buf.freeze())
+    }
+
+    // Discard incomplete messages too far behind the newest, since a fragment was probably lost.
+    fn expire(&mut self) {
+        self.floor = self.floor.max(self.newest.saturating_sub(self.max_pending));
+
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() >= self.floor {
+                break;
+            }
+
+            entry.remove();
+            self.discarded += 1;
+        }
+
+        // Fragments below the floor are ignored anyway.
+        self.completed = self.completed.split_off(&self.floor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(message: u32, index: u32, count: u32, payload: &'static [u8]) -> Fragment {
+        Fragment {
+            message: VarInt::from_u32(message),
+            index: VarInt::from_u32(index),
+            count: VarInt::from_u32(count),
+            payload: Bytes::from_static(payload),
+        }
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(fragment(0, 2, 3, b"c")), None);
+        assert_eq!(reassembly.push(fragment(0, 0, 3, b"a")), None);
+        assert_eq!(reassembly.push(fragment(0, 1, 3, b"b")).unwrap(), "abc");
+        assert!(reassembly.pending.is_empty());
+    }
+
+    #[test]
+    fn interleaved() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(fragment(1, 0, 2, b"x")), None);
+        assert_eq!(
+            reassembly.push(fragment(0, 0, 1, b"single")).unwrap(),
+            "single"
+        );
+        assert_eq!(reassembly.push(fragment(2, 1, 2, b"2")), None);
+        assert_eq!(reassembly.push(fragment(1, 1, 2, b"y")).unwrap(), "xy");
+        assert_eq!(reassembly.push(fragment(2, 0, 2, b"1")).unwrap(), "12");
+    }
+
+    #[test]
+    fn duplicate_fragment() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(fragment(0, 0, 2, b"a")), None);
+        assert_eq!(reassembly.push(fragment(0, 0, 2, b"z")), None);
+        assert_eq!(reassembly.push(fragment(0, 1, 2, b"b")).unwrap(), "ab");
+
+        // A duplicate of a completed message isn't returned again, or counted as discarded later.
+        assert_eq!(reassembly.push(fragment(0, 1, 2, b"b")), None);
+        assert_eq!(reassembly.push(fragment(1, 0, 1, b"one")).unwrap(), "one");
+        assert_eq!(reassembly.push(fragment(1, 0, 1, b"one")), None);
+        assert!(reassembly.pending.is_empty());
+
+        assert_eq!(reassembly.push(fragment(100, 0, 1, b"new")).unwrap(), "new");
+        assert_eq!(reassembly.discarded, 0);
+        assert_eq!(reassembly.completed.len(), 1);
+    }
+
+    #[test]
+    fn mismatched_count() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(fragment(0, 0, 2, b"a")), None);
+        assert_eq!(reassembly.push(fragment(0, 1, 3, b"b")), None);
+        assert_eq!(reassembly.push(fragment(0, 1, 2, b"b")).unwrap(), "ab");
+    }
+
+    #[test]
+    fn invalid_index() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.push(fragment(0, 2, 2, b"a")), None);
+        assert_eq!(reassembly.push(fragment(0, 0, 0, b"a")), None);
+        assert_eq!(reassembly.push(fragment(0, 0, 65, b"a")), None);
+        assert!(reassembly.pending.is_empty());
+    }
+
+    #[test]
+    fn expire() {
+        let mut reassembly = Reassembly {
+            max_pending: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(reassembly.push(fragment(0, 0, 2, b"a")), None);
+        assert_eq!(reassembly.push(fragment(1, 0, 2, b"b")), None);
+
+        // Message 2 is within the window of both.
+        assert_eq!(reassembly.push(fragment(2, 0, 1, b"c")).unwrap(), "c");
+        assert_eq!(reassembly.discarded, 0);
+
+        // Message 3 pushes message 0 out.
+        assert_eq!(reassembly.push(fragment(3, 0, 1, b"d")).unwrap(), "d");
+        assert_eq!(reassembly.discarded, 1);
+
+        // The rest of message 0 arrived too late, while message 1 can still complete.
+        assert_eq!(reassembly.push(fragment(0, 1, 2, b"a")), None);
+        assert_eq!(reassembly.push(fragment(1, 1, 2, b"b")).unwrap(), "bb");
+
+        // A message that's already older than the window is ignored.
+        assert_eq!(reassembly.push(fragment(10, 0, 2, b"x")), None);
+        assert_eq!(reassembly.push(fragment(7, 0, 2, b"y")), None);
+        assert_eq!(reassembly.pending.len(), 1);
+        assert_eq!(reassembly.discarded, 1);
+    }
+}
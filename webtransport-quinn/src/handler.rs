@@ -0,0 +1,133 @@
+use std::{future::Future, panic::AssertUnwindSafe, pin::pin};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+
+use crate::{serve, Request, Server, Session};
+
+/// An application that handles WebTransport sessions, run by [`Server::serve`].
+///
+/// The handler is cloned for each session, so shared state belongs behind an `Arc`.
+/// Middleware can wrap a handler in another type implementing this trait (ex. to authenticate or log), and delegate to it.
+/// Closures taking a [`Session`] implement it too, accepting every request.
+pub trait SessionHandler: Clone + Send + Sync {
+    /// Decide how to respond to the CONNECT request before the session is established, accepting it by default.
+    /// This is a good place to authenticate the client, and to attach state for the session with [`Request::extensions`].
+    /// Other sessions keep running and new requests keep being accepted while this is pending, so it can await a slow lookup.
+    /// A panic refuses the request with a 500 status, like a panic in [`Self::handle`] closes the session.
+    fn respond(&self, request: &mut Request) -> impl Future<Output = Response> + Send {
+        let _ = request;
+        async { Response::Accept }
+    }
+
+    /// Run the accepted session; it's dropped once this returns.
+    fn handle(self, session: Session) -> impl Future<Output = ()> + Send;
+}
+
+impl<F, Fut> SessionHandler for F
+where
+    F: FnOnce(Session) -> Fut + Clone + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    fn handle(self, session: Session) -> impl Future<Output = ()> + Send {
+        self(session)
+    }
+}
+
+/// How a [`SessionHandler`] responds to a CONNECT request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// Accept the session with a 200 OK, see [`Request::ok`].
+    Accept,
+
+    /// Accept the session with a 200 OK and the given response headers, see [`Request::accept_with`].
+    AcceptWith(http::HeaderMap),
+
+    /// Reject the session with the given status, see [`Request::close`].
+    Reject(http::StatusCode),
+
+    /// Reject the session with the given status and response headers, leaving the connection open, see [`Request::reject_with`].
+    RejectWith(http::StatusCode, http::HeaderMap),
+}
+
+// Accept sessions and run the handler for each, until the endpoint is closed or shutdown completes.
+pub(crate) async fn serve_sessions<H, S>(server: &mut Server, handler: H, shutdown: S)
+where
+    H: SessionHandler,
+    S: Future<Output = ()>,
+{
+    let mut shutdown = pin!(shutdown.fuse());
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        futures::select! {
+            request = server.accept().fuse() => match request {
+                Some(request) => running.push(session(handler.clone(), request)),
+                None => break,
+            },
+            _ = running.select_next_some() => {},
+            _ = shutdown => {
+                server.drain().await;
+                break;
+            }
+        }
+    }
+
+    // Keep refusing new connections while the running sessions finish.
+    loop {
+        futures::select! {
+            request = server.accept().fuse() => match request {
+                Some(request) => running.push(session(handler.clone(), request)),
+                None => break,
+            },
+            res = running.next() => if res.is_none() {
+                return;
+            },
+        }
+    }
+
+    // The endpoint was closed, so the sessions will end shortly.
+    while running.next().await.is_some() {}
+}
+
+// Respond to the request and run the handler if it was accepted.
+async fn session<H: SessionHandler>(handler: H, mut request: Request) {
+    // A handler that panics while responding only refuses its own request.
+    let response = AssertUnwindSafe(serve::call(|| handler.respond(&mut request)))
+        .catch_unwind()
+        .await;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(_) => {
+            request
+                .close(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .await
+                .ok();
+            return;
+        }
+    };
+
+    let session = match response {
+        Response::Accept => match request.ok().await {
+            Ok(session) => session,
+            Err(_) => return,
+        },
+        Response::AcceptWith(headers) => match request.accept_with(headers).await {
+            Ok(session) => session,
+            Err(_) => return,
+        },
+        Response::Reject(status) => {
+            request.close(status).await.ok();
+            return;
+        }
+        Response::RejectWith(status, headers) => {
+            request.reject_with(status, headers).await.ok();
+            return;
+        }
+    };
+
+    // A handler that panics only loses its own session, which is closed so the peer isn't left waiting.
+    if let Err(failure) = serve::run(serve::call(|| handler.handle(session.clone())), None).await {
+        session.close(0, failure.reason());
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use bytes::Bytes;
+
+use crate::Fallback;
+
+/// The state of an endpoint, as reported by [`Health`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthState {
+    /// New sessions are being accepted.
+    Accepting,
+
+    /// Existing sessions are being served but new ones should go elsewhere.
+    Draining,
+
+    /// The endpoint is shutting down.
+    Closed,
+}
+
+impl HealthState {
+    fn into_u8(self) -> u8 {
+        match self {
+            Self::Accepting => 0,
+            Self::Draining => 1,
+            Self::Closed => 2,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => Self::Accepting,
+            1 => Self::Draining,
+            _ => Self::Closed,
+        }
+    }
+}
+
+/// Answers `/healthz` and `/readyz` over HTTP/3 on the same endpoint, for Kubernetes-style probes.
+///
+/// `/healthz` returns 200 OK until the endpoint is [`HealthState::Closed`].
+/// `/readyz` returns 200 OK only while the endpoint is [`HealthState::Accepting`].
+/// Otherwise they return 503 Service Unavailable.
+///
+/// This is a cheap handle; clone it and update the state as the endpoint changes.
+#[derive(Clone)]
+pub struct Health {
+    state: Arc<AtomicU8>,
+}
+
+impl Health {
+    /// Create a new handle in the [`HealthState::Accepting`] state.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(AtomicU8::new(HealthState::Accepting.into_u8())),
+        }
+    }
+
+    /// Return the current state.
+    pub fn state(&self) -> HealthState {
+        HealthState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    /// Update the current state.
+    pub fn set_state(&self, state: HealthState) {
+        self.state.store(state.into_u8(), Ordering::Relaxed)
+    }
+
+    /// A [`Fallback`] that answers the health endpoints and returns 404 Not Found for everything else.
+    pub fn fallback(&self) -> Fallback {
+        let health = self.clone();
+        Fallback::new(move |req| {
+            let resp = health
+                .respond(&req)
+                .unwrap_or_else(|| Self::response(http::StatusCode::NOT_FOUND, "not found\n"));
+            async move { resp }
+        })
+    }
+
+    /// A [`Fallback`] that answers the health endpoints and passes everything else to `inner`.
+    pub fn with_fallback(&self, inner: Fallback) -> Fallback {
+        let health = self.clone();
+        Fallback::new(move |req| {
+            let resp = health.respond(&req);
+            let inner = inner.clone();
+
+            async move {
+                match resp {
+                    Some(resp) => resp,
+                    None => inner.handle(req).await,
+                }
+            }
+        })
+    }
+
+    // Returns None if the request isn't for one of the health endpoints.
+    fn respond(&self, req: &http::Request<Bytes>) -> Option<http::Response<Bytes>> {
+        let ok = match req.uri().path() {
+            "/healthz" => self.state() != HealthState::Closed,
+            "/readyz" => self.state() == HealthState::Accepting,
+            _ => return None,
+        };
+
+        if req.method() != http::Method::GET && req.method() != http::Method::HEAD {
+            return Some(Self::response(
+                http::StatusCode::METHOD_NOT_ALLOWED,
+                "method not allowed\n",
+            ));
+        }
+
+        Some(match ok {
+            true => Self::response(http::StatusCode::OK, "ok\n"),
+            false => Self::response(http::StatusCode::SERVICE_UNAVAILABLE, "unavailable\n"),
+        })
+    }
+
+    fn response(status: http::StatusCode, body: &'static str) -> http::Response<Bytes> {
+        let mut resp = http::Response::new(Bytes::from_static(body.as_bytes()));
+        *resp.status_mut() = status;
+        resp
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
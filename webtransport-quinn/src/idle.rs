@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, SystemClock};
+
+// The minimum time between checks for idle sessions.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Closes sessions without any activity for too long, see [`crate::Server::set_idle_policy`].
+///
+/// A session is active while stream data or datagrams are sent or received, in either direction.
+/// Unlike the QUIC idle timeout, keep-alives and acknowledgements don't count as activity,
+/// so a session that's been abandoned by the application (ex. a background browser tab) is still closed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdlePolicy {
+    /// How long a session may be idle before it's closed.
+    pub timeout: Duration,
+
+    /// The error code used to close the session.
+    pub code: u32,
+
+    /// The reason used to close the session.
+    pub reason: String,
+}
+
+impl IdlePolicy {
+    /// Close idle sessions with error code 0 and the reason "idle".
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            code: 0,
+            reason: "idle".to_string(),
+        }
+    }
+
+    // How often to check for idle sessions.
+    pub(crate) fn interval(&self) -> Duration {
+        (self.timeout / 4).max(MIN_INTERVAL)
+    }
+}
+
+// Keeps track of when each session was last active, closing them when they're idle for too long.
+//
+// Activity is sampled from the Quinn frame counters, so the streams don't need to report it.
+#[derive(Clone)]
+pub(crate) struct Reaper {
+    sessions: Arc<Mutex<HashMap<usize, Idle>>>,
+    clock: Arc<dyn Clock>,
+}
+
+struct Idle {
+    conn: quinn::Connection,
+
+    // The number of stream and datagram frames at the last check.
+    activity: u64,
+
+    // When the number of frames last changed.
+    since: Instant,
+}
+
+impl Default for Reaper {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl Reaper {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sessions: Arc::default(),
+            clock,
+        }
+    }
+
+    pub fn track(&self, conn: &quinn::Connection) {
+        let idle = Idle {
+            conn: conn.clone(),
+            activity: activity(conn),
+            since: self.clock.now(),
+        };
+
+        self.sessions.lock().unwrap().insert(conn.stable_id(), idle);
+    }
+
+    // Close any sessions that have been idle for longer than the timeout, and forget any that are closed.
+    pub fn reap(&self, policy: &IdlePolicy) {
+        let now = self.clock.now();
+        let code = webtransport_proto::error_to_http3(policy.code)
+            .try_into()
+            .unwrap();
+
+        self.sessions.lock().unwrap().retain(|_, idle| {
+            if idle.conn.close_reason().is_some() {
+                return false;
+            }
+
+            let activity = activity(&idle.conn);
+            if activity != idle.activity {
+                idle.activity = activity;
+                idle.since = now;
+                return true;
+            }
+
+            if now - idle.since < policy.timeout {
+                return true;
+            }
+
+            idle.conn.close(code, policy.reason.as_bytes());
+            false
+        });
+    }
+}
+
+// Return the number of frames that carried stream data or datagrams.
+fn activity(conn: &quinn::Connection) -> u64 {
+    let stats = conn.stats();
+    let (tx, rx) = (stats.frame_tx, stats.frame_rx);
+
+    tx.stream + rx.stream + tx.datagram + rx.datagram + tx.reset_stream + rx.reset_stream
+}
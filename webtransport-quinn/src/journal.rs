@@ -0,0 +1,421 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    trace::{event, Span},
+    Clock, ProtocolError, SessionClose, SessionInfo, SessionListener, SessionMetrics,
+};
+
+/// An event recorded in a session's [`Journal`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JournalEvent {
+    /// The session was established with the given URI.
+    Connected { uri: String },
+
+    /// A stream was opened, either by us (local) or the peer.
+    StreamOpened { id: u64, bidi: bool, local: bool },
+
+    /// A stream was reset with an error code, either by us (local) or the peer.
+    StreamReset { id: u64, code: u32, local: bool },
+
+    /// A stream was stopped with an error code, either by us (local) or the peer.
+    StreamStopped { id: u64, code: u32, local: bool },
+
+    /// The session was closed for the given reason.
+    Closed { reason: String },
+
+    /// A stream was given a label by the application, see [`crate::SendStream::set_label`].
+    StreamLabeled { id: u64, label: String },
+
+    /// The session was asked to wrap up, either by us (local) or the peer. See [`crate::Session::drain`].
+    Draining { local: bool },
+}
+
+/// A single event in a [`Journal`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The time since the session was established.
+    pub elapsed: Duration,
+
+    pub event: JournalEvent,
+}
+
+/// A bounded record of what happened during a session, for postmortem debugging. See [`crate::Session::set_journal`].
+///
+/// Only the most recent events are kept, but the number of older events that were discarded is recorded.
+/// Enable the `serde` feature to serialize it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Journal {
+    /// When the session was established.
+    pub started: SystemTime,
+
+    /// The events in the order they happened.
+    pub entries: Vec<JournalEntry>,
+
+    /// The number of events that were discarded to stay within the capacity.
+    pub dropped: u64,
+}
+
+// Records the events of a session, if enabled, and reports them to the metrics, the listener, and the session's span with the `tracing` feature.
+pub(crate) struct Recorder {
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    started_at: SystemTime,
+    info: SessionInfo,
+    span: Span,
+
+    log: Mutex<Option<Log>>,
+
+    // Set once the close reason has been reported, since it's observed by every pending operation.
+    closed: AtomicBool,
+
+    metrics: OnceLock<Arc<dyn SessionMetrics>>,
+    listener: OnceLock<Arc<dyn SessionListener>>,
+
+    // The bidirectional streams with one half dropped, so they're reported as closed when the other half is.
+    halves: Mutex<HashSet<quinn::StreamId>>,
+}
+
+struct Log {
+    capacity: usize,
+    entries: VecDeque<JournalEntry>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl Recorder {
+    pub fn new(info: SessionInfo, span: Span, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            started: clock.now(),
+            clock,
+            started_at: SystemTime::now(),
+            info,
+            span,
+            log: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            metrics: OnceLock::new(),
+            listener: OnceLock::new(),
+            halves: Mutex::default(),
+        }
+    }
+
+    // Report the session's events to the metrics from now on, starting with the session being opened.
+    pub fn set_metrics(&self, metrics: Arc<dyn SessionMetrics>) {
+        if self.metrics.set(metrics).is_ok() {
+            self.with_metrics(|metrics| metrics.session_opened());
+        }
+    }
+
+    fn with_metrics<F: FnOnce(&dyn SessionMetrics)>(&self, f: F) {
+        if let Some(metrics) = self.metrics.get() {
+            f(metrics.as_ref())
+        }
+    }
+
+    // Report the session's events to the listener from now on, starting with the session being opened.
+    pub fn set_listener(&self, listener: Arc<dyn SessionListener>) {
+        if self.listener.set(listener).is_ok() {
+            self.with_listener(|listener, info| listener.on_session_open(info));
+        }
+    }
+
+    pub fn with_listener<F: FnOnce(&dyn SessionListener, &SessionInfo)>(&self, f: F) {
+        if let Some(listener) = self.listener.get() {
+            f(listener.as_ref(), &self.info)
+        }
+    }
+
+    // Report that the peer violated the protocol, which closes the session.
+    pub fn protocol_error(&self, error: ProtocolError) {
+        event!(warn, parent: &self.span, ?error, "protocol error");
+        self.with_listener(|listener, info| listener.on_protocol_error(info.remote, &error));
+    }
+
+    // The session's span, used as the parent of its streams.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    // Start recording with the given capacity, or stop recording and discard the journal with None.
+    pub fn enable(&self, capacity: Option<usize>) {
+        let mut log = self.log.lock().unwrap();
+
+        let capacity = match capacity {
+            Some(capacity) => capacity,
+            None => {
+                *log = None;
+                return;
+            }
+        };
+
+        match log.as_mut() {
+            Some(log) => log.resize(capacity),
+            None => {
+                // Record the connect time, even though it happened before recording was enabled.
+                let mut new = Log {
+                    capacity,
+                    entries: VecDeque::new(),
+                    dropped: 0,
+                    closed: false,
+                };
+                new.push(JournalEntry {
+                    elapsed: Duration::ZERO,
+                    event: JournalEvent::Connected {
+                        uri: self.info.uri.to_string(),
+                    },
+                });
+                *log = Some(new);
+            }
+        }
+    }
+
+    // The time since the session was established, by the session's clock.
+    fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.started)
+    }
+
+    pub fn record(&self, event: JournalEvent) {
+        if let Some(log) = self.log.lock().unwrap().as_mut() {
+            log.push(JournalEntry {
+                elapsed: self.elapsed(),
+                event,
+            });
+        }
+    }
+
+    pub fn opened(&self, id: quinn::StreamId, local: bool) {
+        let bidi = id.dir() == quinn_proto::Dir::Bi;
+        event!(debug, parent: &self.span, stream = stream_id(id), bidi, local, "stream opened");
+        self.with_metrics(|metrics| metrics.stream_opened(bidi, local));
+        self.with_listener(|listener, info| listener.on_stream_open(info, id, local));
+
+        self.record(JournalEvent::StreamOpened {
+            id: stream_id(id),
+            bidi,
+            local,
+        })
+    }
+
+    pub fn reset(&self, id: quinn::StreamId, code: u32, local: bool) {
+        event!(debug, parent: &self.span, stream = stream_id(id), code, local, "stream reset");
+        self.with_metrics(|metrics| metrics.stream_reset(code, local));
+
+        self.record(JournalEvent::StreamReset {
+            id: stream_id(id),
+            code,
+            local,
+        })
+    }
+
+    pub fn stopped(&self, id: quinn::StreamId, code: u32, local: bool) {
+        event!(debug, parent: &self.span, stream = stream_id(id), code, local, "stream stopped");
+        self.with_metrics(|metrics| metrics.stream_stopped(code, local));
+
+        self.record(JournalEvent::StreamStopped {
+            id: stream_id(id),
+            code,
+            local,
+        })
+    }
+
+    pub fn labeled(&self, id: quinn::StreamId, label: &str) {
+        self.record(JournalEvent::StreamLabeled {
+            id: stream_id(id),
+            label: label.to_string(),
+        })
+    }
+
+    pub fn draining(&self, local: bool) {
+        event!(info, parent: &self.span, local, "session draining");
+
+        self.record(JournalEvent::Draining { local })
+    }
+
+    // Record the close reason, only the first time it's observed, given the code and reason we used if we closed it ourselves.
+    pub fn closed(&self, reason: &quinn::ConnectionError, local: Option<(u32, String)>) {
+        if !self.closed.swap(true, Ordering::Relaxed) {
+            event!(info, parent: &self.span, %reason, "session closed");
+
+            let error = reason.clone().into();
+            self.with_metrics(|metrics| metrics.session_closed(self.elapsed(), &error));
+
+            let close = SessionClose::new(reason, local);
+            self.with_listener(|listener, info| listener.on_session_close(info, &close));
+        }
+
+        let mut log = self.log.lock().unwrap();
+
+        if let Some(log) = log.as_mut().filter(|log| !log.closed) {
+            log.closed = true;
+            log.push(JournalEntry {
+                elapsed: self.elapsed(),
+                event: JournalEvent::Closed {
+                    reason: reason.to_string(),
+                },
+            });
+        }
+    }
+
+    // Report a half of a stream being dropped, closing the stream once both halves of a bidirectional one are.
+    pub fn dropped(&self, id: quinn::StreamId) {
+        let bidi = id.dir() == quinn_proto::Dir::Bi;
+
+        self.with_metrics(|metrics| {
+            if bidi {
+                let mut halves = self.halves.lock().unwrap();
+                if halves.insert(id) {
+                    return;
+                }
+
+                halves.remove(&id);
+            }
+
+            metrics.stream_closed(bidi)
+        })
+    }
+
+    pub fn sent(&self, size: usize) {
+        self.with_metrics(|metrics| metrics.bytes_sent(size as u64))
+    }
+
+    pub fn received(&self, size: usize) {
+        self.with_metrics(|metrics| metrics.bytes_received(size as u64))
+    }
+
+    pub fn journal(&self) -> Option<Journal> {
+        let log = self.log.lock().unwrap();
+        let log = log.as_ref()?;
+
+        Some(Journal {
+            started: self.started_at,
+            entries: log.entries.iter().cloned().collect(),
+            dropped: log.dropped,
+        })
+    }
+}
+
+impl Log {
+    fn push(&mut self, entry: JournalEntry) {
+        self.entries.push_back(entry);
+        self.resize(self.capacity);
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+fn stream_id(id: quinn::StreamId) -> u64 {
+    quinn::VarInt::from(id).into_inner()
+}
+
+#[cfg(feature = "serde")]
+mod serialize {
+    use serde::ser::{Serialize, SerializeStruct, SerializeStructVariant, Serializer};
+
+    use super::*;
+
+    // Timestamps are serialized as milliseconds, since the Unix epoch for the start time.
+    impl Serialize for Journal {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let started = self
+                .started
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+
+            let mut s = serializer.serialize_struct("Journal", 3)?;
+            s.serialize_field("started_ms", &(started.as_millis() as u64))?;
+            s.serialize_field("entries", &self.entries)?;
+            s.serialize_field("dropped", &self.dropped)?;
+            s.end()
+        }
+    }
+
+    impl Serialize for JournalEntry {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut s = serializer.serialize_struct("JournalEntry", 2)?;
+            s.serialize_field("elapsed_ms", &(self.elapsed.as_secs_f64() * 1000.0))?;
+            s.serialize_field("event", &self.event)?;
+            s.end()
+        }
+    }
+
+    impl Serialize for JournalEvent {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Self::Connected { uri } => {
+                    let mut s =
+                        serializer.serialize_struct_variant("JournalEvent", 0, "Connected", 1)?;
+                    s.serialize_field("uri", uri)?;
+                    s.end()
+                }
+                Self::StreamOpened { id, bidi, local } => {
+                    let mut s = serializer.serialize_struct_variant(
+                        "JournalEvent",
+                        1,
+                        "StreamOpened",
+                        3,
+                    )?;
+                    s.serialize_field("id", id)?;
+                    s.serialize_field("bidi", bidi)?;
+                    s.serialize_field("local", local)?;
+                    s.end()
+                }
+                Self::StreamReset { id, code, local } => {
+                    let mut s =
+                        serializer.serialize_struct_variant("JournalEvent", 2, "StreamReset", 3)?;
+                    s.serialize_field("id", id)?;
+                    s.serialize_field("code", code)?;
+                    s.serialize_field("local", local)?;
+                    s.end()
+                }
+                Self::StreamStopped { id, code, local } => {
+                    let mut s = serializer.serialize_struct_variant(
+                        "JournalEvent",
+                        3,
+                        "StreamStopped",
+                        3,
+                    )?;
+                    s.serialize_field("id", id)?;
+                    s.serialize_field("code", code)?;
+                    s.serialize_field("local", local)?;
+                    s.end()
+                }
+                Self::Closed { reason } => {
+                    let mut s =
+                        serializer.serialize_struct_variant("JournalEvent", 4, "Closed", 1)?;
+                    s.serialize_field("reason", reason)?;
+                    s.end()
+                }
+                Self::StreamLabeled { id, label } => {
+                    let mut s = serializer.serialize_struct_variant(
+                        "JournalEvent",
+                        5,
+                        "StreamLabeled",
+                        2,
+                    )?;
+                    s.serialize_field("id", id)?;
+                    s.serialize_field("label", label)?;
+                    s.end()
+                }
+                Self::Draining { local } => {
+                    let mut s =
+                        serializer.serialize_struct_variant("JournalEvent", 6, "Draining", 1)?;
+                    s.serialize_field("local", local)?;
+                    s.end()
+                }
+            }
+        }
+    }
+}
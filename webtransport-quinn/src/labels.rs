@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The traffic on the streams in a session with the same label, see [`crate::Session::label_stats`].
+///
+/// A stream only counts towards a label after it's been labeled, see [`crate::SendStream::set_label`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LabelStats {
+    /// The number of streams given the label, in either direction.
+    pub streams: u64,
+
+    /// The bytes written to the streams.
+    pub sent: u64,
+
+    /// The bytes read from the streams.
+    pub received: u64,
+}
+
+// The labels used in a session, shared by its streams.
+#[derive(Default)]
+pub(crate) struct Labels {
+    labels: Mutex<HashMap<String, Arc<Label>>>,
+}
+
+impl Labels {
+    // Return the label with the given name, counting another stream towards it.
+    pub fn label(&self, name: &str) -> Arc<Label> {
+        let mut labels = self.labels.lock().unwrap();
+
+        let label = match labels.get(name) {
+            Some(label) => label.clone(),
+            None => {
+                let label = Arc::new(Label::new(name));
+                labels.insert(name.to_string(), label.clone());
+                label
+            }
+        };
+
+        label.streams.fetch_add(1, Ordering::Relaxed);
+        label
+    }
+
+    pub fn stats(&self) -> HashMap<String, LabelStats> {
+        let labels = self.labels.lock().unwrap();
+
+        labels
+            .iter()
+            .map(|(name, label)| (name.clone(), label.stats()))
+            .collect()
+    }
+}
+
+// A label attached to streams, which counts their traffic.
+pub(crate) struct Label {
+    name: String,
+    streams: AtomicU64,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl Label {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            streams: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sent(&self, size: usize) {
+        self.sent.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn received(&self, size: usize) {
+        self.received.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> LabelStats {
+        LabelStats {
+            streams: self.streams.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+        }
+    }
+}
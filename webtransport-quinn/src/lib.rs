@@ -19,29 +19,130 @@
 //!
 //! # Limitations
 //! WebTransport is able to be pooled with HTTP/3 and multiple WebTransport sessions.
-//! This crate avoids that complexity, doing the bare minimum to support a single WebTransport session that owns the entire QUIC connection.
+//! By default this crate avoids that complexity, doing the bare minimum to support a single WebTransport session that owns the entire QUIC connection.
+//! Simple HTTP/3 requests (ex. health checks) can be served on the same connection with a [`Fallback`].
 //! If you want to support HTTP/3 on the same host/port, you should use another crate (ex. `h3-webtransport`).
-//! If you want to support multiple WebTransport sessions over the same QUIC connection... you should just dial a new QUIC connection instead.
+//! Servers can host multiple WebTransport sessions on the same QUIC connection with [`accept_sessions`] or [`Server::set_max_sessions_per_connection`],
+//! in which case each session only receives its own streams and datagrams.
+//!
+//! Quinn 0.10 doesn't support qlog, so connections can't be traced for qvis yet.
+//! Instead, [`Session::stats`] samples the RTT, congestion window and loss, and [`Session::set_journal`] records the session's events.
+//! Enable the `tracing` feature for a span per connection, session and stream,
+//! with events for CONNECT requests, rejections, streams opening, closing and resetting, and dropped datagrams.
+//! Implement [`SessionMetrics`] to export counters for sessions, streams and bytes (ex. to Prometheus).
+//! Implement [`SessionListener`] to hook the lifecycle of each session, ex. for monitoring or admission control.
 
 // External
+mod accounting;
+mod auth;
+mod budget;
+mod builder;
 mod client;
+mod clock;
+mod compat;
+mod coop;
+mod demux;
+#[cfg(feature = "dev")]
+mod dev;
 mod error;
+mod extensions;
+mod fallback;
+mod fingerprint;
+mod flow;
+mod fragment;
+mod handler;
+mod health;
+mod idle;
+mod journal;
+mod labels;
+mod limit;
+mod listener;
+mod metrics;
+mod origin;
+mod path;
+mod peer;
+mod platform;
+mod relay;
+mod router;
+mod sched;
+mod sequence;
+mod serve;
 mod server;
 mod session;
+mod stall;
+mod state;
+mod stats;
 mod stream;
+mod timeout;
+mod tls;
+mod transfer;
+// Also built for the tests, which use rustls's dangerous_configuration via the dev-dependency.
+#[cfg(any(test, feature = "cert-hashes"))]
+mod verify;
 
+pub use accounting::*;
+pub use auth::*;
+pub use budget::*;
+pub use builder::*;
 pub use client::*;
+pub use clock::*;
+pub use compat::*;
+pub use datagram::*;
+pub use demux::*;
+#[cfg(feature = "dev")]
+pub use dev::*;
 pub use error::*;
+pub use extensions::*;
+pub use fallback::*;
+pub use fingerprint::*;
+pub use flow::*;
+pub use fragment::*;
+pub use handler::*;
+pub use health::*;
+pub use idle::*;
+pub use journal::*;
+pub use labels::*;
+pub use limit::*;
+pub use listener::*;
+pub use metrics::*;
+pub use origin::*;
+pub use path::*;
+pub use peer::*;
+pub use platform::*;
+pub use relay::*;
+pub use router::*;
+pub use sched::*;
+pub use sequence::*;
+pub use serve::*;
 pub use server::*;
 pub use session::*;
+pub use stall::*;
+pub use stats::*;
 pub use stream::*;
+pub use timeout::*;
+pub use tls::*;
+pub use transfer::*;
+
+// Also re-exported above, but public so the optional datagram layers can be found together.
+pub mod datagram;
+
+// For language bindings, kept out of the root namespace since the names overlap.
+pub mod facade;
+
+#[cfg(feature = "bench-bin")]
+pub mod bench;
 
 // Internal
 mod connect;
+mod mux;
 mod settings;
+mod trace;
 
 use connect::*;
 use settings::*;
 
+pub use connect::ConnectError;
+pub use settings::MAX_FIELD_SECTION_SIZE;
+
 /// The HTTP/3 ALPN is required when negotiating a QUIC connection.
 pub static ALPN: &[u8] = b"h3";
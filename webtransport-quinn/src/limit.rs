@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+// The maximum time to sleep before checking the bucket again, so changes to the limit apply promptly.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// A token bucket rate limit, see [`crate::Session::set_rate_limit`].
+///
+/// The bucket holds up to `burst` bytes and refills at `rate` bytes per second.
+/// Data can be sent whenever the bucket isn't empty, so short bursts go out immediately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The sustained rate, in bytes per second.
+    pub rate: u64,
+
+    /// The maximum number of bytes that can be sent at once after being idle.
+    pub burst: u64,
+}
+
+impl RateLimit {
+    pub fn new(rate: u64, burst: u64) -> Self {
+        Self { rate, burst }
+    }
+}
+
+// The state of a token bucket.
+pub(crate) struct Bucket {
+    limit: RateLimit,
+
+    // The number of bytes that can be sent, which is negative if we overspent.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: RateLimit, now: Instant) {
+        self.refill(now);
+        self.limit = limit;
+        self.tokens = self.tokens.min(limit.burst as f64);
+    }
+
+    // Return the number of bytes that can be sent now, up to the provided size, or how long to wait.
+    // The caller should call consume with the number of bytes actually sent.
+    pub fn available(&mut self, size: usize, now: Instant) -> Result<usize, Duration> {
+        self.refill(now);
+
+        // Wait until we can send the full size, or a full burst if it's larger.
+        let need = (size as u64).min(self.limit.burst).max(1) as f64;
+        if self.tokens >= need {
+            return Ok((self.tokens as usize).min(size));
+        }
+
+        let rate = self.limit.rate.max(1) as f64;
+        let wait = Duration::from_secs_f64((need - self.tokens) / rate);
+        Err(wait.min(MAX_WAIT))
+    }
+
+    pub fn consume(&mut self, size: usize) {
+        self.tokens -= size as f64;
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+
+        let tokens = self.tokens + elapsed.as_secs_f64() * self.limit.rate as f64;
+        self.tokens = tokens.min(self.limit.burst as f64);
+    }
+}
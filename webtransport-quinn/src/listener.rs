@@ -0,0 +1,115 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use webtransport_proto::VarInt;
+
+use crate::{DatagramDropReason, SessionClose, SessionMetrics};
+
+/// Hooks for the lifecycle of sessions, for monitoring or admission control, see [`crate::ServerBuilder::listener`] and [`crate::ClientBuilder::listener`].
+///
+/// Every method has an empty default, so implement only the events you care about.
+/// They're called inline by whichever task is using the session, so they must not block; hand anything slow to another task.
+/// Unlike [`SessionMetrics`], each event says which session it's about, so the listener can keep its own state per session.
+///
+/// The same instance is shared by every session, so it's also implemented for [`Arc`] to keep a handle to it.
+pub trait SessionListener: Send + Sync {
+    /// A session was established.
+    fn on_session_open(&self, session: &SessionInfo) {
+        let _ = session;
+    }
+
+    /// A session ended, saying who closed it and why.
+    ///
+    /// A session that's dropped without being closed is reported as closed locally.
+    fn on_session_close(&self, session: &SessionInfo, close: &SessionClose) {
+        let _ = (session, close);
+    }
+
+    /// A stream with the given ID was opened, either by us (local) or the peer.
+    fn on_stream_open(&self, session: &SessionInfo, id: quinn::StreamId, local: bool) {
+        let _ = (session, id, local);
+    }
+
+    /// A datagram was dropped instead of being sent, see [`crate::Session::datagram_drops`].
+    fn on_datagram_dropped(&self, session: &SessionInfo, reason: DatagramDropReason) {
+        let _ = (session, reason);
+    }
+
+    /// The peer at the given address violated the protocol, which fails the handshake or closes the session.
+    fn on_protocol_error(&self, remote: SocketAddr, error: &ProtocolError) {
+        let _ = (remote, error);
+    }
+}
+
+/// Identifies the session that a [`SessionListener`] event is about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// The URI of the CONNECT request.
+    pub uri: http::Uri,
+
+    /// The address of the peer.
+    pub remote: SocketAddr,
+
+    /// The ID of the QUIC connection, see [`quinn::Connection::stable_id`], which is shared by the sessions pooled on it.
+    pub connection: usize,
+
+    /// The ID of the session, which is unique within the connection.
+    pub id: u64,
+}
+
+impl SessionInfo {
+    pub(crate) fn new(conn: &quinn::Connection, id: VarInt, uri: &http::Uri) -> Self {
+        Self {
+            uri: uri.clone(),
+            remote: conn.remote_address(),
+            connection: conn.stable_id(),
+            id: id.into_inner(),
+        }
+    }
+}
+
+/// How the peer violated the protocol, see [`SessionListener::on_protocol_error`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The client's HTTP/3 SETTINGS or CONNECT request were malformed or didn't support WebTransport, so the handshake failed.
+    ///
+    /// This is only reported by a [`crate::Server`], with the error message.
+    Handshake(String),
+
+    /// The peer sent a capsule that couldn't be decoded, which closes the session.
+    InvalidCapsule,
+
+    /// The peer exceeded the session's flow control limits, which closes the session.
+    FlowControl,
+
+    /// The peer opened a stream for a session that doesn't exist, which is dropped.
+    UnknownSession,
+}
+
+impl<T: SessionListener + ?Sized> SessionListener for Arc<T> {
+    fn on_session_open(&self, session: &SessionInfo) {
+        (**self).on_session_open(session)
+    }
+
+    fn on_session_close(&self, session: &SessionInfo, close: &SessionClose) {
+        (**self).on_session_close(session, close)
+    }
+
+    fn on_stream_open(&self, session: &SessionInfo, id: quinn::StreamId, local: bool) {
+        (**self).on_stream_open(session, id, local)
+    }
+
+    fn on_datagram_dropped(&self, session: &SessionInfo, reason: DatagramDropReason) {
+        (**self).on_datagram_dropped(session, reason)
+    }
+
+    fn on_protocol_error(&self, remote: SocketAddr, error: &ProtocolError) {
+        (**self).on_protocol_error(remote, error)
+    }
+}
+
+// The metrics and listener handed to each session by a Client or Server.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub metrics: Option<Arc<dyn SessionMetrics>>,
+    pub listener: Option<Arc<dyn SessionListener>>,
+}
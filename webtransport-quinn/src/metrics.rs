@@ -0,0 +1,86 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::SessionError;
+
+/// Hooks for exporting counters and gauges about sessions (ex. to Prometheus or StatsD), see [`crate::Server::set_metrics`] and [`crate::Client::set_metrics`].
+///
+/// Every method has an empty default, so implement only the ones you export.
+/// They're called inline by whichever task is using the session, so they should be cheap and must not block (ex. atomic counters).
+/// Gauges are derived from the pairs: the number of open sessions is `session_opened - session_closed`, and likewise for streams.
+///
+/// The same instance is shared by every session, so it's also implemented for [`Arc`] to keep a handle for reading the values.
+pub trait SessionMetrics: Send + Sync {
+    /// A session was established.
+    fn session_opened(&self) {}
+
+    /// A session ended after being open for the given duration, or was dropped, in which case the error is [`quinn::ConnectionError::LocallyClosed`].
+    ///
+    /// The error says who closed it and why, ex. an application close versus a timeout.
+    fn session_closed(&self, duration: Duration, error: &SessionError) {
+        let _ = (duration, error);
+    }
+
+    /// A stream was opened, either by us (local) or the peer.
+    fn stream_opened(&self, bidi: bool, local: bool) {
+        let _ = (bidi, local);
+    }
+
+    /// A stream was dropped, once both halves of a bidirectional stream are.
+    fn stream_closed(&self, bidi: bool) {
+        let _ = bidi;
+    }
+
+    /// Bytes were written to a stream, excluding the stream header.
+    fn bytes_sent(&self, bytes: u64) {
+        let _ = bytes;
+    }
+
+    /// Bytes were read from a stream, excluding the stream header.
+    fn bytes_received(&self, bytes: u64) {
+        let _ = bytes;
+    }
+
+    /// A stream was reset with an error code, either by us (local) or the peer.
+    fn stream_reset(&self, code: u32, local: bool) {
+        let _ = (code, local);
+    }
+
+    /// A stream was stopped with an error code, either by us (local) or the peer.
+    fn stream_stopped(&self, code: u32, local: bool) {
+        let _ = (code, local);
+    }
+}
+
+impl<T: SessionMetrics + ?Sized> SessionMetrics for Arc<T> {
+    fn session_opened(&self) {
+        (**self).session_opened()
+    }
+
+    fn session_closed(&self, duration: Duration, error: &SessionError) {
+        (**self).session_closed(duration, error)
+    }
+
+    fn stream_opened(&self, bidi: bool, local: bool) {
+        (**self).stream_opened(bidi, local)
+    }
+
+    fn stream_closed(&self, bidi: bool) {
+        (**self).stream_closed(bidi)
+    }
+
+    fn bytes_sent(&self, bytes: u64) {
+        (**self).bytes_sent(bytes)
+    }
+
+    fn bytes_received(&self, bytes: u64) {
+        (**self).bytes_received(bytes)
+    }
+
+    fn stream_reset(&self, code: u32, local: bool) {
+        (**self).stream_reset(code, local)
+    }
+
+    fn stream_stopped(&self, code: u32, local: bool) {
+        (**self).stream_stopped(code, local)
+    }
+}
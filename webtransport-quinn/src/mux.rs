@@ -0,0 +1,584 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{ready, Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{
+    stream::{FuturesUnordered, Stream, StreamExt},
+    task::ArcWake,
+};
+
+use webtransport_proto::{Datagram, Frame, StreamUni, VarInt};
+
+use crate::{
+    coop::{Coop, DEFAULT_YIELD_BUDGET},
+    state::{SessionState, Wakers},
+    Accepted, Connect, SessionAccept, Settings, H3_REQUEST_REJECTED,
+};
+
+// The number of datagrams buffered for each session before the oldest are dropped.
+const MAX_DATAGRAMS: usize = 256;
+
+// The number of streams buffered for sessions whose CONNECT hasn't arrived yet.
+const MAX_BUFFERED: usize = 16;
+
+// The WebTransport error codes used to refuse streams, see the draft's section 4.6.
+const WT_SESSION_GONE: quinn::VarInt = quinn::VarInt::from_u32(0x170d7b68);
+const WT_BUFFERED_STREAM_REJECTED: quinn::VarInt = quinn::VarInt::from_u32(0x3994bd84);
+
+// Type aliases just so clippy doesn't complain about the complexity.
+type AcceptUni = dyn Stream<Item = Result<quinn::RecvStream, quinn::ConnectionError>> + Send;
+type AcceptBi = dyn Stream<Item = Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>>
+    + Send;
+type ReadDatagram = dyn Stream<Item = Result<Bytes, quinn::ConnectionError>> + Send;
+type Decode = dyn Future<Output = Incoming> + Send;
+
+// Demultiplexes the streams and datagrams of a connection shared by multiple sessions.
+//
+// A session with its own connection can simply accept every stream, but here a stream is only useful to the session named in its header.
+// We don't spawn a task, so whichever session (or the acceptor) polls the mux reads the headers of every new stream and queues it for its session.
+// Everything waiting on the mux is woken when something is queued, since it might be theirs.
+pub(crate) struct Mux {
+    conn: quinn::Connection,
+    settings: Settings,
+
+    inner: Mutex<Inner>,
+    wakers: Arc<Wakers>,
+}
+
+struct Inner {
+    accept_uni: Pin<Box<AcceptUni>>,
+    accept_bi: Pin<Box<AcceptBi>>,
+    datagrams: Pin<Box<ReadDatagram>>,
+
+    // Streams that are still reading their header.
+    decoding: FuturesUnordered<Pin<Box<Decode>>>,
+
+    // The sessions that were requested, keyed by session ID.
+    routes: HashMap<VarInt, Route>,
+
+    // CONNECT requests waiting to be accepted.
+    requests: VecDeque<Connect>,
+
+    // The ID of the next CONNECT the peer could send; streams for any session before it are refused unless it's routed.
+    next_request: u64,
+
+    // Streams that arrived before their CONNECT, which can happen since they're independent.
+    early: VecDeque<(VarInt, Early)>,
+
+    // Keep the qpack streams if the endpoint (incorrectly) creates them, so they don't get closed until we're dropped.
+    qpack: Vec<quinn::RecvStream>,
+
+    // The error that closed the connection, once it's closed.
+    closed: Option<quinn::ConnectionError>,
+
+    // Only the server accepts requests; the client opens them instead.
+    server: bool,
+
+    // The number of sessions allowed at once, advertised by the server.
+    max_sessions: usize,
+}
+
+#[derive(Default)]
+struct Route {
+    // Set once the session was accepted, so we know when it's gone.
+    state: Option<Weak<SessionState>>,
+
+    uni: VecDeque<quinn::RecvStream>,
+    bi: VecDeque<(quinn::SendStream, quinn::RecvStream)>,
+    datagrams: VecDeque<Bytes>,
+}
+
+enum Early {
+    Uni(quinn::RecvStream),
+    Bi(quinn::SendStream, quinn::RecvStream),
+}
+
+// The result of reading the header of a new stream.
+enum Incoming {
+    Uni(VarInt, quinn::RecvStream),
+    Bi(VarInt, quinn::SendStream, quinn::RecvStream),
+    Connect(Connect),
+    Qpack(quinn::RecvStream),
+    Ignored,
+}
+
+impl Mux {
+    // Demultiplex a server connection, accepting up to the given number of sessions that we advertised.
+    pub fn server(conn: quinn::Connection, settings: Settings, max_sessions: u32) -> Self {
+        Self::new(conn, settings, true, max_sessions.max(1) as usize)
+    }
+
+    // Demultiplex a client connection, opening up to the number of sessions advertised by the server.
+    pub fn client(conn: quinn::Connection, settings: Settings) -> Self {
+        let max_sessions = settings.max_sessions().try_into().unwrap_or(usize::MAX);
+        Self::new(conn, settings, false, max_sessions)
+    }
+
+    fn new(conn: quinn::Connection, settings: Settings, server: bool, max_sessions: usize) -> Self {
+        // Create streams that just output new streams and datagrams, so it's easy to call from poll.
+        let accept_uni = Box::pin(futures::stream::unfold(conn.clone(), |conn| async {
+            Some((conn.accept_uni().await, conn))
+        }));
+
+        let accept_bi = Box::pin(futures::stream::unfold(conn.clone(), |conn| async {
+            Some((conn.accept_bi().await, conn))
+        }));
+
+        let datagrams = Box::pin(futures::stream::unfold(conn.clone(), |conn| async {
+            Some((conn.read_datagram().await, conn))
+        }));
+
+        let inner = Inner {
+            accept_uni,
+            accept_bi,
+            datagrams,
+            decoding: FuturesUnordered::new(),
+            routes: HashMap::new(),
+            requests: VecDeque::new(),
+            next_request: 0,
+            early: VecDeque::new(),
+            qpack: Vec::new(),
+            closed: None,
+            server,
+            max_sessions,
+        };
+
+        Self {
+            conn,
+            settings,
+            inner: Mutex::new(inner),
+            wakers: Arc::default(),
+        }
+    }
+
+    // Wait for the next CONNECT request, reserving a route for its session.
+    pub fn poll_request(
+        self: &Arc<Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(Connect, Claim), quinn::ConnectionError>> {
+        let connect = ready!(self.poll(cx, |inner| inner.requests.pop_front()))?;
+
+        let claim = Claim {
+            mux: self.clone(),
+            id: connect.session_id(),
+            opened: false,
+        };
+
+        Poll::Ready(Ok((connect, claim)))
+    }
+
+    // Wait for the next unidirectional stream for the session, with the header already read.
+    pub fn poll_uni(
+        &self,
+        id: VarInt,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<quinn::RecvStream, quinn::ConnectionError>> {
+        self.poll(cx, |inner| inner.routes.get_mut(&id)?.uni.pop_front())
+    }
+
+    // Wait for the next bidirectional stream for the session, with the header already read.
+    pub fn poll_bi(
+        &self,
+        id: VarInt,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>> {
+        self.poll(cx, |inner| inner.routes.get_mut(&id)?.bi.pop_front())
+    }
+
+    // Wait for the next datagram for the session, without the quarter stream ID.
+    pub fn poll_datagram(
+        &self,
+        id: VarInt,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Bytes, quinn::ConnectionError>> {
+        self.poll(cx, |inner| inner.routes.get_mut(&id)?.datagrams.pop_front())
+    }
+
+    // Start routing to a session we're opening, before the response arrives since the server can open streams right after it.
+    // Returns None if the peer's SETTINGS_WEBTRANSPORT_MAX_SESSIONS would be exceeded.
+    pub fn register(self: &Arc<Self>, id: VarInt) -> Option<Claim> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.live() >= inner.max_sessions {
+            return None;
+        }
+
+        inner.routes.insert(id, Route::default());
+
+        Some(Claim {
+            mux: self.clone(),
+            id,
+            opened: false,
+        })
+    }
+
+    // Returns true if the limit of sessions is reached, so another can't be opened.
+    pub fn is_full(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        inner.live() >= inner.max_sessions
+    }
+
+    pub fn connection(&self) -> &quinn::Connection {
+        &self.conn
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    // The ID of the first request that hasn't been received, used for a GOAWAY.
+    pub fn next_request(&self) -> VarInt {
+        VarInt::try_from(self.inner.lock().unwrap().next_request).unwrap()
+    }
+
+    fn poll<T, F>(&self, cx: &mut Context<'_>, take: F) -> Poll<Result<T, quinn::ConnectionError>>
+    where
+        F: FnOnce(&mut Inner) -> Option<T>,
+    {
+        self.wakers.register(cx.waker());
+        let waker = futures::task::waker(self.wakers.clone());
+
+        let mut inner = self.inner.lock().unwrap();
+        let routed = inner.drive(&mut Context::from_waker(&waker));
+
+        let res = match take(&mut inner) {
+            Some(item) => Poll::Ready(Ok(item)),
+            None => match &inner.closed {
+                Some(err) => Poll::Ready(Err(err.clone())),
+                None => Poll::Pending,
+            },
+        };
+
+        drop(inner);
+
+        // Wake everybody else in case we queued something for them.
+        if routed {
+            Wakers::wake_by_ref(&self.wakers);
+        }
+
+        res
+    }
+
+    // Mark the session as accepted, so its streams are routed until it's closed or dropped.
+    // Any streams that were already queued count towards the session's flow control.
+    fn open(&self, id: VarInt, state: &Arc<SessionState>) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(route) = inner.routes.get_mut(&id) {
+            route.state = Some(Arc::downgrade(state));
+
+            for _ in &route.uni {
+                state.peer_opened(false);
+            }
+
+            for _ in &route.bi {
+                state.peer_opened(true);
+            }
+        }
+    }
+
+    // Forget about a session that was never accepted, refusing anything queued for it.
+    fn forget(&self, id: VarInt) {
+        let route = self.inner.lock().unwrap().routes.remove(&id);
+        if let Some(route) = route {
+            route.refuse(WT_SESSION_GONE);
+        }
+    }
+}
+
+impl Inner {
+    // Accept new streams and route anything that's ready, returning true if anything was queued.
+    fn drive(&mut self, cx: &mut Context<'_>) -> bool {
+        let mut coop = Coop::new(DEFAULT_YIELD_BUDGET);
+        let mut routed = false;
+
+        loop {
+            // Yield if the peer keeps us busy, after waking everybody so they try again.
+            if coop.poll_proceed(cx).is_pending() {
+                return true;
+            }
+
+            if self.closed.is_none() {
+                if let Poll::Ready(Some(res)) = self.accept_uni.poll_next_unpin(cx) {
+                    match res {
+                        Ok(recv) => self.decoding.push(Box::pin(decode_uni(recv))),
+                        Err(err) => self.close(err),
+                    }
+                    continue;
+                }
+
+                if let Poll::Ready(Some(res)) = self.accept_bi.poll_next_unpin(cx) {
+                    match res {
+                        Ok((send, recv)) => self.decoding.push(Box::pin(decode_bi(send, recv))),
+                        Err(err) => self.close(err),
+                    }
+                    continue;
+                }
+
+                if let Poll::Ready(Some(res)) = self.datagrams.poll_next_unpin(cx) {
+                    match res {
+                        Ok(datagram) => routed |= self.route_datagram(datagram),
+                        Err(err) => self.close(err),
+                    }
+                    continue;
+                }
+            }
+
+            match self.decoding.poll_next_unpin(cx) {
+                Poll::Ready(Some(incoming)) => routed |= self.route(incoming),
+                Poll::Ready(None) | Poll::Pending => return routed,
+            }
+        }
+    }
+
+    fn close(&mut self, err: quinn::ConnectionError) {
+        self.closed.get_or_insert(err);
+    }
+
+    fn route(&mut self, incoming: Incoming) -> bool {
+        match incoming {
+            Incoming::Uni(id, recv) => self.route_stream(id, Early::Uni(recv)),
+            Incoming::Bi(id, send, recv) => self.route_stream(id, Early::Bi(send, recv)),
+            Incoming::Connect(connect) => self.route_connect(connect),
+            Incoming::Qpack(recv) => {
+                self.qpack.push(recv);
+                false
+            }
+            Incoming::Ignored => false,
+        }
+    }
+
+    fn route_connect(&mut self, mut connect: Connect) -> bool {
+        // The server can't send requests to the client.
+        if !self.server {
+            connect.reject();
+            return false;
+        }
+
+        let id = connect.session_id();
+        self.next_request = self.next_request.max(id.into_inner() + 4);
+
+        // Claim any streams that arrived before the CONNECT.
+        let mut route = Route::default();
+        let next = self.next_request;
+
+        for (early_id, stream) in std::mem::take(&mut self.early) {
+            if early_id == id {
+                route.push(stream);
+            } else if early_id.into_inner() >= next {
+                self.early.push_back((early_id, stream));
+            } else {
+                // The stream is for a session before this one that will never be established.
+                stream.refuse(WT_SESSION_GONE);
+            }
+        }
+
+        // Refuse the request unprocessed if the peer exceeded our SETTINGS_WEBTRANSPORT_MAX_SESSIONS.
+        if self.live() >= self.max_sessions {
+            route.refuse(WT_SESSION_GONE);
+            connect.reject();
+            return false;
+        }
+
+        self.routes.insert(id, route);
+        self.requests.push_back(connect);
+
+        true
+    }
+
+    fn route_stream(&mut self, id: VarInt, stream: Early) -> bool {
+        if let Some(route) = self.routes.get_mut(&id) {
+            if route.is_alive() {
+                route.push(stream);
+                return true;
+            }
+
+            // The session is gone, so forget about it.
+            self.routes.remove(&id).unwrap().refuse(WT_SESSION_GONE);
+        }
+
+        // Only a client-initiated bidirectional stream can be a session, and we already saw every ID before the next request.
+        // The client registers its sessions before sending the CONNECT, so it never has to buffer.
+        if !self.server || !id.into_inner().is_multiple_of(4) || id.into_inner() < self.next_request
+        {
+            stream.refuse(WT_SESSION_GONE);
+            return false;
+        }
+
+        if self.early.len() >= MAX_BUFFERED {
+            stream.refuse(WT_BUFFERED_STREAM_REJECTED);
+            return false;
+        }
+
+        self.early.push_back((id, stream));
+        false
+    }
+
+    fn route_datagram(&mut self, buf: Bytes) -> bool {
+        let datagram = match Datagram::decode(buf) {
+            Ok(datagram) => datagram,
+            Err(_) => return false,
+        };
+
+        // The quarter stream ID is the session ID divided by 4.
+        let id = match datagram.quarter_stream_id.into_inner().checked_mul(4) {
+            Some(id) => VarInt::try_from(id),
+            None => return false,
+        };
+
+        let route = match id.ok().and_then(|id| self.routes.get_mut(&id)) {
+            Some(route) if route.is_alive() => route,
+            _ => return false, // Datagrams are unreliable, so just drop it.
+        };
+
+        if route.datagrams.len() >= MAX_DATAGRAMS {
+            route.datagrams.pop_front();
+        }
+
+        route.datagrams.push_back(datagram.payload);
+        true
+    }
+
+    // Return the number of sessions that are requested or established, forgetting any that are gone.
+    fn live(&mut self) -> usize {
+        self.routes.retain(|_, route| {
+            let alive = route.is_alive();
+            if !alive {
+                std::mem::take(route).refuse(WT_SESSION_GONE);
+            }
+            alive
+        });
+
+        self.routes.len()
+    }
+}
+
+impl Route {
+    // Queue the stream, counting it towards the session's flow control once it's accepted.
+    fn push(&mut self, stream: Early) {
+        if let Some(state) = self.state.as_ref().and_then(Weak::upgrade) {
+            state.peer_opened(matches!(stream, Early::Bi(..)));
+        }
+
+        match stream {
+            Early::Uni(recv) => self.uni.push_back(recv),
+            Early::Bi(send, recv) => self.bi.push_back((send, recv)),
+        }
+    }
+
+    // A session is alive until it's accepted and then closed or dropped.
+    fn is_alive(&self) -> bool {
+        match &self.state {
+            None => true,
+            Some(state) => state
+                .upgrade()
+                .is_some_and(|state| state.reason().is_none()),
+        }
+    }
+
+    fn refuse(self, code: quinn::VarInt) {
+        for recv in self.uni {
+            Early::Uni(recv).refuse(code);
+        }
+
+        for (send, recv) in self.bi {
+            Early::Bi(send, recv).refuse(code);
+        }
+    }
+}
+
+impl Early {
+    fn refuse(self, code: quinn::VarInt) {
+        match self {
+            Early::Uni(mut recv) => {
+                recv.stop(code).ok();
+            }
+            Early::Bi(mut send, mut recv) => {
+                send.reset(code).ok();
+                recv.stop(code).ok();
+            }
+        }
+    }
+}
+
+// Reads the header of a unidirectional stream.
+async fn decode_uni(mut recv: quinn::RecvStream) -> Incoming {
+    let typ = match SessionAccept::read_varint(&mut recv).await {
+        Ok(typ) => StreamUni(typ),
+        Err(_) => return Incoming::Ignored,
+    };
+
+    match typ {
+        StreamUni::WEBTRANSPORT => match SessionAccept::read_varint(&mut recv).await {
+            Ok(id) => Incoming::Uni(id, recv),
+            Err(_) => Incoming::Ignored,
+        },
+        StreamUni::QPACK_DECODER | StreamUni::QPACK_ENCODER => Incoming::Qpack(recv),
+        _ => Incoming::Ignored, // ignore unknown streams
+    }
+}
+
+// Reads the header of a bidirectional stream, which is either a WebTransport stream or a new request.
+async fn decode_bi(mut send: quinn::SendStream, mut recv: quinn::RecvStream) -> Incoming {
+    let typ = match SessionAccept::read_varint(&mut recv).await {
+        Ok(typ) => Frame(typ),
+        Err(_) => return Incoming::Ignored,
+    };
+
+    match typ {
+        Frame::WEBTRANSPORT => match SessionAccept::read_varint(&mut recv).await {
+            Ok(id) => Incoming::Bi(id, send, recv),
+            Err(_) => Incoming::Ignored,
+        },
+        Frame::HEADERS => {
+            // Give the request back the frame type we already read.
+            let mut buf = Vec::new();
+            Frame::HEADERS.encode(&mut buf);
+
+            match Connect::read_after(buf, send, recv).await {
+                Ok(Accepted::Connect(connect)) => Incoming::Connect(connect),
+
+                // We don't serve plain HTTP/3 requests on a shared connection.
+                Ok(Accepted::Request {
+                    mut send, mut recv, ..
+                }) => {
+                    send.reset(H3_REQUEST_REJECTED).ok();
+                    recv.stop(H3_REQUEST_REJECTED).ok();
+                    Incoming::Ignored
+                }
+                Err(_) => Incoming::Ignored,
+            }
+        }
+        _ => {
+            send.reset(H3_REQUEST_REJECTED).ok();
+            Incoming::Ignored
+        }
+    }
+}
+
+// Reserves the route of a requested session until it's accepted, forgetting it if the request is rejected or dropped.
+pub(crate) struct Claim {
+    mux: Arc<Mux>,
+    id: VarInt,
+    opened: bool,
+}
+
+impl Claim {
+    // Start routing to the established session, returning the mux for it to use.
+    pub fn open(mut self, state: &Arc<SessionState>) -> Arc<Mux> {
+        self.opened = true;
+        self.mux.open(self.id, state);
+        self.mux.clone()
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if !self.opened {
+            self.mux.forget(self.id);
+        }
+    }
+}
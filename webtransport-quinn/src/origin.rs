@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use crate::Request;
+
+type Check = Arc<dyn Fn(&str, &Request) -> bool + Send + Sync>;
+
+/// Which origins may establish sessions, checked against the `origin` header, see [`crate::Server::set_origin_policy`].
+///
+/// Browsers send the origin of the page that created the session, and any page can connect to any server,
+/// so this is the WebTransport equivalent of CSRF protection.
+/// Requests without an origin header come from non-browser clients (including [`crate::Client`]), and are refused unless [`Self::allow_missing`] is used.
+#[derive(Clone)]
+pub struct OriginPolicy {
+    check: Check,
+    missing: bool,
+}
+
+impl OriginPolicy {
+    /// Allow only the given origins (ex. `https://example.com`), compared case-insensitively.
+    /// They're compared as browsers send them, with the port only if it isn't the default (ex. `https://localhost:4443`).
+    pub fn allow_list<I, S>(origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let origins: Vec<String> = origins.into_iter().map(Into::into).collect();
+
+        Self::custom(move |origin, _| {
+            origins
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        })
+    }
+
+    /// Allow only HTTPS origins with the same host and port as the request's `:authority`.
+    pub fn same_origin() -> Self {
+        Self::custom(|origin, request| is_same_origin(origin, request.authority()))
+    }
+
+    /// Allow the origin when the callback returns true, which is also given the request (ex. to compare with [`Request::authority`]).
+    pub fn custom<F>(check: F) -> Self
+    where
+        F: Fn(&str, &Request) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            check: Arc::new(check),
+            missing: false,
+        }
+    }
+
+    /// Also allow requests without an origin header, so non-browser clients can connect.
+    pub fn allow_missing(mut self) -> Self {
+        self.missing = true;
+        self
+    }
+
+    // Return true if the request is allowed.
+    pub(crate) fn check(&self, request: &Request) -> bool {
+        match request.origin() {
+            Some(origin) => (self.check)(origin, request),
+            None => self.missing,
+        }
+    }
+}
+
+fn is_same_origin(origin: &str, authority: &str) -> bool {
+    let Ok(origin) = origin.parse::<http::Uri>() else {
+        return false;
+    };
+
+    let Ok(authority) = authority.parse::<http::uri::Authority>() else {
+        return false;
+    };
+
+    if origin.scheme() != Some(&http::uri::Scheme::HTTPS) {
+        return false;
+    }
+
+    match origin.authority() {
+        Some(origin) => {
+            origin.host().eq_ignore_ascii_case(authority.host())
+                && origin.port_u16().unwrap_or(443) == authority.port_u16().unwrap_or(443)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_port() {
+        assert!(is_same_origin("https://example.com", "example.com"));
+        assert!(is_same_origin("https://example.com", "example.com:443"));
+        assert!(is_same_origin("https://example.com:443", "example.com"));
+
+        assert!(!is_same_origin("https://example.com", "example.com:4443"));
+        assert!(!is_same_origin("https://example.com:4443", "example.com"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(!is_same_origin("null", "example.com"));
+        assert!(!is_same_origin("https://", "example.com"));
+        assert!(!is_same_origin("https://example.com", ""));
+    }
+}
@@ -0,0 +1,65 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use futures::{stream, Stream};
+
+use crate::Clock;
+
+/// A change to the network path used by a [`crate::Session`], see [`crate::Session::path_events`].
+///
+/// Quinn does not report when path validation starts or fails, so these are detected by sampling the connection.
+/// A failed validation shows up as a migration back to the previous address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathEvent {
+    /// The peer's address changed, because it migrated or a validation failed.
+    Migrated { from: SocketAddr, to: SocketAddr },
+
+    /// A black hole was detected on the path, so large packets are being dropped.
+    BlackHole { count: u64 },
+}
+
+struct Watch {
+    conn: quinn::Connection,
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+
+    // The last sampled values.
+    remote: SocketAddr,
+    black_holes: u64,
+}
+
+// Sample the connection at the given interval of the session's clock, yielding any path changes until it's closed.
+pub(crate) fn watch(
+    conn: quinn::Connection,
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+) -> impl Stream<Item = PathEvent> {
+    let watch = Watch {
+        remote: conn.remote_address(),
+        black_holes: conn.stats().path.black_holes_detected,
+        conn,
+        interval,
+        clock,
+    };
+
+    stream::unfold(watch, |mut watch| async move {
+        loop {
+            let remote = watch.conn.remote_address();
+            if remote != watch.remote {
+                let from = std::mem::replace(&mut watch.remote, remote);
+                return Some((PathEvent::Migrated { from, to: remote }, watch));
+            }
+
+            let count = watch.conn.stats().path.black_holes_detected;
+            if count > watch.black_holes {
+                watch.black_holes = count;
+                return Some((PathEvent::BlackHole { count }, watch));
+            }
+
+            if watch.conn.close_reason().is_some() {
+                return None;
+            }
+
+            watch.clock.sleep(watch.interval).await;
+        }
+    })
+}
@@ -0,0 +1,41 @@
+use std::net::SocketAddr;
+
+/// What's known about a client once the QUIC handshake completes, before any HTTP/3, see [`crate::Server::set_filter`].
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    /// The address of the client.
+    pub remote: SocketAddr,
+
+    /// The server name (SNI) requested by the client, if any.
+    pub server_name: Option<String>,
+
+    /// The negotiated ALPN protocol.
+    pub alpn: Option<Vec<u8>>,
+
+    /// The client's certificate chain, if the server config requested client authentication.
+    pub certificates: Option<Vec<rustls::Certificate>>,
+}
+
+impl PeerInfo {
+    pub(crate) fn new(conn: &quinn::Connection) -> Self {
+        let handshake = conn
+            .handshake_data()
+            .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok());
+
+        Self {
+            remote: conn.remote_address(),
+            server_name: handshake.as_ref().and_then(|h| h.server_name.clone()),
+            alpn: handshake.and_then(|h| h.protocol),
+            certificates: certificates(conn),
+        }
+    }
+}
+
+// The peer's certificate chain, starting with the end-entity certificate, if it presented one.
+pub(crate) fn certificates(conn: &quinn::Connection) -> Option<Vec<rustls::Certificate>> {
+    let identity = conn.peer_identity()?;
+    identity
+        .downcast::<Vec<rustls::Certificate>>()
+        .ok()
+        .map(|certs| *certs)
+}
@@ -0,0 +1,94 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+// The interval used to pace streams with a rate limit, at least 4x the default timer granularity of the platform.
+// Windows timers tick every 15.6ms by default, while other platforms are precise to about a millisecond.
+// This is fixed instead of measured, so setting a rate limit never blocks the thread.
+const PACING_INTERVAL: Duration = match cfg!(windows) {
+    true => Duration::from_millis(64),
+    false => Duration::from_millis(20),
+};
+
+/// What the UDP socket and timer support on this platform, which explains performance differences between operating systems.
+///
+/// Quinn falls back automatically when a capability is missing, at the cost of more CPU or less precise congestion control:
+/// - Without GSO/GRO, each packet is sent or received with a separate syscall.
+/// - Without ECN, congestion is only detected from packet loss.
+/// - Without path MTU discovery, packets stay at the minimum size of 1200 bytes.
+///
+/// This crate paces rate-limited streams with timers, so it uses a longer pacing interval (and larger bursts) on Windows, where the timer is coarse by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The operating system, see [`std::env::consts::OS`].
+    pub os: &'static str,
+
+    /// The number of packets sent with a single syscall using generic segmentation offload (GSO), or 1 if it's unsupported.
+    /// This is only available on Linux and may be disabled at runtime if the network interface rejects it.
+    pub gso_segments: usize,
+
+    /// The number of packets received with a single syscall using generic receive offload (GRO), or 1 if it's unsupported.
+    pub gro_segments: usize,
+
+    /// The number of datagrams sent or received with each syscall, using `sendmmsg`/`recvmmsg` where available.
+    pub batch_size: usize,
+
+    /// True if ECN markings are sent and received, so congestion control can react before packets are lost.
+    /// This isn't supported on Windows.
+    pub ecn: bool,
+
+    /// True if packets are sent with the "don't fragment" bit, which is required for path MTU discovery.
+    pub mtu_discovery: bool,
+
+    /// The duration of the shortest sleep, measured once by asking for 1ms.
+    pub timer_granularity: Duration,
+
+    /// The interval used to pace streams with a rate limit, see [`crate::SendStream::set_rate_limit`].
+    /// This is at least 4x the platform's default timer granularity, so a late timer doesn't reduce the rate.
+    /// It's fixed per platform rather than derived from [`Self::timer_granularity`], so pacing never waits for a measurement.
+    pub pacing_interval: Duration,
+}
+
+/// Return the capabilities of this platform, which are the same for every endpoint. See [`Capabilities`].
+///
+/// The timer is measured the first time this is called, which blocks the thread for a few milliseconds.
+pub fn capabilities() -> Capabilities {
+    static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+    CAPABILITIES.get_or_init(detect).clone()
+}
+
+// The pacing interval, without measuring the timer.
+pub(crate) fn pacing_interval() -> Duration {
+    PACING_INTERVAL
+}
+
+fn detect() -> Capabilities {
+    // Quinn creates the same state for each socket, based on what the platform supports.
+    let udp = quinn::udp::UdpState::new();
+
+    Capabilities {
+        os: std::env::consts::OS,
+        gso_segments: udp.max_gso_segments(),
+        gro_segments: udp.gro_segments(),
+        batch_size: quinn::udp::BATCH_SIZE,
+        // Only the Unix implementation reads and writes the ECN bits.
+        ecn: cfg!(unix),
+        mtu_discovery: !quinn::udp::may_fragment(),
+        timer_granularity: timer_granularity(),
+        pacing_interval: PACING_INTERVAL,
+    }
+}
+
+// Only called once by capabilities, which documents that it blocks.
+fn timer_granularity() -> Duration {
+    // Take the shorter of two samples, in case the thread was descheduled.
+    (0..2)
+        .map(|_| {
+            let start = Instant::now();
+            std::thread::sleep(Duration::from_millis(1));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
@@ -0,0 +1,116 @@
+use futures::FutureExt;
+use thiserror::Error;
+
+use crate::{ReadError, RecvStream, SendStream, StoppedError, StreamClosed, WriteError};
+
+// The maximum size of each chunk copied between streams.
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// An error returned when relaying a stream, see [`relay`].
+///
+/// Whichever stream failed, the error was already propagated to the other one.
+#[derive(Error, Debug)]
+pub enum RelayError {
+    #[error("source reset: {0}")]
+    Reset(u32),
+
+    #[error("destination stopped: {0}")]
+    Stopped(u32),
+
+    #[error("read error: {0}")]
+    Read(ReadError),
+
+    #[error("write error: {0}")]
+    Write(WriteError),
+}
+
+/// Copy a stream until it's finished, preserving end-to-end semantics for proxies.
+///
+/// - When the source is finished, the destination is finished.
+/// - When the source is reset, the destination is reset with the same code.
+/// - When the destination is stopped, the source is stopped with the same code.
+///
+/// If either stream fails for another reason (ex. the session closed), the other is reset or stopped with code 0.
+/// Returns the number of bytes copied.
+pub async fn relay(mut recv: RecvStream, mut send: SendStream) -> Result<u64, RelayError> {
+    let mut total = 0;
+    let mut coop = recv.coop();
+
+    loop {
+        // A fast source and destination are always ready, so yield now and then.
+        coop.proceed().await;
+
+        let res = futures::select! {
+            res = recv.read_chunk(MAX_CHUNK, true).fuse() => res,
+            res = send.stopped().fuse() => {
+                let err = match res {
+                    Ok(code) => WriteError::Stopped(code.unwrap_or(0)),
+                    Err(StoppedError::SessionError(err)) => WriteError::SessionError(err),
+                    Err(StoppedError::Closed) => WriteError::Closed,
+                };
+                return Err(stopped(&mut recv, err));
+            }
+        };
+
+        let chunk = match res {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(ReadError::Reset(code)) => {
+                send.reset(code).ok();
+                return Err(RelayError::Reset(code));
+            }
+            Err(err) => {
+                send.reset(0).ok();
+                return Err(RelayError::Read(err));
+            }
+        };
+
+        total += chunk.bytes.len() as u64;
+
+        if let Err(err) = send.write_chunk(chunk.bytes).await {
+            return Err(stopped(&mut recv, err));
+        }
+    }
+
+    if let Err(err) = send.finish().await {
+        return Err(stopped(&mut recv, err));
+    }
+
+    Ok(total)
+}
+
+/// Relay a pair of bidirectional streams in both directions, see [`relay`].
+///
+/// Priorities aren't sent over the wire, so they can't be copied from the peer.
+/// Instead, the priority and weight of `a`'s send stream are applied to `b`'s, so both legs are scheduled alike.
+/// Each direction runs until it's done, returning the result of `a` to `b` and `b` to `a` respectively.
+pub async fn relay_bi(
+    a: (SendStream, RecvStream),
+    b: (SendStream, RecvStream),
+) -> (Result<u64, RelayError>, Result<u64, RelayError>) {
+    let (a_send, a_recv) = a;
+    let (mut b_send, b_recv) = b;
+
+    // Ignore closed streams, which will error when relayed anyway.
+    let mirror = |b_send: &mut SendStream| -> Result<(), StreamClosed> {
+        b_send.set_priority(a_send.priority()?)?;
+        b_send.set_weight(a_send.weight())
+    };
+    mirror(&mut b_send).ok();
+
+    futures::join!(relay(a_recv, b_send), relay(b_recv, a_send))
+}
+
+// Stop the source after the destination failed to write.
+fn stopped(recv: &mut RecvStream, err: WriteError) -> RelayError {
+    match err {
+        WriteError::Stopped(code) => {
+            recv.stop(code).ok();
+            RelayError::Stopped(code)
+        }
+        err => {
+            recv.stop(0).ok();
+            RelayError::Write(err)
+        }
+    }
+}
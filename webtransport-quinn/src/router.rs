@@ -0,0 +1,285 @@
+use std::{future::Future, sync::Arc};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{Request, Response, Session, SessionHandler};
+
+/// A [`SessionHandler`] that dispatches each session to another handler by the `:path` of its request, see [`crate::Server::serve`].
+///
+/// Routes are matched in the order they were added, so the first match wins.
+/// Each segment of a pattern is either literal (`/chat`), a parameter matching any one segment (`/rooms/:id`),
+/// or a wildcard matching the rest of the path, which may be empty (`/files/*` or `/files/*rest` to capture it).
+/// The query isn't part of the path, and requests that don't match any route are rejected with a 404.
+/// A trailing slash is significant, so `/rooms` doesn't match `/rooms/`; add a route for each if both should be accepted.
+/// A router can be nested as the handler of another, but it matches the full path too, so its patterns need the prefix.
+///
+/// The captured parameters are available from the extensions of the request and session, see [`RouteParams`].
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+#[derive(Clone)]
+struct Route {
+    pattern: Arc<Pattern>,
+    handler: Arc<dyn Dispatch>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dispatch sessions whose path matches the pattern to the given handler.
+    ///
+    /// Panics if the pattern doesn't start with a `/`, or a wildcard isn't the last segment.
+    pub fn route<H: SessionHandler + 'static>(mut self, pattern: &str, handler: H) -> Self {
+        self.routes.push(Route {
+            pattern: Arc::new(Pattern::parse(pattern)),
+            handler: Arc::new(handler),
+        });
+
+        self
+    }
+}
+
+impl SessionHandler for Router {
+    async fn respond(&self, request: &mut Request) -> Response {
+        let matched = self.routes.iter().find_map(|route| {
+            let params = route.pattern.matches(request.path())?;
+            Some((route.handler.clone(), params))
+        });
+
+        let Some((handler, params)) = matched else {
+            return Response::Reject(http::StatusCode::NOT_FOUND);
+        };
+
+        // Remember the route for Self::handle, which only gets the session.
+        request.extensions().insert(params);
+        request.extensions().insert(Matched(handler.clone()));
+
+        handler.respond(request).await
+    }
+
+    fn handle(self, session: Session) -> impl Future<Output = ()> + Send {
+        // A nested router replaces the match with its own, so this skips straight to the innermost handler.
+        let matched = session.extensions().remove::<Matched>();
+
+        async move {
+            if let Some(matched) = matched {
+                matched.0.handle(session).await;
+            }
+        }
+    }
+}
+
+/// The parameters captured by the [`Router`] pattern that matched the session's path.
+///
+/// Retrieve them with `session.extensions().get::<RouteParams>()`, or likewise from the [`Request`] in a nested handler.
+/// Values are as they appear in the path, without percent-decoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteParams {
+    params: Vec<(String, String)>,
+}
+
+impl RouteParams {
+    /// Return the value of the named parameter (ex. `id` for `/rooms/:id`) or wildcard.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the names and values of every parameter, in the order they appear in the pattern.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+// The handler of the matched route, stashed in the session's extensions.
+struct Matched(Arc<dyn Dispatch>);
+
+// A SessionHandler that can be stored behind a pointer, since its futures are boxed.
+trait Dispatch: Send + Sync {
+    fn respond<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, Response>;
+    fn handle(&self, session: Session) -> BoxFuture<'static, ()>;
+}
+
+impl<H: SessionHandler + 'static> Dispatch for H {
+    fn respond<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, Response> {
+        SessionHandler::respond(self, request).boxed()
+    }
+
+    fn handle(&self, session: Session) -> BoxFuture<'static, ()> {
+        SessionHandler::handle(self.clone(), session).boxed()
+    }
+}
+
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+
+    // Matches the rest of the path, captured if named.
+    Wildcard(Option<String>),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let Some(pattern) = pattern.strip_prefix('/') else {
+            panic!("route pattern must start with a '/': {}", pattern);
+        };
+
+        let parts: Vec<&str> = pattern.split('/').collect();
+        let segments = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                if let Some(name) = part.strip_prefix('*') {
+                    assert!(
+                        i + 1 == parts.len(),
+                        "route wildcard must be the last segment: /{}",
+                        pattern
+                    );
+                    Segment::Wildcard((!name.is_empty()).then(|| name.to_string()))
+                } else if let Some(name) = part.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else {
+                    Segment::Literal(part.to_string())
+                }
+            })
+            .collect();
+
+        Self { segments }
+    }
+
+    // Return the captured parameters if the path matches.
+    fn matches(&self, path: &str) -> Option<RouteParams> {
+        let mut params = Vec::new();
+        let mut rest = Some(path.strip_prefix('/')?);
+
+        for segment in &self.segments {
+            if let Segment::Wildcard(name) = segment {
+                if let Some(name) = name {
+                    params.push((name.clone(), rest.unwrap_or_default().to_string()));
+                }
+
+                return Some(RouteParams { params });
+            }
+
+            let (part, next) = match rest?.split_once('/') {
+                Some((part, next)) => (part, Some(next)),
+                None => (rest?, None),
+            };
+            rest = next;
+
+            match segment {
+                Segment::Literal(literal) if literal == part => {}
+                Segment::Param(name) if !part.is_empty() => {
+                    params.push((name.clone(), part.to_string()))
+                }
+                _ => return None,
+            }
+        }
+
+        // The path has more segments than the pattern.
+        if rest.is_some() {
+            return None;
+        }
+
+        Some(RouteParams { params })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        Pattern::parse(pattern)
+            .matches(path)
+            .map(|params| params.params)
+    }
+
+    fn params(params: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn literal() {
+        assert_eq!(matches("/chat", "/chat"), params(&[]));
+        assert_eq!(matches("/chat/lobby", "/chat/lobby"), params(&[]));
+
+        assert_eq!(matches("/chat", "/chats"), None);
+        assert_eq!(matches("/chat", "/chat/lobby"), None);
+        assert_eq!(matches("/chat/lobby", "/chat"), None);
+        assert_eq!(matches("/chat", "chat"), None);
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(matches("/", "/"), params(&[]));
+        assert_eq!(matches("/", "/chat"), None);
+        assert_eq!(matches("/", ""), None);
+    }
+
+    #[test]
+    fn param() {
+        assert_eq!(matches("/rooms/:id", "/rooms/42"), params(&[("id", "42")]));
+        assert_eq!(
+            matches("/rooms/:id/users/:user", "/rooms/42/users/bob"),
+            params(&[("id", "42"), ("user", "bob")])
+        );
+
+        // A parameter matches exactly one non-empty segment.
+        assert_eq!(matches("/rooms/:id", "/rooms"), None);
+        assert_eq!(matches("/rooms/:id", "/rooms/"), None);
+        assert_eq!(matches("/rooms/:id", "/rooms/42/users"), None);
+    }
+
+    #[test]
+    fn wildcard() {
+        assert_eq!(matches("/files/*", "/files/a/b.txt"), params(&[]));
+        assert_eq!(
+            matches("/files/*rest", "/files/a/b.txt"),
+            params(&[("rest", "a/b.txt")])
+        );
+
+        // The rest may be empty.
+        assert_eq!(matches("/files/*rest", "/files/"), params(&[("rest", "")]));
+        assert_eq!(matches("/files/*rest", "/files"), params(&[("rest", "")]));
+        assert_eq!(matches("/*", "/"), params(&[]));
+
+        assert_eq!(matches("/files/*rest", "/other/a"), None);
+    }
+
+    #[test]
+    fn trailing_slash() {
+        assert_eq!(matches("/rooms", "/rooms/"), None);
+        assert_eq!(matches("/rooms/", "/rooms"), None);
+        assert_eq!(matches("/rooms/", "/rooms/"), params(&[]));
+    }
+
+    #[test]
+    #[should_panic(expected = "must start with a '/'")]
+    fn relative_pattern() {
+        Pattern::parse("chat");
+    }
+
+    #[test]
+    #[should_panic(expected = "must be the last segment")]
+    fn wildcard_not_last() {
+        Pattern::parse("/files/*/edit");
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    any::Any,
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    labels::Label, limit::Bucket, stall::Blocked, BlockedStats, Clock, RateLimit, StallPolicy,
+};
+
+// The number of bytes a stream may write each round, per unit of weight.
+const QUANTUM: usize = 1024;
+
+// The maximum time to wait for a round to finish without any progress, before starting a new one anyway.
+// Otherwise a weighted stream that's blocked on flow control would hold back everybody else.
+pub(crate) const ROUND_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How a session orders streams with the same priority, see [`crate::Session::set_schedule_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchedulePolicy {
+    /// Streams with the same priority take turns sending, so they all make progress.
+    /// This is best for bulk transfers and is what Quinn does natively.
+    #[default]
+    RoundRobin,
+
+    /// Streams with the same priority are sent one at a time, in the order they were created.
+    /// This is best for media, where finishing the oldest frame is better than a little of each.
+    ///
+    /// Priorities are clamped to the range of an i16 so the creation order can be included.
+    /// Only 16 bits of the creation order fit, so streams that stay open while more than 32768 newer streams are created may tie with each other,
+    /// but they're still sent before the newer streams.
+    Strict,
+}
+
+impl SchedulePolicy {
+    // Compute the priority passed to Quinn, given the creation order of the stream.
+    pub(crate) fn priority(self, priority: i32, order: u16) -> i32 {
+        match self {
+            Self::RoundRobin => priority,
+            Self::Strict => {
+                // Quinn sends the highest priority first, so older streams get a higher value.
+                let priority = priority.clamp(i16::MIN as i32, i16::MAX as i32);
+                priority * (1 << 16) + (u16::MAX - order) as i32
+            }
+        }
+    }
+}
+
+/// Decides how much data each weighted stream may queue, see [`crate::Session::set_scheduler`].
+///
+/// Scheduling happens in rounds: each weighted stream (see [`crate::SendStream::set_weight`]) is given a credit,
+/// the number of bytes it may hand to Quinn during the round.
+/// A new round starts once the streams that want to write have used their credit.
+/// A stream given no credit waits for the next round, which happens at least every 50ms.
+///
+/// Streams without a weight are never held back, so they don't appear here.
+pub trait Scheduler: Send {
+    /// Return the number of bytes the stream may queue during the next round.
+    fn credit(&mut self, stream: &StreamInfo) -> usize;
+}
+
+/// The default [`Scheduler`], sharing bandwidth in proportion to each stream's weight.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WeightedScheduler;
+
+impl Scheduler for WeightedScheduler {
+    fn credit(&mut self, stream: &StreamInfo) -> usize {
+        QUANTUM * stream.weight() as usize
+    }
+}
+
+/// The state of a weighted stream, provided to the [`Scheduler`].
+#[derive(Clone, Default)]
+pub struct StreamInfo {
+    pub(crate) weight: u16,
+    pub(crate) priority: i32,
+    pub(crate) metadata: Option<Arc<dyn Any + Send + Sync>>,
+    pub(crate) label: Option<Arc<Label>>,
+    pub(crate) sent: u64,
+    pub(crate) writing: bool,
+}
+
+impl StreamInfo {
+    /// The weight of the stream, see [`crate::SendStream::set_weight`].
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    /// The priority of the stream, see [`crate::SendStream::set_priority`].
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// The metadata attached by the application, see [`crate::SendStream::set_metadata`].
+    pub fn metadata(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.metadata.as_deref()
+    }
+
+    /// The label attached by the application, see [`crate::SendStream::set_label`].
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(|label| label.name())
+    }
+
+    /// The total number of bytes queued so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// True if the stream was waiting to write when the round started.
+    pub fn writing(&self) -> bool {
+        self.writing
+    }
+}
+
+// Shares the session's bandwidth between weighted streams, using deficit round-robin by default.
+//
+// Quinn only supports strict priorities, so instead we ration how much data each weighted stream hands to Quinn.
+// Each round, the Scheduler decides how many bytes each stream may write.
+// A new round starts once none of the streams are waiting to use their remaining credit.
+// Quinn buffers are bounded by flow control, so over time the streams are sent in proportion to their credit.
+//
+// Streams without a weight are not rationed and never touch the scheduler.
+pub(crate) struct Sched {
+    state: Mutex<State>,
+
+    policy: Mutex<SchedulePolicy>,
+
+    // The creation order of the open streams, and how many times it was renormalized.
+    orders: Mutex<Orders>,
+    epoch: AtomicU64,
+
+    // The session's rate limit, if any.
+    limit: Mutex<Option<Bucket>>,
+    limited: AtomicBool,
+
+    // Streams that are blocked by flow control or the send window, and what to do if they're blocked for too long.
+    blocked: Mutex<Blocked>,
+    stall: Mutex<Option<StallPolicy>>,
+
+    // Used to close the session when a stream is stalled.
+    conn: quinn::Connection,
+
+    // The session's clock, used for the rate limit and blocked time.
+    clock: Arc<dyn Clock>,
+}
+
+// Quinn's priority only has room for 16 bits of the creation order, so it's relative to a base instead.
+// Once a new stream would be too far ahead, the base moves up to the oldest open stream (or as close as possible),
+// and each stream applies its new priority on its next write.
+#[derive(Default)]
+struct Orders {
+    next: u64,
+    base: u64,
+    open: BTreeSet<u64>,
+}
+
+impl Orders {
+    // Open a new stream, returning its order and whether the base moved.
+    fn next(&mut self) -> (u64, bool) {
+        let order = self.next;
+        self.next += 1;
+        self.open.insert(order);
+
+        if order - self.base <= u16::MAX as u64 {
+            return (order, false);
+        }
+
+        // Leave room for more streams, so this doesn't happen for every new stream while an old one stays open.
+        let oldest = self.open.first().copied().unwrap_or(order);
+        self.base = oldest.max(order - u16::MAX as u64 / 2);
+        (order, true)
+    }
+
+    // Streams older than the base share the highest order, so they still go before newer streams.
+    fn relative(&self, order: u64) -> u16 {
+        order.saturating_sub(self.base).min(u16::MAX as u64) as u16
+    }
+}
+
+struct State {
+    streams: HashMap<quinn::StreamId, Entry>,
+    scheduler: Box<dyn Scheduler>,
+
+    // The total number of bytes written by weighted streams.
+    written: u64,
+}
+
+struct Entry {
+    info: StreamInfo,
+
+    // The number of bytes that can be written this round.
+    credit: usize,
+
+    // Set when we want to use our credit but haven't yet.
+    // Either we're about to write, Quinn blocked the last write, or we were just woken up for a new round.
+    writing: bool,
+
+    // Set when we're out of credit and waiting for the next round.
+    waker: Option<Waker>,
+}
+
+impl Sched {
+    pub fn policy(&self) -> SchedulePolicy {
+        *self.policy.lock().unwrap()
+    }
+
+    pub fn set_policy(&self, policy: SchedulePolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    pub fn set_scheduler(&self, scheduler: Box<dyn Scheduler>) {
+        self.state.lock().unwrap().scheduler = scheduler;
+    }
+
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.limit.lock().unwrap().as_ref().map(Bucket::limit)
+    }
+
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut bucket = self.limit.lock().unwrap();
+
+        match (bucket.as_mut(), limit) {
+            (Some(bucket), Some(limit)) => bucket.set_limit(limit, self.clock.now()),
+            (None, Some(limit)) => *bucket = Some(Bucket::new(limit, self.clock.now())),
+            (_, None) => *bucket = None,
+        }
+
+        self.limited.store(bucket.is_some(), Ordering::Relaxed);
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    // Return the number of bytes the session's rate limit allows us to send now, or how long to wait.
+    pub fn available(&self, size: usize) -> Result<usize, Duration> {
+        match self.limit.lock().unwrap().as_mut() {
+            Some(bucket) => bucket.available(size, self.clock.now()),
+            None => Ok(size),
+        }
+    }
+
+    // Spend the session's rate limit on a datagram, returning false if it should be dropped instead.
+    pub fn datagram(&self, size: usize) -> bool {
+        let mut limit = self.limit.lock().unwrap();
+        let bucket = match limit.as_mut() {
+            Some(bucket) => bucket,
+            None => return true,
+        };
+
+        match bucket.available(size, self.clock.now()) {
+            Ok(available) if available >= size => {
+                bucket.consume(size);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Return the creation order for a new stream, which is open until passed to close_order.
+    pub fn next_order(&self) -> u64 {
+        let (order, renormalized) = self.orders.lock().unwrap().next();
+        if renormalized {
+            self.epoch.fetch_add(1, Ordering::Relaxed);
+        }
+
+        order
+    }
+
+    pub fn close_order(&self, order: u64) {
+        self.orders.lock().unwrap().open.remove(&order);
+    }
+
+    // Return the order relative to the oldest open stream, and the epoch it's valid for.
+    pub fn relative_order(&self, order: u64) -> (u16, u64) {
+        let relative = self.orders.lock().unwrap().relative(order);
+        (relative, self.epoch.load(Ordering::Relaxed))
+    }
+
+    // Incremented each time the relative orders change, so streams know to update their priority.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Relaxed)
+    }
+
+    // Update the state of the stream, or remove it from the scheduler if the weight is zero.
+    pub fn update(&self, id: quinn::StreamId, info: &StreamInfo) {
+        let mut state = self.state.lock().unwrap();
+
+        if info.weight == 0 {
+            state.streams.remove(&id);
+            state.maybe_refill();
+            return;
+        }
+
+        let state = &mut *state;
+        match state.streams.get_mut(&id) {
+            Some(entry) => {
+                // Keep the number of bytes sent, which we track ourselves.
+                let sent = entry.info.sent;
+                entry.info = info.clone();
+                entry.info.sent = sent;
+            }
+            None => {
+                let credit = state.scheduler.credit(info);
+                state.streams.insert(
+                    id,
+                    Entry {
+                        info: info.clone(),
+                        credit,
+                        writing: false,
+                        waker: None,
+                    },
+                );
+            }
+        }
+    }
+
+    // Return the number of bytes the stream may write, up to the provided size.
+    pub fn poll_ready(
+        &self,
+        id: quinn::StreamId,
+        cx: &mut Context<'_>,
+        size: usize,
+    ) -> Poll<usize> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.streams.get_mut(&id) {
+            None => return Poll::Ready(size),
+            Some(entry) if entry.credit > 0 => {
+                entry.writing = true;
+                return Poll::Ready(entry.credit.min(size));
+            }
+            Some(entry) => entry.waker = Some(cx.waker().clone()),
+        }
+
+        state.maybe_refill();
+
+        match state.streams.get_mut(&id) {
+            Some(entry) if entry.credit > 0 => {
+                entry.writing = true;
+                Poll::Ready(entry.credit.min(size))
+            }
+            _ => Poll::Pending,
+        }
+    }
+
+    // Record the result of writing to Quinn: the size written, or Pending if it was blocked.
+    pub fn wrote(&self, id: quinn::StreamId, res: Poll<usize>) {
+        if let (Poll::Ready(size), Some(bucket)) = (res, self.limit.lock().unwrap().as_mut()) {
+            bucket.consume(size);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let entry = match state.streams.get_mut(&id) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        match res {
+            Poll::Ready(size) => {
+                state.written += size as u64;
+                entry.credit = entry.credit.saturating_sub(size);
+                entry.info.sent += size as u64;
+                entry.writing = false;
+
+                // Don't start a new round while we still have credit, since we're probably about to write again.
+                // If not, the waiting streams will time out instead.
+                if entry.credit == 0 {
+                    state.maybe_refill();
+                }
+            }
+            Poll::Pending => entry.writing = true,
+        }
+    }
+
+    // Return the total number of bytes written by weighted streams, used to detect progress.
+    pub fn written(&self) -> u64 {
+        self.state.lock().unwrap().written
+    }
+
+    // Start a new round because a stream has waited too long, unless somebody has written since.
+    pub fn timeout(&self, written: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.written == written {
+            state.refill();
+        }
+    }
+}
+
+impl Sched {
+    pub fn new(conn: quinn::Connection, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                streams: HashMap::new(),
+                scheduler: Box::new(WeightedScheduler),
+                written: 0,
+            }),
+            policy: Default::default(),
+            orders: Default::default(),
+            epoch: Default::default(),
+            limit: Default::default(),
+            limited: Default::default(),
+            blocked: Default::default(),
+            stall: Default::default(),
+            conn,
+            clock,
+        }
+    }
+
+    pub fn block(&self, id: quinn::StreamId, since: Instant) {
+        self.blocked.lock().unwrap().block(id, since)
+    }
+
+    pub fn unblock(&self, id: quinn::StreamId) {
+        self.blocked.lock().unwrap().unblock(id, self.clock.now())
+    }
+
+    pub fn blocked(&self) -> BlockedStats {
+        self.blocked.lock().unwrap().stats(self.clock.now())
+    }
+
+    pub fn stall_policy(&self) -> Option<StallPolicy> {
+        *self.stall.lock().unwrap()
+    }
+
+    pub fn set_stall_policy(&self, policy: Option<StallPolicy>) {
+        *self.stall.lock().unwrap() = policy;
+    }
+
+    // Close the session because a stream was stalled.
+    pub fn close(&self, code: u32) {
+        let code = webtransport_proto::error_to_http3(code).try_into().unwrap();
+        self.conn.close(code, b"stalled")
+    }
+}
+
+impl State {
+    // Start a new round if somebody is waiting and nobody is still using their credit.
+    fn maybe_refill(&mut self) {
+        let waiting = self.streams.values().any(|entry| entry.waker.is_some());
+        let busy = self
+            .streams
+            .values()
+            .any(|entry| entry.writing && entry.credit > 0);
+
+        if waiting && !busy {
+            self.refill();
+        }
+    }
+
+    // Any unused credit is discarded, so idle streams can't save up for a burst.
+    fn refill(&mut self) {
+        for entry in self.streams.values_mut() {
+            entry.info.writing = entry.writing || entry.waker.is_some();
+            entry.credit = self.scheduler.credit(&entry.info);
+
+            // Streams that didn't get any credit keep waiting for the next round.
+            if entry.credit > 0 {
+                if let Some(waker) = entry.waker.take() {
+                    entry.writing = true;
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Quinn sends the stream with the highest priority first.
+    fn strict(orders: &Orders, order: u64) -> i32 {
+        SchedulePolicy::Strict.priority(0, orders.relative(order))
+    }
+
+    #[test]
+    fn strict_order() {
+        let mut orders = Orders::default();
+        let (old, _) = orders.next();
+        let (new, _) = orders.next();
+
+        assert!(strict(&orders, old) > strict(&orders, new));
+        assert!(SchedulePolicy::Strict.priority(1, u16::MAX) > strict(&orders, old));
+    }
+
+    #[test]
+    fn renormalize_to_oldest() {
+        let mut orders = Orders::default();
+
+        for _ in 0..=u16::MAX {
+            let (order, renormalized) = orders.next();
+            assert!(!renormalized);
+            orders.open.remove(&order);
+        }
+
+        // The next stream doesn't fit, but it's the only one open.
+        let (old, renormalized) = orders.next();
+        assert!(renormalized);
+        assert_eq!(orders.base, old);
+
+        let (new, renormalized) = orders.next();
+        assert!(!renormalized);
+        assert!(strict(&orders, old) > strict(&orders, new));
+    }
+
+    #[test]
+    fn renormalize_long_lived() {
+        let mut orders = Orders::default();
+        let (old, _) = orders.next();
+
+        // Keep one stream open while opening and closing many more than fit in the priority.
+        let mut epochs = 0;
+        for _ in 0..3 * u16::MAX as usize {
+            let (order, renormalized) = orders.next();
+            epochs += renormalized as usize;
+
+            let (newest, renormalized) = orders.next();
+            epochs += renormalized as usize;
+
+            assert!(strict(&orders, old) >= strict(&orders, order));
+            assert!(strict(&orders, order) > strict(&orders, newest));
+
+            orders.open.remove(&order);
+            orders.open.remove(&newest);
+        }
+
+        // The base isn't moved for every new stream.
+        assert!(epochs <= 6 * u16::MAX as usize / (u16::MAX as usize / 2) + 1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use webtransport_proto::VarInt;
+
+use crate::{SendDatagramError, Session, SessionError};
+
+/// Datagrams stamped with a sequence number, so stale arrivals are dropped and only the latest state is received.
+///
+/// This suits state updates (ex. game positions or sensor readings) where an older datagram is useless once a newer one arrived.
+/// Datagrams are still unreliable: any datagram may be lost, but none are received out of order or more than once.
+///
+/// This is a custom framing, so both endpoints need to use it, and it shouldn't be mixed with [`Session::recv_datagram`] on the same session.
+/// Create one per session and clone it, since sequence numbers are only unique per instance.
+#[derive(Clone)]
+pub struct SequencedDatagrams {
+    session: Session,
+    state: Arc<Sequence>,
+}
+
+#[derive(Default)]
+struct Sequence {
+    // The sequence number of the next datagram sent.
+    next: AtomicU64,
+
+    // One more than the newest sequence number received, or 0 if none were.
+    received: AtomicU64,
+
+    stale: AtomicU64,
+}
+
+impl Sequence {
+    // Record a received sequence number, returning false (and counting it) if it's stale or a duplicate.
+    fn accept(&self, sequence: u64) -> bool {
+        // Concurrent receivers race here, but only the newest of them wins.
+        let prev = self.received.fetch_max(sequence + 1, Ordering::Relaxed);
+        if prev > sequence {
+            self.stale.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl SequencedDatagrams {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            state: Default::default(),
+        }
+    }
+
+    /// Send a datagram with the next sequence number. See [`Session::send_datagram`].
+    pub fn send(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        let sequence = self.state.next.fetch_add(1, Ordering::Relaxed);
+        let sequence = VarInt::try_from(sequence).map_err(|_| SendDatagramError::TooLarge)?;
+
+        let mut buf = BytesMut::with_capacity(sequence.size() + payload.len());
+        sequence.encode(&mut buf);
+        buf.put_slice(&payload);
+
+        self.session.send_datagram(buf.freeze())
+    }
+
+    /// Receive the next datagram newer than every one received so far, returning its sequence number.
+    ///
+    /// Gaps in the sequence number indicate lost or dropped datagrams.
+    pub async fn recv(&self) -> Result<(u64, Bytes), SessionError> {
+        let mut coop = self.session.coop();
+
+        loop {
+            coop.proceed().await;
+
+            let mut payload = self.session.recv_datagram().await?;
+            let sequence = match VarInt::decode(&mut payload) {
+                Ok(sequence) => sequence.into_inner(),
+                Err(_) => continue,
+            };
+
+            if self.state.accept(sequence) {
+                return Ok((sequence, payload));
+            }
+        }
+    }
+
+    /// The largest payload that can currently be sent, or None if datagrams are unsupported by the peer.
+    ///
+    /// This is [`Session::max_datagram_size`] minus the sequence number (1-8 bytes).
+    pub fn max_payload_size(&self) -> Option<usize> {
+        let sequence = VarInt::try_from(self.state.next.load(Ordering::Relaxed)).ok()?;
+        self.session
+            .max_datagram_size()?
+            .checked_sub(sequence.size())
+    }
+
+    /// The number of datagrams dropped because a newer one was already received.
+    pub fn stale(&self) -> u64 {
+        self.state.stale.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_stale() {
+        let sequence = Sequence::default();
+
+        assert!(sequence.accept(0));
+        assert!(sequence.accept(2));
+
+        // Older than the newest, and a duplicate of it.
+        assert!(!sequence.accept(1));
+        assert!(!sequence.accept(2));
+        assert_eq!(sequence.stale.load(Ordering::Relaxed), 2);
+
+        // Gaps are fine.
+        assert!(sequence.accept(10));
+        assert!(!sequence.accept(0));
+        assert_eq!(sequence.stale.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn first_is_zero() {
+        let sequence = Sequence::default();
+
+        assert!(sequence.accept(0));
+        assert!(!sequence.accept(0));
+        assert_eq!(sequence.stale.load(Ordering::Relaxed), 1);
+    }
+}
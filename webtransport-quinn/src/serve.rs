@@ -0,0 +1,135 @@
+use std::{future::Future, panic::AssertUnwindSafe, time::Duration};
+
+use futures::{
+    future::{BoxFuture, Either},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
+
+use crate::{clock, RecvStream, SendStream, Session, SessionError, Sleep};
+
+/// A stream accepted by [`crate::Session::serve_incoming`].
+pub enum IncomingStream {
+    Uni(RecvStream),
+    Bi(SendStream, RecvStream),
+}
+
+/// What to do with a handler that panics or runs for too long, see [`crate::Session::serve_incoming_with`].
+///
+/// Either failure closes the session with a CLOSE_WEBTRANSPORT_SESSION capsule,
+/// since the handler may have left the rest of the session in an inconsistent state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandlerPolicy {
+    /// How long each handler may run before it's dropped, or None to let it run until it finishes.
+    pub timeout: Option<Duration>,
+
+    /// The error code used to close the session.
+    pub code: u32,
+}
+
+impl HandlerPolicy {
+    /// Close the session with the given error code when a handler panics, without a timeout.
+    pub fn new(code: u32) -> Self {
+        Self {
+            timeout: None,
+            code,
+        }
+    }
+}
+
+// Why a handler didn't finish.
+pub(crate) enum Failure {
+    Panicked,
+    TimedOut,
+}
+
+impl Failure {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Panicked => "handler panicked",
+            Self::TimedOut => "handler timed out",
+        }
+    }
+}
+
+pub(crate) async fn run<Fut>(fut: Fut, timeout: Option<Sleep>) -> Result<(), Failure>
+where
+    Fut: Future<Output = ()>,
+{
+    let fut = AssertUnwindSafe(fut).catch_unwind();
+    let res = match timeout {
+        Some(sleep) => clock::timeout(sleep, fut).await.ok_or(Failure::TimedOut)?,
+        None => fut.await,
+    };
+
+    res.map_err(|_| Failure::Panicked)
+}
+
+// Call the handler, deferring a panic in its synchronous body until the future is polled, so `run` catches it too.
+pub(crate) fn call<F, Fut>(handler: F) -> Either<Fut, BoxFuture<'static, Fut::Output>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future,
+    Fut::Output: 'static,
+{
+    match std::panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(fut) => Either::Left(fut),
+        Err(panic) => Either::Right(async move { std::panic::resume_unwind(panic) }.boxed()),
+    }
+}
+
+// Accept streams and run the handler for each, with at most `limit` running at once.
+pub(crate) async fn serve<F, Fut>(
+    session: &Session,
+    limit: usize,
+    policy: Option<&HandlerPolicy>,
+    mut handler: F,
+) -> SessionError
+where
+    F: FnMut(IncomingStream) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let limit = limit.max(1);
+    let timeout = policy.and_then(|policy| policy.timeout);
+    let sleep = || timeout.map(|timeout| session.clock().sleep(timeout));
+    let mut running = FuturesUnordered::new();
+    let mut coop = session.coop();
+
+    loop {
+        // Streams and handlers are often ready immediately under load, so yield now and then.
+        coop.proceed().await;
+
+        // Stop accepting while at the limit, so flow control pushes back on the peer.
+        let done = if running.len() >= limit {
+            running.next().await
+        } else {
+            futures::select! {
+                res = session.accept_uni().fuse() => match res {
+                    Ok(recv) => {
+                        let fut = call(|| handler(IncomingStream::Uni(recv)));
+                        running.push(run(fut, sleep()));
+                        None
+                    }
+                    Err(err) => return err,
+                },
+                res = session.accept_bi().fuse() => match res {
+                    Ok((send, recv)) => {
+                        let fut = call(|| handler(IncomingStream::Bi(send, recv)));
+                        running.push(run(fut, sleep()));
+                        None
+                    }
+                    Err(err) => return err,
+                },
+                res = running.select_next_some() => Some(res),
+            }
+        };
+
+        // Without a policy, a failure only drops the streams given to that handler.
+        if let (Some(Err(failure)), Some(policy)) = (done, policy) {
+            session
+                .close_gracefully(policy.code, failure.reason())
+                .await;
+            return session.closed_error().await.into();
+        }
+    }
+}
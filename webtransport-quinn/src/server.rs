@@ -1,7 +1,36 @@
-use crate::{Connect, ConnectError, Session, Settings, SettingsError};
+use std::{
+    future::{poll_fn, Future},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, pin_mut, stream::FuturesUnordered, FutureExt, StreamExt};
+
+use crate::{
+    clock, handler,
+    idle::Reaper,
+    listener::Hooks,
+    mux::{Claim, Mux},
+    stats::Counters,
+    trace::{self, event, Span},
+    Accepted, AuthRequest, Authorization, Capabilities, Clock, Compat, Connect, ConnectError,
+    Extensions, Fallback, IdlePolicy, OriginPolicy, PeerInfo, ProtocolError, ServerStats, Serving,
+    Session, SessionHandler, SessionLimits, SessionListener, SessionMetrics, Settings,
+    SettingsError, Sleep, SystemClock, H3_REQUEST_REJECTED,
+};
 
 use thiserror::Error;
 
+// The HTTP/3 error code used to close the connection after the response was delivered.
+const H3_NO_ERROR: quinn::VarInt = quinn::VarInt::from_u32(0x100);
+
+// How long to wait for the client to close the connection after refusing a request while draining.
+const REFUSE_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// An error returned when receiving a new WebTransport session.
 #[derive(Error, Debug)]
 pub enum ServerError {
@@ -27,25 +56,208 @@ pub enum ServerError {
 /// Accept a new WebTransport session from a client.
 /// Returns a [`Request`] which is then used to accept or reject the session based on the URI.
 pub async fn accept(conn: quinn::Connection) -> Result<Request, ServerError> {
+    handshake(conn, Compat::Auto, None).await
+}
+
+async fn handshake(
+    conn: quinn::Connection,
+    compat: Compat,
+    limits: Option<SessionLimits>,
+) -> Result<Request, ServerError> {
     // Perform the H3 handshake by sending/reciving SETTINGS frames.
-    let settings = Settings::connect(&conn).await?;
+    let span = trace::connection(&conn);
+    let settings = trace::instrument(&span, Settings::connect(&conn, compat, 1, limits)).await?;
 
     // Accept the CONNECT request but don't send a response yet.
-    let connect = Connect::accept(&conn).await?;
+    let connect = trace::instrument(&span, Connect::accept(&conn)).await?;
+
+    // Return the resulting request with a reference to the settings/connect streams.
+    Ok(Request {
+        conn,
+        settings,
+        connect,
+        span,
+        fallback: None,
+        serving: Vec::new(),
+        reaper: None,
+        counters: None,
+        extensions: Extensions::default(),
+        clock: Arc::new(SystemClock),
+        hooks: Hooks::default(),
+        claim: None,
+        sessions: None,
+    })
+}
+
+/// Accept a new WebTransport session from a client, serving any plain HTTP/3 requests with the [`Fallback`].
+///
+/// Requests that arrive before the CONNECT are served concurrently while waiting for it.
+/// Requests that arrive after the session is established are served while [`Session::accept_bi`] is being polled.
+pub async fn accept_with_fallback(
+    conn: quinn::Connection,
+    fallback: Fallback,
+) -> Result<Request, ServerError> {
+    // Perform the H3 handshake by sending/reciving SETTINGS frames.
+    let span = trace::connection(&conn);
+    let settings =
+        trace::instrument(&span, Settings::connect(&conn, Compat::Auto, 1, None)).await?;
+
+    // Serve any plain requests while we wait for the CONNECT request.
+    let mut serving = FuturesUnordered::new();
+
+    let connect = loop {
+        let next = trace::instrument(&span, async {
+            let (send, recv) = conn.accept_bi().await.map_err(ConnectError::from)?;
+            Connect::read(send, recv).await
+        })
+        .fuse();
+        pin_mut!(next);
+
+        let accepted = loop {
+            futures::select! {
+                res = next => break res?,
+                _ = serving.select_next_some() => {},
+            }
+        };
+
+        match accepted {
+            Accepted::Connect(connect) => break connect,
+            Accepted::Request {
+                buf, send, recv, ..
+            } => serving.push(fallback.serving(buf, send, recv)),
+        }
+    };
 
     // Return the resulting request with a reference to the settings/connect streams.
+    // Any requests that are still being served are handed over to the session.
     Ok(Request {
         conn,
         settings,
         connect,
+        span,
+        fallback: Some(fallback),
+        serving: serving.into_iter().collect(),
+        reaper: None,
+        counters: None,
+        extensions: Extensions::default(),
+        clock: Arc::new(SystemClock),
+        hooks: Hooks::default(),
+        claim: None,
+        sessions: None,
     })
 }
 
-/// A mostly complete WebTransport handshake, just awaiting the server's decision on whether to accept or reject the session based on the URI.
+/// Accept WebTransport sessions from a client, allowing up to `max_sessions` of them at once on the same connection.
+///
+/// Browsers may pool sessions to the same server on one connection, which [`accept`] doesn't support.
+/// Each request is returned by [`Sessions::accept`], and the resulting sessions only receive their own streams and datagrams.
+/// Closing a session doesn't close the connection, which lasts until every session and the [`Sessions`] are dropped.
+/// Requests beyond `max_sessions` are refused unprocessed, and plain HTTP/3 requests aren't served.
+pub async fn accept_sessions(
+    conn: quinn::Connection,
+    max_sessions: u32,
+) -> Result<Sessions, ServerError> {
+    handshake_sessions(conn, Compat::Auto, max_sessions, None).await
+}
+
+async fn handshake_sessions(
+    conn: quinn::Connection,
+    compat: Compat,
+    max_sessions: u32,
+    limits: Option<SessionLimits>,
+) -> Result<Sessions, ServerError> {
+    // Perform the H3 handshake, advertising how many sessions we allow and the limits of each.
+    let span = trace::connection(&conn);
+    let settings = trace::instrument(
+        &span,
+        Settings::connect(&conn, compat, max_sessions, limits),
+    )
+    .await?;
+    let mux = Mux::server(conn, settings, max_sessions);
+
+    Ok(Sessions {
+        mux: Arc::new(mux),
+        span,
+    })
+}
+
+/// A connection that can host multiple WebTransport sessions, see [`accept_sessions`].
+///
+/// This is a cheap handle; clone it to accept requests from elsewhere.
+#[derive(Clone)]
+pub struct Sessions {
+    mux: Arc<Mux>,
+
+    // The connection's span, shared by its requests.
+    span: Span,
+}
+
+impl Sessions {
+    /// Accept the next WebTransport session on the connection, returning an error once it's closed.
+    ///
+    /// This must be polled (or one of the sessions must be accepting streams or datagrams) for the connection to make progress,
+    /// since incoming streams are routed to their session by whichever task is waiting on the connection.
+    pub async fn accept(&self) -> Result<Request, ServerError> {
+        let requested = poll_fn(|cx| self.mux.poll_request(cx));
+        let (connect, claim) = trace::instrument(&self.span, requested).await?;
+
+        Ok(Request {
+            conn: self.mux.connection().clone(),
+            settings: self.mux.settings().clone(),
+            connect,
+            span: self.span.clone(),
+            fallback: None,
+            serving: Vec::new(),
+            reaper: None,
+            counters: None,
+            extensions: Extensions::default(),
+            clock: Arc::new(SystemClock),
+            hooks: Hooks::default(),
+            claim: Some(claim),
+            sessions: Some(self.clone()),
+        })
+    }
+
+    /// Return the underlying QUIC connection.
+    pub fn connection(&self) -> &quinn::Connection {
+        self.mux.connection()
+    }
+}
+
+/// A mostly complete WebTransport handshake, just awaiting the server's decision on whether to accept or reject the session.
+///
+/// The session isn't established until [`Self::ok`] is called, so routing and authentication can be based on the CONNECT request:
+/// its path, authority, origin, and any other headers sent by the client.
+///
+/// The decision can be deferred: hold the request (or move it to another task) while doing async work like validating a token.
+/// Other sessions and requests on the same connection carry on in the meantime.
+/// A request that's dropped without a decision is cancelled with H3_REQUEST_CANCELLED, so the client isn't left waiting.
 pub struct Request {
     conn: quinn::Connection,
     settings: Settings,
     connect: Connect,
+    fallback: Option<Fallback>,
+    serving: Vec<Serving>,
+
+    // The connection's span, which the session's span is created within.
+    span: Span,
+
+    // Set when accepted by a Server, so the session is closed when idle.
+    reaper: Option<Reaper>,
+
+    // Set when accepted by a Server, so the decision is counted in its stats.
+    counters: Option<Arc<Counters>>,
+
+    // Handed over to the session.
+    extensions: Extensions,
+    clock: Arc<dyn Clock>,
+    hooks: Hooks,
+
+    // Set when the connection is shared, so the session only receives its own streams.
+    claim: Option<Claim>,
+
+    // Set when the connection is shared, so a Server can accept the next request on it.
+    sessions: Option<Sessions>,
 }
 
 impl Request {
@@ -54,15 +266,645 @@ impl Request {
         self.connect.uri()
     }
 
+    /// Returns the method of the request, which is always CONNECT for WebTransport.
+    pub fn method(&self) -> http::Method {
+        http::Method::CONNECT
+    }
+
+    /// Returns the `:path` of the request, without the query. See [`Self::uri`] for the rest.
+    pub fn path(&self) -> &str {
+        self.uri().path()
+    }
+
+    /// Returns the `:authority` of the request, which is the host (and port) the client connected to.
+    pub fn authority(&self) -> &str {
+        // A CONNECT request without an authority is refused before we get this far.
+        self.uri()
+            .authority()
+            .map_or("", |authority| authority.as_str())
+    }
+
+    /// Returns the `origin` header, which browsers send with the page that created the session.
+    ///
+    /// Servers should check this before accepting sessions from a browser, since any page can connect.
+    pub fn origin(&self) -> Option<&str> {
+        self.headers()
+            .get(http::header::ORIGIN)
+            .and_then(|origin| origin.to_str().ok())
+    }
+
+    /// Returns the headers of the request, excluding the pseudo-headers (ex. `:path`), which are available with the methods above.
+    ///
+    /// Only the last value of each header is kept.
+    pub fn headers(&self) -> &http::HeaderMap {
+        self.connect.headers()
+    }
+
+    /// Returns the client's certificate chain, when client authentication is enabled, see [`Session::peer_identity`].
+    pub fn peer_identity(&self) -> Option<Vec<rustls::Certificate>> {
+        crate::peer::certificates(&self.conn)
+    }
+
+    /// Returns the state attached to the request, which is handed over to the [`Session`] if accepted.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
     /// Accept the session, returning a 200 OK.
-    pub async fn ok(mut self) -> Result<Session, quinn::WriteError> {
-        self.connect.respond(http::StatusCode::OK).await?;
-        Ok(Session::new(self.conn, self.settings, self.connect))
+    pub async fn ok(self) -> Result<Session, ServerError> {
+        self.accept_with(http::HeaderMap::new()).await
+    }
+
+    /// Accept the session, returning a 200 OK with the given response headers (ex. `set-cookie`, a session token).
+    ///
+    /// Browsers expose these to the page once the session is ready.
+    /// Only UTF-8 header values are sent, and accepting fails if the response is larger than the client's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    pub async fn accept_with(mut self, headers: http::HeaderMap) -> Result<Session, ServerError> {
+        self.respond(http::StatusCode::OK, headers).await?;
+
+        if let Some(reaper) = &self.reaper {
+            reaper.track(&self.conn);
+        }
+
+        let session = self.span.in_scope(|| {
+            Session::new(
+                self.conn,
+                self.settings,
+                self.connect,
+                self.fallback.map(|fallback| (fallback, self.serving)),
+                self.extensions,
+                self.clock,
+                self.claim,
+            )
+        });
+
+        session.attach(self.hooks);
+
+        if let Some(counters) = &self.counters {
+            counters.accepted(&session);
+        }
+
+        Ok(session)
     }
 
     /// Reject the session, returing your favorite HTTP status code.
-    pub async fn close(mut self, status: http::StatusCode) -> Result<(), quinn::WriteError> {
-        self.connect.respond(status).await?;
+    ///
+    /// The connection is then closed using the status as the reason (ex. "404 Not Found"), which is shown in browser devtools.
+    /// If the connection is shared with other sessions (see [`accept_sessions`]), it's left open instead.
+    pub async fn close(self, status: http::StatusCode) -> Result<(), ServerError> {
+        let reason = match status.canonical_reason() {
+            Some(reason) => format!("{} {}", status.as_str(), reason),
+            None => status.as_str().to_string(),
+        };
+
+        self.close_with_reason(status, &reason).await
+    }
+
+    /// Reject the session with an HTTP status code, then close the connection with a human-readable reason.
+    ///
+    /// The reason is shown in browser devtools, so it's a good place to explain why the session was rejected.
+    pub async fn close_with_reason(
+        mut self,
+        status: http::StatusCode,
+        reason: &str,
+    ) -> Result<(), ServerError> {
+        if let Some(counters) = &self.counters {
+            counters.rejected();
+        }
+
+        event!(info, parent: &self.span, uri = %self.uri(), status = status.as_u16(), "session rejected");
+
+        self.respond(status, http::HeaderMap::new()).await?;
+
+        // Wait until the response is received, otherwise closing the connection would discard it.
+        self.connect.finish().await?;
+
+        if self.claim.is_none() {
+            self.conn.close(H3_NO_ERROR, reason.as_bytes());
+        }
+
         Ok(())
     }
+
+    /// Reject the session with an HTTP error status (ex. 404, 401, 429), which the client receives as the response.
+    ///
+    /// Unlike [`Self::close`], the connection isn't torn down, so the client sees the status rather than a connection error.
+    /// If the connection isn't shared with other sessions (see [`accept_sessions`]), it's closed once the client is done with it.
+    pub async fn reject(self, status: http::StatusCode) -> Result<(), ServerError> {
+        self.reject_with(status, http::HeaderMap::new()).await
+    }
+
+    /// Reject the session with an HTTP error status and response headers (ex. `retry-after`, `www-authenticate`).
+    ///
+    /// The status must not be a 2xx, otherwise the client would think the session was accepted.
+    /// Only UTF-8 header values are sent, and the response fails if it's larger than the client's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    pub async fn reject_with(
+        mut self,
+        status: http::StatusCode,
+        headers: http::HeaderMap,
+    ) -> Result<(), ServerError> {
+        if let Some(counters) = &self.counters {
+            counters.rejected();
+        }
+
+        event!(info, parent: &self.span, uri = %self.uri(), status = status.as_u16(), "session rejected");
+
+        self.respond(status, headers).await?;
+
+        // Wait until the response is received, so it isn't lost if the connection is closed below.
+        self.connect.finish().await?;
+
+        // Leave a shared connection open for the other sessions.
+        if self.claim.is_some() {
+            return Ok(());
+        }
+
+        // Nobody accepts another request on this connection, so close it once the client has (or eventually).
+        let sleep = self.clock.sleep(REFUSE_TIMEOUT);
+        clock::timeout(sleep, self.conn.closed()).await;
+        self.conn.close(H3_NO_ERROR, b"");
+
+        Ok(())
+    }
+
+    // Send the response within the connection's span.
+    async fn respond(
+        &mut self,
+        status: http::StatusCode,
+        headers: http::HeaderMap,
+    ) -> Result<(), ServerError> {
+        let draft = self.settings.draft();
+        let max = self.settings.max_field_section_size();
+        let respond = self.connect.respond(status, headers, draft, max);
+
+        Ok(trace::instrument(&self.span, respond).await?)
+    }
+
+    // Refuse the request without processing it because the server is draining, so the client can retry elsewhere.
+    async fn refuse(mut self) {
+        // Tell the client that neither this request nor any after it will be processed.
+        let id = self.connect.session_id();
+        event!(info, parent: &self.span, uri = %self.uri(), "session refused while draining");
+
+        self.settings.send_goaway(id).await.ok();
+        self.connect.reject();
+
+        // Leave a shared connection open for the other sessions.
+        if self.claim.is_some() {
+            return;
+        }
+
+        // Give the client a chance to receive the GOAWAY before the connection is dropped.
+        let sleep = self.clock.sleep(REFUSE_TIMEOUT);
+        clock::timeout(sleep, self.conn.closed()).await;
+    }
+}
+
+type Filter = Arc<dyn Fn(&PeerInfo) -> bool + Send + Sync>;
+type Authorizer = Arc<dyn Fn(AuthRequest) -> BoxFuture<'static, Authorization> + Send + Sync>;
+
+/// A WebTransport server, accepting sessions on a [`quinn::Endpoint`] configured with the HTTP/3 ALPN.
+///
+/// Unlike [`accept`], the server keeps track of the sessions it accepted, so it can enforce policies across them.
+/// Since this crate doesn't spawn tasks, the policies are enforced while [`Server::accept`] is being polled.
+pub struct Server {
+    endpoint: quinn::Endpoint,
+
+    // Connections that are performing the QUIC and WebTransport handshakes, or None if refused.
+    handshakes: FuturesUnordered<BoxFuture<'static, Result<Option<Request>, ServerError>>>,
+
+    compat: Compat,
+
+    // The flow control limits we advertise for each session, if any.
+    limits: Option<SessionLimits>,
+
+    idle: Option<IdlePolicy>,
+    reaper: Reaper,
+    reaping: Sleep,
+
+    max_sessions: Option<usize>,
+    max_sessions_per_connection: u32,
+    counters: Arc<Counters>,
+
+    filter: Option<Filter>,
+    origin: Option<OriginPolicy>,
+    authorizer: Option<Authorizer>,
+
+    // Set once draining, so in-flight handshakes are refused when they complete.
+    draining: Arc<AtomicBool>,
+
+    // Used for idle reaping, and handed to each session.
+    clock: Arc<dyn Clock>,
+
+    // The metrics and listener handed to each session.
+    hooks: Hooks,
+
+    // Whether to handle requests before the handshake completes, to answer 0-RTT requests sooner.
+    zero_rtt: bool,
+}
+
+impl Server {
+    /// Create an endpoint listening on the given address, see [`quinn::Endpoint::server`].
+    /// The TLS config must include the HTTP/3 [`crate::ALPN`].
+    pub fn bind(addr: SocketAddr, config: quinn::ServerConfig) -> std::io::Result<Self> {
+        let endpoint = quinn::Endpoint::server(config, addr)?;
+        Ok(Self::with_endpoint(endpoint))
+    }
+
+    /// Accept sessions on an existing endpoint, leaving its configuration up to you.
+    ///
+    /// The server config must include the HTTP/3 [`crate::ALPN`].
+    /// Every incoming connection is treated as WebTransport and any that fail the handshake are dropped.
+    /// If the endpoint also serves another protocol, accept the connections yourself and pass them to [`accept`] instead.
+    pub fn with_endpoint(endpoint: quinn::Endpoint) -> Self {
+        Self {
+            endpoint,
+            handshakes: FuturesUnordered::new(),
+            compat: Compat::default(),
+            limits: None,
+            idle: None,
+            reaper: Reaper::default(),
+            reaping: Box::pin(futures::future::pending()),
+            max_sessions: None,
+            max_sessions_per_connection: 1,
+            counters: Arc::default(),
+            filter: None,
+            origin: None,
+            authorizer: None,
+            draining: Arc::default(),
+            clock: Arc::new(SystemClock),
+            hooks: Hooks::default(),
+            zero_rtt: false,
+        }
+    }
+
+    /// Return the underlying endpoint.
+    pub fn endpoint(&self) -> &quinn::Endpoint {
+        &self.endpoint
+    }
+
+    /// Return what the endpoint's socket and timer support on this platform, see [`crate::capabilities`].
+    pub fn capabilities(&self) -> Capabilities {
+        crate::capabilities()
+    }
+
+    /// Return the address the endpoint is listening on.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Move the endpoint to a new UDP socket bound to the given address, see [`quinn::Endpoint::rebind`].
+    ///
+    /// Established sessions are preserved, but QUIC has no way to tell clients the server moved.
+    /// They're only reachable if packets sent to the old address still arrive (ex. the same address in a new network namespace, or behind a load balancer).
+    /// Handshakes in progress are lost.
+    /// On error, the old socket is retained.
+    pub fn rebind(&self, addr: SocketAddr) -> std::io::Result<()> {
+        let socket = std::net::UdpSocket::bind(addr)?;
+        self.endpoint.rebind(socket)
+    }
+
+    /// Choose which revision of the WebTransport draft to speak, detecting it by default.
+    pub fn set_compat(&mut self, compat: Compat) {
+        self.compat = compat;
+    }
+
+    /// Use the given clock for timeouts and idle reaping instead of the system time, see [`Clock`].
+    ///
+    /// This applies to the sessions accepted afterwards, including their [`Session::with_timeout`] wrappers.
+    /// Call it before [`Self::set_idle_policy`], which starts the first idle check.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+        self.reaper = Reaper::new(self.clock.clone());
+    }
+
+    /// Report the events of the sessions accepted afterwards to the given metrics, see [`SessionMetrics`].
+    pub fn set_metrics<M: SessionMetrics + 'static>(&mut self, metrics: M) {
+        self.hooks.metrics = Some(Arc::new(metrics));
+    }
+
+    /// Report the lifecycle of the sessions accepted afterwards to the given listener, see [`SessionListener`].
+    ///
+    /// This includes clients that fail the handshake by violating the protocol, reported to [`SessionListener::on_protocol_error`].
+    pub fn set_listener<L: SessionListener + 'static>(&mut self, listener: L) {
+        self.hooks.listener = Some(Arc::new(listener));
+    }
+
+    /// Handle requests before the QUIC handshake completes, so a CONNECT sent in 0-RTT is answered a round trip sooner, see [`crate::Client::connect_0rtt`].
+    ///
+    /// The TLS config must accept early data (`max_early_data_size` set to `u32::MAX`), see [`crate::ServerBuilder::zero_rtt`].
+    /// 0-RTT data can be replayed by an attacker, so only enable this if accepting a session has no side effects that can't be applied twice.
+    /// The response is also sent before the client finished the handshake, so don't combine this with client certificates, which aren't verified yet.
+    pub fn set_zero_rtt(&mut self, enabled: bool) {
+        self.zero_rtt = enabled;
+    }
+
+    /// Close sessions that have been idle for too long, or disable reaping with None.
+    ///
+    /// This only applies to sessions accepted afterwards, and runs while [`Self::accept`] is being polled.
+    pub fn set_idle_policy(&mut self, policy: Option<IdlePolicy>) {
+        self.reaping = match &policy {
+            Some(policy) => self.clock.sleep(policy.interval()),
+            None => Box::pin(futures::future::pending()),
+        };
+        self.idle = policy;
+    }
+
+    /// Return the current idle policy.
+    pub fn idle_policy(&self) -> Option<&IdlePolicy> {
+        self.idle.as_ref()
+    }
+
+    /// Refuse new sessions with a 503 while the given number of sessions are open, or remove the limit with None.
+    ///
+    /// Only sessions accepted via [`Request::ok`] count towards the limit, not those awaiting a decision.
+    pub fn set_max_sessions(&mut self, max: Option<usize>) {
+        self.max_sessions = max;
+    }
+
+    /// Allow clients to establish up to the given number of sessions on the same connection, see [`accept_sessions`].
+    ///
+    /// The default is 1, so each session has its own connection and closing it closes the connection.
+    /// Otherwise each request on a connection is returned by [`Self::accept`] as it arrives, and the policies apply to each session.
+    /// This only applies to connections accepted afterwards.
+    pub fn set_max_sessions_per_connection(&mut self, max: u32) {
+        self.max_sessions_per_connection = max.max(1);
+    }
+
+    /// Advertise flow control limits for each session, or don't with None (the default), see [`SessionLimits`].
+    ///
+    /// The limits are only enforced if the client advertises them too, in which case it also limits what our sessions can send.
+    /// This is useful with [`Self::set_max_sessions_per_connection`], so one session on a connection can't starve the others.
+    /// This only applies to connections accepted afterwards.
+    pub fn set_session_limits(&mut self, limits: Option<SessionLimits>) {
+        self.limits = limits;
+    }
+
+    /// Refuse connections when the filter returns false, replacing any previous filter.
+    ///
+    /// The filter is called once the QUIC handshake completes, before any HTTP/3 streams are opened,
+    /// so abusive clients can be refused by address, SNI or certificate without spending more work on them.
+    /// Refused connections are closed with H3_REQUEST_REJECTED and are never returned by [`Self::accept`].
+    pub fn set_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&PeerInfo) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+    }
+
+    /// Refuse sessions with a 403 Forbidden unless their origin passes the policy, or accept any origin with None.
+    ///
+    /// Refused sessions are never returned by [`Self::accept`], and the connection is left open so the browser sees the status.
+    pub fn set_origin_policy(&mut self, policy: Option<OriginPolicy>) {
+        self.origin = policy;
+    }
+
+    /// Authorize each request with the given async callback before it's returned by [`Self::accept`], replacing any previous authorizer.
+    ///
+    /// The callback gets the request's headers (ex. `authorization` or `cookie`), and refused requests get a 401 or 403 response instead of a connection error.
+    /// Other requests keep being handled while it's pending, so it can validate tokens against a database or another service.
+    /// It runs after the other policies, so requests they refuse don't cost a lookup.
+    pub fn set_authorizer<F, Fut>(&mut self, authorize: F)
+    where
+        F: Fn(AuthRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Authorization> + Send + 'static,
+    {
+        self.authorizer = Some(Arc::new(move |request| authorize(request).boxed()));
+    }
+
+    /// Stop authorizing requests.
+    pub fn clear_authorizer(&mut self) {
+        self.authorizer = None;
+    }
+
+    /// Stop filtering connections.
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Return counters for the sessions accepted, rejected and refused so far, and the number currently open.
+    pub fn stats(&self) -> ServerStats {
+        self.counters.snapshot()
+    }
+
+    /// Wait until every connection on the endpoint has been closed, see [`quinn::Endpoint::wait_idle`].
+    ///
+    /// Useful for a clean shutdown after closing the endpoint, so peers are told why their session ended.
+    pub async fn wait_idle(&self) {
+        self.endpoint.wait_idle().await
+    }
+
+    /// Stop accepting sessions and tell clients to go elsewhere with an HTTP/3 GOAWAY, see RFC 9114 section 5.2.
+    ///
+    /// Any CONNECT request that arrives afterwards, including on connections still performing the handshake, is rejected unprocessed
+    /// so the client can safely retry it with another server (see [`crate::ClientError::GoAway`]).
+    /// Accepted sessions keep running, but are told that no further requests will be processed; use [`crate::Session::drain`] to ask them to wrap up.
+    /// Keep polling [`Self::accept`] so new connections are refused, and use [`Self::wait_idle`] after closing the endpoint.
+    pub async fn drain(&mut self) {
+        self.draining.store(true, Ordering::Relaxed);
+
+        let goaways = self.counters.goaways();
+        futures::future::join_all(goaways.iter().map(|goaway| goaway.send())).await;
+    }
+
+    /// Returns true once [`Self::drain`] was called.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Accept sessions and run the handler for each, until the endpoint is closed or `shutdown` completes.
+    ///
+    /// This replaces the usual accept loop: the handler decides how to respond to each request, then runs the session.
+    /// Policies like [`Self::set_max_sessions`] still apply, so sessions over the limit are refused before reaching the handler.
+    /// Once `shutdown` completes, the server [drains](Self::drain) and waits for the running sessions to finish.
+    /// This crate doesn't spawn tasks, so every session runs within the returned future; spawn inside the handler if you need them to run in parallel.
+    pub async fn serve<H, S>(&mut self, handler: H, shutdown: S)
+    where
+        H: SessionHandler,
+        S: Future<Output = ()>,
+    {
+        handler::serve_sessions(self, handler, shutdown).await
+    }
+
+    /// Accept the next WebTransport session from a client, see [`accept`].
+    ///
+    /// Handshakes are performed concurrently, and any that fail are skipped.
+    /// Connections refused by [`Self::set_filter`], sessions refused by [`Self::set_origin_policy`] or over the limit set by [`Self::set_max_sessions`],
+    /// and sessions refused while draining are not returned.
+    /// Returns None once the endpoint is closed.
+    ///
+    /// Handshakes only make progress while this is polled, so don't await a slow decision in the accept loop.
+    /// Move the [`Request`] to another task instead, or use [`Self::serve`].
+    pub async fn accept(&mut self) -> Option<Request> {
+        loop {
+            let admission = self.admission();
+            let compat = self.compat;
+            let limits = self.limits;
+            let per_connection = self.max_sessions_per_connection;
+            let counters = self.counters.clone();
+            let filter = self.filter.clone();
+            let zero_rtt = self.zero_rtt;
+
+            futures::select! {
+                conn = self.endpoint.accept().fuse() => {
+                    let conn = conn?;
+                    let handshake = async move {
+                        let conn = match zero_rtt {
+                            // This always succeeds on the server, with 0.5-RTT data.
+                            true => match conn.into_0rtt() {
+                                Ok((conn, _)) => conn,
+                                Err(conn) => conn.await?,
+                            },
+                            false => conn.await?,
+                        };
+                        let remote = conn.remote_address();
+
+                        if filter.is_some_and(|filter| !filter(&PeerInfo::new(&conn))) {
+                            counters.refused_filter();
+                            conn.close(H3_REQUEST_REJECTED, b"refused");
+                            return Ok(None);
+                        }
+
+                        if per_connection > 1 {
+                            let sessions = handshake_sessions(conn, compat, per_connection, limits)
+                                .await
+                                .map_err(|err| admission.failed(remote, err))?;
+                            return Ok(admission.admit_next(sessions).await);
+                        }
+
+                        let request = handshake(conn, compat, limits)
+                            .await
+                            .map_err(|err| admission.failed(remote, err))?;
+                        Ok(admission.admit(request).await)
+                    };
+
+                    self.handshakes.push(handshake.boxed());
+                },
+                res = self.handshakes.select_next_some() => match res {
+                    Ok(Some(request)) => {
+                        // Keep accepting requests on a shared connection.
+                        if let Some(sessions) = request.sessions.clone() {
+                            let admission = self.admission();
+                            let next = async move { Ok(admission.admit_next(sessions).await) };
+                            self.handshakes.push(next.boxed());
+                        }
+
+                        return Some(request);
+                    },
+                    Ok(None) => {},
+                    Err(_) => self.counters.refused_handshake(),
+                },
+                _ = self.reaping.as_mut().fuse() => {
+                    if let Some(policy) = &self.idle {
+                        self.reaper.reap(policy);
+                        self.reaping = self.clock.sleep(policy.interval());
+                    }
+                },
+            }
+        }
+    }
+
+    fn admission(&self) -> Admission {
+        Admission {
+            reaper: self.idle.as_ref().map(|_| self.reaper.clone()),
+            origin: self.origin.clone(),
+            authorizer: self.authorizer.clone(),
+            max_sessions: self.max_sessions,
+            counters: self.counters.clone(),
+            draining: self.draining.clone(),
+            clock: self.clock.clone(),
+            hooks: self.hooks.clone(),
+        }
+    }
+}
+
+// The server's policies, applied to each request before it's returned by Server::accept.
+struct Admission {
+    reaper: Option<Reaper>,
+    origin: Option<OriginPolicy>,
+    authorizer: Option<Authorizer>,
+    max_sessions: Option<usize>,
+    counters: Arc<Counters>,
+    draining: Arc<AtomicBool>,
+    clock: Arc<dyn Clock>,
+    hooks: Hooks,
+}
+
+impl Admission {
+    // Report a handshake that failed because the client violated the protocol to the listener.
+    fn failed(&self, remote: SocketAddr, err: ServerError) -> ServerError {
+        let violation = match &err {
+            ServerError::SettingsError(
+                err @ (SettingsError::ProtoError(_) | SettingsError::WebTransportUnsupported),
+            ) => Some(err.to_string()),
+            ServerError::ConnectError(err @ ConnectError::ProtoError(_)) => Some(err.to_string()),
+            _ => None,
+        };
+
+        if let (Some(listener), Some(violation)) = (&self.hooks.listener, violation) {
+            listener.on_protocol_error(remote, &ProtocolError::Handshake(violation));
+        }
+
+        err
+    }
+
+    // Return the request if the application should decide whether to accept it, otherwise refuse it.
+    async fn admit(&self, mut request: Request) -> Option<Request> {
+        request.reaper = self.reaper.clone();
+        request.clock = self.clock.clone();
+        request.hooks = self.hooks.clone();
+
+        if self.draining.load(Ordering::Relaxed) {
+            self.counters.refused_draining();
+            request.refuse().await;
+            return None;
+        }
+
+        if self
+            .origin
+            .as_ref()
+            .is_some_and(|origin| !origin.check(&request))
+        {
+            // Already counted as refused, so ignore any error while responding.
+            self.counters.refused_origin();
+            request.reject(http::StatusCode::FORBIDDEN).await.ok();
+            return None;
+        }
+
+        if self
+            .max_sessions
+            .is_some_and(|max| self.counters.open() >= max)
+        {
+            // Already counted as refused, so ignore any error while responding.
+            self.counters.refused_limit();
+            request
+                .close(http::StatusCode::SERVICE_UNAVAILABLE)
+                .await
+                .ok();
+            return None;
+        }
+
+        if let Some(authorize) = &self.authorizer {
+            let authorization = authorize(AuthRequest::new(&request)).await;
+            if let Some((status, headers)) = authorization.response() {
+                self.counters.refused_auth();
+                request.reject_with(status, headers).await.ok();
+                return None;
+            }
+        }
+
+        request.counters = Some(self.counters.clone());
+        Some(request)
+    }
+
+    // Accept requests on a shared connection until one is admitted, or None once the connection is closed.
+    async fn admit_next(&self, sessions: Sessions) -> Option<Request> {
+        while let Ok(request) = sessions.accept().await {
+            if let Some(request) = self.admit(request).await {
+                return Some(request);
+            }
+        }
+
+        None
+    }
 }
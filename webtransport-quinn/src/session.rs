@@ -1,16 +1,36 @@
 use std::{
+    collections::HashMap,
     future::{poll_fn, Future},
     ops::Deref,
     pin::{pin, Pin},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
     task::{ready, Context, Poll},
+    time::Duration,
 };
 
+use bytes::Bytes;
 use futures::stream::{FuturesUnordered, Stream, StreamExt};
 
-use crate::{Connect, RecvStream, SendStream, SessionError, Settings, WebTransportError};
+use crate::{
+    coop::Coop,
+    fallback,
+    flow::Flow,
+    journal::Recorder,
+    listener::Hooks,
+    mux::{Claim, Mux},
+    path,
+    sched::Sched,
+    serve,
+    state::{Handoff, SessionState, Waiter},
+    trace::{self, event},
+    BlockedStats, ClientError, Clock, Connect, DatagramDrops, DatagramOptions, DatagramQueue,
+    Draft, Extensions, Fallback, H3Datagrams, HandlerPolicy, IncomingStream, Journal, LabelStats,
+    PathEvent, ProtocolError, RateLimit, RecvStream, RequestError, SchedulePolicy, Scheduler,
+    SendDatagramError, SendStream, Serving, SessionClose, SessionDatagrams, SessionError,
+    SessionInfo, SessionStats, Settings, StallPolicy, TimeoutSession, WebTransportError,
+};
 
-use webtransport_proto::{Frame, StreamUni, VarInt};
+use webtransport_proto::{Capsule, Datagram, Frame, StreamUni, VarInt};
 
 /// An established WebTransport session, acting like a full QUIC connection. See [`quinn::Connection`].
 ///
@@ -21,22 +41,73 @@ use webtransport_proto::{Frame, StreamUni, VarInt};
 ///
 /// Deref is used to expose non-overloaded methods on [`quinn::Connection`].
 /// These should be safe to use with WebTransport, but file a PR if you find one that isn't.
+/// The rest of the connection is available via [`Session::quic_connection`].
 #[derive(Clone)]
 pub struct Session {
     conn: quinn::Connection,
 
+    // The URI from the CONNECT request.
+    uri: http::Uri,
+
+    // The response to the CONNECT request.
+    response: Arc<http::Response<()>>,
+
     // The accept logic is stateful, so use an Arc<Mutex> to share it.
     accept: Arc<Mutex<SessionAccept>>,
 
     // Cache the headers in front of each stream we open.
     header_uni: Vec<u8>,
     header_bi: Vec<u8>,
+
+    // Shares bandwidth between weighted streams.
+    sched: Arc<Sched>,
+
+    // Datagrams for the session, prefixed with the quarter stream ID of the CONNECT request.
+    datagrams: H3Datagrams,
+    quarter_stream_id: quinn::VarInt,
+
+    // The stream ID of the CONNECT request.
+    session_id: VarInt,
+
+    // Routes streams and datagrams to the session when the connection is shared with other sessions.
+    mux: Option<Arc<Mux>>,
+
+    // The draft negotiated with the peer.
+    draft: Draft,
+
+    // The peer's SETTINGS_MAX_FIELD_SECTION_SIZE.
+    max_field_section_size: u64,
+
+    // The peer's SETTINGS_WEBTRANSPORT_MAX_SESSIONS.
+    max_sessions: u64,
+
+    // State attached by the application.
+    extensions: Extensions,
+
+    // Shared with every stream, including the journal and why the session was closed.
+    state: Arc<SessionState>,
+
+    // The waiters of webtransport_generic's poll-based accepts, which stay queued between polls.
+    polling: Polling,
 }
 
 impl Session {
-    pub(crate) fn new(conn: quinn::Connection, settings: Settings, connect: Connect) -> Self {
+    pub(crate) fn new(
+        conn: quinn::Connection,
+        settings: Settings,
+        mut connect: Connect,
+        fallback: Option<(Fallback, Vec<Serving>)>,
+        extensions: Extensions,
+        clock: Arc<dyn Clock>,
+        claim: Option<Claim>,
+    ) -> Self {
         // The session ID is the stream ID of the CONNECT request.
         let session_id = connect.session_id();
+        let uri = connect.uri().clone();
+        let response = Arc::from(connect.take_response());
+
+        let span = trace::session(session_id, &uri);
+        event!(info, parent: &span, "session established");
 
         // Cache the tiny header we write in front of each stream we open.
         let mut header_uni = Vec::new();
@@ -47,67 +118,654 @@ impl Session {
         Frame::WEBTRANSPORT.encode(&mut header_bi);
         session_id.encode(&mut header_bi);
 
+        let sched = Arc::new(Sched::new(conn.clone(), clock.clone()));
+        let datagrams = H3Datagrams::new(conn.clone());
+        let quarter_stream_id = Datagram::quarter_stream_id(session_id).into_inner();
+        let quarter_stream_id = quinn::VarInt::from_u64(quarter_stream_id).unwrap();
+        let draft = settings.draft();
+        let max_field_section_size = settings.max_field_section_size();
+        let max_sessions = settings.max_sessions();
+
+        // Enforce the session's flow control if both endpoints advertised limits.
+        let flow = settings
+            .session_limits()
+            .map(|(local, peer)| Flow::new(local, peer));
+
+        // Watch the CONNECT stream for the peer closing the session, and use it to send our own capsules.
+        // The session only owns the connection if it's not shared with other sessions.
+        let state = SessionState::new(
+            conn.clone(),
+            connect.into_streams(),
+            Recorder::new(
+                SessionInfo::new(&conn, session_id, &uri),
+                span,
+                clock.clone(),
+            ),
+            clock,
+            claim.is_none(),
+            flow,
+        );
+        let state = Arc::new(state);
+
+        // Start receiving the streams and datagrams that were routed to the session.
+        let mux = claim.map(|claim| claim.open(&state));
+
         // Accept logic is stateful, so use an Arc<Mutex> to share it.
-        let accept = SessionAccept::new(conn.clone(), settings, connect);
+        let accept = SessionAccept::new(
+            conn.clone(),
+            settings,
+            session_id,
+            fallback,
+            sched.clone(),
+            state.clone(),
+            mux.clone(),
+        );
 
         Self {
             conn,
+            uri,
+            response,
             accept: Arc::new(Mutex::new(accept)),
             header_uni,
             header_bi,
+            sched,
+            datagrams,
+            quarter_stream_id,
+            session_id,
+            mux,
+            draft,
+            max_field_section_size,
+            max_sessions,
+            extensions,
+            state,
+            polling: Polling::default(),
         }
     }
 
+    /// Establish a session on a QUIC connection you dialed yourself, performing only the HTTP/3 and WebTransport handshakes.
+    ///
+    /// This is useful when managing your own dialing, proxies, or address discovery.
+    /// The connection must be brand new and use the HTTP/3 [`crate::ALPN`]. See [`crate::connect_with`].
+    pub async fn connect_on(
+        conn: quinn::Connection,
+        uri: &http::Uri,
+    ) -> Result<Session, ClientError> {
+        crate::connect_with(conn, uri).await
+    }
+
     /// Accept a new unidirectional stream. See [`quinn::Connection::accept_uni`].
     pub async fn accept_uni(&self) -> Result<RecvStream, SessionError> {
-        poll_fn(|cx| self.accept.lock().unwrap().poll_accept_uni(cx)).await
+        let waiter = self.state.waiter();
+        let handoff = self.accept.lock().unwrap().handoff_uni.clone();
+        let _queued = Queued::new(self.state.clone(), handoff, waiter);
+
+        poll_fn(|cx| self.accept.lock().unwrap().poll_accept_uni(waiter, cx)).await
     }
 
     /// Accept a new bidirectional stream. See [`quinn::Connection::accept_bi`].
     pub async fn accept_bi(&self) -> Result<(SendStream, RecvStream), SessionError> {
-        poll_fn(|cx| self.accept.lock().unwrap().poll_accept_bi(cx)).await
+        let waiter = self.state.waiter();
+        let handoff = self.accept.lock().unwrap().handoff_bi.clone();
+        let _queued = Queued::new(self.state.clone(), handoff, waiter);
+
+        poll_fn(|cx| self.accept.lock().unwrap().poll_accept_bi(waiter, cx)).await
     }
 
     /// Open a new unidirectional stream. See [`quinn::Connection::open_uni`].
     pub async fn open_uni(&self) -> Result<SendStream, SessionError> {
-        let mut send = self.conn.open_uni().await?;
-        Self::write_full(&mut send, &self.header_uni).await?;
-        Ok(SendStream::new(send))
+        let open = async {
+            // Wait until the peer's flow control lets the session open another stream.
+            poll_fn(|_| self.state.poll_open(false)).await;
+
+            let mut send = self.conn.open_uni().await?;
+            Self::write_full(&mut send, &self.header_uni).await?;
+            Ok::<_, SessionError>(send)
+        };
+
+        let send = self.state.or_closed(self.state.waiter(), open).await??;
+        self.state.journal.opened(send.id(), true);
+        Ok(SendStream::new(
+            send,
+            self.sched.clone(),
+            self.state.clone(),
+        ))
     }
 
     /// Open a new bidirectional stream. See [`quinn::Connection::open_bi`].
     pub async fn open_bi(&self) -> Result<(SendStream, RecvStream), SessionError> {
-        let (mut send, recv) = self.conn.open_bi().await?;
-        Self::write_full(&mut send, &self.header_bi).await?;
-        Ok((SendStream::new(send), RecvStream::new(recv)))
+        let open = async {
+            poll_fn(|_| self.state.poll_open(true)).await;
+
+            let (mut send, recv) = self.conn.open_bi().await?;
+            Self::write_full(&mut send, &self.header_bi).await?;
+            Ok::<_, SessionError>((send, recv))
+        };
+
+        let (send, recv) = self.state.or_closed(self.state.waiter(), open).await??;
+        self.state.journal.opened(send.id(), true);
+        Ok((
+            SendStream::new(send, self.sched.clone(), self.state.clone()),
+            RecvStream::new(recv, self.state.clone()),
+        ))
+    }
+
+    /// Accept every incoming stream and run the handler for each, until the session is closed.
+    ///
+    /// This replaces the usual accept loop: up to `limit` handlers run concurrently, and no more streams are accepted until one finishes.
+    /// A handler that panics only loses its own streams; the rest keep running.
+    /// This crate doesn't spawn tasks, so the handlers run within the returned future; spawn it if you need them to run in parallel.
+    /// Returns the error that closed the session.
+    pub async fn serve_incoming<F, Fut>(&self, limit: usize, handler: F) -> SessionError
+    where
+        F: FnMut(IncomingStream) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        serve::serve(self, limit, None, handler).await
+    }
+
+    /// Like [`Self::serve_incoming`], but a handler that panics or exceeds the policy's timeout closes the session.
+    ///
+    /// The session is closed with the policy's error code, so one buggy handler can't leave the peer waiting forever.
+    pub async fn serve_incoming_with<F, Fut>(
+        &self,
+        limit: usize,
+        policy: HandlerPolicy,
+        handler: F,
+    ) -> SessionError
+    where
+        F: FnMut(IncomingStream) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        serve::serve(self, limit, Some(&policy), handler).await
+    }
+
+    /// Send a plain HTTP/3 request on the same connection and wait for the response.
+    ///
+    /// This is meant for small requests made by the client, like fetching a config blob before opening streams.
+    /// It is not a full HTTP/3 client: the request and response are buffered up to [`crate::MAX_REQUEST_SIZE`].
+    /// A relative URI (ex. `/config.json`) is resolved against the URI used to establish the session.
+    pub async fn request(
+        &self,
+        req: http::Request<Bytes>,
+    ) -> Result<http::Response<Bytes>, RequestError> {
+        fallback::request(&self.conn, &self.uri, req).await
+    }
+
+    /// Watch for changes to the network path, sampling the connection at the given interval.
+    ///
+    /// This is useful for long-lived sessions to warn about degraded connectivity before the idle timeout fires.
+    /// The stream ends once the session is closed.
+    pub fn path_events(&self, interval: Duration) -> impl Stream<Item = PathEvent> {
+        path::watch(self.conn.clone(), interval, self.state.clock.clone())
+    }
+
+    /// Choose how streams with the same priority share the connection, see [`SchedulePolicy`].
+    ///
+    /// This applies to streams when they are created or their priority changes.
+    pub fn set_schedule_policy(&self, policy: SchedulePolicy) {
+        self.sched.set_policy(policy)
+    }
+
+    /// Return the current [`SchedulePolicy`].
+    pub fn schedule_policy(&self) -> SchedulePolicy {
+        self.sched.policy()
+    }
+
+    /// Replace the [`Scheduler`] used to share bandwidth between weighted streams.
+    /// The default is a [`crate::WeightedScheduler`].
+    pub fn set_scheduler<S: Scheduler + 'static>(&self, scheduler: S) {
+        self.sched.set_scheduler(Box::new(scheduler))
+    }
+
+    /// Limit the rate of outgoing stream data and datagrams for the whole session, or remove the limit with None.
+    ///
+    /// This is useful for servers to enforce fairness between users.
+    /// The limit can be changed at any time and applies to pending writes.
+    /// Datagrams over the limit are dropped, see [`Self::send_datagram`].
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        self.sched.set_rate_limit(limit)
+    }
+
+    /// Return the current rate limit, see [`Self::set_rate_limit`].
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.sched.rate_limit()
+    }
+
+    /// Return how long writes to the streams in the session have been blocked because Quinn wouldn't take more data.
+    ///
+    /// That's either the peer's flow control, or the send window (see [`crate::MemoryBudget::send`]) being full of unacknowledged data.
+    /// Quinn 0.10 doesn't say which, so this includes waiting on congestion control as well as a peer that doesn't read fast enough.
+    /// Either way it's useful to detect slow receivers.
+    pub fn blocked(&self) -> BlockedStats {
+        self.sched.blocked()
+    }
+
+    /// Return the RTT, congestion window and loss of the connection, ex. for adaptive bitrate or health checks.
+    ///
+    /// This replaces [`quinn::Connection::stats`], which is still available via [`Self::quic_connection`].
+    pub fn stats(&self) -> SessionStats {
+        SessionStats::new(&self.conn.stats())
+    }
+
+    /// Reset streams or close the session when a write is blocked for too long, or remove the policy with None.
+    pub fn set_stall_policy(&self, policy: Option<StallPolicy>) {
+        self.sched.set_stall_policy(policy)
+    }
+
+    /// Return the current stall policy, see [`Self::set_stall_policy`].
+    pub fn stall_policy(&self) -> Option<StallPolicy> {
+        self.sched.stall_policy()
+    }
+
+    /// Send an unreliable datagram, prefixed with the session's quarter stream ID. See [`quinn::Connection::send_datagram`].
+    ///
+    /// The payload must fit in [`Self::max_datagram_size`], otherwise [`SendDatagramError::TooLarge`] is returned.
+    /// Datagrams can't wait, so they're silently dropped instead when they exceed the session's rate limit (see [`Self::set_rate_limit`])
+    /// or are rejected by the outgoing queue (see [`Self::set_datagram_queue`]), counted by [`Self::datagram_drops`].
+    pub fn send_datagram(&self, payload: Bytes) -> Result<(), SendDatagramError> {
+        self.send_datagram_with(payload, &DatagramOptions::default())
+    }
+
+    /// Send an unreliable datagram with the given options, ex. a max age. See [`Self::send_datagram`].
+    pub fn send_datagram_with(
+        &self,
+        payload: Bytes,
+        options: &DatagramOptions,
+    ) -> Result<(), SendDatagramError> {
+        if let Some(err) = self.state.reason() {
+            return Err(SessionError::from(err).into());
+        }
+
+        // Fail before charging the rate limit for a datagram that can't be sent.
+        self.datagrams
+            .check_size(self.quarter_stream_id, payload.len())?;
+
+        if self.datagrams.expire(options) {
+            return Ok(());
+        }
+
+        if !self.sched.datagram(payload.len()) {
+            self.datagrams.rate_limited();
+            return Ok(());
+        }
+
+        self.datagrams.send(self.quarter_stream_id, payload)
+    }
+
+    /// Send several datagrams at once, so Quinn can transmit them together instead of one at a time.
+    ///
+    /// Every payload is checked against [`Self::max_datagram_size`] before any are sent, returning [`SendDatagramError::TooLarge`] for the whole batch.
+    /// The batch is then queued back to back, which lets Quinn coalesce them into fewer system calls (with GSO where supported).
+    /// Otherwise each datagram behaves like [`Self::send_datagram`], including being dropped by the rate limit or queue.
+    pub fn send_datagram_batch(&self, payloads: &[Bytes]) -> Result<(), SendDatagramError> {
+        if let Some(err) = self.state.reason() {
+            return Err(SessionError::from(err).into());
+        }
+
+        for payload in payloads {
+            self.datagrams
+                .check_size(self.quarter_stream_id, payload.len())?;
+        }
+
+        let allowed: Vec<_> = payloads
+            .iter()
+            .filter(|payload| {
+                let allowed = self.sched.datagram(payload.len());
+                if !allowed {
+                    self.datagrams.rate_limited();
+                }
+                allowed
+            })
+            .cloned()
+            .collect();
+
+        self.datagrams.send_batch(self.quarter_stream_id, &allowed)
+    }
+
+    /// Receive the next datagram for the session, without the quarter stream ID. See [`quinn::Connection::read_datagram`].
+    ///
+    /// Datagrams for any other session on the connection are skipped, or queued for that session if the connection is shared.
+    pub async fn recv_datagram(&self) -> Result<Bytes, SessionError> {
+        if let Some(mux) = &self.mux {
+            let recv = poll_fn(|cx| mux.poll_datagram(self.session_id, cx));
+            return Ok(self.state.or_closed(self.state.waiter(), recv).await??);
+        }
+
+        let recv = async {
+            loop {
+                let (id, payload) = self.datagrams.recv().await?;
+                if id == self.quarter_stream_id {
+                    return Ok::<_, SessionError>(payload);
+                }
+            }
+        };
+
+        self.state.or_closed(self.state.waiter(), recv).await?
+    }
+
+    /// Return the maximum payload size of a datagram, or None if datagrams are unsupported by the peer.
+    ///
+    /// This is the QUIC limit minus the session's quarter stream ID prefix (1-8 bytes), so it's the size to use for payloads.
+    /// It depends on the path MTU and can grow as it's discovered, so check it when sizing each payload instead of caching it.
+    /// See [`quinn::Connection::max_datagram_size`].
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.datagrams.max_size(self.quarter_stream_id)
+    }
+
+    /// Drop new datagrams while more than the given number of bytes are queued, or always queue them with None (the default).
+    /// See [`crate::H3Datagrams::set_max_queued`].
+    pub fn set_datagram_max_queued(&self, max: Option<usize>) {
+        self.datagrams.set_max_queued(max)
+    }
+
+    /// Choose what happens to datagrams that don't fit in the outgoing queue, see [`DatagramQueue`].
+    ///
+    /// The default is [`crate::DatagramOverflow::DropOldest`] with the connection's send buffer as the capacity.
+    pub fn set_datagram_queue(&self, queue: DatagramQueue) {
+        self.datagrams.set_queue(queue)
+    }
+
+    /// Return the policy set by [`Self::set_datagram_queue`].
+    pub fn datagram_queue(&self) -> DatagramQueue {
+        self.datagrams.queue()
+    }
+
+    /// Return the number of datagrams silently dropped by [`Self::send_datagram`] instead of being sent.
+    pub fn datagrams_dropped(&self) -> u64 {
+        self.datagrams.dropped()
+    }
+
+    /// Return the number of datagrams that weren't sent for each reason, including those that returned an error.
+    ///
+    /// Datagrams are lost without a trace when the queue overflows, so watch these in production.
+    pub fn datagram_drops(&self) -> DatagramDrops {
+        self.datagrams.drops()
+    }
+
+    /// Return the session's datagrams as a [`Stream`] and [`futures::Sink`], see [`SessionDatagrams`].
+    pub fn datagrams(&self) -> SessionDatagrams {
+        SessionDatagrams::new(self.clone())
+    }
+
+    /// Close the session immediately with an error code and reason, without waiting for anything to be delivered.
+    ///
+    /// If the QUIC connection isn't shared, it's closed with the code and reason, see [`quinn::Connection::close`].
+    /// Otherwise (see [`crate::accept_sessions`] and [`crate::Client::set_pooling`]) only the session is closed, and the other sessions keep working:
+    /// a CLOSE_WEBTRANSPORT_SESSION capsule is written if there's room for it, or else the CONNECT stream is reset, which closes the session without a reason.
+    /// Either way the peer is told the session is closed without relying on it to act, or on this session being polled again.
+    /// Use [`Self::close_gracefully`] so a browser can report the code and reason via `WebTransport.closed`. The reason is truncated to 1024 bytes.
+    pub fn close(&self, code: u32, reason: &str) {
+        self.state.closed_locally(code, reason);
+        let error_code = webtransport_proto::error_to_http3(code).try_into().unwrap();
+
+        if self.mux.is_none() {
+            self.conn.close(error_code, reason.as_bytes());
+            return;
+        }
+
+        // Write as much as we can without blocking; the stream is finished once it's all written.
+        if self.state.send_capsule(&Capsule::close(code, reason)) {
+            let _ = self.state.poll_finish();
+        } else {
+            self.state.reset(error_code);
+        }
+    }
+
+    /// Return a wrapper where each operation, including reads and writes on the resulting streams, fails if it takes longer than the timeout.
+    pub fn with_timeout(&self, timeout: Duration) -> TimeoutSession {
+        TimeoutSession::new(self.clone(), timeout)
+    }
+
+    /// Return the clock used for the session's timeouts, see [`crate::Server::set_clock`] and [`crate::Client::set_clock`].
+    pub fn clock(&self) -> &dyn Clock {
+        self.state.clock.as_ref()
+    }
+
+    /// Limit how many items (ex. accepted streams) an internal loop handles before yielding to the runtime, or 0 to never yield.
+    ///
+    /// The crate doesn't spawn tasks, so a busy session could otherwise monopolize the worker thread polling it.
+    /// This applies to accepting streams, [`Self::serve_incoming`], and [`crate::relay`] on the session's streams. The default is 128.
+    pub fn set_yield_budget(&self, budget: usize) {
+        self.state.yield_budget.set(budget)
+    }
+
+    /// Return the current yield budget, see [`Self::set_yield_budget`].
+    pub fn yield_budget(&self) -> usize {
+        self.state.yield_budget.get()
+    }
+
+    // Start counting the work done by a loop on the session's behalf.
+    pub(crate) fn coop(&self) -> Coop {
+        self.state.yield_budget.coop()
+    }
+
+    // Return a handle to the clock, for wrappers that outlive the borrow.
+    pub(crate) fn clock_handle(&self) -> Arc<dyn Clock> {
+        self.state.clock.clone()
+    }
+
+    /// Return the state attached to the session, shared by every clone.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Return the response to the CONNECT request, with the headers the server attached when accepting the session (ex. a session token).
+    ///
+    /// The status is always a 2xx, since the session wouldn't exist otherwise.
+    /// On the server, this is the response that was sent, see [`crate::Request::accept_with`].
+    pub fn response(&self) -> &http::Response<()> {
+        &self.response
+    }
+
+    /// Return the certificate chain the peer presented during the TLS handshake, starting with its own (end-entity) certificate.
+    ///
+    /// On the server, this is the client's chain, which is only present when client authentication is enabled (see [`crate::ServerBuilder::client_auth`]).
+    /// The chain has already been verified against the configured roots, so authorize on its subject or SAN by parsing the DER (ex. with `x509-parser`).
+    /// On the client, this is the server's chain.
+    ///
+    /// This shadows [`quinn::Connection::peer_identity`], which remains available via [`Self::quic_connection`].
+    pub fn peer_identity(&self) -> Option<Vec<rustls::Certificate>> {
+        crate::peer::certificates(&self.conn)
+    }
+
+    /// Return the underlying QUIC connection, to reach Quinn APIs this crate doesn't wrap yet (ex. [`quinn::Connection::stats`]).
+    ///
+    /// This is an escape hatch, so use it with care:
+    ///   1. Streams and datagrams created on the connection lack the WebTransport header, so the peer won't associate them with the session.
+    ///   2. The connection may be shared with other sessions (see [`crate::Client::set_pooling`]), so closing it closes them too.
+    pub fn quic_connection(&self) -> &quinn::Connection {
+        &self.conn
+    }
+
+    /// Return the revision of the WebTransport draft used by the session, see [`crate::Compat`].
+    pub fn draft(&self) -> Draft {
+        self.draft
+    }
+
+    /// Return the largest field section (uncompressed headers) the peer accepts, from its SETTINGS_MAX_FIELD_SECTION_SIZE.
+    ///
+    /// This is `u64::MAX` if the peer didn't advertise a limit.
+    /// Our own limit is [`crate::MAX_FIELD_SECTION_SIZE`].
+    pub fn max_field_section_size(&self) -> u64 {
+        self.max_field_section_size
+    }
+
+    /// Return how many sessions the peer allows on the connection at once, from its SETTINGS_WEBTRANSPORT_MAX_SESSIONS.
+    ///
+    /// This is 1 for drafts that predate the setting. A client only shares a connection between sessions if the server allows more than one,
+    /// see [`crate::Client::set_pooling`].
+    pub fn max_sessions(&self) -> u64 {
+        self.max_sessions
+    }
+
+    /// Close the session with an error code and a human-readable reason, shown to the application in the browser.
+    ///
+    /// Unlike [`Self::close`], this waits for the peer to receive the CLOSE_WEBTRANSPORT_SESSION capsule,
+    /// so the browser can report the code and reason via `WebTransport.closed`.
+    /// The QUIC connection is then closed with the same reason, which is shown in devtools, unless it's shared with other sessions.
+    /// The reason is truncated to 1024 bytes.
+    pub async fn close_gracefully(&self, code: u32, reason: &str) {
+        self.state.send_capsule(&Capsule::close(code, reason));
+
+        // Ignore any errors, since we're closing anyway.
+        let finish = poll_fn(|_| self.state.poll_finish());
+        let _ = self.state.or_closed(self.state.waiter(), finish).await;
+
+        self.state.closed_locally(code, reason);
+
+        if self.mux.is_none() {
+            let code = webtransport_proto::error_to_http3(code).try_into().unwrap();
+            self.conn.close(code, reason.as_bytes());
+        }
+    }
+
+    /// Ask the peer to wrap up the session by sending a DRAIN_WEBTRANSPORT_SESSION capsule, ex. before the server shuts down.
+    ///
+    /// The session keeps working; the peer is expected to finish what it's doing and close it, see [`Self::draining`].
+    /// This waits until the capsule is written, and fails if the session is closed first.
+    pub async fn drain(&self) -> Result<(), SessionError> {
+        self.state.send_capsule(&Capsule::DrainWebTransportSession);
+        let write = poll_fn(|_| self.state.poll_flush());
+
+        self.state
+            .or_closed(self.state.waiter(), write)
+            .await?
+            .map_err(WebTransportError::from)?;
+
+        self.state.journal.draining(true);
+        Ok(())
     }
 
-    pub async fn read_datagram(&self) {
-        unimplemented!("datagrams")
+    /// Wait until the peer asks us to wrap up the session with a DRAIN_WEBTRANSPORT_SESSION capsule, or until the session is closed.
+    ///
+    /// The session keeps working afterwards, so stop starting new work and close it once the current work is done.
+    /// Like [`Self::closed`], this reads the CONNECT stream, so it needs to be polled to notice the capsule.
+    pub async fn draining(&self) {
+        // Waiters are woken when the capsule arrives, so this only needs to check the flag.
+        let drain = poll_fn(|_| match self.state.is_draining() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        });
+
+        self.state.or_closed(self.state.waiter(), drain).await.ok();
+    }
+
+    /// Returns true once the peer asked us to wrap up the session, see [`Self::draining`].
+    pub fn is_draining(&self) -> bool {
+        self.state.is_draining()
+    }
+
+    /// Wait until the peer sends an HTTP/3 GOAWAY on the control stream, ex. because the server is shutting down, or until the session is closed.
+    ///
+    /// The session keeps working, but the peer won't process any new requests on the connection, so connect new sessions elsewhere.
+    /// [`crate::Client`] already stops sharing the connection (see [`crate::Client::set_pooling`]).
+    pub async fn peer_going_away(&self) {
+        let settings = self.accept.lock().unwrap().settings.clone();
+        let goaway = settings.recv_goaway(u64::MAX);
+
+        self.state.or_closed(self.state.waiter(), goaway).await.ok();
     }
 
-    pub async fn send_datagram(&self) {
-        unimplemented!("datagrams")
+    /// Returns true once the peer sent an HTTP/3 GOAWAY, see [`Self::peer_going_away`].
+    pub fn is_peer_going_away(&self) -> bool {
+        self.accept.lock().unwrap().settings.is_going_away()
     }
 
-    pub fn max_datagram_size(&self) {
-        unimplemented!("datagrams")
+    /// Tell the client that no further requests on the connection will be processed with an HTTP/3 GOAWAY, see RFC 9114 section 5.2.
+    ///
+    /// The session keeps running. This is for servers that accept sessions themselves, since [`crate::Server::drain`] does it for every session.
+    /// Only the server processes requests, so this should not be used by a client.
+    pub async fn send_goaway(&self) {
+        self.goaway().send().await
     }
 
-    /// Immediately close the connection with an error code and reason. See [`quinn::Connection::close`].
-    pub fn close(&self, code: u32, reason: &[u8]) {
-        let code = webtransport_proto::error_to_http3(code).try_into().unwrap();
-        self.conn.close(code, reason)
+    /// Wait until the session is closed, returning who closed it and why.
+    ///
+    /// The session is also closed when the peer sends a CLOSE_WEBTRANSPORT_SESSION capsule, in which case the QUIC connection is closed for them.
+    /// The code and reason then come from the capsule, as if the peer closed the connection itself.
+    /// See [`Self::close_reason`] for the underlying error.
+    pub async fn closed(&self) -> SessionClose {
+        let err = self.closed_error().await;
+        SessionClose::new(&err, self.state.local())
     }
 
-    /// Wait until the session is closed, returning the error. See [`quinn::Connection::closed`].
-    pub async fn closed(&self) -> SessionError {
-        self.conn.closed().await.into()
+    // Wait until the session is closed, returning the error. See [`quinn::Connection::closed`].
+    pub(crate) async fn closed_error(&self) -> quinn::ConnectionError {
+        let res = self
+            .state
+            .or_closed(self.state.waiter(), self.conn.closed());
+        let (Ok(err) | Err(err)) = res.await;
+        self.state.journal.closed(&err, self.state.local());
+        err
     }
 
     /// Return why the session was closed, or None if it's not closed. See [`quinn::Connection::close_reason`].
     pub fn close_reason(&self) -> Option<SessionError> {
-        self.conn.close_reason().map(Into::into)
+        let err = self.state.reason().or_else(|| self.conn.close_reason())?;
+        self.state.journal.closed(&err, self.state.local());
+        Some(err.into())
+    }
+
+    /// Record a bounded journal of the session's events with the given capacity, or stop recording with None.
+    ///
+    /// The journal includes when the session was established, streams being opened, labeled, reset, or stopped, and the close reason.
+    /// Only the most recent `capacity` events are kept, so it's cheap enough to enable for every session.
+    pub fn set_journal(&self, capacity: Option<usize>) {
+        self.state.journal.enable(capacity)
+    }
+
+    /// Return the traffic of the session's streams for each label, see [`SendStream::set_label`].
+    pub fn label_stats(&self) -> HashMap<String, LabelStats> {
+        self.state.labels.stats()
+    }
+
+    /// Return a snapshot of the journal, or None if it's not enabled. See [`Self::set_journal`].
+    ///
+    /// The close reason is included once the session is closed, so call this after [`Self::closed`] for a postmortem.
+    pub fn journal(&self) -> Option<Journal> {
+        if let Some(err) = self.state.reason().or_else(|| self.conn.close_reason()) {
+            self.state.journal.closed(&err, self.state.local());
+        }
+
+        self.state.journal.journal()
+    }
+
+    // Report the session's events to the metrics and listener, starting with it being opened.
+    pub(crate) fn attach(&self, hooks: Hooks) {
+        if let Some(metrics) = hooks.metrics {
+            self.state.journal.set_metrics(metrics);
+        }
+
+        if let Some(listener) = hooks.listener {
+            // Hold the state weakly, so clones of the datagrams don't delay reporting the close when it's dropped.
+            let state = Arc::downgrade(&self.state);
+            self.datagrams.set_listener(Box::new(move |reason| {
+                if let Some(state) = state.upgrade() {
+                    state
+                        .journal
+                        .with_listener(|listener, info| listener.on_datagram_dropped(info, reason));
+                }
+            }));
+
+            self.state.journal.set_listener(listener);
+        }
+    }
+
+    // The stream ID of the CONNECT request, which is unique per connection.
+    pub(crate) fn session_id(&self) -> VarInt {
+        self.session_id
+    }
+
+    // Return a handle used to send a GOAWAY, without keeping the session alive.
+    pub(crate) fn goaway(&self) -> GoAway {
+        let session_id = self.accept.lock().unwrap().session_id;
+
+        GoAway {
+            accept: Arc::downgrade(&self.accept),
+            mux: self.mux.as_ref().map(Arc::downgrade),
+            // The next request the client could send, which we won't process.
+            id: VarInt::try_from(session_id.into_inner() + 4).unwrap(),
+        }
     }
 
     async fn write_full(send: &mut quinn::SendStream, buf: &[u8]) -> Result<(), SessionError> {
@@ -119,6 +777,50 @@ impl Session {
     }
 }
 
+// Sends a GOAWAY on the control stream of a session, if it still exists.
+#[derive(Clone)]
+pub(crate) struct GoAway {
+    accept: Weak<Mutex<SessionAccept>>,
+
+    // Set if the connection is shared, in which case requests after this session might have been processed too.
+    mux: Option<Weak<Mux>>,
+    id: VarInt,
+}
+
+impl GoAway {
+    pub async fn send(&self) {
+        let accept = match self.accept.upgrade() {
+            Some(accept) => accept,
+            None => return,
+        };
+
+        let id = match self.mux.as_ref().and_then(Weak::upgrade) {
+            Some(mux) => mux.next_request(),
+            None => self.id,
+        };
+
+        // Ignore any errors, since the session is closed.
+        let mut frame = Settings::goaway_frame(id);
+        let _ = poll_fn(|cx| {
+            let accept = accept.lock().unwrap();
+            accept.settings.poll_send_goaway(cx, &mut frame)
+        })
+        .await;
+    }
+
+    // Returns false once a session sharing its connection is closed or dropped.
+    // Otherwise the session lasts as long as the connection, so it's up to the caller to check that.
+    pub fn is_open(&self) -> bool {
+        if self.mux.is_none() {
+            return true;
+        }
+
+        self.accept
+            .upgrade()
+            .is_some_and(|accept| accept.lock().unwrap().state.reason().is_none())
+    }
+}
+
 impl Deref for Session {
     type Target = quinn::Connection;
 
@@ -134,34 +836,88 @@ type AcceptBi = dyn Stream<Item = Result<(quinn::SendStream, quinn::RecvStream),
 type PendingUni = dyn Future<Output = Result<(StreamUni, quinn::RecvStream), SessionError>> + Send;
 type PendingBi = dyn Future<Output = Result<Option<(SendStream, RecvStream)>, SessionError>> + Send;
 
+// Removes a waiter from the accept queue once it's done or dropped, so the next one takes over.
+struct Queued {
+    state: Arc<SessionState>,
+    handoff: Arc<Handoff>,
+    waiter: Waiter,
+}
+
+impl Queued {
+    fn new(state: Arc<SessionState>, handoff: Arc<Handoff>, waiter: Waiter) -> Self {
+        Self {
+            state,
+            handoff,
+            waiter,
+        }
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.handoff.remove(self.waiter);
+        self.state.remove(self.waiter);
+    }
+}
+
+// The accepts in progress through webtransport_generic, each with its own waiter like the async methods.
+// They're dropped once ready, or with the handle, and clones start without any so they don't share a waiter.
+#[derive(Default)]
+struct Polling {
+    uni: Option<Queued>,
+    bi: Option<Queued>,
+}
+
+impl Clone for Polling {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
 // Logic just for accepting streams, which is annoying because of the stream header.
 pub struct SessionAccept {
     session_id: VarInt,
 
-    // Keep a reference to the settings and connect stream to avoid closing them until dropped.
-    #[allow(dead_code)]
+    // Keep a reference to the settings to avoid closing the control streams until dropped.
     settings: Settings,
-    #[allow(dead_code)]
-    connect: Connect,
 
     // We also need to keep a reference to the qpack streams if the endpoint (incorrectly) creates them.
     // Again, this is just so they don't get closed until we drop the session.
     qpack_encoder: Option<quinn::RecvStream>,
     qpack_decoder: Option<quinn::RecvStream>,
 
+    // Used to serve plain HTTP/3 requests, if configured.
+    fallback: Option<Fallback>,
+
+    // Handed to each stream we accept.
+    sched: Arc<Sched>,
+    state: Arc<SessionState>,
+
     accept_uni: Pin<Box<AcceptUni>>,
     accept_bi: Pin<Box<AcceptBi>>,
 
+    // The tasks waiting to accept each type of stream, so only one of them is woken per stream.
+    handoff_uni: Arc<Handoff>,
+    handoff_bi: Arc<Handoff>,
+
+    // Set if the connection is shared, so streams are accepted by the mux instead.
+    mux: Option<Arc<Mux>>,
+
     // Keep track of work being done to read/write the WebTransport stream header.
     pending_uni: FuturesUnordered<Pin<Box<PendingUni>>>,
     pending_bi: FuturesUnordered<Pin<Box<PendingBi>>>,
 }
 
 impl SessionAccept {
-    pub(crate) fn new(conn: quinn::Connection, settings: Settings, connect: Connect) -> Self {
-        // The session ID is the stream ID of the CONNECT request.
-        let session_id = connect.session_id();
-
+    pub(crate) fn new(
+        conn: quinn::Connection,
+        settings: Settings,
+        session_id: VarInt,
+        fallback: Option<(Fallback, Vec<Serving>)>,
+        sched: Arc<Sched>,
+        state: Arc<SessionState>,
+        mux: Option<Arc<Mux>>,
+    ) -> Self {
         // Create a stream that just outputs new streams, so it's easy to call from poll.
         let accept_uni = Box::pin(futures::stream::unfold(conn.clone(), |conn| async {
             Some((conn.accept_uni().await, conn))
@@ -171,35 +927,94 @@ impl SessionAccept {
             Some((conn.accept_bi().await, conn))
         }));
 
+        // Finish serving any plain requests that arrived before the CONNECT.
+        let (fallback, serving) = match fallback {
+            Some((fallback, serving)) => (Some(fallback), serving),
+            None => (None, Vec::new()),
+        };
+
+        let pending_bi = serving
+            .into_iter()
+            .map(|serving| -> Pin<Box<PendingBi>> {
+                Box::pin(async move {
+                    serving.await;
+                    Ok(None)
+                })
+            })
+            .collect();
+
         Self {
             session_id,
 
             settings,
-            connect,
             qpack_decoder: None,
             qpack_encoder: None,
 
+            fallback,
+            sched,
+            state,
+
             accept_uni,
             accept_bi,
 
+            handoff_uni: Arc::default(),
+            handoff_bi: Arc::default(),
+
+            mux,
+
             pending_uni: FuturesUnordered::new(),
-            pending_bi: FuturesUnordered::new(),
+            pending_bi,
         }
     }
 
-    // This is poll-based because we accept and decode streams in parallel.
-    // In async land I would use tokio::JoinSet, but that requires a runtime.
-    // It's better to use FuturesUnordered instead because it's agnostic.
-    pub fn poll_accept_uni(
+    // Accept the next unidirectional stream, unless the session is closed first.
+    // The waiter is queued until it's ready, and Quinn only wakes the first waiter in the queue.
+    pub(crate) fn poll_accept_uni(
         &mut self,
+        waiter: Waiter,
         cx: &mut Context<'_>,
     ) -> Poll<Result<RecvStream, SessionError>> {
+        if let Some(err) = self.state.reason() {
+            self.handoff_uni.remove(waiter);
+            return Poll::Ready(Err(err.into()));
+        }
+
+        self.handoff_uni.register(waiter, cx.waker());
+        let waker = futures::task::waker(self.handoff_uni.clone());
+
+        if let Poll::Ready(res) = self.poll_next_uni(&mut Context::from_waker(&waker)) {
+            self.handoff_uni.remove(waiter);
+            return Poll::Ready(res);
+        }
+
+        // Every waiter is woken when the session is closed.
+        let err = ready!(self.state.poll_closed(waiter, cx));
+        self.handoff_uni.remove(waiter);
+        Poll::Ready(Err(err.into()))
+    }
+
+    // This is poll-based because we accept and decode streams in parallel.
+    // In async land I would use tokio::JoinSet, but that requires a runtime.
+    // It's better to use FuturesUnordered instead because it's agnostic.
+    fn poll_next_uni(&mut self, cx: &mut Context<'_>) -> Poll<Result<RecvStream, SessionError>> {
+        if let Some(mux) = &self.mux {
+            let recv = ready!(mux.poll_uni(self.session_id, cx))?;
+            self.state.journal.opened(recv.id(), false);
+            self.state.accepted(false, true);
+            return Poll::Ready(Ok(RecvStream::new(recv, self.state.clone())));
+        }
+
+        let mut coop = self.state.yield_budget.coop();
+
         loop {
+            // Yield if the peer keeps us busy with new or ignored streams.
+            ready!(coop.poll_proceed(cx));
+
             // Accept any new streams.
             if let Poll::Ready(Some(res)) = self.accept_uni.poll_next_unpin(cx) {
                 // Start decoding the header and add the future to the list of pending streams.
                 let recv = res?;
-                let pending = Self::decode_uni(recv, self.session_id);
+                let pending = Self::decode_uni(recv, self.session_id, self.state.clone());
                 self.pending_uni.push(Box::pin(pending));
 
                 continue;
@@ -214,7 +1029,9 @@ impl SessionAccept {
             // Decide if we keep looping based on the type.
             match typ {
                 StreamUni::WEBTRANSPORT => {
-                    let recv = RecvStream::new(recv);
+                    self.state.journal.opened(recv.id(), false);
+                    self.state.accepted(false, false);
+                    let recv = RecvStream::new(recv, self.state.clone());
                     return Poll::Ready(Ok(recv));
                 }
                 StreamUni::QPACK_DECODER => {
@@ -232,6 +1049,7 @@ impl SessionAccept {
     async fn decode_uni(
         mut recv: quinn::RecvStream,
         expected_session: VarInt,
+        state: Arc<SessionState>,
     ) -> Result<(StreamUni, quinn::RecvStream), SessionError> {
         // Read the VarInt at the start of the stream.
         let typ = Self::read_varint(&mut recv).await?;
@@ -241,6 +1059,7 @@ impl SessionAccept {
             // Read the session_id and validate it
             let session_id = Self::read_varint(&mut recv).await?;
             if session_id != expected_session {
+                state.journal.protocol_error(ProtocolError::UnknownSession);
                 return Err(WebTransportError::UnknownSession.into());
             }
         }
@@ -249,16 +1068,61 @@ impl SessionAccept {
         Ok((typ, recv))
     }
 
-    pub fn poll_accept_bi(
+    // Accept the next bidirectional stream, unless the session is closed first.
+    pub(crate) fn poll_accept_bi(
         &mut self,
+        waiter: Waiter,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(SendStream, RecvStream), SessionError>> {
+        if let Some(err) = self.state.reason() {
+            self.handoff_bi.remove(waiter);
+            return Poll::Ready(Err(err.into()));
+        }
+
+        self.handoff_bi.register(waiter, cx.waker());
+        let waker = futures::task::waker(self.handoff_bi.clone());
+
+        if let Poll::Ready(res) = self.poll_next_bi(&mut Context::from_waker(&waker)) {
+            self.handoff_bi.remove(waiter);
+            return Poll::Ready(res);
+        }
+
+        let err = ready!(self.state.poll_closed(waiter, cx));
+        self.handoff_bi.remove(waiter);
+        Poll::Ready(Err(err.into()))
+    }
+
+    fn poll_next_bi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(SendStream, RecvStream), SessionError>> {
+        if let Some(mux) = &self.mux {
+            let (send, recv) = ready!(mux.poll_bi(self.session_id, cx))?;
+            self.state.journal.opened(send.id(), false);
+            self.state.accepted(true, true);
+            let send = SendStream::new(send, self.sched.clone(), self.state.clone());
+            let recv = RecvStream::new(recv, self.state.clone());
+            return Poll::Ready(Ok((send, recv)));
+        }
+
+        let mut coop = self.state.yield_budget.coop();
+
         loop {
+            // Yield if the peer keeps us busy with new or ignored streams.
+            ready!(coop.poll_proceed(cx));
+
             // Accept any new streams.
             if let Poll::Ready(Some(res)) = self.accept_bi.poll_next_unpin(cx) {
                 // Start decoding the header and add the future to the list of pending streams.
                 let (send, recv) = res?;
-                let pending = Self::decode_bi(send, recv, self.session_id);
+                let pending = Self::decode_bi(
+                    send,
+                    recv,
+                    self.session_id,
+                    self.fallback.clone(),
+                    self.sched.clone(),
+                    self.state.clone(),
+                );
                 self.pending_bi.push(Box::pin(pending));
 
                 continue;
@@ -283,21 +1147,37 @@ impl SessionAccept {
         send: quinn::SendStream,
         mut recv: quinn::RecvStream,
         expected_session: VarInt,
+        fallback: Option<Fallback>,
+        sched: Arc<Sched>,
+        state: Arc<SessionState>,
     ) -> Result<Option<(SendStream, RecvStream)>, SessionError> {
         let typ = Self::read_varint(&mut recv).await?;
-        if Frame(typ) != Frame::WEBTRANSPORT {
-            return Ok(None);
+
+        match (Frame(typ), fallback) {
+            (Frame::WEBTRANSPORT, _) => {}
+            (Frame::HEADERS, Some(fallback)) => {
+                // Serve the plain HTTP/3 request, giving it back the frame type we already read.
+                let mut buf = Vec::new();
+                Frame::HEADERS.encode(&mut buf);
+                fallback.serve(buf, send, recv).await.ok();
+
+                return Ok(None);
+            }
+            _ => return Ok(None),
         }
 
         // Read the session ID and validate it.
         let session_id = Self::read_varint(&mut recv).await?;
         if session_id != expected_session {
+            state.journal.protocol_error(ProtocolError::UnknownSession);
             return Err(WebTransportError::UnknownSession.into());
         }
 
         // Wrap the streams in our own types for correct error codes.
-        let send = SendStream::new(send);
-        let recv = RecvStream::new(recv);
+        state.journal.opened(send.id(), false);
+        state.accepted(true, false);
+        let send = SendStream::new(send, sched, state.clone());
+        let recv = RecvStream::new(recv, state);
 
         Ok(Some((send, recv)))
     }
@@ -314,7 +1194,7 @@ impl SessionAccept {
     }
 
     // Read a varint from the stream.
-    async fn read_varint(recv: &mut quinn::RecvStream) -> Result<VarInt, SessionError> {
+    pub(crate) async fn read_varint(recv: &mut quinn::RecvStream) -> Result<VarInt, SessionError> {
         // 8 bytes is the max size of a varint
         let mut buf = [0; 8];
 
@@ -345,7 +1225,18 @@ impl webtransport_generic::Session for Session {
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Self::RecvStream, Self::Error>> {
-        self.accept.lock().unwrap().poll_accept_uni(cx)
+        let queued = self.polling.uni.get_or_insert_with(|| {
+            let handoff = self.accept.lock().unwrap().handoff_uni.clone();
+            Queued::new(self.state.clone(), handoff, self.state.waiter())
+        });
+
+        let res = ready!(self
+            .accept
+            .lock()
+            .unwrap()
+            .poll_accept_uni(queued.waiter, cx));
+        self.polling.uni = None;
+        Poll::Ready(res)
     }
 
     /// Accept an incoming bidirectional stream
@@ -355,7 +1246,18 @@ impl webtransport_generic::Session for Session {
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(Self::SendStream, Self::RecvStream), Self::Error>> {
-        self.accept.lock().unwrap().poll_accept_bi(cx)
+        let queued = self.polling.bi.get_or_insert_with(|| {
+            let handoff = self.accept.lock().unwrap().handoff_bi.clone();
+            Queued::new(self.state.clone(), handoff, self.state.waiter())
+        });
+
+        let res = ready!(self
+            .accept
+            .lock()
+            .unwrap()
+            .poll_accept_bi(queued.waiter, cx));
+        self.polling.bi = None;
+        Poll::Ready(res)
     }
 
     /// Poll the connection to create a new bidirectional stream.
@@ -376,6 +1278,6 @@ impl webtransport_generic::Session for Session {
 
     /// Close the connection immediately
     fn close(&mut self, code: u32, reason: &[u8]) {
-        Session::close(self, code, reason)
+        Session::close(self, code, &String::from_utf8_lossy(reason))
     }
 }
@@ -1,8 +1,22 @@
+use bytes::{Buf, Bytes};
 use futures::try_join;
-use std::io;
+use std::{
+    future::{poll_fn, Future},
+    io,
+    pin::pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
 
 use thiserror::Error;
 
+use webtransport_proto::{Setting, VarInt};
+
+use crate::{state::Wakers, Compat, Draft, SessionLimits};
+
+/// The largest field section (uncompressed headers) we accept, advertised with SETTINGS_MAX_FIELD_SECTION_SIZE.
+pub const MAX_FIELD_SECTION_SIZE: u64 = 16 * 1024;
+
 #[derive(Error, Debug)]
 pub enum SettingsError {
     #[error("quic stream was closed early")]
@@ -24,27 +38,205 @@ pub enum SettingsError {
     WriteError(#[from] quinn::WriteError),
 }
 
+// Cloned by each session on the connection, since there's only one pair of control streams.
+#[derive(Clone)]
 pub struct Settings {
-    // A reference to the send/recv stream, so we don't close it until dropped.
-    #[allow(dead_code)]
-    send: quinn::SendStream,
+    // The draft used for the session, detected from the peer's settings unless forced.
+    draft: Draft,
+
+    // The largest field section the peer accepts, which is unlimited unless advertised.
+    max_field_section_size: u64,
+
+    // The number of sessions the peer allows on the connection, at least 1.
+    max_sessions: u64,
+
+    // Our limits and the peer's for each session, if both endpoints advertised them.
+    limits: Option<(SessionLimits, SessionLimits)>,
+
+    control: Arc<Mutex<Control>>,
+
+    // Every session waiting for a GOAWAY, since they share the control stream.
+    wakers: Arc<Wakers>,
+}
 
-    #[allow(dead_code)]
+struct Control {
+    // The control streams, which must not be closed until the connection is.
+    send: quinn::SendStream,
     recv: quinn::RecvStream,
+
+    // Any data received after the peer's SETTINGS, containing the next control frame.
+    buf: Vec<u8>,
+
+    // The ID from the latest GOAWAY received, if any.
+    goaway: Option<VarInt>,
 }
 
 impl Settings {
-    // Establish the H3 connection.
-    pub async fn connect(conn: &quinn::Connection) -> Result<Self, SettingsError> {
+    // Establish the H3 connection, advertising the number of sessions we allow on it and the limits of each, if any.
+    pub async fn connect(
+        conn: &quinn::Connection,
+        compat: Compat,
+        max_sessions: u32,
+        limits: Option<SessionLimits>,
+    ) -> Result<Self, SettingsError> {
+        let limits = limits.map(SessionLimits::clamp);
+
         let recv = Self::accept(conn);
-        let send = Self::open(conn);
+        let send = Self::open(conn, compat, max_sessions, limits);
 
         // Run both tasks concurrently until one errors or they both complete.
-        let (send, recv) = try_join!(send, recv)?;
-        Ok(Self { send, recv })
+        let (send, (recv, settings, buf)) = try_join!(send, recv)?;
+        Self::new(compat, limits, send, recv, settings, buf)
     }
 
-    async fn accept(conn: &quinn::Connection) -> Result<quinn::RecvStream, SettingsError> {
+    // Interpret the peer's SETTINGS, keeping the control streams and anything received after the SETTINGS.
+    fn new(
+        compat: Compat,
+        limits: Option<SessionLimits>,
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+        settings: webtransport_proto::Settings,
+        buf: Vec<u8>,
+    ) -> Result<Self, SettingsError> {
+        let draft = settings
+            .draft()
+            .ok_or(SettingsError::WebTransportUnsupported)?;
+        let draft = compat.resolve(draft);
+
+        let max_field_section_size = settings
+            .get(&Setting::MAX_FIELD_SECTION_SIZE)
+            .map(|max| max.into_inner())
+            .unwrap_or(u64::MAX);
+
+        let max_sessions = settings.supports_webtransport().max(1);
+        let limits = limits.zip(Self::limits(&settings));
+
+        let control = Control {
+            send,
+            recv,
+            buf,
+            goaway: None,
+        };
+
+        Ok(Self {
+            draft,
+            max_field_section_size,
+            max_sessions,
+            limits,
+            control: Arc::new(Mutex::new(control)),
+            wakers: Arc::default(),
+        })
+    }
+
+    // Establish the H3 connection like connect, but with our SETTINGS sent in 0-RTT.
+    // They're sent again once the handshake completes if the server rejected the 0-RTT data, which discards them.
+    // The server's SETTINGS aren't affected, so they're received either way.
+    pub async fn connect_early<F: Future<Output = bool>>(
+        conn: &quinn::Connection,
+        compat: Compat,
+        max_sessions: u32,
+        limits: Option<SessionLimits>,
+        accepted: F,
+    ) -> Result<Self, SettingsError> {
+        let limits = limits.map(SessionLimits::clamp);
+
+        let recv = Self::accept(conn);
+        let send = async {
+            let send = Self::open(conn, compat, max_sessions, limits).await;
+            match accepted.await {
+                true => send,
+                false => Self::open(conn, compat, max_sessions, limits).await,
+            }
+        };
+
+        let (send, (recv, settings, buf)) = try_join!(send, recv)?;
+        Self::new(compat, limits, send, recv, settings, buf)
+    }
+
+    pub fn draft(&self) -> Draft {
+        self.draft
+    }
+
+    // The peer's SETTINGS_MAX_FIELD_SECTION_SIZE, which our HEADERS must not exceed.
+    pub fn max_field_section_size(&self) -> u64 {
+        self.max_field_section_size
+    }
+
+    // The peer's SETTINGS_WEBTRANSPORT_MAX_SESSIONS, which is 1 for older drafts.
+    pub fn max_sessions(&self) -> u64 {
+        self.max_sessions
+    }
+
+    // Our limits and the peer's for each session, which are only enforced if both endpoints advertised them.
+    pub fn session_limits(&self) -> Option<(SessionLimits, SessionLimits)> {
+        self.limits
+    }
+
+    // Returns true if the peer sent a GOAWAY, without waiting for one.
+    pub fn is_going_away(&self) -> bool {
+        // Read any control frames that already arrived, without replacing the waker of any session waiting for one.
+        let waker = futures::task::waker(self.wakers.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut control = self.control.lock().unwrap();
+        while let Poll::Ready(Ok(_)) = control.poll_goaway(&mut cx) {}
+
+        control.goaway.is_some()
+    }
+
+    // Wait for a GOAWAY from the peer that refuses the request with the given ID, returning the ID of the first request it won't process.
+    // Any other frames on the control stream are ignored.
+    pub async fn recv_goaway(&self, id: u64) -> Result<VarInt, SettingsError> {
+        poll_fn(|cx| {
+            self.wakers.register(cx.waker());
+            let waker = futures::task::waker(self.wakers.clone());
+            let mut cx = Context::from_waker(&waker);
+
+            let mut control = self.control.lock().unwrap();
+
+            loop {
+                // The ID can only decrease, so a GOAWAY that already arrived is still valid.
+                if let Some(goaway) = control.goaway.filter(|goaway| goaway.into_inner() <= id) {
+                    return Poll::Ready(Ok(goaway));
+                }
+
+                ready!(control.poll_goaway(&mut cx))?;
+            }
+        })
+        .await
+    }
+
+    // Send a GOAWAY with the ID of the first request we won't process.
+    pub async fn send_goaway(&self, id: VarInt) -> Result<(), quinn::WriteError> {
+        let mut frame = Self::goaway_frame(id);
+        poll_fn(|cx| self.poll_send_goaway(cx, &mut frame)).await
+    }
+
+    // Write the encoded GOAWAY frame, advancing it as it's written.
+    pub fn poll_send_goaway(
+        &self,
+        cx: &mut Context<'_>,
+        frame: &mut Bytes,
+    ) -> Poll<Result<(), quinn::WriteError>> {
+        let mut control = self.control.lock().unwrap();
+
+        while frame.has_remaining() {
+            let size = ready!(pin!(control.send.write(frame)).poll(cx))?;
+            frame.advance(size);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    pub fn goaway_frame(id: VarInt) -> Bytes {
+        let mut buf = Vec::new();
+        webtransport_proto::GoAway { id }.encode(&mut buf);
+        buf.into()
+    }
+
+    async fn accept(
+        conn: &quinn::Connection,
+    ) -> Result<(quinn::RecvStream, webtransport_proto::Settings, Vec<u8>), SettingsError> {
         let mut recv = conn.accept_uni().await?;
         let mut buf = Vec::new();
 
@@ -63,17 +255,58 @@ impl Settings {
                 Err(e) => return Err(e.into()),
             };
 
-            if settings.supports_webtransport() == 0 {
-                return Err(SettingsError::WebTransportUnsupported);
-            }
+            // Keep anything after the SETTINGS frame, which could be a GOAWAY.
+            let size = limit.position() as usize;
+            buf.drain(..size);
 
-            return Ok(recv);
+            return Ok((recv, settings, buf));
         }
     }
 
-    async fn open(conn: &quinn::Connection) -> Result<quinn::SendStream, SettingsError> {
+    // The peer's limits for each session, if it advertised any of them.
+    // A limit that's missing is 0, see draft-ietf-webtrans-http3 section 5.
+    fn limits(settings: &webtransport_proto::Settings) -> Option<SessionLimits> {
+        let get = |setting| settings.get(&setting).map(|max| max.into_inner());
+        let max_streams_uni = get(Setting::WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI);
+        let max_streams_bidi = get(Setting::WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI);
+        let max_data = get(Setting::WEBTRANSPORT_INITIAL_MAX_DATA);
+
+        if max_streams_uni.is_none() && max_streams_bidi.is_none() && max_data.is_none() {
+            return None;
+        }
+
+        Some(SessionLimits {
+            max_streams_uni: max_streams_uni.unwrap_or(0),
+            max_streams_bidi: max_streams_bidi.unwrap_or(0),
+            max_data: max_data.unwrap_or(0),
+        })
+    }
+
+    async fn open(
+        conn: &quinn::Connection,
+        compat: Compat,
+        max_sessions: u32,
+        limits: Option<SessionLimits>,
+    ) -> Result<quinn::SendStream, SettingsError> {
         let mut settings = webtransport_proto::Settings::default();
-        settings.enable_webtransport(1);
+        settings.enable_webtransport(max_sessions, compat.advertise());
+        settings.insert(
+            Setting::MAX_FIELD_SECTION_SIZE,
+            VarInt::try_from(MAX_FIELD_SECTION_SIZE).unwrap(),
+        );
+
+        if let Some(limits) = limits {
+            let max = |limit| VarInt::from_u64(limit).unwrap();
+            settings.insert(
+                Setting::WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI,
+                max(limits.max_streams_uni),
+            );
+            settings.insert(
+                Setting::WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI,
+                max(limits.max_streams_bidi),
+            );
+            settings.insert(Setting::WEBTRANSPORT_INITIAL_MAX_DATA, max(limits.max_data));
+        }
 
         let mut buf = Vec::new();
         settings.encode(&mut buf);
@@ -84,3 +317,31 @@ impl Settings {
         Ok(send)
     }
 }
+
+impl Control {
+    fn poll_goaway(&mut self, cx: &mut Context<'_>) -> Poll<Result<VarInt, SettingsError>> {
+        loop {
+            let mut limit = io::Cursor::new(&self.buf);
+
+            match webtransport_proto::GoAway::decode(&mut limit) {
+                Ok(goaway) => {
+                    let size = limit.position() as usize;
+                    self.buf.drain(..size);
+
+                    if let Some(goaway) = goaway {
+                        self.goaway = Some(goaway.id);
+                        return Poll::Ready(Ok(goaway.id));
+                    }
+
+                    continue;
+                }
+                Err(webtransport_proto::SettingsError::UnexpectedEnd) => {} // More data needed.
+                Err(e) => return Poll::Ready(Err(e.into())),
+            }
+
+            let chunk = ready!(pin!(self.recv.read_chunk(usize::MAX, true)).poll(cx))?;
+            let chunk = chunk.ok_or(SettingsError::UnexpectedEnd)?;
+            self.buf.extend_from_slice(&chunk.bytes);
+        }
+    }
+}
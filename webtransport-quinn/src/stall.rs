@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// What to do with a stream that stays blocked for too long, see [`StallPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallAction {
+    /// Reset the stream with the given error code.
+    Reset(u32),
+
+    /// Close the entire session with the given error code.
+    Close(u32),
+}
+
+/// Detects slow receivers: streams whose writes stay blocked, see [`crate::Session::blocked`].
+///
+/// This only applies while a write is pending, so a stream that isn't being written to is never considered stalled.
+/// See [`crate::Session::set_stall_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StallPolicy {
+    /// How long a write may be blocked before the action is taken.
+    pub timeout: Duration,
+
+    /// The action to take.
+    pub action: StallAction,
+}
+
+/// The amount of time the streams in a session spent blocked, see [`crate::Session::blocked`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockedStats {
+    /// The total time spent blocked, summed over every stream.
+    pub total: Duration,
+
+    /// The number of streams that are currently blocked.
+    pub streams: usize,
+
+    /// The longest time that any stream has currently been blocked.
+    pub longest: Duration,
+}
+
+// Keeps track of which streams in a session are blocked.
+#[derive(Default)]
+pub(crate) struct Blocked {
+    since: HashMap<quinn::StreamId, Instant>,
+    total: Duration,
+}
+
+impl Blocked {
+    pub fn block(&mut self, id: quinn::StreamId, since: Instant) {
+        self.since.insert(id, since);
+    }
+
+    pub fn unblock(&mut self, id: quinn::StreamId, now: Instant) {
+        if let Some(since) = self.since.remove(&id) {
+            self.total += now.saturating_duration_since(since);
+        }
+    }
+
+    pub fn stats(&self, now: Instant) -> BlockedStats {
+        let current = self
+            .since
+            .values()
+            .map(|since| now.saturating_duration_since(*since));
+
+        BlockedStats {
+            total: self.total + current.clone().sum::<Duration>(),
+            streams: self.since.len(),
+            longest: current.max().unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{ready, Context, Poll, Waker},
+};
+
+use bytes::Bytes;
+use futures::task::ArcWake;
+use webtransport_proto::{Capsule, CapsuleReader};
+
+use crate::{
+    coop::YieldBudget, flow::Flow, journal::Recorder, labels::Labels, Clock, ProtocolError,
+};
+
+// The HTTP/3 error code used to close a session that exceeded our flow control limits.
+const WT_FLOW_CONTROL_ERROR: quinn::VarInt = quinn::VarInt::from_u32(0x045d4487);
+
+// Something waiting on the session, which is woken when it's closed.
+// Each one is only polled by one task at a time, so it only needs to remember the latest waker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Waiter {
+    Send(quinn::StreamId),
+    Recv(quinn::StreamId),
+    Op(u64),
+}
+
+// The state shared by a session and all of its streams.
+//
+// The peer can close the session with a CLOSE_WEBTRANSPORT_SESSION capsule (or by finishing the CONNECT stream) without closing the QUIC connection.
+// We don't spawn a task to watch for that, so instead any pending operation reads the CONNECT stream while it waits.
+// When the session closes for any reason, the reason is recorded and every waiter is woken,
+// including writes waiting on the scheduler or a rate limit, which Quinn doesn't know about.
+// Capsules we send (ex. raising the peer's flow control limits) are queued here too, and written by whoever polls next.
+pub(crate) struct SessionState {
+    conn: quinn::Connection,
+
+    // Records what happened during the session, if enabled.
+    pub journal: Recorder,
+
+    // The labels attached to streams.
+    pub labels: Labels,
+
+    // Used for timeouts, so tests can control time.
+    pub clock: Arc<dyn Clock>,
+
+    // How much work the session's internal loops do before yielding to the runtime.
+    pub yield_budget: YieldBudget,
+
+    // Why the session was closed, set before the QUIC connection is closed.
+    reason: OnceLock<quinn::ConnectionError>,
+
+    // The code and reason we closed the session with, if we did.
+    local: OnceLock<(u32, String)>,
+
+    // Set once the peer sent a DRAIN_WEBTRANSPORT_SESSION capsule.
+    draining: AtomicBool,
+
+    // Whether the session owns the QUIC connection, so closing one closes the other.
+    exclusive: bool,
+
+    // The receive side of the CONNECT stream and any partial frame or capsule.
+    watch: Mutex<Watch>,
+
+    // The send side of the CONNECT stream and any capsules that weren't written yet.
+    outgoing: Mutex<Outgoing>,
+
+    // The session's flow control, if both endpoints advertised limits.
+    flow: Option<Flow>,
+
+    waiters: Arc<Waiters>,
+    next_op: AtomicU64,
+}
+
+struct Watch {
+    recv: quinn::RecvStream,
+    reader: CapsuleReader,
+}
+
+struct Outgoing {
+    send: quinn::SendStream,
+    buf: Vec<u8>,
+}
+
+// The wakers of everything waiting on the session, handed to Quinn as a single waker for the CONNECT stream.
+#[derive(Default)]
+struct Waiters {
+    wakers: Mutex<HashMap<Waiter, Waker>>,
+}
+
+impl ArcWake for Waiters {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let wakers = std::mem::take(&mut *arc_self.wakers.lock().unwrap());
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+}
+
+// Every task waiting on a shared resource (ex. a connection's streams), handed to Quinn as a single waker that wakes all of them.
+// Each task is only woken once per registration, since they're forgotten when woken.
+#[derive(Default)]
+pub(crate) struct Wakers {
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl ArcWake for Wakers {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let wakers = std::mem::take(&mut *arc_self.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Wakers {
+    // Wake the task the next time the resource is woken, unless it's already registered.
+    pub fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+// Tasks waiting to accept a stream, handed to Quinn as a single waker that only wakes the first of them.
+//
+// Every incoming stream would otherwise wake every task waiting to accept one, although only one of them can take it.
+// Once the first task is done (or gives up), the next one is woken in case there's another stream ready for it.
+#[derive(Default)]
+pub(crate) struct Handoff {
+    queue: Mutex<VecDeque<(Waiter, Waker)>>,
+}
+
+impl ArcWake for Handoff {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some((_, waker)) = arc_self.queue.lock().unwrap().front() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl Handoff {
+    // Add the waiter to the back of the queue, or update its waker if it's already queued.
+    pub fn register(&self, waiter: Waiter, waker: &Waker) {
+        let mut queue = self.queue.lock().unwrap();
+
+        match queue.iter_mut().find(|(queued, _)| *queued == waiter) {
+            Some((_, queued)) => queued.clone_from(waker),
+            None => queue.push_back((waiter, waker.clone())),
+        }
+    }
+
+    // Remove the waiter from the queue, waking the next one if it was first.
+    pub fn remove(&self, waiter: Waiter) {
+        let mut queue = self.queue.lock().unwrap();
+
+        let first = queue.front().is_some_and(|(queued, _)| *queued == waiter);
+        queue.retain(|(queued, _)| *queued != waiter);
+
+        if first {
+            if let Some((_, waker)) = queue.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl SessionState {
+    pub fn new(
+        conn: quinn::Connection,
+        connect: (quinn::SendStream, quinn::RecvStream),
+        journal: Recorder,
+        clock: Arc<dyn Clock>,
+        exclusive: bool,
+        flow: Option<Flow>,
+    ) -> Self {
+        let (send, recv) = connect;
+
+        Self {
+            conn,
+            journal,
+            labels: Labels::default(),
+            clock,
+            yield_budget: YieldBudget::default(),
+            reason: OnceLock::new(),
+            local: OnceLock::new(),
+            draining: AtomicBool::new(false),
+            exclusive,
+            watch: Mutex::new(Watch {
+                recv,
+                reader: CapsuleReader::default(),
+            }),
+            outgoing: Mutex::new(Outgoing {
+                send,
+                buf: Vec::new(),
+            }),
+            flow,
+            waiters: Default::default(),
+            next_op: AtomicU64::new(0),
+        }
+    }
+
+    // Return why the session was closed, if we know yet.
+    pub fn reason(&self) -> Option<quinn::ConnectionError> {
+        self.reason.get().cloned()
+    }
+
+    // Record why the session was closed and wake everything waiting on it, returning the first reason recorded.
+    pub fn closed(&self, err: quinn::ConnectionError) -> quinn::ConnectionError {
+        let err = self.reason.get_or_init(|| err).clone();
+        Waiters::wake_by_ref(&self.waiters);
+        err
+    }
+
+    // Record that we closed the session with the given code and reason, unless it was already closed.
+    pub fn closed_locally(&self, code: u32, reason: &str) {
+        self.local.get_or_init(|| (code, reason.to_string()));
+        self.closed(quinn::ConnectionError::LocallyClosed);
+    }
+
+    // Return the code and reason we closed the session with, if we did.
+    pub fn local(&self) -> Option<(u32, String)> {
+        self.local.get().cloned()
+    }
+
+    // Return true once the peer asked us to wrap up the session.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    // Return a new waiter for a session-level operation, like opening a stream.
+    pub fn waiter(&self) -> Waiter {
+        Waiter::Op(self.next_op.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // Stop waking the waiter, ex. because the stream was dropped.
+    pub fn remove(&self, waiter: Waiter) {
+        self.waiters.wakers.lock().unwrap().remove(&waiter);
+    }
+
+    // Wait until the session is closed, returning the reason.
+    pub fn poll_closed(
+        &self,
+        waiter: Waiter,
+        cx: &mut Context<'_>,
+    ) -> Poll<quinn::ConnectionError> {
+        if let Some(err) = self.reason() {
+            return Poll::Ready(err);
+        }
+
+        self.waiters
+            .wakers
+            .lock()
+            .unwrap()
+            .insert(waiter, cx.waker().clone());
+
+        // Register a waker that wakes every waiter, since only one task can be polling the CONNECT stream.
+        let waker = futures::task::waker(self.waiters.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        // Write any capsules that didn't fit earlier; errors mean the session is closing anyway.
+        let _ = self.outgoing.lock().unwrap().poll_flush(&mut cx);
+
+        let mut watch = self.watch.lock().unwrap();
+
+        loop {
+            let chunk = ready!(pin!(watch.recv.read_chunk(usize::MAX, true)).poll(&mut cx));
+
+            let err = match chunk {
+                Ok(Some(chunk)) => {
+                    let mut capsules = Vec::new();
+                    if watch.reader.decode(&chunk.bytes, &mut capsules).is_err() {
+                        // An invalid capsule closes the session without a reason.
+                        self.journal.protocol_error(ProtocolError::InvalidCapsule);
+                        self.close(0, "")
+                    } else {
+                        match self.handle(capsules) {
+                            Some((code, reason)) => self.close(code, &reason),
+                            None => continue,
+                        }
+                    }
+                }
+                // Finishing or resetting the CONNECT stream closes the session without a reason.
+                Ok(None) | Err(quinn::ReadError::Reset(_)) => self.close(0, ""),
+                Err(quinn::ReadError::ConnectionLost(err)) => self.closed(err),
+                Err(_) => self.close(0, ""),
+            };
+
+            return Poll::Ready(err);
+        }
+    }
+
+    // Run the future until it's done, unless the session is closed first.
+    // The close reason takes priority, so the operation doesn't fail with a less useful error because we closed the connection.
+    pub async fn or_closed<F: Future>(
+        &self,
+        waiter: Waiter,
+        fut: F,
+    ) -> Result<F::Output, quinn::ConnectionError> {
+        let mut fut = pin!(fut);
+        let _registered = Registered {
+            state: self,
+            waiter,
+        };
+
+        poll_fn(|cx| {
+            if let Some(err) = self.reason() {
+                return Poll::Ready(Err(err));
+            }
+
+            if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+                return Poll::Ready(self.reason().map_or(Ok(res), Err));
+            }
+
+            self.poll_closed(waiter, cx).map(Err)
+        })
+        .await
+    }
+
+    // Queue a capsule (in a DATA frame) on the CONNECT stream and write as much as we can without blocking, returning true if it was all written.
+    // Anything left is written by the next task to poll the session.
+    pub fn send_capsule(&self, capsule: &Capsule) -> bool {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        capsule.encode_frame(&mut outgoing.buf);
+
+        let waker = futures::task::waker(self.waiters.clone());
+        let _ = outgoing.poll_flush(&mut Context::from_waker(&waker));
+
+        outgoing.buf.is_empty()
+    }
+
+    // Reset the CONNECT stream, which closes the session, dropping any capsules that weren't written yet.
+    pub fn reset(&self, code: quinn::VarInt) {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        outgoing.buf.clear();
+        let _ = outgoing.send.reset(code);
+    }
+
+    // Wait until every queued capsule is written.
+    // The task is only woken if it's registered as a waiter, so this must be polled via or_closed.
+    pub fn poll_flush(&self) -> Poll<Result<(), quinn::WriteError>> {
+        let waker = futures::task::waker(self.waiters.clone());
+        self.outgoing
+            .lock()
+            .unwrap()
+            .poll_flush(&mut Context::from_waker(&waker))
+    }
+
+    // Like poll_flush, but then finish the CONNECT stream and wait until the peer has acknowledged it.
+    pub fn poll_finish(&self) -> Poll<Result<(), quinn::WriteError>> {
+        let waker = futures::task::waker(self.waiters.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut outgoing = self.outgoing.lock().unwrap();
+        ready!(outgoing.poll_flush(&mut cx))?;
+        outgoing.send.poll_finish(&mut cx)
+    }
+
+    // Wait until the peer lets us open another stream, telling it if we're blocked.
+    // Every waiter is woken when the peer raises the limit, so this must be polled via or_closed.
+    pub fn poll_open(&self, bidi: bool) -> Poll<()> {
+        let flow = match &self.flow {
+            Some(flow) => flow,
+            None => return Poll::Ready(()),
+        };
+
+        match flow.open(bidi) {
+            Ok(()) => Poll::Ready(()),
+            Err(blocked) => {
+                if let Some(capsule) = blocked {
+                    self.send_capsule(&capsule);
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    // Returns true if writes have to reserve the session's flow control credit.
+    pub fn is_flow_controlled(&self) -> bool {
+        self.flow.is_some()
+    }
+
+    // Wait until the peer lets us send more data, returning how much of the given size we can send, see poll_open.
+    // Anything that isn't written must be refunded.
+    pub fn poll_reserve(&self, size: usize) -> Poll<usize> {
+        let flow = match &self.flow {
+            Some(flow) => flow,
+            None => return Poll::Ready(size),
+        };
+
+        match flow.reserve(size) {
+            Ok(size) => Poll::Ready(size),
+            Err(blocked) => {
+                if let Some(capsule) = blocked {
+                    self.send_capsule(&capsule);
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    // Return flow control credit that was reserved but not written.
+    pub fn refund(&self, size: usize) {
+        if let Some(flow) = &self.flow {
+            flow.refund(size);
+        }
+    }
+
+    // Count a stream opened by the peer, closing the session if it exceeded our limit.
+    pub fn peer_opened(&self, bidi: bool) {
+        if self.flow.as_ref().is_some_and(|flow| !flow.opened(bidi)) {
+            self.violated();
+        }
+    }
+
+    // Count a stream accepted by the application, raising the peer's limit if needed.
+    // Streams are counted as opened here too, unless they were already counted when routed to a shared session.
+    pub fn accepted(&self, bidi: bool, routed: bool) {
+        if !routed {
+            self.peer_opened(bidi);
+        }
+
+        if let Some(capsule) = self.flow.as_ref().and_then(|flow| flow.accepted(bidi)) {
+            self.send_capsule(&capsule);
+        }
+    }
+
+    // Count data read by the application, raising the peer's limit if needed or closing the session if it exceeded it.
+    pub fn received(&self, size: usize) {
+        let flow = match &self.flow {
+            Some(flow) => flow,
+            None => return,
+        };
+
+        match flow.received(size) {
+            Ok(Some(capsule)) => {
+                self.send_capsule(&capsule);
+            }
+            Ok(None) => {}
+            Err(()) => self.violated(),
+        }
+    }
+
+    // Close the session because the peer exceeded our flow control limits, by resetting the CONNECT stream.
+    fn violated(&self) {
+        self.journal.protocol_error(ProtocolError::FlowControl);
+        self.closed_locally(0, "flow control error");
+
+        self.outgoing
+            .lock()
+            .unwrap()
+            .send
+            .reset(WT_FLOW_CONTROL_ERROR)
+            .ok();
+        self.watch
+            .lock()
+            .unwrap()
+            .recv
+            .stop(WT_FLOW_CONTROL_ERROR)
+            .ok();
+
+        if self.exclusive {
+            self.conn
+                .close(WT_FLOW_CONTROL_ERROR, b"flow control error");
+        }
+    }
+
+    // Close the QUIC connection on behalf of the peer if the session owns it, so the application only has to watch the connection.
+    fn close(&self, code: u32, reason: &str) -> quinn::ConnectionError {
+        let error_code = webtransport_proto::error_to_http3(code).try_into().unwrap();
+        let err = quinn::ConnectionError::ApplicationClosed(quinn::ApplicationClose {
+            error_code,
+            reason: Bytes::copy_from_slice(reason.as_bytes()),
+        });
+
+        let err = self.closed(err);
+        if self.exclusive {
+            self.conn.close(error_code, reason.as_bytes());
+        }
+        err
+    }
+
+    // Handle the capsules read from the CONNECT stream, returning the code and reason once the session is closed.
+    // A drain or a raised flow control limit wakes every waiter, and other capsules are ignored.
+    fn handle(&self, capsules: Vec<Capsule>) -> Option<(u32, String)> {
+        for capsule in capsules {
+            if let Capsule::CloseWebTransportSession { code, reason } = capsule {
+                return Some((code, reason));
+            }
+
+            if capsule == Capsule::DrainWebTransportSession
+                && !self.draining.swap(true, Ordering::Relaxed)
+            {
+                self.journal.draining(false);
+                Waiters::wake_by_ref(&self.waiters);
+            }
+
+            if self.flow.as_ref().is_some_and(|flow| flow.update(&capsule)) {
+                Waiters::wake_by_ref(&self.waiters);
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for SessionState {
+    fn drop(&mut self) {
+        // Report the close even if nobody waited for it, since this is the last chance.
+        let reason = self.reason().or_else(|| self.conn.close_reason());
+        let reason = reason.unwrap_or(quinn::ConnectionError::LocallyClosed);
+        self.journal.closed(&reason, self.local());
+    }
+}
+
+impl Outgoing {
+    // Write the queued capsules, consuming them as they're written.
+    // Anything that can't be written is dropped on error, since the session is closing anyway.
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), quinn::WriteError>> {
+        while !self.buf.is_empty() {
+            let res = ready!(pin!(self.send.write(&self.buf)).poll(cx));
+            match res {
+                Ok(size) => {
+                    self.buf.drain(..size);
+                }
+                Err(err) => {
+                    self.buf.clear();
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Stops waking the waiter once the operation is done or dropped.
+struct Registered<'a> {
+    state: &'a SessionState,
+    waiter: Waiter,
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        self.state.remove(self.waiter);
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{session::GoAway, Session};
+
+/// A snapshot of the sessions handled by a [`crate::Server`], see [`crate::Server::stats`].
+///
+/// The counters are cumulative since the server was created, except for `open`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Sessions accepted by the application, see [`crate::Request::ok`].
+    pub accepted: u64,
+
+    /// Sessions rejected by the application, see [`crate::Request::close`].
+    pub rejected: u64,
+
+    /// Sessions refused because the server was at its limit, see [`crate::Server::set_max_sessions`].
+    pub refused_limit: u64,
+
+    /// Connections refused by the filter, see [`crate::Server::set_filter`].
+    pub refused_filter: u64,
+
+    /// Sessions refused because the server was draining, see [`crate::Server::drain`].
+    pub refused_draining: u64,
+
+    /// Sessions refused with a 403 because of their origin, see [`crate::Server::set_origin_policy`].
+    pub refused_origin: u64,
+
+    /// Sessions refused with a 401 or 403 by the authorizer, see [`crate::Server::set_authorizer`].
+    pub refused_auth: u64,
+
+    /// Connections that failed the QUIC or WebTransport handshake.
+    pub refused_handshake: u64,
+
+    /// Sessions that were accepted and are still open.
+    pub open: u64,
+}
+
+/// A snapshot of the QUIC connection carrying a session, see [`Session::stats`].
+///
+/// The counters are cumulative for the connection, so they include every session when it's shared (see [`crate::Client::set_pooling`]).
+/// The bytes include QUIC and UDP overhead, like [`crate::Usage`].
+///
+/// NOTE: Quinn 0.10 doesn't expose the probe timeout (PTO) count, so it's missing until the dependency allows it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// The smoothed round-trip time.
+    pub rtt: Duration,
+
+    /// The congestion window, the most bytes that may be in flight.
+    pub cwnd: u64,
+
+    /// How many times the congestion controller reduced the window.
+    pub congestion_events: u64,
+
+    /// The bytes sent in UDP datagrams.
+    pub bytes_sent: u64,
+
+    /// The bytes received in UDP datagrams.
+    pub bytes_received: u64,
+
+    /// The QUIC packets sent.
+    pub packets_sent: u64,
+
+    /// The QUIC packets declared lost.
+    pub packets_lost: u64,
+
+    /// The bytes in the QUIC packets declared lost.
+    pub bytes_lost: u64,
+}
+
+impl SessionStats {
+    pub(crate) fn new(stats: &quinn_proto::ConnectionStats) -> Self {
+        Self {
+            rtt: stats.path.rtt,
+            cwnd: stats.path.cwnd,
+            congestion_events: stats.path.congestion_events,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            packets_sent: stats.path.sent_packets,
+            packets_lost: stats.path.lost_packets,
+            bytes_lost: stats.path.lost_bytes,
+        }
+    }
+}
+
+/// The traffic of one half of a stream, see [`crate::SendStream::stats`] and [`crate::RecvStream::stats`].
+///
+/// NOTE: Quinn 0.10 doesn't count retransmissions per stream, so only [`SessionStats::bytes_lost`] is available for the connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The bytes written or read by the application, excluding the WebTransport stream header.
+    pub bytes: u64,
+
+    /// The time from when the stream was opened or accepted until the first byte was written or read, if any.
+    pub time_to_first_byte: Option<Duration>,
+}
+
+// Counts the bytes written or read on one half of a stream.
+pub(crate) struct StreamCounter {
+    opened: Instant,
+    stats: StreamStats,
+}
+
+impl StreamCounter {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            opened: now,
+            stats: StreamStats::default(),
+        }
+    }
+
+    pub fn add(&mut self, size: usize, now: Instant) {
+        if size > 0 && self.stats.time_to_first_byte.is_none() {
+            self.stats.time_to_first_byte = Some(now.saturating_duration_since(self.opened));
+        }
+
+        self.stats.bytes += size as u64;
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.stats
+    }
+}
+
+// The counters shared between the server and the requests it returned.
+#[derive(Default)]
+pub(crate) struct Counters {
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    refused_limit: AtomicU64,
+    refused_filter: AtomicU64,
+    refused_draining: AtomicU64,
+    refused_origin: AtomicU64,
+    refused_auth: AtomicU64,
+    refused_handshake: AtomicU64,
+
+    // The accepted sessions keyed by connection and session ID, forgotten once they're closed.
+    sessions: Mutex<HashMap<(usize, u64), (quinn::Connection, GoAway)>>,
+}
+
+impl Counters {
+    pub fn accepted(&self, session: &Session) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+
+        let conn = (**session).clone();
+        let key = (conn.stable_id(), session.session_id().into_inner());
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(key, (conn, session.goaway()));
+    }
+
+    pub fn rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_limit(&self) {
+        self.refused_limit.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_filter(&self) {
+        self.refused_filter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_draining(&self) {
+        self.refused_draining.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_origin(&self) {
+        self.refused_origin.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_auth(&self) {
+        self.refused_auth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused_handshake(&self) {
+        self.refused_handshake.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Return the number of sessions that are still open.
+    pub fn open(&self) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (conn, goaway)| conn.close_reason().is_none() && goaway.is_open());
+        sessions.len()
+    }
+
+    // Return a handle to send a GOAWAY to each connection with a session that's still open.
+    // There's only one control stream per connection, so sessions sharing it only need one.
+    pub fn goaways(&self) -> Vec<GoAway> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (conn, goaway)| conn.close_reason().is_none() && goaway.is_open());
+
+        let mut goaways = HashMap::new();
+        for ((stable_id, _), (_, goaway)) in sessions.iter() {
+            goaways.entry(*stable_id).or_insert_with(|| goaway.clone());
+        }
+
+        goaways.into_values().collect()
+    }
+
+    pub fn snapshot(&self) -> ServerStats {
+        ServerStats {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            refused_limit: self.refused_limit.load(Ordering::Relaxed),
+            refused_filter: self.refused_filter.load(Ordering::Relaxed),
+            refused_draining: self.refused_draining.load(Ordering::Relaxed),
+            refused_origin: self.refused_origin.load(Ordering::Relaxed),
+            refused_auth: self.refused_auth.load(Ordering::Relaxed),
+            refused_handshake: self.refused_handshake.load(Ordering::Relaxed),
+            open: self.open() as u64,
+        }
+    }
+}
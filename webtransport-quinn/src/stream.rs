@@ -1,30 +1,147 @@
 use std::{
+    any::Any,
+    future::poll_fn,
     io,
+    ops::{Deref, DerefMut},
     pin::{pin, Pin},
+    sync::Arc,
     task::{ready, Context, Poll},
+    time::{Duration, Instant},
 };
 
 use bytes::{Buf, BufMut, Bytes};
 use futures::Future;
 
-use crate::{ReadError, ReadExactError, ReadToEndError, StoppedError, StreamClosed, WriteError};
+use crate::{
+    coop::Coop,
+    labels::Label,
+    limit::Bucket,
+    platform,
+    sched::{Sched, ROUND_TIMEOUT},
+    state::{SessionState, Waiter},
+    stats::StreamCounter,
+    trace::{self, event, Span},
+    RateLimit, ReadError, ReadExactError, ReadToEndError, StallAction, StoppedError, StreamClosed,
+    StreamInfo, StreamStats, WriteError,
+};
 
 /// A stream that can be used to send bytes. See [`quinn::SendStream`].
 ///
 /// This wrapper is needed literally just for error codes, which is unfortunate.
 /// WebTransport uses u32 error codes and they're mapped in a reserved HTTP/3 error space.
+///
+/// Quinn 0.10 doesn't expose how much written data is still unsent or unacknowledged, so neither can this wrapper.
+/// The total is capped by the connection's send window (see [`crate::MemoryBudget::send`]), after which writes wait;
+/// use [`Self::blocked`] to see how long they waited, or [`Self::set_rate_limit`] to pace the stream below the available bandwidth.
 pub struct SendStream {
     inner: quinn::SendStream,
+
+    // Shared with the other streams in the session, used when the stream is weighted.
+    sched: Arc<Sched>,
+    info: StreamInfo,
+
+    // The creation order, used to compute the Quinn priority, and the sched epoch it was computed for.
+    order: u64,
+    epoch: u64,
+
+    // Used to force a new scheduling round if nothing was written while we're waiting.
+    timeout: Option<Sleep>,
+    written: u64,
+
+    // Used to wait until the rate limit allows us to write.
+    pacing: Option<Sleep>,
+
+    // The stream's own rate limit, see set_rate_limit.
+    limit: Option<Box<Bucket>>,
+
+    // How long writes were blocked, how much was written, and the stream's span.
+    telemetry: Box<SendTelemetry>,
+
+    // Used to apply the session's stall policy, and the code if it reset the stream.
+    stall: Option<Sleep>,
+    stalled: Option<u32>,
+
+    // Records resets and stops in the session's journal, and tells us when the session is closed.
+    state: Arc<SessionState>,
+}
+
+type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+// Boxed to keep the stream small, since IncomingStream holds both halves.
+struct SendTelemetry {
+    // When the current write was first blocked by Quinn, and the total time spent blocked.
+    blocked: Option<Instant>,
+    blocked_total: Duration,
+
+    // Counts the bytes written.
+    counter: StreamCounter,
+
+    // The parent of the stream's events, with the `tracing` feature.
+    span: Span,
+}
+
+// Stops tracking the blocked time and stall timer once a write is done or dropped, so the next write doesn't inherit them.
+struct Writing<'a>(&'a mut SendStream);
+
+impl Deref for Writing<'_> {
+    type Target = SendStream;
+
+    fn deref(&self) -> &SendStream {
+        self.0
+    }
+}
+
+impl DerefMut for Writing<'_> {
+    fn deref_mut(&mut self) -> &mut SendStream {
+        self.0
+    }
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        self.0.unblock();
+    }
 }
 
 impl SendStream {
-    pub(crate) fn new(stream: quinn::SendStream) -> Self {
-        Self { inner: stream }
+    pub(crate) fn new(
+        stream: quinn::SendStream,
+        sched: Arc<Sched>,
+        state: Arc<SessionState>,
+    ) -> Self {
+        let order = sched.next_order();
+        let span = trace::stream(state.journal.span(), stream.id(), "send");
+        let mut this = Self {
+            inner: stream,
+            sched,
+            info: StreamInfo::default(),
+            order,
+            epoch: 0,
+            timeout: None,
+            written: 0,
+            pacing: None,
+            limit: None,
+            telemetry: Box::new(SendTelemetry {
+                blocked: None,
+                blocked_total: Duration::ZERO,
+                counter: StreamCounter::new(state.clock.now()),
+                span,
+            }),
+            stall: None,
+            stalled: None,
+            state,
+        };
+
+        // Apply the session's scheduling policy.
+        this.set_priority(0).ok();
+        this
     }
 
     /// Abruptly reset the stream with the provided error code. See [`quinn::SendStream::reset`].
     /// This is a u32 with WebTransport because we share the error space with HTTP/3.
     pub fn reset(&mut self, code: u32) -> Result<(), StreamClosed> {
+        self.state.journal.reset(self.inner.id(), code, true);
+
         let code = webtransport_proto::error_to_http3(code);
         let code = quinn::VarInt::try_from(code).unwrap();
         self.inner.reset(code).map_err(Into::into)
@@ -33,20 +150,35 @@ impl SendStream {
     /// Wait until the stream has been stopped and return the error code. See [`quinn::SendStream::stopped`].
     /// Unlike Quinn, this returns None if the code is not a valid WebTransport error code.
     pub async fn stopped(&mut self) -> Result<Option<u32>, StoppedError> {
-        let code = self.inner.stopped().await?;
-        Ok(webtransport_proto::error_from_http3(code.into_inner()))
+        let waiter = Waiter::Send(self.inner.id());
+        let code = self.state.or_closed(waiter, self.inner.stopped()).await;
+        let code = code.unwrap_or_else(|err| Err(quinn::StoppedError::ConnectionLost(err)))?;
+        let code = webtransport_proto::error_from_http3(code.into_inner());
+
+        if let Some(code) = code {
+            self.state.journal.stopped(self.inner.id(), code, false);
+        }
+
+        Ok(code)
     }
 
     // Unfortunately, we have to wrap WriteError for a bunch of functions.
 
     /// Write some data to the stream, returning the size written. See [`quinn::SendStream::write`].
     pub async fn write(&mut self, buf: &[u8]) -> Result<usize, WriteError> {
-        self.inner.write(buf).await.map_err(Into::into)
+        let mut this = Writing(self);
+        let res = poll_fn(|cx| this.poll_write(cx, buf)).await;
+        res.map_err(|err| this.write_error(err))
     }
 
     /// Write all of the data to the stream. See [`quinn::SendStream::write_all`].
-    pub async fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError> {
-        self.inner.write_all(buf).await.map_err(Into::into)
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<(), WriteError> {
+        while !buf.is_empty() {
+            let size = self.write(buf).await?;
+            buf = &buf[size..];
+        }
+
+        Ok(())
     }
 
     /// Write chunks of data to the stream. See [`quinn::SendStream::write_chunks`].
@@ -54,30 +186,458 @@ impl SendStream {
         &mut self,
         bufs: &mut [Bytes],
     ) -> Result<quinn_proto::Written, WriteError> {
-        self.inner.write_chunks(bufs).await.map_err(Into::into)
+        if !self.is_gated() {
+            let mut this = Writing(self);
+            let res = poll_fn(|cx| {
+                let res = this.poll_inner(cx, |inner, cx| pin!(inner.write_chunks(bufs)).poll(cx));
+                this.poll_closed(cx, res)
+            })
+            .await;
+            let written = res.map_err(|err| this.write_error(err))?;
+
+            this.sent(written.bytes);
+
+            return Ok(written);
+        }
+
+        // We might only be allowed to write part of a chunk, so write them one at a time.
+        let mut written = quinn_proto::Written::default();
+        for buf in bufs.iter_mut() {
+            if !buf.is_empty() {
+                let size = self.write(buf).await?;
+                buf.advance(size);
+                written.bytes += size;
+
+                if !buf.is_empty() {
+                    break;
+                }
+            }
+
+            written.chunks += 1;
+        }
+
+        Ok(written)
     }
 
     /// Write a chunk of data to the stream. See [`quinn::SendStream::write_chunk`].
     pub async fn write_chunk(&mut self, buf: Bytes) -> Result<(), WriteError> {
-        self.inner.write_chunk(buf).await.map_err(Into::into)
+        self.write_all_chunks(&mut [buf]).await
     }
 
     /// Write all of the chunks of data to the stream. See [`quinn::SendStream::write_all_chunks`].
     pub async fn write_all_chunks(&mut self, bufs: &mut [Bytes]) -> Result<(), WriteError> {
-        self.inner.write_all_chunks(bufs).await.map_err(Into::into)
+        let mut offset = 0;
+        while offset < bufs.len() {
+            let written = self.write_chunks(&mut bufs[offset..]).await?;
+            offset += written.chunks;
+        }
+
+        Ok(())
+    }
+
+    /// Share bandwidth with the other weighted streams in the session, in proportion to the weight.
+    ///
+    /// For example, streams with weights 70 and 30 will be sent at a 70/30 split when both have data to send.
+    /// A weight of 0 (the default) disables weighting, so writes are never held back.
+    ///
+    /// Quinn still sends streams with a higher priority first; weights only share bandwidth within a priority.
+    pub fn set_weight(&mut self, weight: u16) -> Result<(), StreamClosed> {
+        // Return an error if the stream is closed, just like set_priority.
+        let id = self.inner.id();
+        self.inner.priority()?;
+
+        self.info.weight = weight;
+        self.sched.update(id, &self.info);
+
+        Ok(())
+    }
+
+    /// Return the weight of the stream, see [`Self::set_weight`].
+    pub fn weight(&self) -> u16 {
+        self.info.weight
+    }
+
+    /// Limit the rate of the stream in bytes per second, or remove the limit with 0 (the default).
+    ///
+    /// Writes are paced, so the data is spread out instead of sent in bursts.
+    /// This is useful for background transfers that shouldn't starve other streams in the session.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64) {
+        if bytes_per_sec == 0 {
+            self.limit = None;
+            return;
+        }
+
+        // Allow a burst of a single pacing interval, or at least a full packet.
+        // The interval is longer on Windows, where the timer is coarse, see Capabilities.
+        let interval = platform::pacing_interval();
+        let burst = (bytes_per_sec * interval.as_millis() as u64 / 1000).max(1200);
+        let limit = RateLimit::new(bytes_per_sec, burst);
+
+        match &mut self.limit {
+            Some(bucket) => bucket.set_limit(limit, self.state.clock.now()),
+            None => self.limit = Some(Box::new(Bucket::new(limit, self.state.clock.now()))),
+        }
+    }
+
+    /// Return the rate limit of the stream in bytes per second, see [`Self::set_rate_limit`].
+    pub fn rate_limit(&self) -> u64 {
+        self.limit
+            .as_ref()
+            .map(|bucket| bucket.limit().rate)
+            .unwrap_or(0)
+    }
+
+    /// Attach application data to the stream, which is provided to a custom [`crate::Scheduler`].
+    pub fn set_metadata<T: Any + Send + Sync>(&mut self, metadata: T) {
+        self.info.metadata = Some(Arc::new(metadata));
+
+        if self.info.weight > 0 {
+            self.sched.update(self.inner.id(), &self.info);
+        }
+    }
+
+    /// Return the metadata attached to the stream, see [`Self::set_metadata`].
+    pub fn metadata(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.info.metadata()
+    }
+
+    /// Attach a short label to the stream (ex. "audio", "video", "control"), ideally right after it's opened or accepted.
+    ///
+    /// The label is recorded in the session's [`crate::Journal`], provided to a custom [`crate::Scheduler`],
+    /// and the stream's traffic from now on is counted in [`crate::Session::label_stats`].
+    /// Labels are shared by the whole session, so keep the number of distinct labels small.
+    pub fn set_label(&mut self, label: &str) {
+        if self.label() == Some(label) {
+            return;
+        }
+
+        let label = self.state.labels.label(label);
+        self.state.journal.labeled(self.inner.id(), label.name());
+        self.info.label = Some(label);
+
+        if self.info.weight > 0 {
+            self.sched.update(self.inner.id(), &self.info);
+        }
+    }
+
+    /// Return the label attached to the stream, see [`Self::set_label`].
+    pub fn label(&self) -> Option<&str> {
+        self.info.label()
+    }
+
+    // Returns true if writes have to wait for the scheduler, a rate limit, or the session's flow control.
+    fn is_gated(&self) -> bool {
+        self.info.weight > 0
+            || self.limit.is_some()
+            || self.sched.is_limited()
+            || self.state.is_flow_controlled()
+    }
+
+    // Write to Quinn, counting the size written towards the stream's label.
+    fn poll_write(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, quinn::WriteError>> {
+        let res = self.poll_write_gated(cx, buf);
+
+        if let Poll::Ready(Ok(size)) = &res {
+            self.sent(*size);
+        }
+
+        self.poll_closed(cx, res)
+    }
+
+    // Count the size written towards the stream's stats and label.
+    fn sent(&mut self, size: usize) {
+        self.telemetry.counter.add(size, self.state.clock.now());
+
+        if let Some(label) = &self.info.label {
+            label.sent(size);
+        }
+
+        self.state.journal.sent(size);
+    }
+
+    /// Return the bytes written to the stream and how long it took to write the first one.
+    pub fn stats(&self) -> StreamStats {
+        self.telemetry.counter.stats()
+    }
+
+    // Fail a pending write with the session's close reason once it's closed, which includes writes waiting on the scheduler.
+    // Writes that failed because the connection was closed also get the reason, since it's more useful than LocallyClosed.
+    fn poll_closed<R>(
+        &self,
+        cx: &mut Context<'_>,
+        res: Poll<Result<R, quinn::WriteError>>,
+    ) -> Poll<Result<R, quinn::WriteError>> {
+        let err = match res {
+            Poll::Pending => ready!(self.state.poll_closed(Waiter::Send(self.inner.id()), cx)),
+            Poll::Ready(Err(quinn::WriteError::ConnectionLost(err))) => {
+                self.state.reason().unwrap_or(err)
+            }
+            res => return res,
+        };
+
+        Poll::Ready(Err(quinn::WriteError::ConnectionLost(err)))
+    }
+
+    // Write to Quinn once the scheduler lets us, returning the size written.
+    fn poll_write_gated(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, quinn::WriteError>> {
+        if !self.is_gated() || buf.is_empty() {
+            return self.poll_inner(cx, |inner, cx| pin!(inner.write(buf)).poll(cx));
+        }
+
+        // Reserve the session's flow control credit first, refunding whatever isn't written.
+        // The task is woken by the session when the peer raises the limit, since it's waiting on the session too.
+        let reserved = ready!(self.state.poll_reserve(buf.len()));
+
+        let size = match self.poll_ready(cx, reserved) {
+            Poll::Ready(size) => size,
+            Poll::Pending => {
+                self.state.refund(reserved);
+                return Poll::Pending;
+            }
+        };
+
+        let res = self.poll_inner(cx, |inner, cx| pin!(inner.write(&buf[..size])).poll(cx));
+
+        let wrote = match &res {
+            Poll::Ready(Ok(size)) => Poll::Ready(*size),
+            Poll::Ready(Err(_)) => Poll::Ready(0),
+            Poll::Pending => Poll::Pending,
+        };
+        self.sched.wrote(self.inner.id(), wrote);
+
+        let written = match wrote {
+            Poll::Ready(size) => size,
+            Poll::Pending => 0,
+        };
+        self.state.refund(reserved - written);
+
+        if let (Poll::Ready(size), Some(limit)) = (wrote, self.limit.as_mut()) {
+            limit.consume(size);
+        }
+
+        res
+    }
+
+    // Poll a write to Quinn, keeping track of how long it's blocked by flow control or the send window.
+    fn poll_inner<R, F>(&mut self, cx: &mut Context<'_>, f: F) -> Poll<Result<R, quinn::WriteError>>
+    where
+        F: FnOnce(&mut quinn::SendStream, &mut Context<'_>) -> Poll<Result<R, quinn::WriteError>>,
+    {
+        let id = self.inner.id();
+
+        // Newer streams have been renormalized, so catch up before writing more.
+        if self.epoch != self.sched.epoch() {
+            self.set_priority(self.info.priority).ok();
+        }
+
+        if let Poll::Ready(res) = f(&mut self.inner, cx) {
+            self.unblock();
+
+            if let Err(err) = &res {
+                self.observe(err);
+            }
+
+            return Poll::Ready(res);
+        }
+
+        let now = self.state.clock.now();
+        let since = match self.telemetry.blocked {
+            Some(since) => since,
+            None => {
+                self.telemetry.blocked = Some(now);
+                self.sched.block(id, now);
+                now
+            }
+        };
+
+        let policy = match self.sched.stall_policy() {
+            Some(policy) => policy,
+            None => return Poll::Pending,
+        };
+
+        let clock = &self.state.clock;
+        let stall = self.stall.get_or_insert_with(|| {
+            let timeout = policy
+                .timeout
+                .saturating_sub(now.saturating_duration_since(since));
+            clock.sleep(timeout)
+        });
+        ready!(stall.as_mut().poll(cx));
+
+        // We've been blocked for too long.
+        self.unblock();
+
+        Poll::Ready(Err(match policy.action {
+            StallAction::Reset(code) => {
+                self.reset(code).ok();
+                self.stalled = Some(code);
+                quinn::WriteError::UnknownStream
+            }
+            StallAction::Close(code) => {
+                self.sched.close(code);
+                quinn::WriteError::ConnectionLost(quinn::ConnectionError::LocallyClosed)
+            }
+        }))
+    }
+
+    // Convert the error, reporting the reset by the stall policy instead of the stream being closed.
+    fn write_error(&self, err: quinn::WriteError) -> WriteError {
+        match (err, self.stalled) {
+            (quinn::WriteError::UnknownStream, Some(code)) => WriteError::Stalled(code),
+            (err, _) => err.into(),
+        }
+    }
+
+    // Record a STOP_SENDING from the peer in the journal.
+    fn observe(&self, err: &quinn::WriteError) {
+        if let quinn::WriteError::Stopped(code) = err {
+            if let Some(code) = webtransport_proto::error_from_http3(code.into_inner()) {
+                self.state.journal.stopped(self.inner.id(), code, false);
+            }
+        }
+    }
+
+    fn unblock(&mut self) {
+        if let Some(since) = self.telemetry.blocked.take() {
+            let now = self.state.clock.now();
+            self.telemetry.blocked_total += now.saturating_duration_since(since);
+            self.sched.unblock(self.inner.id());
+        }
+
+        self.stall = None;
+    }
+
+    /// Return the total time writes spent blocked, including any current write. See [`crate::Session::blocked`].
+    pub fn blocked(&self) -> Duration {
+        let now = self.state.clock.now();
+        let current = self
+            .telemetry
+            .blocked
+            .map(|since| now.saturating_duration_since(since))
+            .unwrap_or_default();
+        self.telemetry.blocked_total + current
+    }
+
+    // Wait until the scheduler and rate limit let us write, returning the allowed size.
+    fn poll_ready(&mut self, cx: &mut Context<'_>, size: usize) -> Poll<usize> {
+        let size = match self.info.weight {
+            0 => size,
+            _ => ready!(self.poll_sched(cx, size)),
+        };
+
+        self.poll_limit(cx, size)
+    }
+
+    // Wait until the scheduler gives us credit, returning the allowed size.
+    fn poll_sched(&mut self, cx: &mut Context<'_>, size: usize) -> Poll<usize> {
+        let id = self.inner.id();
+
+        loop {
+            if let Poll::Ready(size) = self.sched.poll_ready(id, cx, size) {
+                self.timeout = None;
+                return Poll::Ready(size);
+            }
+
+            if self.timeout.is_none() {
+                self.written = self.sched.written();
+                self.timeout = Some(self.state.clock.sleep(ROUND_TIMEOUT));
+            }
+
+            ready!(self.timeout.as_mut().unwrap().as_mut().poll(cx));
+
+            self.timeout = None;
+            self.sched.timeout(self.written);
+        }
+    }
+
+    // Wait until the session and stream rate limits let us write, returning the allowed size.
+    fn poll_limit(&mut self, cx: &mut Context<'_>, size: usize) -> Poll<usize> {
+        loop {
+            let available = self
+                .sched
+                .available(size)
+                .and_then(|size| match self.limit.as_mut() {
+                    Some(limit) => limit.available(size, self.state.clock.now()),
+                    None => Ok(size),
+                });
+
+            let wait = match available {
+                Ok(size) => {
+                    self.pacing = None;
+                    return Poll::Ready(size);
+                }
+                Err(wait) => wait,
+            };
+
+            let clock = &self.state.clock;
+            let pacing = self.pacing.get_or_insert_with(|| clock.sleep(wait));
+            ready!(pacing.as_mut().poll(cx));
+
+            self.pacing = None;
+        }
     }
 
     /// Wait until all of the data has been written to the stream. See [`quinn::SendStream::finish`].
     pub async fn finish(&mut self) -> Result<(), WriteError> {
-        self.inner.finish().await.map_err(Into::into)
+        let res = poll_fn(|cx| {
+            let res = self.inner.poll_finish(cx);
+            self.poll_closed(cx, res)
+        })
+        .await;
+
+        if let Err(err) = &res {
+            self.observe(err);
+        }
+
+        res.map_err(|err| self.write_error(err))
     }
 
+    /// Set the priority of the stream. See [`quinn::SendStream::set_priority`].
+    /// Streams with the same priority are ordered based on the session's [`crate::SchedulePolicy`].
     pub fn set_priority(&mut self, order: i32) -> Result<(), StreamClosed> {
-        self.inner.set_priority(order).map_err(Into::into)
+        let (relative, epoch) = self.sched.relative_order(self.order);
+        let priority = self.sched.policy().priority(order, relative);
+        self.inner.set_priority(priority)?;
+
+        self.epoch = epoch;
+
+        self.info.priority = order;
+
+        if self.info.weight > 0 {
+            self.sched.update(self.inner.id(), &self.info);
+        }
+
+        Ok(())
     }
 
+    /// Return the priority of the stream, as set by [`Self::set_priority`].
     pub fn priority(&self) -> Result<i32, StreamClosed> {
-        self.inner.priority().map_err(Into::into)
+        self.inner.priority()?;
+        Ok(self.info.priority)
+    }
+}
+
+impl Drop for SendStream {
+    fn drop(&mut self) {
+        event!(debug, parent: &self.telemetry.span, bytes = self.stats().bytes, blocked = ?self.blocked(), "stream closed");
+
+        self.unblock();
+        self.state.remove(Waiter::Send(self.inner.id()));
+        self.state.journal.dropped(self.inner.id());
+        self.sched.close_order(self.order);
+
+        if self.info.weight > 0 {
+            self.info.weight = 0;
+            self.sched.update(self.inner.id(), &self.info);
+        }
     }
 }
 
@@ -87,7 +647,11 @@ impl tokio::io::AsyncWrite for SendStream {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.inner).poll_write(cx, buf)
+        let res = SendStream::poll_write(&mut self, cx, buf);
+        res.map_err(|err| match self.write_error(err.clone()) {
+            err @ WriteError::Stalled(_) => io::Error::new(io::ErrorKind::TimedOut, err),
+            _ => err.into(),
+        })
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
@@ -107,16 +671,18 @@ impl webtransport_generic::SendStream for SendStream {
         cx: &mut Context<'_>,
         buf: &mut B,
     ) -> Poll<Result<usize, Self::Error>> {
-        let res = pin!(self.write(buf.chunk())).poll(cx);
+        let res = SendStream::poll_write(self, cx, buf.chunk());
         if let Poll::Ready(Ok(size)) = res {
             buf.advance(size);
         }
 
-        res.map_err(Into::into)
+        res.map_err(|err| self.write_error(err))
     }
 
     fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.inner.poll_finish(cx).map_err(Into::into)
+        let res = self.inner.poll_finish(cx);
+        self.poll_closed(cx, res)
+            .map_err(|err| self.write_error(err))
     }
 
     fn reset(&mut self, reset_code: u32) {
@@ -126,21 +692,118 @@ impl webtransport_generic::SendStream for SendStream {
     fn set_priority(&mut self, order: i32) {
         SendStream::set_priority(self, order).ok();
     }
+
+    fn set_weight(&mut self, weight: u16) -> bool {
+        SendStream::set_weight(self, weight).is_ok()
+    }
+
+    /// Groups are stream labels, which are provided to a custom [`crate::Scheduler`], see [`SendStream::set_label`].
+    fn set_group(&mut self, group: &str) -> bool {
+        self.set_label(group);
+        true
+    }
 }
 
 /// A stream that can be used to recieve bytes. See [`quinn::RecvStream`].
 pub struct RecvStream {
     inner: quinn::RecvStream,
+
+    // Records resets and stops in the session's journal, and tells us when the session is closed.
+    state: Arc<SessionState>,
+
+    // Counts the traffic for the stream's label, if any.
+    label: Option<Arc<Label>>,
+
+    // Counts the bytes read, see stats.
+    counter: StreamCounter,
+
+    // The parent of the stream's events, with the `tracing` feature.
+    span: Span,
 }
 
 impl RecvStream {
-    pub(crate) fn new(stream: quinn::RecvStream) -> Self {
-        Self { inner: stream }
+    // Start counting the work done by a loop on the session's behalf.
+    pub(crate) fn coop(&self) -> Coop {
+        self.state.yield_budget.coop()
+    }
+
+    pub(crate) fn new(stream: quinn::RecvStream, state: Arc<SessionState>) -> Self {
+        let span = trace::stream(state.journal.span(), stream.id(), "recv");
+        let counter = StreamCounter::new(state.clock.now());
+
+        Self {
+            inner: stream,
+            state,
+            label: None,
+            counter,
+            span,
+        }
+    }
+
+    /// Attach a short label to the stream (ex. "audio", "video", "control"), ideally right after it's opened or accepted.
+    ///
+    /// See [`SendStream::set_label`]; the label of each half of a bidirectional stream is counted separately.
+    pub fn set_label(&mut self, label: &str) {
+        if self.label() == Some(label) {
+            return;
+        }
+
+        let label = self.state.labels.label(label);
+        self.state.journal.labeled(self.inner.id(), label.name());
+        self.label = Some(label);
+    }
+
+    /// Return the label attached to the stream, see [`Self::set_label`].
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(|label| label.name())
+    }
+
+    /// Return the bytes read from the stream and how long it took from when it was accepted to read the first one.
+    pub fn stats(&self) -> StreamStats {
+        self.counter.stats()
+    }
+
+    // Count the size read towards the stream's stats, its label and the session's flow control.
+    fn received(&mut self, size: usize) {
+        self.counter.add(size, self.state.clock.now());
+
+        if let Some(label) = &self.label {
+            label.received(size);
+        }
+
+        self.state.received(size);
+        self.state.journal.received(size);
+    }
+
+    // Fail a pending read with the session's close reason once it's closed, see SendStream::poll_closed.
+    fn poll_closed<R>(
+        &self,
+        cx: &mut Context<'_>,
+        res: Poll<Result<R, quinn::ReadError>>,
+    ) -> Poll<Result<R, quinn::ReadError>> {
+        let err = match res {
+            Poll::Pending => ready!(self.state.poll_closed(Waiter::Recv(self.inner.id()), cx)),
+            Poll::Ready(Err(quinn::ReadError::ConnectionLost(err))) => {
+                self.state.reason().unwrap_or(err)
+            }
+            res => return res,
+        };
+
+        Poll::Ready(Err(quinn::ReadError::ConnectionLost(err)))
+    }
+
+    // Record a RESET_STREAM from the peer in the journal.
+    fn observe(&self, err: &ReadError) {
+        if let ReadError::Reset(code) = err {
+            self.state.journal.reset(self.inner.id(), *code, false);
+        }
     }
 
     /// Tell the other end to stop sending data with the given error code. See [`quinn::RecvStream::stop`].
     /// This is a u32 with WebTransport since it shares the error space with HTTP/3.
     pub fn stop(&mut self, code: u32) -> Result<(), quinn::UnknownStream> {
+        self.state.journal.stopped(self.inner.id(), code, true);
+
         let code = webtransport_proto::error_to_http3(code);
         let code = quinn::VarInt::try_from(code).unwrap();
         self.inner.stop(code)
@@ -150,12 +813,42 @@ impl RecvStream {
 
     /// Read some data into the buffer and return the amount read. See [`quinn::RecvStream::read`].
     pub async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadError> {
-        self.inner.read(buf).await.map_err(Into::into)
+        let res = poll_fn(|cx| {
+            let res = pin!(self.inner.read(buf)).poll(cx);
+            self.poll_closed(cx, res)
+        })
+        .await;
+
+        let res = res.map_err(Into::into);
+        if let Ok(Some(size)) = &res {
+            self.received(*size);
+        }
+
+        res.inspect_err(|err| self.observe(err))
     }
 
     /// Fill the entire buffer with data. See [`quinn::RecvStream::read_exact`].
     pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError> {
-        self.inner.read_exact(buf).await.map_err(Into::into)
+        if self.state.is_flow_controlled() {
+            return self.read_exact_counted(buf).await;
+        }
+
+        let waiter = Waiter::Recv(self.inner.id());
+        let res = self
+            .state
+            .or_closed(waiter, self.inner.read_exact(buf))
+            .await;
+        let res = res
+            .unwrap_or_else(|err| Err(quinn::ReadError::ConnectionLost(err).into()))
+            .map_err(ReadExactError::from);
+
+        match &res {
+            Ok(()) => self.received(buf.len()),
+            Err(ReadExactError::ReadError(err)) => self.observe(err),
+            Err(_) => {}
+        }
+
+        res
     }
 
     /// Read a chunk of data from the stream. See [`quinn::RecvStream::read_chunk`].
@@ -164,20 +857,85 @@ impl RecvStream {
         max_length: usize,
         ordered: bool,
     ) -> Result<Option<quinn::Chunk>, ReadError> {
-        self.inner
-            .read_chunk(max_length, ordered)
-            .await
-            .map_err(Into::into)
+        let res = poll_fn(|cx| {
+            let res = pin!(self.inner.read_chunk(max_length, ordered)).poll(cx);
+            self.poll_closed(cx, res)
+        })
+        .await;
+
+        if let Ok(Some(chunk)) = &res {
+            self.received(chunk.bytes.len());
+        }
+
+        res.map_err(Into::into).inspect_err(|err| self.observe(err))
     }
 
     /// Read chunks of data from the stream. See [`quinn::RecvStream::read_chunks`].
     pub async fn read_chunks(&mut self, bufs: &mut [Bytes]) -> Result<Option<usize>, ReadError> {
-        self.inner.read_chunks(bufs).await.map_err(Into::into)
+        let res = poll_fn(|cx| {
+            let res = pin!(self.inner.read_chunks(bufs)).poll(cx);
+            self.poll_closed(cx, res)
+        })
+        .await;
+
+        let res = res.map_err(Into::into);
+        if let Ok(Some(count)) = &res {
+            self.received(bufs[..*count].iter().map(|buf| buf.len()).sum());
+        }
+
+        res.inspect_err(|err| self.observe(err))
     }
 
     /// Read until the end of the stream or the limit is hit. See [`quinn::RecvStream::read_to_end`].
     pub async fn read_to_end(&mut self, size_limit: usize) -> Result<Vec<u8>, ReadToEndError> {
-        self.inner.read_to_end(size_limit).await.map_err(Into::into)
+        if self.state.is_flow_controlled() {
+            return self.read_to_end_counted(size_limit).await;
+        }
+
+        let waiter = Waiter::Recv(self.inner.id());
+        let res = self
+            .state
+            .or_closed(waiter, self.inner.read_to_end(size_limit))
+            .await;
+        let res = res
+            .unwrap_or_else(|err| Err(quinn::ReadError::ConnectionLost(err).into()))
+            .map_err(ReadToEndError::from);
+
+        match &res {
+            Ok(buf) => self.received(buf.len()),
+            Err(ReadToEndError::ReadError(err)) => self.observe(err),
+            Err(_) => {}
+        }
+
+        res
+    }
+
+    // Like read_exact, but counting each read as it happens.
+    // The session's flow control only raises the peer's limit as data is read, which could otherwise deadlock on a large buffer.
+    async fn read_exact_counted(&mut self, mut buf: &mut [u8]) -> Result<(), ReadExactError> {
+        while !buf.is_empty() {
+            match self.read(buf).await? {
+                Some(size) => buf = &mut buf[size..],
+                None => return Err(ReadExactError::FinishedEarly),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Like read_to_end, but counting each chunk as it happens, see read_exact_counted.
+    async fn read_to_end_counted(&mut self, size_limit: usize) -> Result<Vec<u8>, ReadToEndError> {
+        let mut buf = Vec::new();
+
+        while let Some(chunk) = self.read_chunk(usize::MAX, true).await? {
+            if buf.len() + chunk.bytes.len() > size_limit {
+                return Err(ReadToEndError::TooLong);
+            }
+
+            buf.extend_from_slice(&chunk.bytes);
+        }
+
+        Ok(buf)
     }
 
     // We purposely don't expose the stream ID or 0RTT because it's not valid with WebTransport
@@ -189,7 +947,26 @@ impl tokio::io::AsyncRead for RecvStream {
         cx: &mut Context<'_>,
         buf: &mut tokio::io::ReadBuf,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.inner).poll_read(cx, buf)
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if res.is_pending() {
+            let err = ready!(self.state.poll_closed(Waiter::Recv(self.inner.id()), cx));
+            return Poll::Ready(Err(quinn::ReadError::ConnectionLost(err).into()));
+        }
+
+        self.received(buf.filled().len() - filled);
+
+        res
+    }
+}
+
+impl Drop for RecvStream {
+    fn drop(&mut self) {
+        event!(debug, parent: &self.span, bytes = self.stats().bytes, "stream closed");
+
+        self.state.remove(Waiter::Recv(self.inner.id()));
+        self.state.journal.dropped(self.inner.id());
     }
 }
 
@@ -0,0 +1,270 @@
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{
+    clock, Clock, ReadError, ReadExactError, ReadToEndError, RecvStream, SendStream, Session,
+    SessionError, WriteError,
+};
+
+/// An error returned by the wrappers in [`Session::with_timeout`], when the operation fails or takes too long.
+#[derive(Error, Debug)]
+pub enum TimeoutError<E> {
+    #[error("timed out after {0:?}")]
+    Elapsed(Duration),
+
+    #[error(transparent)]
+    Inner(#[from] E),
+}
+
+impl<E> TimeoutError<E> {
+    /// Returns true if the operation timed out.
+    pub fn is_elapsed(&self) -> bool {
+        matches!(self, Self::Elapsed(_))
+    }
+}
+
+// Run the operation, returning an error if it doesn't complete in time according to the session's clock.
+async fn deadline<T, E, F>(clock: &dyn Clock, timeout: Duration, f: F) -> Result<T, TimeoutError<E>>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    match clock::timeout(clock.sleep(timeout), f).await {
+        Some(res) => res.map_err(TimeoutError::Inner),
+        None => Err(TimeoutError::Elapsed(timeout)),
+    }
+}
+
+/// A [`Session`] where each operation fails with [`TimeoutError::Elapsed`] if it takes too long, see [`Session::with_timeout`].
+///
+/// Streams are wrapped too, so reads and writes also carry the timeout.
+/// Each operation has its own deadline, so a long transfer is fine as long as it keeps making progress.
+/// Deref is used to expose the rest of the [`Session`] without a timeout.
+#[derive(Clone)]
+pub struct TimeoutSession {
+    session: Session,
+    timeout: Duration,
+}
+
+impl TimeoutSession {
+    pub(crate) fn new(session: Session, timeout: Duration) -> Self {
+        Self { session, timeout }
+    }
+
+    /// Return the timeout applied to each operation.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Return the session without a timeout.
+    pub fn into_inner(self) -> Session {
+        self.session
+    }
+
+    /// Accept a new unidirectional stream, see [`Session::accept_uni`].
+    pub async fn accept_uni(&self) -> Result<TimeoutRecvStream, TimeoutError<SessionError>> {
+        let recv = deadline(
+            self.session.clock(),
+            self.timeout,
+            self.session.accept_uni(),
+        )
+        .await?;
+        Ok(self.recv(recv))
+    }
+
+    /// Accept a new bidirectional stream, see [`Session::accept_bi`].
+    pub async fn accept_bi(
+        &self,
+    ) -> Result<(TimeoutSendStream, TimeoutRecvStream), TimeoutError<SessionError>> {
+        let (send, recv) =
+            deadline(self.session.clock(), self.timeout, self.session.accept_bi()).await?;
+        Ok((self.send(send), self.recv(recv)))
+    }
+
+    /// Open a new unidirectional stream, see [`Session::open_uni`].
+    pub async fn open_uni(&self) -> Result<TimeoutSendStream, TimeoutError<SessionError>> {
+        let send = deadline(self.session.clock(), self.timeout, self.session.open_uni()).await?;
+        Ok(self.send(send))
+    }
+
+    /// Open a new bidirectional stream, see [`Session::open_bi`].
+    pub async fn open_bi(
+        &self,
+    ) -> Result<(TimeoutSendStream, TimeoutRecvStream), TimeoutError<SessionError>> {
+        let (send, recv) =
+            deadline(self.session.clock(), self.timeout, self.session.open_bi()).await?;
+        Ok((self.send(send), self.recv(recv)))
+    }
+
+    fn send(&self, stream: SendStream) -> TimeoutSendStream {
+        TimeoutSendStream {
+            stream,
+            timeout: self.timeout,
+            clock: self.session.clock_handle(),
+        }
+    }
+
+    fn recv(&self, stream: RecvStream) -> TimeoutRecvStream {
+        TimeoutRecvStream {
+            stream,
+            timeout: self.timeout,
+            clock: self.session.clock_handle(),
+        }
+    }
+}
+
+impl Deref for TimeoutSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+/// A [`SendStream`] where each write fails with [`TimeoutError::Elapsed`] if it takes too long.
+///
+/// DerefMut is used to expose the rest of the [`SendStream`] without a timeout.
+pub struct TimeoutSendStream {
+    stream: SendStream,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl TimeoutSendStream {
+    /// Change the timeout for this stream.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Return the stream without a timeout.
+    pub fn into_inner(self) -> SendStream {
+        self.stream
+    }
+
+    /// Write some data to the stream, see [`SendStream::write`].
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, TimeoutError<WriteError>> {
+        deadline(self.clock.as_ref(), self.timeout, self.stream.write(buf)).await
+    }
+
+    /// Write the entire buffer to the stream, see [`SendStream::write_all`].
+    pub async fn write_all(&mut self, buf: &[u8]) -> Result<(), TimeoutError<WriteError>> {
+        deadline(
+            self.clock.as_ref(),
+            self.timeout,
+            self.stream.write_all(buf),
+        )
+        .await
+    }
+
+    /// Write a chunk of data to the stream, see [`SendStream::write_chunk`].
+    pub async fn write_chunk(&mut self, buf: Bytes) -> Result<(), TimeoutError<WriteError>> {
+        deadline(
+            self.clock.as_ref(),
+            self.timeout,
+            self.stream.write_chunk(buf),
+        )
+        .await
+    }
+
+    /// Wait until all of the data has been written to the stream, see [`SendStream::finish`].
+    pub async fn finish(&mut self) -> Result<(), TimeoutError<WriteError>> {
+        deadline(self.clock.as_ref(), self.timeout, self.stream.finish()).await
+    }
+}
+
+impl Deref for TimeoutSendStream {
+    type Target = SendStream;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+impl DerefMut for TimeoutSendStream {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.stream
+    }
+}
+
+/// A [`RecvStream`] where each read fails with [`TimeoutError::Elapsed`] if it takes too long.
+///
+/// DerefMut is used to expose the rest of the [`RecvStream`] without a timeout.
+pub struct TimeoutRecvStream {
+    stream: RecvStream,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl TimeoutRecvStream {
+    /// Change the timeout for this stream.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Return the stream without a timeout.
+    pub fn into_inner(self) -> RecvStream {
+        self.stream
+    }
+
+    /// Read some data into the buffer, see [`RecvStream::read`].
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TimeoutError<ReadError>> {
+        deadline(self.clock.as_ref(), self.timeout, self.stream.read(buf)).await
+    }
+
+    /// Fill the entire buffer with data, see [`RecvStream::read_exact`].
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), TimeoutError<ReadExactError>> {
+        deadline(
+            self.clock.as_ref(),
+            self.timeout,
+            self.stream.read_exact(buf),
+        )
+        .await
+    }
+
+    /// Read a chunk of data from the stream, see [`RecvStream::read_chunk`].
+    pub async fn read_chunk(
+        &mut self,
+        max_length: usize,
+        ordered: bool,
+    ) -> Result<Option<quinn::Chunk>, TimeoutError<ReadError>> {
+        deadline(
+            self.clock.as_ref(),
+            self.timeout,
+            self.stream.read_chunk(max_length, ordered),
+        )
+        .await
+    }
+
+    /// Read until the end of the stream or the limit is hit, see [`RecvStream::read_to_end`].
+    pub async fn read_to_end(
+        &mut self,
+        size_limit: usize,
+    ) -> Result<Vec<u8>, TimeoutError<ReadToEndError>> {
+        deadline(
+            self.clock.as_ref(),
+            self.timeout,
+            self.stream.read_to_end(size_limit),
+        )
+        .await
+    }
+}
+
+impl Deref for TimeoutRecvStream {
+    type Target = RecvStream;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+impl DerefMut for TimeoutRecvStream {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.stream
+    }
+}
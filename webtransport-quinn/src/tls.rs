@@ -0,0 +1,272 @@
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use ring::{aead, rand::SecureRandom};
+
+use bytes::{Buf, BufMut};
+
+/// How a client caches TLS sessions so later connections can do a resumed handshake.
+///
+/// Only one ticket is used per resumption, so a fleet of reconnecting clients should share a store large enough for all of its servers.
+#[derive(Clone)]
+pub enum SessionCache {
+    /// Never resume sessions.
+    Disabled,
+
+    /// Keep up to the given number of sessions in memory (rustls defaults to 256).
+    Memory(usize),
+
+    /// Use a custom store, ex. one shared between multiple clients.
+    Store(Arc<dyn rustls::client::ClientSessionStore>),
+}
+
+impl SessionCache {
+    /// Configure the resumption cache used by the given TLS config.
+    pub fn apply(&self, tls: &mut rustls::ClientConfig) {
+        tls.resumption = match self {
+            Self::Disabled => rustls::client::Resumption::disabled(),
+            Self::Memory(size) => rustls::client::Resumption::in_memory_sessions(*size),
+            Self::Store(store) => rustls::client::Resumption::store(store.clone()),
+        };
+    }
+
+    /// Use the given [`TicketStore`], ex. one that applies its own eviction policy per server.
+    pub fn tickets<T: TicketStore + 'static>(store: T) -> Self {
+        Self::Store(Arc::new(Tickets(store)))
+    }
+}
+
+impl Default for SessionCache {
+    fn default() -> Self {
+        Self::Memory(256)
+    }
+}
+
+/// Where a client keeps the TLS resumption state of each server, keyed by its authority (the TLS server name, ex. `example.com` or an IP address).
+///
+/// Install it with [`SessionCache::tickets`].
+/// Unlike [`rustls::client::ClientSessionStore`], it only deals with what QUIC uses: TLS 1.3 tickets and key exchange hints.
+///
+/// NOTE: rustls 0.21 doesn't allow TLS 1.3 tickets to be serialized, so they can only be kept in memory and are lost on restart,
+/// which means the first connection to each server after a restart is always a full handshake without 0-RTT.
+/// Quinn 0.10 also doesn't support address validation tokens on the client, so there's nothing to persist there either.
+/// The key exchange hint is plain data, so it can be persisted to skip a HelloRetryRequest after a restart.
+pub trait TicketStore: Send + Sync {
+    /// Save a ticket for the server; it may issue several per connection.
+    fn insert(&self, authority: &str, ticket: SessionTicket);
+
+    /// Remove and return a ticket for the server, since each ticket should only be used once.
+    fn take(&self, authority: &str) -> Option<SessionTicket>;
+
+    /// Remember the key exchange group the server chose, as its IANA code point.
+    fn set_kx_hint(&self, authority: &str, group: u16) {
+        let _ = (authority, group);
+    }
+
+    /// Return the key exchange group the server chose last time, if known.
+    fn kx_hint(&self, authority: &str) -> Option<u16> {
+        let _ = authority;
+        None
+    }
+}
+
+/// A TLS 1.3 session ticket for resuming a connection, see [`TicketStore`].
+///
+/// It contains the resumption secret, so it's not printed by [`fmt::Debug`].
+pub struct SessionTicket(rustls::client::Tls13ClientSessionValue);
+
+impl SessionTicket {
+    /// Returns true if the ticket can be used to send early data, see [`crate::Client::connect_0rtt`].
+    pub fn allows_early_data(&self) -> bool {
+        self.0.max_early_data_size() > 0
+    }
+}
+
+impl fmt::Debug for SessionTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionTicket")
+            .field("allows_early_data", &self.allows_early_data())
+            .finish_non_exhaustive()
+    }
+}
+
+// Adapts a TicketStore to rustls, ignoring TLS 1.2 sessions since QUIC always uses TLS 1.3.
+struct Tickets<T>(T);
+
+impl<T: TicketStore> rustls::client::ClientSessionStore for Tickets<T> {
+    fn set_kx_hint(&self, server_name: &rustls::ServerName, group: rustls::NamedGroup) {
+        if let Some(authority) = authority(server_name) {
+            self.0.set_kx_hint(&authority, group.get_u16())
+        }
+    }
+
+    fn kx_hint(&self, server_name: &rustls::ServerName) -> Option<rustls::NamedGroup> {
+        let group = self.0.kx_hint(&authority(server_name)?)?;
+        Some(rustls::NamedGroup::from(group))
+    }
+
+    fn set_tls12_session(
+        &self,
+        _: &rustls::ServerName,
+        _: rustls::client::Tls12ClientSessionValue,
+    ) {
+    }
+
+    fn tls12_session(
+        &self,
+        _: &rustls::ServerName,
+    ) -> Option<rustls::client::Tls12ClientSessionValue> {
+        None
+    }
+
+    fn remove_tls12_session(&self, _: &rustls::ServerName) {}
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: &rustls::ServerName,
+        value: rustls::client::Tls13ClientSessionValue,
+    ) {
+        if let Some(authority) = authority(server_name) {
+            self.0.insert(&authority, SessionTicket(value))
+        }
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &rustls::ServerName,
+    ) -> Option<rustls::client::Tls13ClientSessionValue> {
+        let ticket = self.0.take(&authority(server_name)?)?;
+        Some(ticket.0)
+    }
+}
+
+// The key for a server's resumption state, which is the host of the URI it was dialed with.
+fn authority(server_name: &rustls::ServerName) -> Option<String> {
+    match server_name {
+        rustls::ServerName::DnsName(name) => Some(name.as_ref().to_string()),
+        rustls::ServerName::IpAddress(ip) => Some(ip.to_string()),
+        _ => None,
+    }
+}
+
+/// A secret used to encrypt session tickets, shared by every server in a fleet. See [`TicketKeys`].
+#[derive(Clone)]
+pub struct TicketKey {
+    id: u32,
+    secret: [u8; 32],
+}
+
+impl TicketKey {
+    /// Create a key from an identifier and a 256-bit secret.
+    /// The identifier must be unique among the keys in use, ex. a counter incremented on each rotation.
+    pub fn new(id: u32, secret: [u8; 32]) -> Self {
+        Self { id, secret }
+    }
+
+    /// Generate a random key with the given identifier.
+    pub fn generate(id: u32) -> Self {
+        let mut secret = [0; 32];
+        ring::rand::SystemRandom::new().fill(&mut secret).unwrap();
+        Self { id, secret }
+    }
+
+    /// Returns the identifier.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the secret, to be distributed to the rest of the fleet.
+    pub fn secret(&self) -> &[u8; 32] {
+        &self.secret
+    }
+}
+
+/// Encrypts session tickets with caller-provided keys so resumption works across a fleet of servers.
+///
+/// Install it with `rustls::ServerConfig::ticketer`; unlike [`rustls::Ticketer`], the keys are not random per process.
+/// Tickets are encrypted with the current key and decrypted with any key that hasn't been rotated out.
+/// Rotate regularly to limit the damage to forward secrecy; the lifetime hint should not exceed the rotation period.
+pub struct TicketKeys {
+    // The first key is used for encryption.
+    keys: RwLock<Vec<(u32, aead::LessSafeKey)>>,
+    lifetime: Duration,
+}
+
+impl TicketKeys {
+    /// Encrypt tickets with the current key, accepting tickets from the previous keys too.
+    pub fn new(lifetime: Duration, current: TicketKey, previous: &[TicketKey]) -> Arc<Self> {
+        let this = Self {
+            keys: Default::default(),
+            lifetime,
+        };
+        this.rotate(current, previous);
+        Arc::new(this)
+    }
+
+    /// Replace the keys, usually to promote a new current key and retire the oldest one.
+    pub fn rotate(&self, current: TicketKey, previous: &[TicketKey]) {
+        let keys = std::iter::once(&current)
+            .chain(previous)
+            .map(|key| {
+                let unbound = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key.secret).unwrap();
+                (key.id, aead::LessSafeKey::new(unbound))
+            })
+            .collect();
+
+        *self.keys.write().unwrap() = keys;
+    }
+}
+
+impl rustls::server::ProducesTickets for TicketKeys {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime.as_secs().try_into().unwrap_or(u32::MAX)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        let (id, key) = keys.first()?;
+
+        let mut nonce = [0; aead::NONCE_LEN];
+        ring::rand::SystemRandom::new().fill(&mut nonce).ok()?;
+
+        // The ticket is: key ID + nonce + ciphertext + tag
+        let mut ticket = Vec::with_capacity(4 + nonce.len() + plain.len() + 16);
+        ticket.put_u32(*id);
+        ticket.put_slice(&nonce);
+
+        let mut sealed = plain.to_vec();
+        let nonce = aead::Nonce::assume_unique_for_key(nonce);
+        let aad = aead::Aad::from(id.to_be_bytes());
+        key.seal_in_place_append_tag(nonce, aad, &mut sealed).ok()?;
+
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn decrypt(&self, mut cipher: &[u8]) -> Option<Vec<u8>> {
+        if cipher.remaining() < 4 + aead::NONCE_LEN {
+            return None;
+        }
+
+        let id = cipher.get_u32();
+        let nonce = aead::Nonce::try_assume_unique_for_key(&cipher[..aead::NONCE_LEN]).ok()?;
+        cipher.advance(aead::NONCE_LEN);
+
+        let keys = self.keys.read().unwrap();
+        let (_, key) = keys.iter().find(|(key_id, _)| *key_id == id)?;
+
+        let mut plain = cipher.to_vec();
+        let aad = aead::Aad::from(id.to_be_bytes());
+        let size = key.open_in_place(nonce, aad, &mut plain).ok()?.len();
+        plain.truncate(size);
+
+        Some(plain)
+    }
+}
@@ -0,0 +1,94 @@
+// Spans and events for the `tracing` feature, which compile to nothing without it.
+//
+// Every connection gets a `webtransport.connection` span, each session a `webtransport.session` span within it,
+// and each half of a stream a `webtransport.stream` span within the session.
+use std::future::Future;
+
+use webtransport_proto::VarInt;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+// A stand-in with the few methods we use, so the spans can be stored unconditionally.
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn in_scope<F: FnOnce() -> T, T>(&self, f: F) -> T {
+        f()
+    }
+}
+
+// Emit an event at the given level (ex. `event!(debug, code, "stream reset")`), with the same syntax as the tracing macros.
+// The parent is still evaluated without the feature, so the spans stored for it are used.
+macro_rules! event {
+    ($level:ident, parent: $parent:expr, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!(parent: $parent, $($arg)+);
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = $parent;
+    };
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+
+// Create a span at the given level, with the same syntax as the tracing macros.
+// The arguments are ignored without the feature, so the functions below allow unused variables.
+macro_rules! span {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::$level!($($arg)+);
+
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::Span;
+
+        span
+    }};
+}
+
+pub(crate) use event;
+
+// The span of a QUIC connection, used by the handshake and as the parent of its sessions.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn connection(conn: &quinn::Connection) -> Span {
+    span!(
+        info_span,
+        "webtransport.connection",
+        id = conn.stable_id(),
+        remote = %conn.remote_address(),
+    )
+}
+
+// The span of a session, within the current span (the connection's).
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn session(id: VarInt, uri: &http::Uri) -> Span {
+    span!(info_span, "webtransport.session", id = id.into_inner(), uri = %uri)
+}
+
+// The span of one half of a stream within the session's span, since each half is dropped separately.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn stream(session: &Span, id: quinn::StreamId, half: &'static str) -> Span {
+    span!(
+        debug_span,
+        parent: session,
+        "webtransport.stream",
+        id = quinn::VarInt::from(id).into_inner(),
+        half,
+    )
+}
+
+// Run the future within the span.
+pub(crate) fn instrument<F: Future>(span: &Span, future: F) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::instrument(future, span.clone());
+
+    #[cfg(not(feature = "tracing"))]
+    let _ = span;
+
+    future
+}
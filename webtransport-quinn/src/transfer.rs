@@ -0,0 +1,234 @@
+use std::{future::poll_fn, io, pin::Pin};
+
+use bytes::{Buf, BufMut};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{ReadExactError, RecvStream, SendStream, WriteError};
+
+// The default number of bytes covered by each checksum.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+// The largest chunk we'll accept, so a peer can't make us buffer an arbitrary amount.
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+// The size of a SHA-256 digest.
+const CHECKSUM_SIZE: usize = 32;
+
+/// An error returned when sending or receiving a file, see [`FileSender`] and [`FileReceiver`].
+#[derive(Error, Debug)]
+pub enum TransferError {
+    #[error("read error: {0}")]
+    Read(#[from] ReadExactError),
+
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("checksum mismatch at offset {0}")]
+    Checksum(u64),
+
+    #[error("expected offset {expected} but the sender started at {actual}")]
+    OffsetMismatch { expected: u64, actual: u64 },
+
+    #[error("chunk too large: {0} bytes")]
+    ChunkTooLarge(usize),
+}
+
+/// Sends a file (or any [`AsyncRead`]) over a unidirectional stream, so it can be verified and resumed by a [`FileReceiver`].
+///
+/// The stream starts with the offset of the first byte, followed by length-prefixed chunks that each end with a SHA-256 checksum.
+/// An empty chunk marks the end of the file, so an interrupted transfer can be told apart from a complete one.
+#[derive(Clone, Debug)]
+pub struct FileSender {
+    chunk_size: usize,
+}
+
+impl FileSender {
+    /// Send the file in chunks of 64KB.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the number of bytes covered by each checksum, up to 1MB.
+    ///
+    /// Smaller chunks waste less data when resuming, at the cost of more overhead.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.clamp(1, MAX_CHUNK_SIZE);
+        self
+    }
+
+    /// Send the contents of the reader, starting at the given offset, then finish the stream.
+    ///
+    /// The reader must already be positioned at the offset (ex. using [`tokio::io::AsyncSeekExt::seek`] for a file); only the offset is sent.
+    /// Use [`FileReceiver::offset`] to resume an interrupted transfer on a new stream.
+    /// Returns the offset after the last byte, which is the size of the file.
+    pub async fn send<R: AsyncRead + Unpin>(
+        &self,
+        stream: &mut SendStream,
+        mut reader: R,
+        mut offset: u64,
+    ) -> Result<u64, TransferError> {
+        let mut header = Vec::with_capacity(8);
+        header.put_u64(offset);
+        stream.write_all(&header).await?;
+
+        let mut buf = vec![0; 4 + self.chunk_size + CHECKSUM_SIZE];
+
+        loop {
+            let size = fill(&mut reader, &mut buf[4..4 + self.chunk_size]).await?;
+
+            // An empty chunk without a checksum marks the end.
+            (&mut buf[..4]).put_u32(size as u32);
+            if size == 0 {
+                stream.write_all(&buf[..4]).await?;
+                break;
+            }
+
+            let checksum = checksum(&buf[4..4 + size]);
+            buf[4 + size..4 + size + CHECKSUM_SIZE].copy_from_slice(checksum.as_ref());
+
+            stream.write_all(&buf[..4 + size + CHECKSUM_SIZE]).await?;
+            offset += size as u64;
+        }
+
+        stream.finish().await?;
+
+        Ok(offset)
+    }
+}
+
+impl Default for FileSender {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+/// Receives a file sent by a [`FileSender`], verifying each chunk before it's written.
+///
+/// The receiver remembers how much of the file was verified, so if the stream is interrupted,
+/// ask the sender to resume from [`Self::offset`] on a new stream and call [`Self::recv`] again with the same writer.
+/// How the offset is sent back is up to the application.
+#[derive(Clone, Debug, Default)]
+pub struct FileReceiver {
+    offset: u64,
+    complete: bool,
+}
+
+impl FileReceiver {
+    /// Receive a file from the start.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive the rest of a file, when the first `offset` bytes were received previously.
+    pub fn resume(offset: u64) -> Self {
+        Self {
+            offset,
+            complete: false,
+        }
+    }
+
+    /// Return the number of bytes that were verified and written, which is where the transfer should resume.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns true once the end of the file was received.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Receive chunks from the stream and write them, until the end of the file.
+    ///
+    /// Returns an error if the stream is interrupted or corrupted, in which case only the verified data was written.
+    /// The sender must start at [`Self::offset`], otherwise [`TransferError::OffsetMismatch`] is returned.
+    pub async fn recv<W: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut RecvStream,
+        writer: &mut W,
+    ) -> Result<(), TransferError> {
+        let mut header = [0; 8];
+        stream.read_exact(&mut header).await?;
+
+        let actual = (&header[..]).get_u64();
+        if actual != self.offset {
+            return Err(TransferError::OffsetMismatch {
+                expected: self.offset,
+                actual,
+            });
+        }
+
+        let mut buf = Vec::new();
+
+        loop {
+            let mut size = [0; 4];
+            stream.read_exact(&mut size).await?;
+
+            let size = (&size[..]).get_u32() as usize;
+            if size == 0 {
+                break;
+            }
+
+            if size > MAX_CHUNK_SIZE {
+                return Err(TransferError::ChunkTooLarge(size));
+            }
+
+            buf.resize(size + CHECKSUM_SIZE, 0);
+            stream.read_exact(&mut buf).await?;
+
+            let (chunk, expected) = buf.split_at(size);
+            if checksum(chunk).as_ref() != expected {
+                return Err(TransferError::Checksum(self.offset));
+            }
+
+            write_all(writer, chunk).await?;
+            self.offset += size as u64;
+        }
+
+        poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await?;
+        self.complete = true;
+
+        Ok(())
+    }
+}
+
+fn checksum(data: &[u8]) -> ring::digest::Digest {
+    ring::digest::digest(&ring::digest::SHA256, data)
+}
+
+// Read until the buffer is full or the reader is done, returning the number of bytes read.
+async fn fill<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let mut read = ReadBuf::new(&mut buf[filled..]);
+        poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut read)).await?;
+
+        let size = read.filled().len();
+        if size == 0 {
+            break;
+        }
+
+        filled += size;
+    }
+
+    Ok(filled)
+}
+
+async fn write_all<W: AsyncWrite + Unpin>(writer: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let size = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, buf)).await?;
+        if size == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+
+        buf.advance(size);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,358 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::CertificateHash;
+
+// Trusts a server by the hash of its certificate, with the same rules as a browser:
+// the certificate must match one of the hashes, currently be valid, and be valid for at most 14 days.
+// The chain and server name aren't checked, but the handshake is still signed by the certificate's key.
+pub(crate) struct HashVerifier {
+    hashes: Vec<CertificateHash>,
+}
+
+impl HashVerifier {
+    pub fn new(hashes: Vec<CertificateHash>) -> Self {
+        Self { hashes }
+    }
+}
+
+impl rustls::client::ServerCertVerifier for HashVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        if !self.hashes.contains(&CertificateHash::new(end_entity)) {
+            return Err(rustls::CertificateError::UnknownIssuer.into());
+        }
+
+        let (not_before, not_after) =
+            validity(&end_entity.0).ok_or(rustls::CertificateError::BadEncoding)?;
+
+        if now < not_before {
+            return Err(rustls::CertificateError::NotValidYet.into());
+        }
+
+        if now > not_after {
+            return Err(rustls::CertificateError::Expired.into());
+        }
+
+        match not_after.duration_since(not_before) {
+            Ok(period) if period <= CertificateHash::MAX_VALIDITY => {}
+            _ => return Err(rustls::CertificateError::ApplicationVerificationFailure.into()),
+        }
+
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+// Read the validity period of a DER-encoded X.509 certificate, which rustls doesn't expose.
+//
+// Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL, serialNumber, signature, issuer, validity, ... }, ... }
+fn validity(cert: &[u8]) -> Option<(SystemTime, SystemTime)> {
+    let (cert, _) = der(cert, 0x30)?;
+    let (tbs, _) = der(cert, 0x30)?;
+
+    let tbs = match der(tbs, 0xa0) {
+        Some((_, rest)) => rest,
+        None => tbs,
+    };
+
+    let (_, tbs) = der(tbs, 0x02)?; // serialNumber
+    let (_, tbs) = der(tbs, 0x30)?; // signature
+    let (_, tbs) = der(tbs, 0x30)?; // issuer
+    let (validity, _) = der(tbs, 0x30)?;
+
+    let (not_before, validity) = time(validity)?;
+    let (not_after, _) = time(validity)?;
+
+    Some((not_before, not_after))
+}
+
+// Split off a DER value with the given tag, returning its contents and the remaining input.
+fn der(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual, input) = input.split_first()?;
+    if actual != tag {
+        return None;
+    }
+
+    let (&first, mut input) = input.split_first()?;
+    let size = match first {
+        0..=0x7f => first as usize,
+        0x81..=0x84 => {
+            let (bytes, rest) = split(input, (first & 0x7f) as usize)?;
+            input = rest;
+            bytes.iter().fold(0, |size, b| (size << 8) | *b as usize)
+        }
+        _ => return None,
+    };
+
+    split(input, size)
+}
+
+fn split(input: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+    match at <= input.len() {
+        true => Some(input.split_at(at)),
+        false => None,
+    }
+}
+
+// Split off a UTCTime (YYMMDDHHMMSSZ) or GeneralizedTime (YYYYMMDDHHMMSSZ), the only forms allowed in a certificate.
+fn time(input: &[u8]) -> Option<(SystemTime, &[u8])> {
+    let (year, digits, rest) = match der(input, 0x17) {
+        Some((digits, rest)) => {
+            let year = number(digits.get(..2)?)?;
+            let year = if year < 50 { 2000 + year } else { 1900 + year };
+            (year, digits.get(2..)?, rest)
+        }
+        None => {
+            let (digits, rest) = der(input, 0x18)?;
+            (number(digits.get(..4)?)?, digits.get(4..)?, rest)
+        }
+    };
+
+    if digits.len() != 11 || digits[10] != b'Z' {
+        return None;
+    }
+
+    let month = number(&digits[0..2])?;
+    let day = number(&digits[2..4])?;
+    let hour = number(&digits[4..6])?;
+    let minute = number(&digits[6..8])?;
+    let second = number(&digits[8..10])?;
+
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // The number of days since 1970-01-01 in the proleptic Gregorian calendar, from Howard Hinnant's days_from_civil.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // Earlier times can't be represented, but no certificate we'd trust by hash starts before 1970.
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some((UNIX_EPOCH + Duration::from_secs(seconds), rest))
+}
+
+fn number(digits: &[u8]) -> Option<u64> {
+    digits.iter().try_fold(0, |number, digit| match digit {
+        b'0'..=b'9' => Some(number * 10 + (digit - b'0') as u64),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustls::client::ServerCertVerifier;
+
+    // Encode a DER value, with a long-form length when needed.
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            size @ 0..=0x7f => out.push(size as u8),
+            size @ 0x80..=0xff => out.extend([0x81, size as u8]),
+            size => out.extend([0x82, (size >> 8) as u8, size as u8]),
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    fn utc(time: &str) -> Vec<u8> {
+        tlv(0x17, time.as_bytes())
+    }
+
+    fn generalized(time: &str) -> Vec<u8> {
+        tlv(0x18, time.as_bytes())
+    }
+
+    // A certificate with just enough structure to find the validity period.
+    fn cert(version: bool, issuer: &[u8], not_before: Vec<u8>, not_after: Vec<u8>) -> Vec<u8> {
+        let mut tbs = Vec::new();
+        if version {
+            tbs.extend(tlv(0xa0, &tlv(0x02, &[2])));
+        }
+        tbs.extend(tlv(0x02, &[1])); // serialNumber
+        tbs.extend(tlv(0x30, &tlv(0x06, &[0x2a]))); // signature
+        tbs.extend(tlv(0x30, issuer));
+        tbs.extend(tlv(0x30, &[not_before, not_after].concat()));
+        tbs.extend(tlv(0x30, &[])); // subject
+
+        let mut cert = tlv(0x30, &tbs);
+        cert.extend(tlv(0x30, &[])); // signatureAlgorithm
+        cert.extend(tlv(0x03, &[0])); // signatureValue
+        tlv(0x30, &cert)
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn utc_time() {
+        // Two digit years before 50 are in the 2000s.
+        let der = cert(true, &[], utc("991231000000Z"), utc("240229130509Z"));
+        assert_eq!(validity(&der), Some((at(946598400), at(1709211909))));
+
+        let der = cert(true, &[], utc("491231000000Z"), utc("491231000000Z"));
+        assert_eq!(validity(&der), Some((at(2524521600), at(2524521600))));
+    }
+
+    #[test]
+    fn generalized_time() {
+        let der = cert(
+            true,
+            &[],
+            generalized("20240229130509Z"),
+            generalized("21010301000000Z"),
+        );
+        assert_eq!(validity(&der), Some((at(1709211909), at(4139078400))));
+
+        // Certificates switch to GeneralizedTime in 2050, so both can appear in the same one.
+        let der = cert(
+            true,
+            &[],
+            utc("491231000000Z"),
+            generalized("20500101000000Z"),
+        );
+        assert_eq!(validity(&der), Some((at(2524521600), at(2524608000))));
+    }
+
+    #[test]
+    fn long_form_lengths() {
+        for size in [0x80, 0xff, 0x100, 0x1234] {
+            let issuer = vec![0; size];
+            let der = cert(true, &issuer, utc("991231000000Z"), utc("240229130509Z"));
+            assert_eq!(
+                validity(&der),
+                Some((at(946598400), at(1709211909))),
+                "{size}"
+            );
+        }
+
+        // Indefinite and oversized lengths aren't allowed in DER.
+        assert_eq!(der(&[0x30, 0x80, 0x00, 0x00], 0x30), None);
+        assert_eq!(der(&[0x30, 0x85, 0, 0, 0, 0, 1, 0], 0x30), None);
+
+        // Neither are lengths longer than the input.
+        assert_eq!(der(&[0x30, 0x82, 0x01], 0x30), None);
+        assert_eq!(der(&[0x30, 0x81, 0x02, 0x00], 0x30), None);
+    }
+
+    #[test]
+    fn no_version() {
+        // The version is optional and defaults to v1.
+        let der = cert(false, &[], utc("991231000000Z"), utc("240229130509Z"));
+        assert_eq!(validity(&der), Some((at(946598400), at(1709211909))));
+    }
+
+    #[test]
+    fn invalid_time() {
+        for time in [
+            utc("9912310000Z"),
+            utc("991231000000"),
+            utc("991231000000+0100"),
+            utc("991331000000Z"),
+            utc("991200000000Z"),
+            utc("991231240000Z"),
+            utc("99123100a000Z"),
+            generalized("991231000000Z"),
+            generalized("19691231000000Z"),
+            tlv(0x19, b"991231000000Z"),
+        ] {
+            let der = cert(true, &[], time.clone(), utc("240229130509Z"));
+            assert_eq!(validity(&der), None, "{:?}", time);
+        }
+    }
+
+    #[test]
+    fn rcgen_validity() {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]);
+        params.not_before = rcgen::date_time_ymd(2049, 12, 31);
+        params.not_after = rcgen::date_time_ymd(2050, 1, 1);
+
+        let der = rcgen::Certificate::from_params(params)
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+        assert_eq!(validity(&der), Some((at(2524521600), at(2524608000))));
+    }
+
+    fn verify(
+        cert: &rustls::Certificate,
+        hashes: Vec<CertificateHash>,
+        now: u64,
+    ) -> Result<(), rustls::Error> {
+        let name = rustls::ServerName::try_from("localhost").unwrap();
+        HashVerifier::new(hashes)
+            .verify_server_cert(cert, &[], &name, &mut std::iter::empty(), &[], at(now))
+            .map(|_| ())
+    }
+
+    // A certificate valid from 2024-02-29 for the given number of days.
+    fn valid_for(days: u8) -> rustls::Certificate {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]);
+        params.not_before = rcgen::date_time_ymd(2024, 2, 29);
+        params.not_after = rcgen::date_time_ymd(2024, 3, days);
+
+        let gen = rcgen::Certificate::from_params(params).unwrap();
+        rustls::Certificate(gen.serialize_der().unwrap())
+    }
+
+    const START: u64 = 1709164800;
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn verify_hash() {
+        let cert = valid_for(13);
+        let hash = CertificateHash::new(&cert);
+        let other = CertificateHash::from([0; 32]);
+
+        assert_eq!(verify(&cert, vec![other, hash], START + DAY), Ok(()));
+        assert_eq!(
+            verify(&cert, vec![other], START + DAY),
+            Err(rustls::CertificateError::UnknownIssuer.into())
+        );
+        assert_eq!(
+            verify(&cert, vec![hash], START - 1),
+            Err(rustls::CertificateError::NotValidYet.into())
+        );
+        assert_eq!(
+            verify(&cert, vec![hash], START + 14 * DAY + 1),
+            Err(rustls::CertificateError::Expired.into())
+        );
+    }
+
+    #[test]
+    fn verify_max_validity() {
+        // Valid until March 14th, exactly 14 days.
+        let cert = valid_for(14);
+        assert_eq!(
+            verify(&cert, vec![CertificateHash::new(&cert)], START + DAY),
+            Ok(())
+        );
+
+        let cert = valid_for(15);
+        assert_eq!(
+            verify(&cert, vec![CertificateHash::new(&cert)], START + DAY),
+            Err(rustls::CertificateError::ApplicationVerificationFailure.into())
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// Each incoming stream is handed to exactly one of the tasks waiting to accept it.
+mod common;
+
+use futures::future::{self, poll_fn};
+use webtransport_generic::Session as _;
+
+use common::{pair, settle, timeout};
+
+#[tokio::test]
+async fn concurrent_accepts() {
+    const ACCEPTORS: usize = 32;
+
+    let pair = pair(None).await;
+
+    let acceptors: Vec<_> = (0..ACCEPTORS)
+        .map(|_| {
+            let server = pair.server.clone();
+            tokio::spawn(async move { server.accept_uni().await.unwrap() })
+        })
+        .collect();
+    settle().await;
+
+    for i in 0..ACCEPTORS {
+        let mut send = pair.client.open_uni().await.unwrap();
+        send.write_all(&[i as u8]).await.unwrap();
+        send.finish().await.unwrap();
+    }
+
+    let mut accepted = Vec::new();
+    for mut recv in timeout(future::try_join_all(acceptors)).await.unwrap() {
+        accepted.extend(recv.read_to_end(1).await.unwrap());
+    }
+
+    accepted.sort();
+    assert_eq!(accepted, (0..ACCEPTORS as u8).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn generic_accepts() {
+    let pair = pair(None).await;
+
+    // Each clone polls with its own waiter, so neither misses its wakeup.
+    let acceptors: Vec<_> = (0..2)
+        .map(|_| {
+            let mut server = pair.server.clone();
+            tokio::spawn(async move { poll_fn(|cx| server.poll_accept_uni(cx)).await.unwrap() })
+        })
+        .collect();
+    settle().await;
+
+    for i in 0..2 {
+        let mut send = pair.client.open_uni().await.unwrap();
+        send.write_all(&[i]).await.unwrap();
+        send.finish().await.unwrap();
+    }
+
+    let mut accepted = Vec::new();
+    for mut recv in timeout(future::try_join_all(acceptors)).await.unwrap() {
+        accepted.extend(recv.read_to_end(1).await.unwrap());
+    }
+
+    accepted.sort();
+    assert_eq!(accepted, [0, 1]);
+}
@@ -0,0 +1,122 @@
+// The client's connection handling: racing addresses, pooling and preconnecting.
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{endpoints, timeout, url};
+use webtransport_quinn::{Session, SessionInfo, SessionListener};
+
+// Records the URI of each session that was opened.
+#[derive(Clone, Default)]
+struct Opened(Arc<Mutex<Vec<http::Uri>>>);
+
+impl SessionListener for Opened {
+    fn on_session_open(&self, session: &SessionInfo) {
+        self.0.lock().unwrap().push(session.uri.clone());
+    }
+}
+
+#[tokio::test]
+async fn connect_addrs_listener() {
+    let (mut client, server) = endpoints();
+    let mut server = server.build().unwrap();
+
+    let opened = Opened::default();
+    client.set_listener(opened.clone());
+
+    let uri = url(&server, "/raced");
+    let addr = server.local_addr().unwrap();
+    let accept = tokio::spawn(async move {
+        let session = server.accept().await.unwrap().ok().await.unwrap();
+        (server, session)
+    });
+
+    // Include an address the client can't reach, so there's more than one attempt.
+    let addrs = ["[::1]:9".parse().unwrap(), addr];
+    let _session = timeout(client.connect_addrs(&uri, &addrs)).await.unwrap();
+    let (_server, _accepted) = accept.await.unwrap();
+
+    assert_eq!(*opened.0.lock().unwrap(), vec![uri]);
+}
+
+// Accept sessions in the background, since a server with pooled connections needs to keep accepting.
+fn serve(mut server: webtransport_quinn::Server) -> tokio::sync::mpsc::UnboundedReceiver<Session> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Some(request) = server.accept().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                if let Ok(session) = request.ok().await {
+                    let _ = tx.send(session);
+                }
+            });
+        }
+    });
+
+    rx
+}
+
+#[tokio::test]
+async fn pool_reuse() {
+    let (client, server) = endpoints();
+    let server = server.max_sessions_per_connection(2).build().unwrap();
+    let uri = url(&server, "/pooled");
+    let mut accepted = serve(server);
+
+    let a = timeout(client.connect(&uri)).await.unwrap();
+    let b = timeout(client.connect(&uri)).await.unwrap();
+    let _accepted = (accepted.recv().await, accepted.recv().await);
+
+    assert_eq!(
+        a.quic_connection().stable_id(),
+        b.quic_connection().stable_id()
+    );
+
+    // The connection is full, so the next session gets its own.
+    let c = timeout(client.connect(&uri)).await.unwrap();
+    assert_ne!(
+        a.quic_connection().stable_id(),
+        c.quic_connection().stable_id()
+    );
+}
+
+#[tokio::test]
+async fn pool_evict_closed() {
+    let (client, server) = endpoints();
+    let server = server.max_sessions_per_connection(4).build().unwrap();
+    let uri = url(&server, "/pooled");
+    let mut accepted = serve(server);
+
+    let a = timeout(client.connect(&uri)).await.unwrap();
+    let _accepted = accepted.recv().await;
+
+    a.quic_connection().close(0u32.into(), b"gone");
+
+    // The closed connection is skipped instead of failing the session.
+    let b = timeout(client.connect(&uri)).await.unwrap();
+    let _accepted = accepted.recv().await;
+
+    assert_ne!(
+        a.quic_connection().stable_id(),
+        b.quic_connection().stable_id()
+    );
+    assert!(b.quic_connection().close_reason().is_none());
+}
+
+#[tokio::test]
+async fn preconnect_reuse() {
+    let (client, server) = endpoints();
+    let server = server.build().unwrap();
+    let uri = url(&server, "/warm");
+    let endpoint = server.endpoint().clone();
+    let mut accepted = serve(server);
+
+    timeout(client.preconnect(&uri)).await.unwrap();
+
+    // Refuse new connections, so the session can only succeed on the warm one.
+    endpoint.set_server_config(None);
+
+    let _session = timeout(client.connect(&uri)).await.unwrap();
+    let _accepted = accepted.recv().await;
+}
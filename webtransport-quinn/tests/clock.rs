@@ -0,0 +1,108 @@
+// Stalls and rate limits use the session's clock, so they can be tested without waiting in real time.
+mod common;
+
+use std::time::Duration;
+
+use common::{pair_with_clock, settle, timeout};
+use webtransport_quinn::{ManualClock, RateLimit, StallAction, StallPolicy, WriteError};
+
+#[tokio::test]
+async fn rate_limit() {
+    let clock = ManualClock::new();
+    let pair = pair_with_clock(clock.clone()).await;
+
+    pair.server.set_rate_limit(Some(RateLimit::new(1000, 1000)));
+
+    let mut send = pair.server.open_uni().await.unwrap();
+
+    // The burst is sent right away, but the rest waits for the bucket to refill.
+    let write = tokio::spawn(async move {
+        send.write_all(&[0; 2000]).await.unwrap();
+        send
+    });
+    settle().await;
+    assert!(!write.is_finished());
+
+    clock.advance(Duration::from_millis(500));
+    settle().await;
+    assert!(!write.is_finished());
+
+    clock.advance(Duration::from_millis(500));
+    timeout(write).await.unwrap();
+}
+
+#[tokio::test]
+async fn stall_reset() {
+    let clock = ManualClock::new();
+    let pair = pair_with_clock(clock.clone()).await;
+
+    pair.server.set_stall_policy(Some(StallPolicy {
+        timeout: Duration::from_secs(10),
+        action: StallAction::Reset(7),
+    }));
+
+    let mut send = pair.server.open_uni().await.unwrap();
+
+    // The client never reads, so the writes eventually block on flow control.
+    let write = tokio::spawn(async move {
+        let buf = vec![0; 64 * 1024];
+        loop {
+            if let Err(err) = send.write(&buf).await {
+                return err;
+            }
+        }
+    });
+    settle().await;
+    assert!(!write.is_finished());
+
+    // Real time doesn't matter, only the clock.
+    clock.advance(Duration::from_secs(9));
+    settle().await;
+    assert!(!write.is_finished());
+    assert!(pair.server.blocked().streams == 1);
+
+    clock.advance(Duration::from_secs(1));
+    match timeout(write).await.unwrap() {
+        WriteError::Stalled(7) => {}
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let blocked = pair.server.blocked();
+    assert_eq!(blocked.streams, 0);
+    assert_eq!(blocked.total, Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn stall_dropped_write() {
+    let clock = ManualClock::new();
+    let pair = pair_with_clock(clock.clone()).await;
+
+    pair.server.set_stall_policy(Some(StallPolicy {
+        timeout: Duration::from_secs(10),
+        action: StallAction::Reset(7),
+    }));
+
+    let mut send = pair.server.open_uni().await.unwrap();
+    let buf = vec![0; 64 * 1024];
+
+    // Write until blocked, then give up on the write.
+    while let Ok(res) = tokio::time::timeout(Duration::from_millis(100), send.write(&buf)).await {
+        res.unwrap();
+    }
+
+    // Nothing is blocked anymore, so the time until the next write doesn't count.
+    assert_eq!(pair.server.blocked().streams, 0);
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(send.blocked(), Duration::ZERO);
+
+    // The next write starts a new stall timer, instead of inheriting the old one.
+    let write = tokio::spawn(async move { send.write(&buf).await });
+    settle().await;
+    assert!(!write.is_finished());
+
+    clock.advance(Duration::from_secs(10));
+    match timeout(write).await.unwrap() {
+        Err(WriteError::Stalled(7)) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
@@ -0,0 +1,157 @@
+// Closing a session wakes everything waiting on it, which then fails with the close reason.
+// Each test closes the session from the other side while an operation is pending.
+mod common;
+
+use common::{assert_closed_by_peer, pair, settle, timeout};
+use webtransport_quinn::{ReadError, SessionLimits, WriteError};
+
+const CODE: u32 = 42;
+const REASON: &str = "going away";
+
+#[tokio::test]
+async fn pending_read() {
+    let pair = pair(None).await;
+
+    let mut send = pair.client.open_uni().await.unwrap();
+    send.write_all(b"hi").await.unwrap();
+
+    let mut recv = pair.server.accept_uni().await.unwrap();
+    let mut buf = [0; 2];
+    recv.read_exact(&mut buf).await.unwrap();
+
+    let read = tokio::spawn(async move { recv.read(&mut [0; 16]).await });
+    settle().await;
+    assert!(!read.is_finished());
+
+    pair.client.close(CODE, REASON);
+
+    match timeout(read).await.unwrap() {
+        Err(ReadError::SessionError(err)) => assert_closed_by_peer(&err, CODE, REASON),
+        res => panic!("unexpected read result: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn pending_write() {
+    let pair = pair(None).await;
+
+    let mut send = pair.server.open_uni().await.unwrap();
+
+    // The client never reads, so the writes eventually block on flow control.
+    let write = tokio::spawn(async move {
+        let buf = vec![0; 64 * 1024];
+        loop {
+            if let Err(err) = send.write(&buf).await {
+                return err;
+            }
+        }
+    });
+    settle().await;
+    assert!(!write.is_finished());
+
+    pair.client.close(CODE, REASON);
+
+    match timeout(write).await.unwrap() {
+        WriteError::SessionError(err) => assert_closed_by_peer(&err, CODE, REASON),
+        err => panic!("unexpected write error: {:?}", err),
+    }
+}
+
+#[tokio::test]
+async fn pending_accept_uni() {
+    let pair = pair(None).await;
+
+    let server = pair.server.clone();
+    let accept = tokio::spawn(async move { server.accept_uni().await.map(|_| ()) });
+    settle().await;
+    assert!(!accept.is_finished());
+
+    pair.client.close(CODE, REASON);
+
+    let err = timeout(accept).await.unwrap().unwrap_err();
+    assert_closed_by_peer(&err, CODE, REASON);
+}
+
+#[tokio::test]
+async fn pending_accept_bi() {
+    let pair = pair(None).await;
+
+    let server = pair.server.clone();
+    let accept = tokio::spawn(async move { server.accept_bi().await.map(|_| ()) });
+    settle().await;
+    assert!(!accept.is_finished());
+
+    pair.client.close(CODE, REASON);
+
+    let err = timeout(accept).await.unwrap().unwrap_err();
+    assert_closed_by_peer(&err, CODE, REASON);
+}
+
+// The server doesn't let the client open any streams, so opening one waits for a WT_MAX_STREAMS capsule.
+fn no_streams() -> Option<(SessionLimits, SessionLimits)> {
+    let server = SessionLimits {
+        max_streams_uni: 0,
+        max_streams_bidi: 0,
+        ..Default::default()
+    };
+
+    Some((SessionLimits::default(), server))
+}
+
+#[tokio::test]
+async fn pending_open_uni() {
+    let pair = pair(no_streams()).await;
+
+    let client = pair.client.clone();
+    let open = tokio::spawn(async move { client.open_uni().await.map(|_| ()) });
+    settle().await;
+    assert!(!open.is_finished());
+
+    pair.server.close(CODE, REASON);
+
+    let err = timeout(open).await.unwrap().unwrap_err();
+    assert_closed_by_peer(&err, CODE, REASON);
+}
+
+#[tokio::test]
+async fn pending_open_bi() {
+    let pair = pair(no_streams()).await;
+
+    let client = pair.client.clone();
+    let open = tokio::spawn(async move { client.open_bi().await.map(|_| ()) });
+    settle().await;
+    assert!(!open.is_finished());
+
+    pair.server.close(CODE, REASON);
+
+    let err = timeout(open).await.unwrap().unwrap_err();
+    assert_closed_by_peer(&err, CODE, REASON);
+}
+
+#[tokio::test]
+async fn pending_recv_datagram() {
+    let pair = pair(None).await;
+
+    let server = pair.server.clone();
+    let recv = tokio::spawn(async move { server.recv_datagram().await });
+    settle().await;
+    assert!(!recv.is_finished());
+
+    pair.client.close(CODE, REASON);
+
+    let err = timeout(recv).await.unwrap().unwrap_err();
+    assert_closed_by_peer(&err, CODE, REASON);
+}
+
+#[tokio::test]
+async fn close_connection() {
+    let pair = pair(None).await;
+
+    // The connection isn't shared, so it's closed right away instead of waiting for the peer to act on the capsule.
+    pair.client.close(CODE, REASON);
+    assert!(quinn::Connection::close_reason(&pair.client).is_some());
+
+    // The peer's QUIC connection is closed with the code and reason, even though nothing polls its session.
+    let err = timeout(quinn::Connection::closed(&pair.server)).await;
+    assert_closed_by_peer(&err.into(), CODE, REASON);
+}
@@ -0,0 +1,116 @@
+// Shared setup for the loopback tests, which connect a client and server over localhost.
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use webtransport_quinn::{
+    ClientBuilder, ManualClock, ServerBuilder, Session, SessionError, SessionLimits,
+};
+
+// A connected pair of sessions, keeping the endpoints alive for as long as the sessions are used.
+pub struct Pair {
+    pub client: Session,
+    pub server: Session,
+    _endpoints: (webtransport_quinn::Client, webtransport_quinn::Server),
+}
+
+// Connect a session over localhost, with the given limits advertised by both the client and server.
+pub async fn pair(limits: Option<(SessionLimits, SessionLimits)>) -> Pair {
+    connect(limits, None).await
+}
+
+// Connect a session over localhost, with both endpoints using the given clock.
+pub async fn pair_with_clock(clock: ManualClock) -> Pair {
+    connect(None, Some(clock)).await
+}
+
+// A client that trusts the server's self-signed certificate, and a server builder that's ready to bind.
+pub fn endpoints() -> (webtransport_quinn::Client, ServerBuilder) {
+    let gen = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let cert = rustls::Certificate(gen.serialize_der().unwrap());
+    let key = rustls::PrivateKey(gen.serialize_private_key_der());
+
+    let server = ServerBuilder::new(vec![cert.clone()], key).bind("127.0.0.1:0".parse().unwrap());
+    let client = ClientBuilder::new()
+        .bind("127.0.0.1:0".parse().unwrap())
+        .without_native_roots()
+        .add_root_certificate(cert)
+        .build()
+        .unwrap();
+
+    (client, server)
+}
+
+// The URL to connect to the server over localhost.
+pub fn url(server: &webtransport_quinn::Server, path: &str) -> http::Uri {
+    format!(
+        "https://localhost:{}{}",
+        server.local_addr().unwrap().port(),
+        path
+    )
+    .parse()
+    .unwrap()
+}
+
+async fn connect(
+    limits: Option<(SessionLimits, SessionLimits)>,
+    clock: Option<ManualClock>,
+) -> Pair {
+    let (mut client, mut server) = endpoints();
+
+    if let Some((client_limits, server_limits)) = limits {
+        server = server.session_limits(server_limits);
+        client.set_session_limits(Some(client_limits));
+    }
+
+    let mut server = server.build().unwrap();
+
+    if let Some(clock) = clock {
+        server.set_clock(clock.clone());
+        client.set_clock(clock);
+    }
+    let uri = url(&server, "/");
+
+    let accept = tokio::spawn(async move {
+        let session = server.accept().await.unwrap().ok().await.unwrap();
+        (server, session)
+    });
+
+    let session = client.connect(&uri).await.unwrap();
+    let (server, server_session) = accept.await.unwrap();
+
+    Pair {
+        client: session,
+        server: server_session,
+        _endpoints: (client, server),
+    }
+}
+
+// Wait for the operation, failing if it's still pending, so a missed wakeup fails the test instead of hanging it.
+pub async fn timeout<F: std::future::Future>(fut: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(5), fut)
+        .await
+        .expect("timed out")
+}
+
+// Give an operation the chance to start waiting, before the session is closed under it.
+pub async fn settle() {
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+// Check that the peer closed the session with the given code and reason.
+pub fn assert_closed_by_peer(err: &SessionError, code: u32, reason: &str) {
+    match err {
+        SessionError::ConnectionError(quinn::ConnectionError::ApplicationClosed(close)) => {
+            let actual = webtransport_proto::error_from_http3(close.error_code.into_inner());
+            assert_eq!(actual, Some(code), "wrong code in {:?}", close);
+            assert_eq!(
+                close.reason,
+                reason.as_bytes(),
+                "wrong reason in {:?}",
+                close
+            );
+        }
+        err => panic!("expected the peer to close the session: {:?}", err),
+    }
+}
@@ -0,0 +1,68 @@
+// The facade runs the operations on a stream in the order they were created, regardless of when they're first polled.
+mod common;
+
+use common::{pair, settle, timeout};
+use webtransport_quinn::facade;
+
+#[tokio::test]
+async fn writes_in_creation_order() {
+    let pair = pair(None).await;
+    let client = facade::Session::from(pair.client);
+
+    let send = timeout(client.open_uni()).await.unwrap();
+    let first = send.write(b"first ".to_vec());
+    let second = send.write(b"second".to_vec());
+
+    // Poll the second write before the first, as a foreign executor might.
+    let second = tokio::spawn(second);
+    settle().await;
+    assert!(!second.is_finished());
+
+    timeout(first).await.unwrap();
+    timeout(second).await.unwrap().unwrap();
+    timeout(send.finish()).await.unwrap();
+
+    let mut recv = pair.server.accept_uni().await.unwrap();
+    let data = timeout(recv.read_to_end(1024)).await.unwrap();
+    assert_eq!(data, b"first second");
+}
+
+#[tokio::test]
+async fn reads_in_creation_order() {
+    let pair = pair(None).await;
+    let server = facade::Session::from(pair.server);
+
+    let mut send = pair.client.open_uni().await.unwrap();
+    send.write_all(b"ab").await.unwrap();
+    send.finish().await.unwrap();
+
+    let recv = match timeout(server.next_event()).await {
+        facade::Event::UniStream(recv) => recv,
+        _ => panic!("expected a stream"),
+    };
+
+    let first = recv.read(1);
+    let second = tokio::spawn(recv.read(1));
+    settle().await;
+    assert!(!second.is_finished());
+
+    assert_eq!(timeout(first).await.unwrap(), Some(b"a".to_vec()));
+    assert_eq!(timeout(second).await.unwrap().unwrap(), Some(b"b".to_vec()));
+}
+
+#[tokio::test]
+async fn dropped_operation() {
+    let pair = pair(None).await;
+    let client = facade::Session::from(pair.client);
+
+    let send = timeout(client.open_uni()).await.unwrap();
+
+    // An operation that's dropped without being polled doesn't hold up the ones after it.
+    drop(send.write(b"dropped".to_vec()));
+    timeout(send.write(b"kept".to_vec())).await.unwrap();
+    timeout(send.finish()).await.unwrap();
+
+    let mut recv = pair.server.accept_uni().await.unwrap();
+    let data = timeout(recv.read_to_end(1024)).await.unwrap();
+    assert_eq!(data, b"kept");
+}
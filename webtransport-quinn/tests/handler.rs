@@ -0,0 +1,81 @@
+// Running a SessionHandler per session, where a handler that panics only loses its own session.
+mod common;
+
+use std::future::Future;
+
+use common::{endpoints, timeout, url};
+use webtransport_proto::ConnectError::WrongStatus;
+use webtransport_quinn::{ClientError, ConnectError, Request, Response, Session, SessionHandler};
+
+// Panics in its synchronous body for some paths, and echoes a bidirectional stream otherwise.
+#[derive(Clone)]
+struct Panicky;
+
+struct Path(String);
+
+impl SessionHandler for Panicky {
+    fn respond(&self, request: &mut Request) -> impl Future<Output = Response> + Send {
+        if request.path() == "/respond" {
+            panic!("synchronous panic in respond");
+        }
+
+        request
+            .extensions()
+            .insert(Path(request.path().to_string()));
+        async { Response::Accept }
+    }
+
+    fn handle(self, session: Session) -> impl Future<Output = ()> + Send {
+        if session.extensions().get::<Path>().unwrap().0 == "/handle" {
+            panic!("synchronous panic in handle");
+        }
+
+        async move {
+            let (mut send, mut recv) = session.accept_bi().await.unwrap();
+            let data = recv.read_to_end(1024).await.unwrap();
+            send.write_all(&data).await.unwrap();
+            send.finish().await.unwrap();
+            session.closed().await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn panic_before_future() {
+    let (client, server) = endpoints();
+    let mut server = server.build().unwrap();
+    let (respond, handle, echo) = (
+        url(&server, "/respond"),
+        url(&server, "/handle"),
+        url(&server, "/"),
+    );
+
+    let serve = tokio::spawn(async move { server.serve(Panicky, std::future::pending()).await });
+
+    // Each panic only affects its own session.
+    match timeout(client.connect(&respond)).await {
+        Err(ClientError::ConnectError(ConnectError::ProtoError(WrongStatus(Some(status))))) => {
+            assert_eq!(status, http::StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("expected the request to be refused"),
+    }
+
+    // The session is closed right after it's accepted, which may be before the client reads the response.
+    let reason = match timeout(client.connect(&handle)).await {
+        Ok(session) => timeout(session.closed()).await.reason.into_bytes(),
+        Err(ClientError::ConnectError(ConnectError::ReadError(
+            quinn::ReadError::ConnectionLost(quinn::ConnectionError::ApplicationClosed(close)),
+        ))) => close.reason.to_vec(),
+        Err(err) => panic!("unexpected error: {:?}", err),
+    };
+    assert_eq!(reason, b"handler panicked");
+
+    let session = timeout(client.connect(&echo)).await.unwrap();
+    let (mut send, mut recv) = session.open_bi().await.unwrap();
+    send.write_all(b"echo").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(timeout(recv.read_to_end(1024)).await.unwrap(), b"echo");
+
+    assert!(!serve.is_finished());
+}
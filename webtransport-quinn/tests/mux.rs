@@ -0,0 +1,165 @@
+// Sessions sharing a connection, accepted with accept_sessions.
+mod common;
+
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use common::{endpoints, timeout, url};
+use webtransport_proto::ConnectRequest;
+use webtransport_quinn::{Session, Sessions};
+
+// H3_REQUEST_REJECTED, used to refuse a request unprocessed.
+const REQUEST_REJECTED: u64 = 0x10b;
+
+// Check that nothing arrives for a while, polling the future so the connection makes progress meanwhile.
+async fn nothing<F: std::future::Future>(fut: F) {
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), fut)
+            .await
+            .is_err(),
+        "unexpectedly ready"
+    );
+}
+
+// Two client sessions pooled on one connection, and the server's side of each.
+struct Shared {
+    clients: (Session, Session),
+    servers: (Session, Session),
+    sessions: Sessions,
+    uri: http::Uri,
+    _endpoints: (webtransport_quinn::Client, webtransport_quinn::Server),
+}
+
+async fn shared() -> Shared {
+    let (client, server) = endpoints();
+    let server = server.build().unwrap();
+    let uri = url(&server, "/a");
+
+    let endpoint = server.endpoint().clone();
+    let accept = tokio::spawn(async move {
+        let conn = endpoint.accept().await.unwrap().await.unwrap();
+        let sessions = webtransport_quinn::accept_sessions(conn, 2).await.unwrap();
+
+        let a = sessions.accept().await.unwrap();
+        assert_eq!(a.path(), "/a");
+        let a = a.ok().await.unwrap();
+
+        let b = sessions.accept().await.unwrap();
+        assert_eq!(b.path(), "/b");
+        let b = b.ok().await.unwrap();
+
+        (sessions, a, b)
+    });
+
+    let a = timeout(client.connect(&uri)).await.unwrap();
+    let b = timeout(client.connect(&url(&server, "/b"))).await.unwrap();
+    let (sessions, server_a, server_b) = accept.await.unwrap();
+
+    // The client pooled the second session on the same connection.
+    assert_eq!(
+        a.quic_connection().stable_id(),
+        b.quic_connection().stable_id()
+    );
+
+    Shared {
+        clients: (a, b),
+        servers: (server_a, server_b),
+        sessions,
+        uri,
+        _endpoints: (client, server),
+    }
+}
+
+#[tokio::test]
+async fn route_streams() {
+    let shared = shared().await;
+    let (a, b) = &shared.clients;
+    let (server_a, server_b) = &shared.servers;
+
+    // Sent in the opposite order they're accepted, so they can't just be handed out in order.
+    let mut send = b.open_uni().await.unwrap();
+    send.write_all(b"uni b").await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut send = a.open_uni().await.unwrap();
+    send.write_all(b"uni a").await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut recv = timeout(server_a.accept_uni()).await.unwrap();
+    assert_eq!(recv.read_to_end(1024).await.unwrap(), b"uni a");
+    let mut recv = timeout(server_b.accept_uni()).await.unwrap();
+    assert_eq!(recv.read_to_end(1024).await.unwrap(), b"uni b");
+
+    // And the other way, for bidirectional streams opened by the server.
+    let (mut send, _recv) = server_b.open_bi().await.unwrap();
+    send.write_all(b"bi b").await.unwrap();
+    send.finish().await.unwrap();
+
+    nothing(a.accept_bi()).await;
+    let (_send, mut recv) = timeout(b.accept_bi()).await.unwrap();
+    assert_eq!(recv.read_to_end(1024).await.unwrap(), b"bi b");
+}
+
+#[tokio::test]
+async fn route_datagrams() {
+    let shared = shared().await;
+    let (a, b) = &shared.clients;
+    let (server_a, server_b) = &shared.servers;
+
+    b.send_datagram(Bytes::from_static(b"datagram b")).unwrap();
+    a.send_datagram(Bytes::from_static(b"datagram a")).unwrap();
+
+    assert_eq!(
+        timeout(server_a.recv_datagram()).await.unwrap(),
+        "datagram a"
+    );
+    assert_eq!(
+        timeout(server_b.recv_datagram()).await.unwrap(),
+        "datagram b"
+    );
+
+    server_a.send_datagram(Bytes::from_static(b"to a")).unwrap();
+
+    nothing(b.recv_datagram()).await;
+    assert_eq!(timeout(a.recv_datagram()).await.unwrap(), "to a");
+}
+
+#[tokio::test]
+async fn refuse_over_limit() {
+    let shared = shared().await;
+
+    // The client won't exceed the limit itself, so send a third CONNECT on the connection directly.
+    let conn = shared.clients.0.quic_connection().clone();
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+
+    let request = ConnectRequest {
+        uri: shared.uri.clone(),
+        headers: http::HeaderMap::new(),
+    };
+    let mut buf = BytesMut::new();
+    request.encode(&mut buf);
+    send.write_all(&buf).await.unwrap();
+
+    // Accepting drives the connection, but the request never reaches it.
+    let sessions = shared.sessions.clone();
+    let pending = tokio::spawn(async move { sessions.accept().await.map(|_| ()) });
+
+    match timeout(recv.read_to_end(1024)).await {
+        Err(quinn::ReadToEndError::Read(quinn::ReadError::Reset(code))) => {
+            assert_eq!(code.into_inner(), REQUEST_REJECTED)
+        }
+        res => panic!("expected the request to be refused: {:?}", res),
+    }
+
+    // The existing sessions are unaffected.
+    let (a, _) = &shared.clients;
+    let (server_a, _) = &shared.servers;
+    a.send_datagram(Bytes::from_static(b"still here")).unwrap();
+    assert_eq!(
+        timeout(server_a.recv_datagram()).await.unwrap(),
+        "still here"
+    );
+
+    assert!(!pending.is_finished());
+    pending.abort();
+}
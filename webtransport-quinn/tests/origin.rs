@@ -0,0 +1,94 @@
+// Refusing sessions by their origin header, see Server::set_origin_policy.
+mod common;
+
+use common::{endpoints, timeout, url};
+use webtransport_proto::ConnectError::WrongStatus;
+use webtransport_quinn::{ClientError, ConnectError, OriginPolicy};
+
+// Connect with the origin header, returning whether the policy allowed it.
+// The origin is the string `{port}` replaced with the server's port, so it can match the authority.
+async fn allowed(policy: OriginPolicy, origin: Option<&str>) -> bool {
+    let (client, server) = endpoints();
+    let mut server = server.build().unwrap();
+    server.set_origin_policy(Some(policy));
+
+    let uri = url(&server, "/");
+    let port = server.local_addr().unwrap().port();
+
+    // Refused requests are answered by the server itself, so this only returns for an allowed one.
+    let accept = tokio::spawn(async move {
+        let session = server.accept().await.unwrap().ok().await.unwrap();
+        (server, session)
+    });
+
+    let mut request = http::Request::builder().uri(uri);
+    if let Some(origin) = origin {
+        request = request.header("origin", origin.replace("{port}", &port.to_string()));
+    }
+
+    let res = timeout(client.connect_with(request.body(()).unwrap())).await;
+    accept.abort();
+
+    match res {
+        Ok(_) => true,
+        Err(ClientError::ConnectError(ConnectError::ProtoError(WrongStatus(Some(status))))) => {
+            assert_eq!(status, http::StatusCode::FORBIDDEN);
+            false
+        }
+        Err(err) => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[tokio::test]
+async fn allow_list() {
+    let policy = || OriginPolicy::allow_list(["https://example.com", "https://localhost:4443"]);
+
+    assert!(allowed(policy(), Some("https://example.com")).await);
+    assert!(allowed(policy(), Some("HTTPS://EXAMPLE.COM")).await);
+    assert!(allowed(policy(), Some("https://localhost:4443")).await);
+
+    assert!(!allowed(policy(), Some("https://evil.com")).await);
+    assert!(!allowed(policy(), Some("http://example.com")).await);
+    assert!(!allowed(policy(), Some("https://localhost")).await);
+    assert!(!allowed(policy(), None).await);
+}
+
+#[tokio::test]
+async fn same_origin() {
+    let policy = OriginPolicy::same_origin;
+
+    assert!(allowed(policy(), Some("https://localhost:{port}")).await);
+    assert!(allowed(policy(), Some("https://LOCALHOST:{port}")).await);
+
+    // A different port, host or scheme is a different origin.
+    assert!(!allowed(policy(), Some("https://localhost:1")).await);
+    assert!(!allowed(policy(), Some("https://localhost")).await);
+    assert!(!allowed(policy(), Some("https://example.com:{port}")).await);
+    assert!(!allowed(policy(), Some("http://localhost:{port}")).await);
+    assert!(!allowed(policy(), Some("not an origin")).await);
+    assert!(!allowed(policy(), None).await);
+}
+
+#[tokio::test]
+async fn custom() {
+    let policy = || {
+        OriginPolicy::custom(|origin, request| {
+            origin.ends_with(".example.com") && request.path() == "/"
+        })
+    };
+
+    assert!(allowed(policy(), Some("https://app.example.com")).await);
+    assert!(!allowed(policy(), Some("https://example.org")).await);
+    assert!(!allowed(policy(), None).await);
+}
+
+#[tokio::test]
+async fn allow_missing() {
+    let policy = || OriginPolicy::allow_list(["https://example.com"]).allow_missing();
+
+    assert!(allowed(policy(), None).await);
+    assert!(allowed(policy(), Some("https://example.com")).await);
+
+    // A browser's origin is still checked.
+    assert!(!allowed(policy(), Some("https://evil.com")).await);
+}
@@ -0,0 +1,68 @@
+// Running a handler per incoming stream, where a handler that panics only loses its own streams.
+mod common;
+
+use common::{pair, settle, timeout};
+use webtransport_quinn::{CloseSource, HandlerPolicy, IncomingStream};
+
+#[tokio::test]
+async fn panic_before_future() {
+    let pair = pair(None).await;
+
+    // Unidirectional streams panic before the handler returns its future, while bidirectional streams are echoed.
+    let server = pair.server.clone();
+    let serve = tokio::spawn(async move {
+        server
+            .serve_incoming(16, |stream| {
+                let (mut send, mut recv) = match stream {
+                    IncomingStream::Uni(_) => panic!("synchronous panic"),
+                    IncomingStream::Bi(send, recv) => (send, recv),
+                };
+
+                async move {
+                    let data = recv.read_to_end(1024).await.unwrap();
+                    send.write_all(&data).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            })
+            .await
+    });
+
+    let mut uni = pair.client.open_uni().await.unwrap();
+    uni.write_all(b"panic").await.unwrap();
+    uni.finish().await.unwrap();
+    settle().await;
+
+    // The other streams are still served.
+    for _ in 0..2 {
+        let (mut send, mut recv) = pair.client.open_bi().await.unwrap();
+        send.write_all(b"echo").await.unwrap();
+        send.finish().await.unwrap();
+        assert_eq!(timeout(recv.read_to_end(1024)).await.unwrap(), b"echo");
+    }
+
+    assert!(!serve.is_finished());
+}
+
+#[tokio::test]
+async fn panic_before_future_with_policy() {
+    let pair = pair(None).await;
+
+    let server = pair.server.clone();
+    let serve = tokio::spawn(async move {
+        server
+            .serve_incoming_with(16, HandlerPolicy::new(7), |_| -> std::future::Ready<()> {
+                panic!("synchronous panic")
+            })
+            .await
+    });
+
+    let mut uni = pair.client.open_uni().await.unwrap();
+    uni.write_all(b"panic").await.unwrap();
+    uni.finish().await.unwrap();
+
+    // The policy closes the session, instead of the panic unwinding through the serve loop.
+    timeout(serve).await.unwrap();
+    let close = timeout(pair.client.closed()).await;
+    assert_eq!(close.source, CloseSource::Peer);
+    assert_eq!((close.code, close.reason.as_str()), (7, "handler panicked"));
+}
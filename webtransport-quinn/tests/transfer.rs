@@ -0,0 +1,163 @@
+// Sending files with FileSender and verifying them with FileReceiver.
+mod common;
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use common::{pair, timeout};
+use tokio::io::{AsyncRead, ReadBuf};
+use webtransport_quinn::{FileReceiver, FileSender, ReadExactError, TransferError};
+
+const CHUNK_SIZE: usize = 100;
+
+fn data() -> Vec<u8> {
+    (0..1050u32).map(|i| i as u8).collect()
+}
+
+// Reads the data, then fails instead of ending, as if the disk went away.
+struct Failing {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl AsyncRead for Failing {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos == self.data.len() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let size = buf.remaining().min(self.data.len() - self.pos);
+        buf.put_slice(&self.data[self.pos..self.pos + size]);
+        self.pos += size;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn complete() {
+    let pair = pair(None).await;
+    let data = data();
+
+    let mut send = pair.client.open_uni().await.unwrap();
+    let sender = FileSender::new().with_chunk_size(CHUNK_SIZE);
+    let size = sender.send(&mut send, &data[..], 0).await.unwrap();
+    assert_eq!(size, data.len() as u64);
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    let mut receiver = FileReceiver::new();
+    let mut file = Vec::new();
+    timeout(receiver.recv(&mut recv, &mut file)).await.unwrap();
+
+    assert!(receiver.is_complete());
+    assert_eq!(receiver.offset(), data.len() as u64);
+    assert_eq!(file, data);
+}
+
+#[tokio::test]
+async fn resume() {
+    let pair = pair(None).await;
+    let data = data();
+    let sender = FileSender::new().with_chunk_size(CHUNK_SIZE);
+
+    // The first attempt fails partway through a chunk.
+    let mut send = pair.client.open_uni().await.unwrap();
+    let reader = Failing {
+        data: data[..450].to_vec(),
+        pos: 0,
+    };
+    assert!(matches!(
+        sender.send(&mut send, reader, 0).await,
+        Err(TransferError::Io(_))
+    ));
+
+    // The stream ends without the end marker, so the receiver can tell it's incomplete.
+    send.finish().await.unwrap();
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    let mut receiver = FileReceiver::new();
+    let mut file = Vec::new();
+    assert!(matches!(
+        timeout(receiver.recv(&mut recv, &mut file)).await,
+        Err(TransferError::Read(ReadExactError::FinishedEarly))
+    ));
+
+    // Only the verified chunks were written.
+    assert!(!receiver.is_complete());
+    assert_eq!(receiver.offset(), 400);
+    assert_eq!(file, data[..400]);
+
+    // A sender that starts over is refused.
+    let mut send = pair.client.open_uni().await.unwrap();
+    sender.send(&mut send, &data[..], 0).await.unwrap();
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    match timeout(receiver.recv(&mut recv, &mut file)).await {
+        Err(TransferError::OffsetMismatch { expected, actual }) => {
+            assert_eq!((expected, actual), (400, 0))
+        }
+        res => panic!("expected an offset mismatch: {:?}", res),
+    }
+
+    // Resume from the receiver's offset on a new stream.
+    let offset = receiver.offset();
+    let mut send = pair.client.open_uni().await.unwrap();
+    let size = sender
+        .send(&mut send, &data[offset as usize..], offset)
+        .await
+        .unwrap();
+    assert_eq!(size, data.len() as u64);
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    timeout(receiver.recv(&mut recv, &mut file)).await.unwrap();
+
+    assert!(receiver.is_complete());
+    assert_eq!(file, data);
+}
+
+#[tokio::test]
+async fn bad_checksum() {
+    let pair = pair(None).await;
+
+    // A valid chunk, followed by one with the wrong checksum.
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&0u64.to_be_bytes());
+    for (payload, corrupt) in [(&b"good"[..], false), (&b"evil"[..], true)] {
+        let mut checksum = ring::digest::digest(&ring::digest::SHA256, payload)
+            .as_ref()
+            .to_vec();
+        if corrupt {
+            checksum[0] ^= 1;
+        }
+
+        stream.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        stream.extend_from_slice(payload);
+        stream.extend_from_slice(&checksum);
+    }
+    stream.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut send = pair.client.open_uni().await.unwrap();
+    send.write_all(&stream).await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut recv = timeout(pair.server.accept_uni()).await.unwrap();
+    let mut receiver = FileReceiver::new();
+    let mut file = Vec::new();
+
+    match timeout(receiver.recv(&mut recv, &mut file)).await {
+        Err(TransferError::Checksum(offset)) => assert_eq!(offset, 4),
+        res => panic!("expected a checksum mismatch: {:?}", res),
+    }
+
+    // The corrupt chunk wasn't written, so the transfer can resume before it.
+    assert_eq!(file, b"good");
+    assert_eq!(receiver.offset(), 4);
+    assert!(!receiver.is_complete());
+}
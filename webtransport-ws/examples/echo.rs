@@ -0,0 +1,74 @@
+use std::future::poll_fn;
+
+use bytes::Bytes;
+use tokio::net::{TcpListener, TcpStream};
+use webtransport_generic::{RecvStream, SendStream, Session};
+
+// Runs an echo server and a client against it over a plain WebSocket.
+// A TLS stream (ex. tokio-rustls) can be used instead of the TcpStream for wss.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if let Err(err) = serve(tcp).await {
+                    println!("server error: {}", err);
+                }
+            });
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await?;
+    let url = format!("ws://localhost:{}/echo", addr.port()).parse()?;
+
+    let (session, driver) = webtransport_ws::connect(tcp, &url).await?;
+    let driver = tokio::spawn(driver);
+
+    let (mut send, mut recv) = session.open_bi().await?;
+    send.write_all(b"hello bi").await?;
+    send.finish()?;
+    let msg = recv.read_to_end(1024).await?;
+    println!("bi: {}", String::from_utf8_lossy(&msg));
+
+    session.close(0, b"done");
+
+    // Wait until the close was sent and the server closed the WebSocket too.
+    driver.await??;
+
+    Ok(())
+}
+
+async fn serve(tcp: TcpStream) -> anyhow::Result<()> {
+    let (mut session, driver) = webtransport_ws::accept(tcp).await?;
+    println!("accepted session: {}", session.url());
+
+    tokio::spawn(driver);
+
+    echo(&mut session).await
+}
+
+// Only uses the webtransport-generic traits, so it works with the other backends too.
+async fn echo<S: Session>(session: &mut S) -> anyhow::Result<()> {
+    loop {
+        let (mut send, mut recv) = match poll_fn(|cx| session.poll_accept_bidi(cx)).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("server session closed: {}", err);
+                return Ok(());
+            }
+        };
+
+        let mut msg = Vec::new();
+        while poll_fn(|cx| recv.poll_recv(cx, &mut msg)).await?.is_some() {}
+
+        let mut msg = Bytes::from(msg);
+        while !msg.is_empty() {
+            poll_fn(|cx| send.poll_send(cx, &mut msg)).await?;
+        }
+
+        poll_fn(|cx| send.poll_finish(cx)).await?;
+    }
+}
@@ -0,0 +1,30 @@
+use http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use webtransport_h2::{Driver, Role, Session};
+
+use crate::{transport::WsTransport, ClientError, PROTOCOL};
+
+/// Establish a WebTransport session over a WebSocket, using an existing connection such as a TLS stream.
+///
+/// The URL should use the `ws` or `wss` scheme; the `webtransport` subprotocol is requested during the handshake.
+/// The returned [`Driver`] must be spawned (or otherwise polled) for the session to make any progress.
+pub async fn connect<T>(io: T, url: &Uri) -> Result<(Session, Driver), ClientError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut request = url.into_client_request()?;
+    request
+        .headers_mut()
+        .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(PROTOCOL));
+
+    let (ws, response) = tokio_tungstenite::client_async(request, io).await?;
+
+    let protocol = response.headers().get(SEC_WEBSOCKET_PROTOCOL);
+    if protocol.map(|p| p.as_bytes()) != Some(PROTOCOL.as_bytes()) {
+        return Err(ClientError::UnsupportedProtocol);
+    }
+
+    let transport = WsTransport::new(ws);
+    Ok(Session::new(Role::Client, url.clone(), transport))
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+/// An error returned by [`crate::connect`].
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] tungstenite::Error),
+
+    #[error("server didn't select the webtransport subprotocol")]
+    UnsupportedProtocol,
+}
+
+/// An error returned by [`crate::accept`].
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] tungstenite::Error),
+}
@@ -0,0 +1,26 @@
+//! WebTransport over a WebSocket, for clients that can reach neither UDP nor HTTP/2 extended CONNECT.
+//!
+//! The session uses the same capsules as webtransport-h2, carried in binary messages of a WebSocket that negotiated the
+//! `webtransport` subprotocol. The [`Session`], [`SendStream`] and [`RecvStream`] are the webtransport-h2 types, so they
+//! implement the webtransport-generic traits and application code written against those traits works with any backend.
+//!
+//! The library doesn't spawn tasks, so [`connect`] and [`accept`] return a [`Driver`] that must be spawned.
+//!
+//! This has the same limitations as webtransport-h2: everything shares one TCP connection, datagrams are delivered reliably,
+//! and both endpoints start with fixed stream and flow control limits. This isn't a standard mapping, so both endpoints must use this crate.
+mod client;
+mod error;
+mod server;
+mod transport;
+
+pub use client::*;
+pub use error::*;
+pub use server::*;
+
+pub use webtransport_h2::{
+    Driver, ReadError, ReadToEndError, RecvStream, SendDatagramError, SendStream, Session,
+    SessionError, WriteError, MAX_DATAGRAM_SIZE,
+};
+
+/// The WebSocket subprotocol that both endpoints must negotiate.
+pub const PROTOCOL: &str = "webtransport";
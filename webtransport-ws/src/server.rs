@@ -0,0 +1,50 @@
+use http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue, StatusCode};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use webtransport_h2::{Driver, Role, Session};
+
+use crate::{transport::WsTransport, ServerError, PROTOCOL};
+
+/// Perform the WebSocket handshake on an accepted connection and establish a WebTransport session.
+///
+/// The client must request the `webtransport` subprotocol, otherwise it's rejected with a 400.
+/// The URL only includes the path, which is available with [`Session::url`] to decide whether to keep the session.
+/// The returned [`Driver`] must be spawned (or otherwise polled) for the session to make any progress.
+pub async fn accept<T>(io: T) -> Result<(Session, Driver), ServerError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut url = None;
+
+    // The error response type is dictated by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, mut response: Response| {
+        let offered = request
+            .headers()
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|protocol| protocol.trim() == PROTOCOL);
+
+        if !offered {
+            let mut response = ErrorResponse::new(Some("webtransport subprotocol required".into()));
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            return Err(response);
+        }
+
+        response
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(PROTOCOL));
+
+        url = Some(request.uri().clone());
+
+        Ok(response)
+    };
+
+    let ws = tokio_tungstenite::accept_hdr_async(io, callback).await?;
+    let url = url.expect("handshake succeeded without a request");
+
+    let transport = WsTransport::new(ws);
+    Ok(Session::new(Role::Server, url, transport))
+}
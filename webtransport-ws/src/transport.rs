@@ -0,0 +1,116 @@
+use std::{
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{
+    tungstenite::{self, error::ProtocolError, Message},
+    WebSocketStream,
+};
+use webtransport_h2::{SessionError, Transport};
+
+// Carries the capsules in binary WebSocket messages, which don't have to line up with capsule boundaries.
+// Finishing the session sends a Close frame, and the connection closes after the peer does the same.
+pub(crate) struct WsTransport<T> {
+    ws: WebSocketStream<T>,
+
+    // Set when a Close frame needs to be sent.
+    closing: bool,
+
+    // Set once both sides finished, so the connection can be read until it closes.
+    released: bool,
+}
+
+impl<T> WsTransport<T> {
+    pub fn new(ws: WebSocketStream<T>) -> Self {
+        Self {
+            ws,
+            closing: false,
+            released: false,
+        }
+    }
+}
+
+impl<T> Transport for WsTransport<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SessionError>> {
+        if self.closing {
+            ready!(self.ws.poll_ready_unpin(cx)).map_err(error)?;
+
+            match self.ws.start_send_unpin(Message::Close(None)) {
+                // The peer closed first, so tungstenite already replied.
+                Ok(()) | Err(tungstenite::Error::Protocol(ProtocolError::SendAfterClosing)) => {}
+                Err(err) => return Poll::Ready(Err(error(err))),
+            }
+
+            self.closing = false;
+        }
+
+        match self.ws.poll_flush_unpin(cx) {
+            Poll::Ready(Ok(())) | Poll::Pending => {}
+            Poll::Ready(Err(tungstenite::Error::ConnectionClosed)) => return Poll::Ready(Ok(())),
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(error(err))),
+        }
+
+        if !self.released {
+            return Poll::Pending;
+        }
+
+        // Read until the closing handshake is done, which also flushes our reply.
+        loop {
+            match ready!(self.ws.poll_next_unpin(cx)) {
+                Some(Ok(_)) => continue,
+                Some(Err(tungstenite::Error::ConnectionClosed)) | None => {
+                    return Poll::Ready(Ok(()))
+                }
+                Some(Err(err)) => return Poll::Ready(Err(error(err))),
+            }
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, SessionError>> {
+        loop {
+            match ready!(self.ws.poll_next_unpin(cx)) {
+                Some(Ok(Message::Binary(data))) => return Poll::Ready(Ok(Some(data.into()))),
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(None)),
+                // Pings are answered by tungstenite, and there's nothing to do with text.
+                Some(Ok(_)) => continue,
+                Some(Err(tungstenite::Error::ConnectionClosed)) => return Poll::Ready(Ok(None)),
+                Some(Err(err)) => return Poll::Ready(Err(error(err))),
+            }
+        }
+    }
+
+    fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Bytes,
+    ) -> Poll<Result<(), SessionError>> {
+        ready!(self.ws.poll_ready_unpin(cx)).map_err(error)?;
+
+        let data = buf.split_to(buf.len());
+        self.ws
+            .start_send_unpin(Message::Binary(data.into()))
+            .map_err(error)?;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn finish(&mut self) -> Result<(), SessionError> {
+        self.closing = true;
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.released = true;
+    }
+}
+
+fn error(err: tungstenite::Error) -> SessionError {
+    SessionError::TransportError(Arc::new(err))
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use webtransport_ws::{Session, SessionError};
+
+// Wait for the operation, failing if it's still pending, so a missed wakeup fails the test instead of hanging it.
+async fn timeout<F: std::future::Future>(fut: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(5), fut)
+        .await
+        .expect("timed out")
+}
+
+// A connected pair of sessions over a WebSocket on localhost, with their drivers spawned.
+#[allow(clippy::type_complexity)]
+async fn pair() -> (
+    (Session, tokio::task::JoinHandle<Result<(), SessionError>>),
+    (Session, tokio::task::JoinHandle<Result<(), SessionError>>),
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let accept = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let (session, driver) = webtransport_ws::accept(tcp).await.unwrap();
+        (session, tokio::spawn(driver))
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let url = format!("ws://localhost:{}/room", addr.port())
+        .parse()
+        .unwrap();
+    let (session, driver) = webtransport_ws::connect(tcp, &url).await.unwrap();
+
+    let client = (session, tokio::spawn(driver));
+    let server = accept.await.unwrap();
+
+    (client, server)
+}
+
+#[tokio::test]
+async fn stream_round_trip() {
+    let ((client, _), (server, _)) = pair().await;
+    assert_eq!(server.url().path(), "/room");
+
+    let echo = tokio::spawn(async move {
+        let (mut send, mut recv) = server.accept_bi().await.unwrap();
+        let msg = recv.read_to_end(1024).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().unwrap();
+        server
+    });
+
+    let (mut send, mut recv) = client.open_bi().await.unwrap();
+    send.write_all(b"hello ws").await.unwrap();
+    send.finish().unwrap();
+
+    let msg = timeout(recv.read_to_end(1024)).await.unwrap();
+    assert_eq!(msg, b"hello ws");
+    echo.await.unwrap();
+}
+
+#[tokio::test]
+async fn close() {
+    let ((client, client_driver), (server, server_driver)) = pair().await;
+    client.close(42, b"goodbye");
+
+    match timeout(server.closed()).await {
+        SessionError::Closed { code, reason } => {
+            assert_eq!(code, 42);
+            assert_eq!(reason, "goodbye");
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    // Both ends close the WebSocket, so the drivers return.
+    timeout(client_driver).await.unwrap().unwrap();
+    timeout(server_driver).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn missing_protocol() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let accept = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        webtransport_ws::accept(tcp).await.map(|_| ())
+    });
+
+    // A plain WebSocket without the subprotocol.
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let url = format!("ws://localhost:{}/", addr.port());
+    assert!(tokio_tungstenite::client_async(url, tcp).await.is_err());
+    assert!(timeout(accept).await.unwrap().is_err());
+}